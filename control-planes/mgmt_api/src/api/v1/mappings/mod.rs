@@ -33,6 +33,7 @@ where
             id: val.id,
             spec: val.spec.into(),
             status: val.status.map(|s| s.into()),
+            warnings: Vec::new(),
         }
     }
 }
@@ -14,13 +14,15 @@
 
 use crate::domain::models::{
     QueryJoin, QueryJoinKey, QueryLanguage, QuerySourceLabel, QuerySources, QuerySpec, QueryStatus,
-    QuerySubscription, Resource, RetentionPolicy, SourceMiddlewareConfig, ViewSpec,
+    QuerySubscription, Resource, RetentionPolicy, SourceMiddlewareConfig, ViewExportFormat,
+    ViewExportSchedule, ViewJoinSpec, ViewJoinedQuery, ViewSpec,
 };
 
 use super::{
     ContinuousQueryDto, QueryJoinDto, QueryJoinKeyDto, QueryLanguageDto, QuerySourceLabelDto,
     QuerySourcesDto, QuerySpecDto, QueryStatusDto, QuerySubscriptionDto, RetentionPolicyDto,
-    SourceMiddlewareConfigDto, ViewSpecDto,
+    SourceMiddlewareConfigDto, ViewExportFormatDto, ViewExportScheduleDto, ViewJoinSpecDto,
+    ViewJoinedQueryDto, ViewSpecDto,
 };
 
 impl From<QueryStatus> for QueryStatusDto {
@@ -69,6 +71,7 @@ impl From<Resource<QuerySpec, QueryStatus>> for ContinuousQueryDto {
             id: res.id,
             spec: res.spec.into(),
             status: res.status.map(|s| s.into()),
+            warnings: res.warnings,
         }
     }
 }
@@ -120,6 +123,8 @@ impl From<QuerySubscriptionDto> for QuerySubscription {
                 None => Vec::new(),
             },
             pipeline: subscription.pipeline.unwrap_or_default(),
+            node_predicates: subscription.node_predicates.unwrap_or_default(),
+            rel_predicates: subscription.rel_predicates.unwrap_or_default(),
         }
     }
 }
@@ -137,6 +142,8 @@ impl From<QuerySubscription> for QuerySubscriptionDto {
                     .collect(),
             ),
             pipeline: Some(subscription.pipeline),
+            node_predicates: Some(subscription.node_predicates),
+            rel_predicates: Some(subscription.rel_predicates),
         }
     }
 }
@@ -229,6 +236,69 @@ impl From<ViewSpecDto> for ViewSpec {
         ViewSpec {
             enabled: spec.enabled,
             retention_policy: spec.retention_policy.into(),
+            index_fields: spec.index_fields.unwrap_or_default(),
+            key_fields: spec.key_fields.unwrap_or_default(),
+            exports: spec
+                .exports
+                .unwrap_or_default()
+                .into_iter()
+                .map(|e| e.into())
+                .collect(),
+            join: spec.join.map(|j| j.into()),
+        }
+    }
+}
+
+impl From<ViewJoinSpecDto> for ViewJoinSpec {
+    fn from(join: ViewJoinSpecDto) -> Self {
+        ViewJoinSpec {
+            queries: join
+                .queries
+                .into_iter()
+                .map(|q| ViewJoinedQuery {
+                    query_id: q.query_id,
+                    key_field: q.key_field,
+                })
+                .collect(),
+        }
+    }
+}
+
+impl From<ViewJoinSpec> for ViewJoinSpecDto {
+    fn from(join: ViewJoinSpec) -> Self {
+        ViewJoinSpecDto {
+            queries: join
+                .queries
+                .into_iter()
+                .map(|q| ViewJoinedQueryDto {
+                    query_id: q.query_id,
+                    key_field: q.key_field,
+                })
+                .collect(),
+        }
+    }
+}
+
+impl From<ViewExportScheduleDto> for ViewExportSchedule {
+    fn from(schedule: ViewExportScheduleDto) -> Self {
+        ViewExportSchedule {
+            format: match schedule.format {
+                ViewExportFormatDto::Parquet => ViewExportFormat::Parquet,
+                ViewExportFormatDto::Csv => ViewExportFormat::Csv,
+            },
+            interval_seconds: schedule.interval_seconds,
+        }
+    }
+}
+
+impl From<ViewExportSchedule> for ViewExportScheduleDto {
+    fn from(schedule: ViewExportSchedule) -> Self {
+        ViewExportScheduleDto {
+            format: match schedule.format {
+                ViewExportFormat::Parquet => ViewExportFormatDto::Parquet,
+                ViewExportFormat::Csv => ViewExportFormatDto::Csv,
+            },
+            interval_seconds: schedule.interval_seconds,
         }
     }
 }
@@ -250,6 +320,10 @@ impl From<ViewSpec> for ViewSpecDto {
         ViewSpecDto {
             enabled: spec.enabled,
             retention_policy: spec.retention_policy.into(),
+            index_fields: Some(spec.index_fields),
+            key_fields: Some(spec.key_fields),
+            exports: Some(spec.exports.into_iter().map(|e| e.into()).collect()),
+            join: spec.join.map(|j| j.into()),
         }
     }
 }
@@ -271,3 +345,40 @@ impl From<QueryLanguage> for QueryLanguageDto {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn subscription_predicates_reach_query_host_payload() {
+        let predicates = json!({
+            "Vehicle": { "status": { "eq": "active" } }
+        });
+        let dto: QuerySpecDto = serde_json::from_value(json!({
+            "mode": "query",
+            "query": "MATCH (v:Vehicle) RETURN v",
+            "sources": {
+                "subscriptions": [{
+                    "id": "fleet",
+                    "nodes": [{ "sourceLabel": "Vehicle" }],
+                    "nodePredicates": predicates,
+                    "relPredicates": { "LOCATED_AT": { "since": { "gte": 10 } } }
+                }]
+            }
+        }))
+        .expect("valid query spec");
+
+        let spec: QuerySpec = dto.into();
+        let payload = serde_json::to_value(resource_provider_api::models::QuerySpec::from(spec))
+            .expect("serializable query spec");
+
+        let subscription = &payload["sources"]["subscriptions"][0];
+        assert_eq!(subscription["nodePredicates"], predicates);
+        assert_eq!(
+            subscription["relPredicates"],
+            json!({ "LOCATED_AT": { "since": { "gte": 10 } } })
+        );
+    }
+}
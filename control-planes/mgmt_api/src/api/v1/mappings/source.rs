@@ -12,9 +12,16 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
-use crate::domain::models::{Resource, SourceSpec, SourceStatus};
+use crate::domain::models::{
+    ElementSchema, PropertySchema, Resource, SourceSchema, SourceSpec, SourceStatus,
+    SourceSubscription, SubscriptionFix, SubscriptionFixAction, SubscriptionReconciliation,
+};
 
-use super::{SourceDto, SourceSpecDto, SourceStatusDto};
+use super::{
+    ElementSchemaDto, PropertySchemaDto, SourceDto, SourceSchemaDto, SourceSpecDto,
+    SourceStatusDto, SourceSubscriptionDto, SubscriptionFixActionDto, SubscriptionFixDto,
+    SubscriptionReconciliationDto,
+};
 
 impl From<SourceStatusDto> for SourceStatus {
     fn from(status: SourceStatusDto) -> Self {
@@ -75,3 +82,75 @@ impl From<Resource<SourceSpec, SourceStatus>> for SourceDto {
         }
     }
 }
+
+impl From<SourceSubscription> for SourceSubscriptionDto {
+    fn from(subscription: SourceSubscription) -> Self {
+        SourceSubscriptionDto {
+            query_node_id: subscription.query_node_id,
+            query_id: subscription.query_id,
+            node_labels: subscription.node_labels,
+            rel_labels: subscription.rel_labels,
+            subscribed_at: subscription.subscribed_at,
+            orphaned: subscription.orphaned,
+        }
+    }
+}
+
+impl From<SubscriptionFixAction> for SubscriptionFixActionDto {
+    fn from(action: SubscriptionFixAction) -> Self {
+        match action {
+            SubscriptionFixAction::Subscribe => SubscriptionFixActionDto::Subscribe,
+            SubscriptionFixAction::Unsubscribe => SubscriptionFixActionDto::Unsubscribe,
+        }
+    }
+}
+
+impl From<SubscriptionFix> for SubscriptionFixDto {
+    fn from(fix: SubscriptionFix) -> Self {
+        SubscriptionFixDto {
+            action: fix.action.into(),
+            query_node_id: fix.query_node_id,
+            query_id: fix.query_id,
+            error: fix.error,
+        }
+    }
+}
+
+impl From<SubscriptionReconciliation> for SubscriptionReconciliationDto {
+    fn from(reconciliation: SubscriptionReconciliation) -> Self {
+        SubscriptionReconciliationDto {
+            source_id: reconciliation.source_id,
+            completed_at: reconciliation.completed_at,
+            fixes: reconciliation.fixes.into_iter().map(|f| f.into()).collect(),
+        }
+    }
+}
+
+impl From<PropertySchema> for PropertySchemaDto {
+    fn from(property: PropertySchema) -> Self {
+        PropertySchemaDto {
+            name: property.name,
+            property_type: property.property_type,
+        }
+    }
+}
+
+impl From<ElementSchema> for ElementSchemaDto {
+    fn from(element: ElementSchema) -> Self {
+        ElementSchemaDto {
+            label: element.label,
+            properties: element.properties.into_iter().map(|p| p.into()).collect(),
+            start_label: element.start_label,
+            end_label: element.end_label,
+        }
+    }
+}
+
+impl From<SourceSchema> for SourceSchemaDto {
+    fn from(schema: SourceSchema) -> Self {
+        SourceSchemaDto {
+            nodes: schema.nodes.into_iter().map(|n| n.into()).collect(),
+            relations: schema.relations.into_iter().map(|r| r.into()).collect(),
+        }
+    }
+}
@@ -38,6 +38,8 @@ pub struct QuerySubscriptionDto {
     pub nodes: Option<Vec<QuerySourceLabelDto>>,
     pub relations: Option<Vec<QuerySourceLabelDto>>,
     pub pipeline: Option<Vec<String>>,
+    pub node_predicates: Option<Map<String, Value>>,
+    pub rel_predicates: Option<Map<String, Value>>,
 }
 
 #[derive(Serialize, Deserialize, Debug, ToSchema)]
@@ -103,6 +105,37 @@ pub struct QueryStatusDto {
 pub struct ViewSpecDto {
     pub enabled: bool,
     pub retention_policy: RetentionPolicyDto,
+    pub index_fields: Option<Vec<String>>,
+    pub key_fields: Option<Vec<String>>,
+    pub exports: Option<Vec<ViewExportScheduleDto>>,
+    pub join: Option<ViewJoinSpecDto>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct ViewJoinSpecDto {
+    pub queries: Vec<ViewJoinedQueryDto>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct ViewJoinedQueryDto {
+    pub query_id: String,
+    pub key_field: String,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct ViewExportScheduleDto {
+    pub format: ViewExportFormatDto,
+    pub interval_seconds: u64,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub enum ViewExportFormatDto {
+    Parquet,
+    Csv,
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone, Copy, ToSchema)]
@@ -126,6 +159,10 @@ impl Default for ViewSpecDto {
         ViewSpecDto {
             enabled: true,
             retention_policy: RetentionPolicyDto::Latest,
+            index_fields: None,
+            key_fields: None,
+            exports: None,
+            join: None,
         }
     }
 }
@@ -135,4 +172,6 @@ pub struct ContinuousQueryDto {
     pub id: String,
     pub spec: QuerySpecDto,
     pub status: Option<QueryStatusDto>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub warnings: Vec<String>,
 }
@@ -46,3 +46,67 @@ pub struct SourceDto {
     pub spec: SourceSpecDto,
     pub status: Option<SourceStatusDto>,
 }
+
+#[derive(Serialize, Deserialize, Debug, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct SourceSubscriptionDto {
+    pub query_node_id: String,
+    pub query_id: String,
+    pub node_labels: Vec<String>,
+    pub rel_labels: Vec<String>,
+    pub subscribed_at: Option<i64>,
+    pub orphaned: bool,
+}
+
+#[derive(Serialize, Deserialize, Debug, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub enum SubscriptionFixActionDto {
+    Subscribe,
+    Unsubscribe,
+}
+
+#[derive(Serialize, Deserialize, Debug, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct SubscriptionFixDto {
+    pub action: SubscriptionFixActionDto,
+    pub query_node_id: String,
+    pub query_id: String,
+    pub error: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Debug, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct SubscriptionReconciliationDto {
+    pub source_id: String,
+    pub completed_at: i64,
+    pub fixes: Vec<SubscriptionFixDto>,
+}
+
+#[derive(Serialize, Deserialize, Debug, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct SourceSchemaDto {
+    pub nodes: Vec<ElementSchemaDto>,
+    pub relations: Vec<ElementSchemaDto>,
+}
+
+#[derive(Serialize, Deserialize, Debug, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct ElementSchemaDto {
+    pub label: String,
+    pub properties: Vec<PropertySchemaDto>,
+    /// The label of the start node, for relations
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub start_label: Option<String>,
+    /// The label of the end node, for relations
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub end_label: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Debug, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct PropertySchemaDto {
+    pub name: String,
+    /// One of string, integer, float, boolean, list, object or any
+    #[serde(rename = "type")]
+    pub property_type: String,
+}
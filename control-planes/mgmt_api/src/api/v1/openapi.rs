@@ -24,6 +24,10 @@ use utoipa::OpenApi;
         super::sources::delete,
         super::sources::list,
         super::sources::ready_wait,
+        super::sources::subscriptions,
+        super::sources::get_reconciliation,
+        super::sources::reconcile,
+        super::sources::schema,
 
         // Query Containers
         super::query_containers::upsert,
@@ -75,6 +79,13 @@ use utoipa::OpenApi;
             // Source DTOs
             SourceSpecDto,
             SourceStatusDto,
+            SourceSubscriptionDto,
+            SubscriptionReconciliationDto,
+            SubscriptionFixDto,
+            SubscriptionFixActionDto,
+            SourceSchemaDto,
+            ElementSchemaDto,
+            PropertySchemaDto,
 
             // Query Container DTOs
             QueryContainerSpecDto,
@@ -96,6 +107,10 @@ use utoipa::OpenApi;
             QuerySourcesDto,
             SourceMiddlewareConfigDto,
             ViewSpecDto,
+            ViewExportScheduleDto,
+            ViewExportFormatDto,
+            ViewJoinSpecDto,
+            ViewJoinedQueryDto,
             RetentionPolicyDto,
 
             // Provider DTOs
@@ -17,8 +17,14 @@ use std::time::Duration;
 use utoipa;
 
 use super::constants::MAX_READY_WAIT_TIMEOUT_SECS;
-use super::models::{ReadyWaitParams, SourceDto, SourceSpecDto};
-use crate::domain::resource_services::SourceDomainService;
+use super::models::{
+    ReadyWaitParams, SourceDto, SourceSchemaDto, SourceSpecDto, SourceSubscriptionDto,
+    SubscriptionReconciliationDto,
+};
+use crate::domain::{
+    resource_services::SourceDomainService, source_schema_service::SourceSchemaService,
+    subscription_service::SubscriptionService,
+};
 
 #[utoipa::path(
     put,
@@ -163,6 +169,135 @@ pub async fn ready_wait(
     }
 }
 
+#[utoipa::path(
+    get,
+    path = "/v1/sources/{id}/subscriptions",
+    tag = "Sources",
+    operation_id = "list_source_subscriptions",
+    params(
+        ("id" = String, Path, description = "Source ID")
+    ),
+    responses(
+        (status = 200, description = "Subscriptions registered with the source. Subscriptions that no query accounts for are flagged as orphaned.", body = Vec<SourceSubscriptionDto>),
+        (status = 404, description = "Source not found"),
+        (status = 500, description = "Internal server error")
+    )
+)]
+pub async fn subscriptions(
+    service: web::Data<SourceDomainService>,
+    subscription_service: web::Data<SubscriptionService>,
+    id: web::Path<String>,
+) -> impl Responder {
+    log::debug!("list_subscriptions: {:?}", id);
+    let id = id.into_inner();
+
+    if let Err(e) = service.get(&id).await {
+        return e.into();
+    }
+
+    match subscription_service.list(&id).await {
+        Ok(res) => {
+            let result = res
+                .into_iter()
+                .map(SourceSubscriptionDto::from)
+                .collect::<Vec<_>>();
+            HttpResponse::Ok().json(result)
+        }
+        Err(e) => e.into(),
+    }
+}
+
+#[utoipa::path(
+    get,
+    path = "/v1/sources/{id}/subscriptions/reconciliation",
+    tag = "Sources",
+    operation_id = "get_source_subscription_reconciliation",
+    params(
+        ("id" = String, Path, description = "Source ID")
+    ),
+    responses(
+        (status = 200, description = "Outcome of the last reconciliation of the subscriptions of the source", body = SubscriptionReconciliationDto),
+        (status = 404, description = "Source not found, or not reconciled yet"),
+        (status = 500, description = "Internal server error")
+    )
+)]
+pub async fn get_reconciliation(
+    subscription_service: web::Data<SubscriptionService>,
+    id: web::Path<String>,
+) -> impl Responder {
+    log::debug!("get_reconciliation: {:?}", id);
+
+    match subscription_service.last_reconciliation(&id.into_inner()) {
+        Some(res) => HttpResponse::Ok().json(SubscriptionReconciliationDto::from(res)),
+        None => HttpResponse::NotFound().finish(),
+    }
+}
+
+#[utoipa::path(
+    post,
+    path = "/v1/sources/{id}/subscriptions/reconciliation",
+    tag = "Sources",
+    operation_id = "reconcile_source_subscriptions",
+    params(
+        ("id" = String, Path, description = "Source ID")
+    ),
+    responses(
+        (status = 200, description = "Orphaned subscriptions were removed and missing subscriptions restored. Fixes that failed carry an error.", body = SubscriptionReconciliationDto),
+        (status = 404, description = "Source not found"),
+        (status = 500, description = "Internal server error")
+    )
+)]
+pub async fn reconcile(
+    service: web::Data<SourceDomainService>,
+    subscription_service: web::Data<SubscriptionService>,
+    id: web::Path<String>,
+) -> impl Responder {
+    log::debug!("reconcile_subscriptions: {:?}", id);
+    let id = id.into_inner();
+
+    if let Err(e) = service.get(&id).await {
+        return e.into();
+    }
+
+    match subscription_service.reconcile(&id).await {
+        Ok(res) => HttpResponse::Ok().json(SubscriptionReconciliationDto::from(res)),
+        Err(e) => e.into(),
+    }
+}
+
+#[utoipa::path(
+    get,
+    path = "/v1/sources/{id}/schema",
+    tag = "Sources",
+    operation_id = "get_source_schema",
+    params(
+        ("id" = String, Path, description = "Source ID")
+    ),
+    responses(
+        (status = 200, description = "The node and relation labels the source declares it can produce, with their properties", body = SourceSchemaDto),
+        (status = 404, description = "Source not found, or the source does not declare a schema"),
+        (status = 500, description = "Internal server error")
+    )
+)]
+pub async fn schema(
+    service: web::Data<SourceDomainService>,
+    schema_service: web::Data<dyn SourceSchemaService>,
+    id: web::Path<String>,
+) -> impl Responder {
+    log::debug!("get_schema: {:?}", id);
+    let id = id.into_inner();
+
+    if let Err(e) = service.get(&id).await {
+        return e.into();
+    }
+
+    match schema_service.get(&id).await {
+        Ok(Some(schema)) => HttpResponse::Ok().json(SourceSchemaDto::from(schema)),
+        Ok(None) => HttpResponse::NotFound().body("The source does not declare a schema"),
+        Err(e) => e.into(),
+    }
+}
+
 pub fn configure_routes() -> actix_web::Scope {
     web::scope("/v1/sources")
         .route("/{id}", web::put().to(upsert))
@@ -170,4 +305,14 @@ pub fn configure_routes() -> actix_web::Scope {
         .route("/{id}", web::delete().to(delete))
         .route("", web::get().to(list))
         .route("/{id}/ready-wait", web::get().to(ready_wait))
+        .route("/{id}/subscriptions", web::get().to(subscriptions))
+        .route("/{id}/schema", web::get().to(schema))
+        .route(
+            "/{id}/subscriptions/reconciliation",
+            web::get().to(get_reconciliation),
+        )
+        .route(
+            "/{id}/subscriptions/reconciliation",
+            web::post().to(reconcile),
+        )
 }
@@ -19,6 +19,9 @@ use super::{
     result_service::ResultService,
 };
 
+/// Prefix of the ids of the transient queries created for debugging
+pub const DEBUG_QUERY_PREFIX: &str = "debug-";
+
 pub struct DebugService {
     //dapr_client: dapr::Client<TonicClient>,
     result_service: Arc<ResultService>,
@@ -44,7 +47,7 @@ impl DebugService {
         mut spec: QuerySpec,
         mut cancellation: oneshot::Receiver<()>,
     ) -> Result<impl Stream<Item = Result<ResultEvent, DomainError>>, DomainError> {
-        let temp_id = format!("debug-{}", uuid::Uuid::new_v4());
+        let temp_id = format!("{}{}", DEBUG_QUERY_PREFIX, uuid::Uuid::new_v4());
         log::info!("debugging query: {}", temp_id);
         spec.transient = Some(true);
 
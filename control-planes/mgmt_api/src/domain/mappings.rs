@@ -259,6 +259,8 @@ impl From<QuerySubscription> for resource_provider_api::models::QuerySubscriptio
                 .map(|v| v.into())
                 .collect(),
             pipeline: query_subscription.pipeline,
+            node_predicates: query_subscription.node_predicates,
+            rel_predicates: query_subscription.rel_predicates,
         }
     }
 }
@@ -434,6 +436,39 @@ impl From<ViewSpec> for resource_provider_api::models::ViewSpec {
         resource_provider_api::models::ViewSpec {
             enabled: view_spec.enabled,
             retention_policy: view_spec.retention_policy.into(),
+            index_fields: view_spec.index_fields,
+            key_fields: view_spec.key_fields,
+            exports: view_spec.exports.into_iter().map(|e| e.into()).collect(),
+            join: view_spec.join.map(|j| j.into()),
+        }
+    }
+}
+
+impl From<ViewJoinSpec> for resource_provider_api::models::ViewJoinSpec {
+    fn from(join: ViewJoinSpec) -> resource_provider_api::models::ViewJoinSpec {
+        resource_provider_api::models::ViewJoinSpec {
+            queries: join
+                .queries
+                .into_iter()
+                .map(|q| resource_provider_api::models::ViewJoinedQuery {
+                    query_id: q.query_id,
+                    key_field: q.key_field,
+                })
+                .collect(),
+        }
+    }
+}
+
+impl From<ViewExportSchedule> for resource_provider_api::models::ViewExportSchedule {
+    fn from(schedule: ViewExportSchedule) -> resource_provider_api::models::ViewExportSchedule {
+        resource_provider_api::models::ViewExportSchedule {
+            format: match schedule.format {
+                ViewExportFormat::Parquet => {
+                    resource_provider_api::models::ViewExportFormat::Parquet
+                }
+                ViewExportFormat::Csv => resource_provider_api::models::ViewExportFormat::Csv,
+            },
+            interval_seconds: schedule.interval_seconds,
         }
     }
 }
@@ -19,3 +19,5 @@ pub mod query_actor_service;
 pub mod resource_provider_services;
 pub mod resource_services;
 pub mod result_service;
+pub mod source_schema_service;
+pub mod subscription_service;
@@ -26,6 +26,8 @@ pub struct Resource<TSpec, TStatus> {
     pub id: String,
     pub spec: TSpec,
     pub status: Option<TStatus>,
+    /// Problems found while applying the resource that did not prevent it from being applied
+    pub warnings: Vec<String>,
 }
 
 #[derive(Serialize, Deserialize, Debug)]
@@ -154,6 +156,12 @@ pub struct QuerySubscription {
     pub nodes: Vec<QuerySourceLabel>,
     pub relations: Vec<QuerySourceLabel>,
     pub pipeline: Vec<String>,
+    /// Property predicates by node label, that limit the changes routed to the query
+    #[serde(default)]
+    pub node_predicates: Map<String, Value>,
+    /// Property predicates by relation label, that limit the changes routed to the query
+    #[serde(default)]
+    pub rel_predicates: Map<String, Value>,
 }
 
 #[derive(Serialize, Deserialize, Clone, Debug)]
@@ -219,6 +227,77 @@ pub struct QueryStatus {
     pub error_message: Option<String>,
 }
 
+/// A subscription of a continuous query, as registered with the change router of a source
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct SourceSubscription {
+    pub query_node_id: String,
+    pub query_id: String,
+    pub node_labels: Vec<String>,
+    pub rel_labels: Vec<String>,
+    #[serde(default)]
+    pub subscribed_at: Option<i64>,
+
+    /// Set when the query no longer exists, or no longer subscribes to the source from this query node
+    #[serde(default)]
+    pub orphaned: bool,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub enum SubscriptionFixAction {
+    Subscribe,
+    Unsubscribe,
+}
+
+/// A subscription that the reconciler added to or removed from a source
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct SubscriptionFix {
+    pub action: SubscriptionFixAction,
+    pub query_node_id: String,
+    pub query_id: String,
+    pub error: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct SubscriptionReconciliation {
+    pub source_id: String,
+    pub completed_at: i64,
+    pub fixes: Vec<SubscriptionFix>,
+}
+
+/// The labels a source declares it can produce, and the properties of each
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct SourceSchema {
+    #[serde(default)]
+    pub nodes: Vec<ElementSchema>,
+    #[serde(default)]
+    pub relations: Vec<ElementSchema>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct ElementSchema {
+    pub label: String,
+    #[serde(default)]
+    pub properties: Vec<PropertySchema>,
+    #[serde(default)]
+    pub start_label: Option<String>,
+    #[serde(default)]
+    pub end_label: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct PropertySchema {
+    pub name: String,
+    #[serde(rename = "type")]
+    pub property_type: String,
+}
+
 #[derive(Serialize, Deserialize, Clone, Debug)]
 #[serde(rename_all = "camelCase")]
 pub struct SourceProviderStatus {
@@ -322,6 +401,41 @@ pub enum EndpointSetting {
 pub struct ViewSpec {
     pub enabled: bool,
     pub retention_policy: RetentionPolicy,
+    #[serde(default)]
+    pub index_fields: Vec<String>,
+    #[serde(default)]
+    pub key_fields: Vec<String>,
+    #[serde(default)]
+    pub exports: Vec<ViewExportSchedule>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub join: Option<ViewJoinSpec>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct ViewJoinSpec {
+    pub queries: Vec<ViewJoinedQuery>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct ViewJoinedQuery {
+    pub query_id: String,
+    pub key_field: String,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct ViewExportSchedule {
+    pub format: ViewExportFormat,
+    pub interval_seconds: u64,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy)]
+#[serde(rename_all = "camelCase")]
+pub enum ViewExportFormat {
+    Parquet,
+    Csv,
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone, Copy)]
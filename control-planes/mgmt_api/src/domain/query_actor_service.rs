@@ -66,6 +66,27 @@ impl QueryActorService {
         Ok(())
     }
 
+    pub async fn get_status(&self, id: &str, container: &str) -> Result<QueryStatus, DomainError> {
+        let mut mut_dapr = self.dapr_client.clone();
+
+        match mut_dapr
+            .invoke_actor::<String, &str, (), QueryStatus>(
+                format!("{}.ContinuousQuery", container),
+                id.to_string(),
+                "getStatus",
+                (),
+                None,
+            )
+            .await
+        {
+            Err(e) => {
+                log::error!("Error getting query status: {}", e);
+                Err(DomainError::Internal { inner: Box::new(e) })
+            }
+            Ok(qs) => Ok(qs),
+        }
+    }
+
     pub async fn wait_for_ready_or_error(
         &self,
         id: &str,
@@ -134,6 +134,7 @@ where
             id: id.to_string(),
             spec: source,
             status: None,
+            warnings: Vec::new(),
         })
     }
 
@@ -226,6 +227,7 @@ where
 
                 status.map(|s| s.into())
             },
+            warnings: Vec::new(),
         })
     }
 
@@ -258,6 +260,7 @@ where
 
                     status.map(|s| s.into())
                 },
+                warnings: Vec::new(),
             });
         }
 
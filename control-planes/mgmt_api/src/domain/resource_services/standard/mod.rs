@@ -34,8 +34,9 @@ pub use query_service::QueryDomainServiceImpl;
 
 #[async_trait]
 pub trait StandardSpecValidator<TSpec> {
-    async fn validate(&self, _spec: &TSpec) -> Result<(), DomainError> {
-        Ok(())
+    /// Rejects an invalid spec, or returns warnings about a spec that can still be applied
+    async fn validate(&self, _spec: &TSpec) -> Result<Vec<String>, DomainError> {
+        Ok(Vec::new())
     }
 }
 
@@ -73,8 +74,9 @@ where
     ) -> Result<Resource<TSpec, TStatus>, DomainError> {
         log::info!("Setting resource: {}", id);
 
+        let mut warnings = Vec::new();
         for validator in &self.validators {
-            validator.validate(&resource).await?;
+            warnings.extend(validator.validate(&resource).await?);
         }
 
         log::info!("Validated resource: {}", id);
@@ -109,6 +111,7 @@ where
             id: id.to_string(),
             spec: resource,
             status: None,
+            warnings,
         })
     }
 
@@ -160,6 +163,7 @@ where
 
                 status.map(|s| s.into())
             },
+            warnings: Vec::new(),
         })
     }
 
@@ -192,6 +196,7 @@ where
 
                     status.map(|s| s.into())
                 },
+                warnings: Vec::new(),
             });
         }
 
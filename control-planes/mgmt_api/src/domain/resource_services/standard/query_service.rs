@@ -12,7 +12,7 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
-use std::collections::HashSet;
+use std::collections::{HashMap, HashSet};
 use std::sync::Arc;
 
 use super::{
@@ -20,7 +20,10 @@ use super::{
     StandardSpecValidator,
 };
 use crate::{
-    domain::models::{DomainError, QuerySpec, QueryStatus},
+    domain::{
+        models::{DomainError, QuerySpec, QueryStatus, QuerySubscription, SourceSchema},
+        source_schema_service::SourceSchemaService,
+    },
     persistence::QueryRepository,
 };
 use async_trait::async_trait;
@@ -39,6 +42,7 @@ impl QueryDomainServiceImpl {
         dapr_client: dapr::Client<TonicClient>,
         repo: Arc<QueryRepository>,
         container_service: Arc<QueryContainerDomainService>,
+        source_schema_service: Arc<dyn SourceSchemaService>,
     ) -> Self {
         QueryDomainServiceImpl {
             dapr_client,
@@ -47,6 +51,7 @@ impl QueryDomainServiceImpl {
             ready_check: |status| status.status == "Running",
             validators: vec![Box::new(QuerySpecValidator {
                 query_container_service: container_service,
+                source_schema_service,
             })],
             _tspec: std::marker::PhantomData,
             _tstatus: std::marker::PhantomData,
@@ -58,11 +63,71 @@ impl QueryDomainServiceImpl {
 
 struct QuerySpecValidator {
     query_container_service: Arc<QueryContainerDomainService>,
+    source_schema_service: Arc<dyn SourceSchemaService>,
+}
+
+impl QuerySpecValidator {
+    /// Warns about subscribed labels that the source does not declare, these usually come from a typo
+    /// and leave the query silently empty. They are not rejected, since a schema may be incomplete.
+    /// The schema of each source is fetched once, however many subscriptions it has.
+    async fn undeclared_label_warnings(&self, spec: &QuerySpec) -> Vec<String> {
+        let mut schemas: HashMap<&str, Option<SourceSchema>> = HashMap::new();
+        let mut warnings = Vec::new();
+
+        for sub in &spec.sources.subscriptions {
+            if !schemas.contains_key(sub.id.as_str()) {
+                let schema = match self.source_schema_service.get(&sub.id).await {
+                    Ok(schema) => schema,
+                    Err(e) => {
+                        log::debug!("Skipping schema check of source '{}': {}", sub.id, e);
+                        None
+                    }
+                };
+                schemas.insert(&sub.id, schema);
+            }
+            let Some(schema) = &schemas[sub.id.as_str()] else {
+                continue;
+            };
+
+            for label in undeclared_labels(sub, schema) {
+                let warning = format!(
+                    "Query subscribes to label '{}' that source '{}' does not declare",
+                    label, sub.id
+                );
+                log::warn!("{}", warning);
+                warnings.push(warning);
+            }
+        }
+
+        warnings
+    }
+}
+
+/// The node and relation labels of a subscription that are missing from the schema of its source
+fn undeclared_labels<'a>(
+    subscription: &'a QuerySubscription,
+    schema: &SourceSchema,
+) -> Vec<&'a str> {
+    let nodes: HashSet<&str> = schema.nodes.iter().map(|n| n.label.as_str()).collect();
+    let relations: HashSet<&str> = schema.relations.iter().map(|r| r.label.as_str()).collect();
+
+    let undeclared_nodes = subscription
+        .nodes
+        .iter()
+        .map(|l| l.source_label.as_str())
+        .filter(|l| !nodes.contains(l));
+    let undeclared_relations = subscription
+        .relations
+        .iter()
+        .map(|l| l.source_label.as_str())
+        .filter(|l| !relations.contains(l));
+
+    undeclared_nodes.chain(undeclared_relations).collect()
 }
 
 #[async_trait]
 impl StandardSpecValidator<QuerySpec> for QuerySpecValidator {
-    async fn validate(&self, spec: &QuerySpec) -> Result<(), DomainError> {
+    async fn validate(&self, spec: &QuerySpec) -> Result<Vec<String>, DomainError> {
         let qc = match self.query_container_service.get(&spec.container).await {
             Ok(qc) => qc,
             Err(e) => match e {
@@ -97,9 +162,11 @@ impl StandardSpecValidator<QuerySpec> for QuerySpecValidator {
             }
         }
 
+        let warnings = self.undeclared_label_warnings(spec).await;
+
         match qc.status {
             Some(status) => match status.available {
-                true => Ok(()),
+                true => Ok(warnings),
                 false => Err(DomainError::QueryContainerOffline),
             },
             None => Err(DomainError::QueryContainerOffline),
@@ -145,6 +212,7 @@ mod tests {
                     default_store: "default".to_string(),
                 },
                 status: self.status.clone(),
+                warnings: Vec::new(),
             })
         }
 
@@ -163,6 +231,28 @@ mod tests {
         }
     }
 
+    struct TestSourceSchemaService {
+        schema: Option<SourceSchema>,
+        requested: std::sync::Mutex<Vec<String>>,
+    }
+
+    impl TestSourceSchemaService {
+        fn new(schema: Option<SourceSchema>) -> Arc<Self> {
+            Arc::new(TestSourceSchemaService {
+                schema,
+                requested: std::sync::Mutex::new(Vec::new()),
+            })
+        }
+    }
+
+    #[async_trait]
+    impl SourceSchemaService for TestSourceSchemaService {
+        async fn get(&self, source_id: &str) -> Result<Option<SourceSchema>, DomainError> {
+            self.requested.lock().unwrap().push(source_id.to_string());
+            Ok(self.schema.clone())
+        }
+    }
+
     fn make_query_spec_with_pipeline(pipeline: Vec<String>) -> QuerySpec {
         QuerySpec {
             container: "qc1".to_string(),
@@ -175,6 +265,8 @@ mod tests {
                     nodes: vec![],
                     relations: vec![],
                     pipeline,
+                    node_predicates: serde_json::Map::new(),
+                    rel_predicates: serde_json::Map::new(),
                 }],
                 joins: vec![],
                 middleware: vec![
@@ -194,6 +286,10 @@ mod tests {
             view: ViewSpec {
                 enabled: false,
                 retention_policy: RetentionPolicy::Latest,
+                index_fields: vec![],
+                key_fields: vec![],
+                exports: vec![],
+                join: None,
             },
             transient: None,
         }
@@ -209,6 +305,7 @@ mod tests {
         });
         let validator = QuerySpecValidator {
             query_container_service: svc,
+            source_schema_service: TestSourceSchemaService::new(None),
         };
 
         let spec = make_query_spec_with_pipeline(vec!["mw1".into(), "mw2".into()]);
@@ -228,6 +325,7 @@ mod tests {
         });
         let validator = QuerySpecValidator {
             query_container_service: svc,
+            source_schema_service: TestSourceSchemaService::new(None),
         };
 
         let spec = make_query_spec_with_pipeline(vec!["unknown".into()]);
@@ -241,4 +339,80 @@ mod tests {
             other => panic!("expected InvalidSpec, got: {:?}", other),
         }
     }
+
+    fn element(label: &str) -> ElementSchema {
+        ElementSchema {
+            label: label.to_string(),
+            properties: vec![],
+            start_label: None,
+            end_label: None,
+        }
+    }
+
+    fn labels(labels: &[&str]) -> Vec<QuerySourceLabel> {
+        labels
+            .iter()
+            .map(|l| QuerySourceLabel {
+                source_label: l.to_string(),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn undeclared_labels_are_checked_against_nodes_and_relations() {
+        let schema = SourceSchema {
+            nodes: vec![element("Vehicle"), element("Location")],
+            relations: vec![element("LOCATED_AT")],
+        };
+        let subscription = QuerySubscription {
+            id: "src1".to_string(),
+            nodes: labels(&["Vehicle", "Vehicel", "LOCATED_AT"]),
+            relations: labels(&["LOCATED_AT", "Location"]),
+            pipeline: vec![],
+            node_predicates: serde_json::Map::new(),
+            rel_predicates: serde_json::Map::new(),
+        };
+
+        assert_eq!(
+            undeclared_labels(&subscription, &schema),
+            vec!["Vehicel", "LOCATED_AT", "Location"]
+        );
+    }
+
+    #[tokio::test]
+    async fn validate_passes_when_labels_are_not_declared() {
+        let svc: Arc<QueryContainerDomainService> = Arc::new(TestQueryContainerService {
+            status: Some(QueryContainerStatus {
+                available: true,
+                messages: None,
+            }),
+        });
+        let schema_svc = TestSourceSchemaService::new(Some(SourceSchema {
+            nodes: vec![element("Driver")],
+            relations: vec![],
+        }));
+        let validator = QuerySpecValidator {
+            query_container_service: svc,
+            source_schema_service: schema_svc.clone(),
+        };
+
+        let mut spec = make_query_spec_with_pipeline(vec![]);
+        spec.sources.subscriptions[0].nodes = labels(&["Vehicle"]);
+        spec.sources.subscriptions.push(QuerySubscription {
+            id: "sub1".to_string(),
+            nodes: labels(&["Driver"]),
+            relations: vec![],
+            pipeline: vec![],
+            node_predicates: serde_json::Map::new(),
+            rel_predicates: serde_json::Map::new(),
+        });
+
+        let warnings = validator.validate(&spec).await.unwrap();
+
+        assert_eq!(
+            warnings,
+            vec!["Query subscribes to label 'Vehicle' that source 'sub1' does not declare"]
+        );
+        assert_eq!(*schema_svc.requested.lock().unwrap(), vec!["sub1"]);
+    }
 }
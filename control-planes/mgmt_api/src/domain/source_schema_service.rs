@@ -0,0 +1,96 @@
+// Copyright 2025 The Drasi Authors.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::time::Duration;
+
+use async_trait::async_trait;
+use reqwest::StatusCode;
+
+use super::models::{DomainError, SourceSchema};
+
+#[async_trait]
+pub trait SourceSchemaService: Send + Sync {
+    /// Returns the schema a source declares, or None if the source does not declare one or does not
+    /// return it in time
+    async fn get(&self, source_id: &str) -> Result<Option<SourceSchema>, DomainError>;
+}
+
+/// How long to wait for a source to return its schema, the schema is only used for warnings
+/// so a source that does not respond should not hold up applying a query
+const SCHEMA_REQUEST_TIMEOUT: Duration = Duration::from_secs(3);
+
+/// Reads schemas from the query api of each source, which forwards the request to the source proxy
+pub struct SourceSchemaServiceImpl {
+    http_client: reqwest::Client,
+}
+
+impl SourceSchemaServiceImpl {
+    pub fn new() -> Self {
+        SourceSchemaServiceImpl {
+            http_client: reqwest::Client::new(),
+        }
+    }
+}
+
+impl Default for SourceSchemaServiceImpl {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[async_trait]
+impl SourceSchemaService for SourceSchemaServiceImpl {
+    async fn get(&self, source_id: &str) -> Result<Option<SourceSchema>, DomainError> {
+        let resp = match self
+            .http_client
+            .get(format!("http://{}-query-api/schema", source_id))
+            .timeout(SCHEMA_REQUEST_TIMEOUT)
+            .send()
+            .await
+        {
+            Ok(r) => r,
+            Err(e) if e.is_timeout() => {
+                log::warn!("Timed out getting schema of source {}", source_id);
+                return Ok(None);
+            }
+            Err(e) => {
+                log::error!("Error getting schema of source {}: {}", source_id, e);
+                return Err(DomainError::Internal { inner: Box::new(e) });
+            }
+        };
+
+        match resp.status() {
+            StatusCode::NOT_FOUND => return Ok(None),
+            status if !status.is_success() => {
+                log::error!("Error getting schema of source {}: {}", source_id, status);
+                return Err(DomainError::Internal {
+                    inner: Box::new(std::io::Error::other("Error getting schema")),
+                });
+            }
+            _ => {}
+        }
+
+        match resp.json::<SourceSchema>().await {
+            Ok(schema) => Ok(Some(schema)),
+            Err(e) if e.is_timeout() => {
+                log::warn!("Timed out getting schema of source {}", source_id);
+                Ok(None)
+            }
+            Err(e) => {
+                log::error!("Error parsing schema of source {}: {}", source_id, e);
+                Err(DomainError::Internal { inner: Box::new(e) })
+            }
+        }
+    }
+}
@@ -0,0 +1,413 @@
+use std::{
+    collections::{HashMap, HashSet},
+    sync::{Arc, RwLock},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use serde_json::json;
+
+use super::{
+    debug_service::DEBUG_QUERY_PREFIX,
+    models::{
+        DomainError, QuerySpec, QuerySubscription, SourceSubscription, SubscriptionFix,
+        SubscriptionFixAction, SubscriptionReconciliation,
+    },
+    query_actor_service::QueryActorService,
+};
+use crate::{QueryRepository, SourceRepository};
+
+pub struct SubscriptionService {
+    query_repo: Arc<QueryRepository>,
+    source_repo: Arc<SourceRepository>,
+    query_actor_service: Arc<QueryActorService>,
+    http_client: reqwest::Client,
+    reconciliations: RwLock<HashMap<String, SubscriptionReconciliation>>,
+}
+
+impl SubscriptionService {
+    pub fn new(
+        query_repo: Arc<QueryRepository>,
+        source_repo: Arc<SourceRepository>,
+        query_actor_service: Arc<QueryActorService>,
+    ) -> Self {
+        SubscriptionService {
+            query_repo,
+            source_repo,
+            query_actor_service,
+            http_client: reqwest::Client::new(),
+            reconciliations: RwLock::new(HashMap::new()),
+        }
+    }
+
+    /// Lists the subscriptions registered with a source, flagging the ones that no query accounts for
+    pub async fn list(&self, source_id: &str) -> Result<Vec<SourceSubscription>, DomainError> {
+        let mut subscriptions = self.fetch(source_id).await?;
+        let queries: HashMap<String, QuerySpec> =
+            self.query_repo.list().await.into_iter().collect();
+
+        for subscription in &mut subscriptions {
+            subscription.orphaned = is_orphaned(source_id, subscription, &queries);
+        }
+
+        Ok(subscriptions)
+    }
+
+    /// Brings the subscriptions registered with a source in line with the query repository.
+    /// Orphaned subscriptions are removed, and running queries that subscribe to the source but are
+    /// not registered with it are subscribed again, without bootstrapping.
+    pub async fn reconcile(
+        &self,
+        source_id: &str,
+    ) -> Result<SubscriptionReconciliation, DomainError> {
+        let subscriptions = self.fetch(source_id).await?;
+        let queries: HashMap<String, QuerySpec> =
+            self.query_repo.list().await.into_iter().collect();
+
+        let mut fixes = Vec::new();
+
+        for subscription in &subscriptions {
+            if !is_orphaned(source_id, subscription, &queries) {
+                continue;
+            }
+            log::info!(
+                "Removing orphaned subscription of query {} on node {} from source {}",
+                subscription.query_id,
+                subscription.query_node_id,
+                source_id
+            );
+            let error = self
+                .unsubscribe(
+                    source_id,
+                    &subscription.query_node_id,
+                    &subscription.query_id,
+                )
+                .await
+                .err();
+            fixes.push(SubscriptionFix {
+                action: SubscriptionFixAction::Unsubscribe,
+                query_node_id: subscription.query_node_id.clone(),
+                query_id: subscription.query_id.clone(),
+                error,
+            });
+        }
+
+        let registered: HashSet<(&str, &str)> = subscriptions
+            .iter()
+            .map(|s| (s.query_node_id.as_str(), s.query_id.as_str()))
+            .collect();
+
+        for (query_id, query) in &queries {
+            let Some(query_subscription) = query
+                .sources
+                .subscriptions
+                .iter()
+                .find(|s| s.id == source_id)
+            else {
+                continue;
+            };
+            if registered.contains(&(query.container.as_str(), query_id.as_str())) {
+                continue;
+            }
+
+            // queries that are still starting will subscribe, and bootstrap, by themselves
+            match self
+                .query_actor_service
+                .get_status(query_id, &query.container)
+                .await
+            {
+                Ok(status) if status.status == "Running" => {}
+                Ok(_) => continue,
+                Err(e) => {
+                    log::warn!("Skipping query {}: {}", query_id, e);
+                    continue;
+                }
+            }
+
+            log::info!(
+                "Restoring missing subscription of query {} on node {} to source {}",
+                query_id,
+                query.container,
+                source_id
+            );
+            let subscription = subscription_request(&query.container, query_id, query_subscription);
+            let error = self.subscribe(source_id, &subscription).await.err();
+            fixes.push(SubscriptionFix {
+                action: SubscriptionFixAction::Subscribe,
+                query_node_id: query.container.clone(),
+                query_id: query_id.clone(),
+                error,
+            });
+        }
+
+        let reconciliation = SubscriptionReconciliation {
+            source_id: source_id.to_string(),
+            completed_at: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map(|d| d.as_millis() as i64)
+                .unwrap_or_default(),
+            fixes,
+        };
+
+        self.reconciliations
+            .write()
+            .expect("reconciliations lock poisoned")
+            .insert(source_id.to_string(), reconciliation.clone());
+
+        Ok(reconciliation)
+    }
+
+    /// Reconciles the subscriptions of every source
+    pub async fn reconcile_all(&self) {
+        for (source_id, _) in self.source_repo.list().await {
+            match self.reconcile(&source_id).await {
+                Ok(reconciliation) => {
+                    let failed = reconciliation
+                        .fixes
+                        .iter()
+                        .filter(|f| f.error.is_some())
+                        .count();
+                    if !reconciliation.fixes.is_empty() {
+                        log::info!(
+                            "Reconciled subscriptions of source {}: {} fixed, {} failed",
+                            source_id,
+                            reconciliation.fixes.len() - failed,
+                            failed
+                        );
+                    }
+                }
+                Err(e) => log::warn!(
+                    "Error reconciling subscriptions of source {}: {}",
+                    source_id,
+                    e
+                ),
+            }
+        }
+    }
+
+    /// Returns the outcome of the last reconciliation of a source
+    pub fn last_reconciliation(&self, source_id: &str) -> Option<SubscriptionReconciliation> {
+        self.reconciliations
+            .read()
+            .expect("reconciliations lock poisoned")
+            .get(source_id)
+            .cloned()
+    }
+
+    async fn subscribe(
+        &self,
+        source_id: &str,
+        subscription: &serde_json::Value,
+    ) -> Result<(), String> {
+        let resp = self
+            .http_client
+            .post(format!(
+                "http://{}-query-api/subscription?bootstrap=false",
+                source_id
+            ))
+            .json(subscription)
+            .send()
+            .await
+            .map_err(|e| e.to_string())?;
+
+        if !resp.status().is_success() {
+            return Err(format!(
+                "{} {}",
+                resp.status(),
+                resp.text().await.unwrap_or_default()
+            ));
+        }
+        Ok(())
+    }
+
+    async fn unsubscribe(
+        &self,
+        source_id: &str,
+        query_node_id: &str,
+        query_id: &str,
+    ) -> Result<(), String> {
+        let resp = self
+            .http_client
+            .delete(format!(
+                "http://{}-query-api/subscription/{}/{}",
+                source_id, query_node_id, query_id
+            ))
+            .send()
+            .await
+            .map_err(|e| e.to_string())?;
+
+        if !resp.status().is_success() {
+            return Err(format!(
+                "{} {}",
+                resp.status(),
+                resp.text().await.unwrap_or_default()
+            ));
+        }
+        Ok(())
+    }
+
+    async fn fetch(&self, source_id: &str) -> Result<Vec<SourceSubscription>, DomainError> {
+        let resp = match self
+            .http_client
+            .get(format!("http://{}-query-api/subscriptions", source_id))
+            .send()
+            .await
+        {
+            Ok(r) => r,
+            Err(e) => {
+                log::error!("Error getting subscriptions of source {}: {}", source_id, e);
+                return Err(DomainError::Internal { inner: Box::new(e) });
+            }
+        };
+
+        if !resp.status().is_success() {
+            log::error!(
+                "Error getting subscriptions of source {}: {}",
+                source_id,
+                resp.status()
+            );
+            return Err(DomainError::Internal {
+                inner: Box::new(std::io::Error::other("Error getting subscriptions")),
+            });
+        }
+
+        match resp.json::<Vec<SourceSubscription>>().await {
+            Ok(subscriptions) => Ok(subscriptions),
+            Err(e) => {
+                log::error!("Error parsing subscriptions of source {}: {}", source_id, e);
+                Err(DomainError::Internal { inner: Box::new(e) })
+            }
+        }
+    }
+}
+
+/// The subscription request that query-host sends to a source when a query starts, so a restored
+/// subscription filters changes the same way. The bootstrap page size is left out, since restored
+/// subscriptions are not bootstrapped.
+fn subscription_request(
+    query_node_id: &str,
+    query_id: &str,
+    subscription: &QuerySubscription,
+) -> serde_json::Value {
+    json!({
+        "queryNodeId": query_node_id,
+        "queryId": query_id,
+        "nodeLabels": subscription.nodes.iter().map(|l| l.source_label.clone()).collect::<Vec<String>>(),
+        "relLabels": subscription.relations.iter().map(|l| l.source_label.clone()).collect::<Vec<String>>(),
+        "nodePredicates": subscription.node_predicates,
+        "relPredicates": subscription.rel_predicates,
+    })
+}
+
+pub fn is_orphaned(
+    source_id: &str,
+    subscription: &SourceSubscription,
+    queries: &HashMap<String, QuerySpec>,
+) -> bool {
+    // debug queries are never saved to the repository
+    if subscription.query_id.starts_with(DEBUG_QUERY_PREFIX) {
+        return false;
+    }
+
+    match queries.get(&subscription.query_id) {
+        Some(query) => {
+            query.container != subscription.query_node_id
+                || !query
+                    .sources
+                    .subscriptions
+                    .iter()
+                    .any(|s| s.id == source_id)
+        }
+        None => true,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::domain::models::*;
+
+    fn query(container: &str, sources: &[&str]) -> QuerySpec {
+        QuerySpec {
+            container: container.to_string(),
+            mode: "query".to_string(),
+            query: "MATCH (n) RETURN n".to_string(),
+            query_language: None,
+            sources: QuerySources {
+                subscriptions: sources
+                    .iter()
+                    .map(|id| QuerySubscription {
+                        id: id.to_string(),
+                        nodes: vec![],
+                        relations: vec![],
+                        pipeline: vec![],
+                        node_predicates: serde_json::Map::new(),
+                        rel_predicates: serde_json::Map::new(),
+                    })
+                    .collect(),
+                joins: vec![],
+                middleware: vec![],
+            },
+            storage_profile: None,
+            view: ViewSpec {
+                enabled: false,
+                retention_policy: RetentionPolicy::Latest,
+                index_fields: vec![],
+                key_fields: vec![],
+                exports: vec![],
+                join: None,
+            },
+            transient: None,
+        }
+    }
+
+    fn subscription(query_node_id: &str, query_id: &str) -> SourceSubscription {
+        SourceSubscription {
+            query_node_id: query_node_id.to_string(),
+            query_id: query_id.to_string(),
+            node_labels: vec!["Item".to_string()],
+            rel_labels: vec![],
+            subscribed_at: None,
+            orphaned: false,
+        }
+    }
+
+    #[test]
+    fn test_orphaned_subscriptions() {
+        let mut queries = HashMap::new();
+        queries.insert("q1".to_string(), query("qc1", &["src1"]));
+        queries.insert("q2".to_string(), query("qc1", &["src2"]));
+
+        assert!(!is_orphaned("src1", &subscription("qc1", "q1"), &queries));
+        assert!(is_orphaned("src1", &subscription("qc2", "q1"), &queries));
+        assert!(is_orphaned("src1", &subscription("qc1", "q2"), &queries));
+        assert!(is_orphaned("src1", &subscription("qc1", "q3"), &queries));
+        assert!(!is_orphaned(
+            "src1",
+            &subscription("qc1", "debug-1234"),
+            &queries
+        ));
+    }
+
+    #[test]
+    fn test_subscription_request_keeps_predicates() {
+        let mut spec = query("qc1", &["src1"]);
+        let sub = &mut spec.sources.subscriptions[0];
+        sub.nodes = vec![QuerySourceLabel {
+            source_label: "Item".to_string(),
+        }];
+        sub.node_predicates
+            .insert("Item".to_string(), json!({ "status": { "eq": "active" } }));
+
+        assert_eq!(
+            subscription_request("qc1", "q1", &spec.sources.subscriptions[0]),
+            json!({
+                "queryNodeId": "qc1",
+                "queryId": "q1",
+                "nodeLabels": ["Item"],
+                "relLabels": [],
+                "nodePredicates": { "Item": { "status": { "eq": "active" } } },
+                "relPredicates": {},
+            })
+        );
+    }
+}
@@ -27,8 +27,12 @@ use utoipa_swagger_ui::SwaggerUi;
 
 use crate::{
     domain::{
-        debug_service::DebugService, models::ChangeStreamConfig, resource_services::*,
+        debug_service::DebugService,
+        models::ChangeStreamConfig,
+        resource_services::*,
         result_service::ResultService,
+        source_schema_service::{SourceSchemaService, SourceSchemaServiceImpl},
+        subscription_service::SubscriptionService,
     },
     persistence::*,
 };
@@ -53,6 +57,10 @@ async fn main() -> Result<(), std::io::Error> {
         .unwrap_or_else(|_| "3500".to_string())
         .parse::<u16>()
         .unwrap();
+    let reconcile_interval_secs = std::env::var("SUBSCRIPTION_RECONCILE_INTERVAL_SECS")
+        .unwrap_or_else(|_| "300".to_string())
+        .parse::<u64>()
+        .unwrap();
 
     // Introduce delay so that dapr grpc port is assigned before app tries to connect
     std::thread::sleep(std::time::Duration::new(5, 0));
@@ -63,6 +71,27 @@ async fn main() -> Result<(), std::io::Error> {
         .expect("Unable to connect to Dapr");
     let mongo_client = mongodb::Client::with_uri_str(&mongo_uri).await.unwrap();
 
+    let subscription_service = {
+        let db = mongo_client.database(&mongo_db);
+        web::Data::new(SubscriptionService::new(
+            Arc::new(QueryRepositoryImpl::new(db.clone())),
+            Arc::new(SourceRepositoryImpl::new(db)),
+            Arc::new(QueryActorService::new(dapr_client.clone())),
+        ))
+    };
+
+    if reconcile_interval_secs > 0 {
+        let subscription_service = subscription_service.clone();
+        actix_web::rt::spawn(async move {
+            let mut interval =
+                tokio::time::interval(std::time::Duration::from_secs(reconcile_interval_secs));
+            loop {
+                interval.tick().await;
+                subscription_service.reconcile_all().await;
+            }
+        });
+    }
+
     log::info!("starting HTTP server at http://localhost:8080");
     //wait for the schema to be populated before starting the server
 
@@ -109,10 +138,13 @@ async fn main() -> Result<(), std::io::Error> {
             web::Data::from(Arc::new(reaction_domain_svc) as Arc<ReactionDomainService>);
 
         let query_repo = Arc::new(QueryRepositoryImpl::new(db.clone()));
+        let source_schema_svc = Arc::new(SourceSchemaServiceImpl::new());
+
         let query_domain_svc = QueryDomainServiceImpl::new(
             dapr_client.clone(),
             query_repo.clone(),
             qc_domain_svc.clone(),
+            source_schema_svc.clone(),
         );
         let query_domain_svc_arc: web::Data<QueryDomainService> =
             web::Data::from(Arc::new(query_domain_svc) as Arc<QueryDomainService>);
@@ -157,6 +189,10 @@ async fn main() -> Result<(), std::io::Error> {
             .app_data(source_provider_domain_svc_arc)
             .app_data(reaction_provider_domain_svc_arc)
             .app_data(web::Data::new(debug_service))
+            .app_data(subscription_service.clone())
+            .app_data(web::Data::from(
+                source_schema_svc as Arc<dyn SourceSchemaService>,
+            ))
             // Configure the new explicit handler modules
             .service(api::v1::sources::configure_routes())
             .service(api::v1::query_containers::configure_routes())
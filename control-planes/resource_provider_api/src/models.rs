@@ -264,6 +264,12 @@ pub struct QuerySubscription {
 
     #[serde(default)]
     pub pipeline: Vec<String>,
+
+    #[serde(default, skip_serializing_if = "Map::is_empty")]
+    pub node_predicates: Map<String, Value>,
+
+    #[serde(default, skip_serializing_if = "Map::is_empty")]
+    pub rel_predicates: Map<String, Value>,
 }
 
 #[derive(Serialize, Deserialize, Debug)]
@@ -325,6 +331,41 @@ pub struct QueryStatus {
 pub struct ViewSpec {
     pub enabled: bool,
     pub retention_policy: RetentionPolicy,
+    #[serde(default)]
+    pub index_fields: Vec<String>,
+    #[serde(default)]
+    pub key_fields: Vec<String>,
+    #[serde(default)]
+    pub exports: Vec<ViewExportSchedule>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub join: Option<ViewJoinSpec>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct ViewJoinSpec {
+    pub queries: Vec<ViewJoinedQuery>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct ViewJoinedQuery {
+    pub query_id: String,
+    pub key_field: String,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct ViewExportSchedule {
+    pub format: ViewExportFormat,
+    pub interval_seconds: u64,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy)]
+#[serde(rename_all = "camelCase")]
+pub enum ViewExportFormat {
+    Parquet,
+    Csv,
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone, Copy)]
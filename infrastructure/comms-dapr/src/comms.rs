@@ -72,6 +72,25 @@ impl Publisher for DaprHttpPublisher {
     }
 }
 
+/// A service invocation that reached the app but was answered with an error status
+#[derive(Debug)]
+pub struct InvocationError {
+    pub status: reqwest::StatusCode,
+    pub body: String,
+}
+
+impl std::fmt::Display for InvocationError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "Service invocation request failed with status: {} and body: {}",
+            self.status, self.body
+        )
+    }
+}
+
+impl std::error::Error for InvocationError {}
+
 #[derive(Debug, Clone)]
 pub struct DaprHttpInvoker {
     client: reqwest::Client,
@@ -142,12 +161,10 @@ impl Invoker for DaprHttpInvoker {
                 if resp.status().is_success() {
                     Ok(resp.bytes().await?)
                 } else {
-                    let error_message = format!(
-                        "Service invocation request failed with status: {} and body: {}",
-                        resp.status(),
-                        resp.text().await.unwrap_or_default()
-                    );
-                    Err(Box::from(error_message))
+                    Err(Box::new(InvocationError {
+                        status: resp.status(),
+                        body: resp.text().await.unwrap_or_default(),
+                    }))
                 }
             }
             Err(e) => Err(Box::new(e)),
@@ -51,6 +51,28 @@ impl HttpStreamingInvoker {
         stream_type: StreamType,
         headers: Option<Headers>,
     ) -> Result<impl Stream<Item = Result<Value, StreamBodyError>> + Send, InvokeError> {
+        let (_, stream) = self
+            .invoke_with_response_headers(data, app_id, verb, path, stream_type, headers)
+            .await?;
+        Ok(stream)
+    }
+
+    /// Like `invoke`, but also returns the headers of the response
+    pub async fn invoke_with_response_headers(
+        &self,
+        data: Payload,
+        app_id: &str,
+        verb: Verb,
+        path: &str,
+        stream_type: StreamType,
+        headers: Option<Headers>,
+    ) -> Result<
+        (
+            Headers,
+            impl Stream<Item = Result<Value, StreamBodyError>> + Send,
+        ),
+        InvokeError,
+    > {
         let uri = format!("http://{}/{}", app_id, path);
 
         let request_headers = {
@@ -104,12 +126,24 @@ impl HttpStreamingInvoker {
             )));
         }
 
+        let response_headers = Headers::new(
+            resp.headers()
+                .iter()
+                .filter_map(|(key, value)| {
+                    value
+                        .to_str()
+                        .ok()
+                        .map(|value| (key.to_string(), value.to_string()))
+                })
+                .collect(),
+        );
+
         let stream = match stream_type {
             StreamType::JsonArray => resp.json_array_stream::<Value>(usize::MAX),
             StreamType::JsonNewLine => resp.json_nl_stream::<Value>(usize::MAX),
         };
 
-        Ok(stream)
+        Ok((response_headers, stream))
     }
 }
 
@@ -22,6 +22,7 @@ use axum::{
     Router,
 };
 use publisher::Publisher;
+use serde_json::value::RawValue;
 
 mod publisher;
 
@@ -60,6 +61,7 @@ async fn main() {
 
     let app = Router::new()
         .route("/change", post(change))
+        .route("/changes", post(changes))
         .route("/data", post(data))
         .with_state(shared_state);
 
@@ -130,6 +132,58 @@ async fn change(
     }
 }
 
+/// Publishes a batch of changes, given as a JSON array, in the order they appear in the array
+async fn changes(
+    State(state): State<Arc<AppState>>,
+    headers: HeaderMap,
+    body: String,
+) -> impl IntoResponse {
+    let trace_state = match headers.get("tracestate") {
+        Some(trace_state) => match trace_state.to_str() {
+            Ok(ts) => Some(ts.to_string()),
+            Err(_) => None,
+        },
+        None => None,
+    };
+
+    let trace_parent = match headers.get("traceparent") {
+        Some(trace_state) => match trace_state.to_str() {
+            Ok(ts) => Some(ts.to_string()),
+            Err(_) => None,
+        },
+        None => None,
+    };
+
+    let items: Vec<Box<RawValue>> = match serde_json::from_str(&body) {
+        Ok(items) => items,
+        Err(e) => {
+            log::error!("Error parsing batch of changes: {:?}", e);
+            return StatusCode::BAD_REQUEST;
+        }
+    };
+
+    log::info!("Publishing {} changes", items.len());
+
+    let items = items
+        .into_iter()
+        .map(|item| item.get().to_string())
+        .collect();
+    match state
+        .publisher
+        .publish_batch(items, trace_state, trace_parent)
+        .await
+    {
+        Ok(_) => {
+            log::debug!("Published changes");
+            StatusCode::OK
+        }
+        Err(e) => {
+            log::error!("Error publishing changes: {:?}", e);
+            StatusCode::BAD_GATEWAY
+        }
+    }
+}
+
 async fn data(
     State(state): State<Arc<AppState>>,
     headers: HeaderMap,
@@ -59,26 +59,46 @@ impl Publisher {
         trace_parent: Option<String>,
     ) -> Result<(), PublishError> {
         let mut connection = self.connection.clone();
-        let mut items = Vec::with_capacity(4);
-        let now = match SystemTime::now().duration_since(SystemTime::UNIX_EPOCH) {
-            Ok(now) => now.as_nanos().to_string(),
+        let items = stream_entry(data, enqueue_time()?, trace_state, trace_parent);
+
+        let _: redis::Value = match connection.xadd(&self.topic, "*", &items).await {
+            Ok(ret) => {
+                log::debug!("Publish result: {:?}", ret);
+                ret
+            }
             Err(e) => {
                 return Err(PublishError::Other(format!(
-                    "Error getting current time: {}",
+                    "Error publishing to topic: {}",
                     e
                 )))
             }
         };
-        items.push(("data", data));
-        items.push(("enqueue_time", now));
-        if let Some(trace_state) = trace_state {
-            items.push(("tracestate", trace_state));
+
+        Ok(())
+    }
+
+    /// Appends several items to the stream in a single round trip, in the order they are given.
+    /// The items are added in a transaction, so either all of them are published or none are.
+    pub async fn publish_batch(
+        &self,
+        data: Vec<String>,
+        trace_state: Option<String>,
+        trace_parent: Option<String>,
+    ) -> Result<(), PublishError> {
+        if data.is_empty() {
+            return Ok(());
         }
-        if let Some(trace_parent) = trace_parent {
-            items.push(("traceparent", trace_parent));
+
+        let mut connection = self.connection.clone();
+        let now = enqueue_time()?;
+        let mut pipe = redis::pipe();
+        pipe.atomic();
+        for item in data {
+            let items = stream_entry(item, now.clone(), trace_state.clone(), trace_parent.clone());
+            pipe.xadd(&self.topic, "*", &items);
         }
 
-        let _: redis::Value = match connection.xadd(&self.topic, "*", &items).await {
+        let _: redis::Value = match pipe.query_async(&mut connection).await {
             Ok(ret) => {
                 log::debug!("Publish result: {:?}", ret);
                 ret
@@ -94,3 +114,31 @@ impl Publisher {
         Ok(())
     }
 }
+
+fn enqueue_time() -> Result<String, PublishError> {
+    match SystemTime::now().duration_since(SystemTime::UNIX_EPOCH) {
+        Ok(now) => Ok(now.as_nanos().to_string()),
+        Err(e) => Err(PublishError::Other(format!(
+            "Error getting current time: {}",
+            e
+        ))),
+    }
+}
+
+fn stream_entry(
+    data: String,
+    enqueue_time: String,
+    trace_state: Option<String>,
+    trace_parent: Option<String>,
+) -> Vec<(&'static str, String)> {
+    let mut items = Vec::with_capacity(4);
+    items.push(("data", data));
+    items.push(("enqueue_time", enqueue_time));
+    if let Some(trace_state) = trace_state {
+        items.push(("tracestate", trace_state));
+    }
+    if let Some(trace_parent) = trace_parent {
+        items.push(("traceparent", trace_parent));
+    }
+    items
+}
@@ -25,6 +25,8 @@ use drasi_core::{
 use serde::{Deserialize, Serialize};
 use serde_json::{Map, Value};
 
+use crate::models::SubscriptionPredicates;
+
 #[derive(Serialize, Deserialize, Debug, Clone)]
 #[allow(clippy::upper_case_acronyms)]
 pub enum QueryLanguage {
@@ -59,6 +61,23 @@ pub struct QuerySubscription {
 
     #[serde(default)]
     pub pipeline: Vec<String>,
+
+    /// Property predicates by node label, passed to the source when bootstrapping
+    #[serde(default, skip_serializing_if = "Map::is_empty")]
+    pub node_predicates: Map<String, Value>,
+
+    /// Property predicates by relation label, passed to the source when bootstrapping
+    #[serde(default, skip_serializing_if = "Map::is_empty")]
+    pub rel_predicates: Map<String, Value>,
+}
+
+impl QuerySubscription {
+    pub fn predicates(&self) -> SubscriptionPredicates {
+        SubscriptionPredicates {
+            node_predicates: self.node_predicates.clone(),
+            rel_predicates: self.rel_predicates.clone(),
+        }
+    }
 }
 
 #[derive(Serialize, Deserialize, Debug, Hash, PartialEq, Eq, Clone)]
@@ -112,6 +131,41 @@ pub struct QuerySources {
 pub struct ViewSpec {
     pub enabled: bool,
     pub retention_policy: RetentionPolicy,
+    #[serde(default)]
+    pub index_fields: Vec<String>,
+    #[serde(default)]
+    pub key_fields: Vec<String>,
+    #[serde(default)]
+    pub exports: Vec<ViewExportSchedule>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub join: Option<ViewJoinSpec>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct ViewJoinSpec {
+    pub queries: Vec<ViewJoinedQuery>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct ViewJoinedQuery {
+    pub query_id: String,
+    pub key_field: String,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct ViewExportSchedule {
+    pub format: ViewExportFormat,
+    pub interval_seconds: u64,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy)]
+#[serde(rename_all = "camelCase")]
+pub enum ViewExportFormat {
+    Parquet,
+    Csv,
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone, Copy)]
@@ -153,6 +207,10 @@ pub struct ChangeEvent {
     source_id: String,
     time: ChangeEventTime,
 
+    /// Stamped by the change router, follows the order of the changes in the source
+    #[serde(default)]
+    sequence: Option<u64>,
+
     queries: Vec<String>,
 
     #[serde(rename = "type")]
@@ -259,6 +317,10 @@ impl ChangeEvent {
         self.time.ms
     }
 
+    pub fn get_sequence(&self) -> Option<u64> {
+        self.sequence
+    }
+
     pub fn has_query(&self, query_id: &str) -> bool {
         self.queries.contains(&query_id.to_string())
     }
@@ -278,6 +340,7 @@ impl ChangeEvent {
                 seq: 0,
                 ms: future_ref.original_time,
             },
+            sequence: None,
             queries: vec![query_id.to_string()],
             op: ChangeType::Future,
             future_due_time: Some(future_ref.due_time),
@@ -466,6 +529,24 @@ impl ResultEvent {
         })
     }
 
+    /// A change that adds the given rows, used to send the current results of a query
+    pub fn from_rows(
+        query_id: &str,
+        rows: Vec<Map<String, Value>>,
+        sequence: u64,
+        source_time_ms: u64,
+    ) -> Self {
+        ResultEvent::Change(ResultChangeEvent {
+            query_id: query_id.to_string(),
+            sequence,
+            source_time_ms,
+            added_results: rows,
+            updated_results: Vec::new(),
+            deleted_results: Vec::new(),
+            metadata: None,
+        })
+    }
+
     pub fn from_control_signal(
         query_id: &str,
         sequence: u64,
@@ -482,6 +563,48 @@ impl ResultEvent {
     }
 }
 
+impl ResultChangeEvent {
+    /// The changes to the result rows, in the order view-svc applies them to a view.
+    /// Each is the key of a row with its new value, or `None` when the row is removed.
+    pub fn row_changes(&self) -> Vec<(String, Option<&Map<String, Value>>)> {
+        let mut changes = Vec::new();
+
+        for deleted in &self.deleted_results {
+            changes.push((row_key(deleted, None), None));
+        }
+
+        for update in &self.updated_results {
+            let grouping_keys = update.grouping_keys.as_ref();
+            if let Some(before) = &update.before {
+                changes.push((row_key(before, grouping_keys), None));
+            }
+            if let Some(after) = &update.after {
+                changes.push((row_key(after, grouping_keys), Some(after)));
+            }
+        }
+
+        for added in &self.added_results {
+            changes.push((row_key(added, None), Some(added)));
+        }
+
+        changes
+    }
+}
+
+/// Keys the rows of an aggregation by their grouping values, and other rows by all their values
+fn row_key(row: &Map<String, Value>, grouping_keys: Option<&Vec<String>>) -> String {
+    match grouping_keys {
+        Some(grouping_keys) => Value::Array(
+            grouping_keys
+                .iter()
+                .map(|key| row.get(key).cloned().unwrap_or(Value::Null))
+                .collect(),
+        )
+        .to_string(),
+        None => Value::Object(row.clone()).to_string(),
+    }
+}
+
 fn variables_to_json(source: QueryVariables) -> Map<String, Value> {
     let mut map = Map::new();
     for (key, value) in source {
@@ -0,0 +1,149 @@
+// Copyright 2024 The Drasi Authors.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::{
+    sync::Mutex,
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
+
+use drasi_core::models::ElementReference;
+use redis::{aio::MultiplexedConnection, AsyncCommands, RedisError, Script};
+
+/// How long the sequence of a deleted element is kept, to discard changes to the element that arrive late
+const TOMBSTONE_RETENTION: Duration = Duration::from_secs(60 * 60);
+
+/// How often tombstones older than the retention are removed
+const TRIM_INTERVAL: Duration = Duration::from_secs(60);
+
+/// Records the sequence of a change unless a change with a later sequence was already applied to the element,
+/// and keeps the time of deletes in a sorted set so their tombstones can be trimmed.
+/// Sequences are zero padded so they compare as strings, Lua numbers cannot hold every u64.
+const ADVANCE_SCRIPT: &str = r#"
+local last = redis.call('HGET', KEYS[1], ARGV[1])
+if last and last > ARGV[2] then
+    return 0
+end
+redis.call('HSET', KEYS[1], ARGV[1], ARGV[2])
+if ARGV[3] == '1' then
+    redis.call('ZADD', KEYS[2], ARGV[4], ARGV[1])
+else
+    redis.call('ZREM', KEYS[2], ARGV[1])
+end
+return 1
+"#;
+
+/// Removes the tombstones of elements deleted before the watermark
+const TRIM_SCRIPT: &str = r#"
+local expired = redis.call('ZRANGEBYSCORE', KEYS[2], '-inf', ARGV[1])
+for _, element in ipairs(expired) do
+    redis.call('HDEL', KEYS[1], element)
+end
+redis.call('ZREMRANGEBYSCORE', KEYS[2], '-inf', ARGV[1])
+return #expired
+"#;
+
+/// Tracks the sequence number, stamped by the change router, of the last change applied to each element of a query,
+/// so changes that are delivered out of order are discarded.
+///
+/// The sequence of a deleted element is kept as a tombstone, so a change to the element that arrives after
+/// its deletion does not bring it back. Tombstones are removed once they are older than `TOMBSTONE_RETENTION`.
+/// Sequences are kept in a Redis hash per query, next to the change stream, and are cleared when the query
+/// is bootstrapped again or deleted. Checking and recording the sequence of a change takes a single round trip.
+pub struct ElementSequences {
+    key: String,
+    tombstones_key: String,
+    connection: MultiplexedConnection,
+    advance_script: Script,
+    trim_script: Script,
+    last_trim: Mutex<Instant>,
+}
+
+impl ElementSequences {
+    pub async fn connect(url: &str, query_id: &str) -> Result<Self, RedisError> {
+        let client = redis::Client::open(url)?;
+        let connection = client.get_multiplexed_async_connection().await?;
+
+        Ok(Self {
+            key: format!("{}-element-sequences", query_id),
+            tombstones_key: format!("{}-element-tombstones", query_id),
+            connection,
+            advance_script: Script::new(ADVANCE_SCRIPT),
+            trim_script: Script::new(TRIM_SCRIPT),
+            last_trim: Mutex::new(Instant::now()),
+        })
+    }
+
+    /// Records `sequence` as the last change applied to the element, and returns false without recording it
+    /// when the change is stale, because a change with a later sequence number was already applied.
+    /// A change delivered again after a failure has the same sequence number, and is applied again.
+    pub async fn advance(
+        &self,
+        reference: &ElementReference,
+        sequence: u64,
+        deleted: bool,
+    ) -> Result<bool, RedisError> {
+        self.trim_if_due().await?;
+
+        let mut connection = self.connection.clone();
+        self.advance_script
+            .key(&self.key)
+            .key(&self.tombstones_key)
+            .arg(field(reference))
+            .arg(format!("{:020}", sequence))
+            .arg(if deleted { "1" } else { "0" })
+            .arg(now_ms())
+            .invoke_async(&mut connection)
+            .await
+    }
+
+    pub async fn clear(&self) -> Result<(), RedisError> {
+        let mut connection = self.connection.clone();
+        connection.del(&[&self.key, &self.tombstones_key]).await
+    }
+
+    async fn trim_if_due(&self) -> Result<(), RedisError> {
+        {
+            let mut last_trim = self.last_trim.lock().expect("last trim lock poisoned");
+            if last_trim.elapsed() < TRIM_INTERVAL {
+                return Ok(());
+            }
+            *last_trim = Instant::now();
+        }
+
+        let watermark = now_ms().saturating_sub(TOMBSTONE_RETENTION.as_millis() as u64);
+        let mut connection = self.connection.clone();
+        let trimmed: u64 = self
+            .trim_script
+            .key(&self.key)
+            .key(&self.tombstones_key)
+            .arg(watermark)
+            .invoke_async(&mut connection)
+            .await?;
+        if trimmed > 0 {
+            log::debug!("Trimmed {} element tombstones from {}", trimmed, self.key);
+        }
+        Ok(())
+    }
+}
+
+fn field(reference: &ElementReference) -> String {
+    format!("{}:{}", reference.source_id, reference.element_id)
+}
+
+fn now_ms() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_millis() as u64
+}
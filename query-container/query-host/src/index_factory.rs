@@ -56,6 +56,25 @@ pub struct IndexSet {
     pub future_queue: Arc<dyn FutureQueue>,
 }
 
+impl IndexSet {
+    pub fn in_memory(enable_archive: bool) -> Self {
+        let mut element_index = InMemoryElementIndex::new();
+        if enable_archive {
+            element_index.enable_archive();
+        }
+        let element_index = Arc::new(element_index);
+        let result_index = InMemoryResultIndex::new();
+        let future_queue = InMemoryFutureQueue::new();
+
+        IndexSet {
+            element_index: element_index.clone(),
+            archive_index: element_index,
+            result_index: Arc::new(result_index),
+            future_queue: Arc::new(future_queue),
+        }
+    }
+}
+
 impl IndexFactory {
     pub fn new() -> Self {
         let mut storage_specs = BTreeMap::new();
@@ -198,22 +217,7 @@ impl IndexFactory {
         };
 
         match spec {
-            StorageSpec::Memory { enable_archive } => {
-                let mut element_index = InMemoryElementIndex::new();
-                if *enable_archive {
-                    element_index.enable_archive();
-                }
-                let element_index = Arc::new(element_index);
-                let result_index = InMemoryResultIndex::new();
-                let future_queue = InMemoryFutureQueue::new();
-
-                Ok(IndexSet {
-                    element_index: element_index.clone(),
-                    archive_index: element_index,
-                    result_index: Arc::new(result_index),
-                    future_queue: Arc::new(future_queue),
-                })
-            }
+            StorageSpec::Memory { enable_archive } => Ok(IndexSet::in_memory(*enable_archive)),
             StorageSpec::Redis {
                 connection_string,
                 cache_size,
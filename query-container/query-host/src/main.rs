@@ -36,12 +36,14 @@ use crate::index_factory::IndexFactory;
 
 mod api;
 mod change_stream;
+mod element_sequences;
 mod future_consumer;
 mod index_factory;
 mod models;
 mod query_actor;
 mod query_worker;
 mod result_publisher;
+mod result_rows;
 mod source_client;
 
 #[tokio::main]
@@ -86,7 +88,11 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
     let index_factory = Arc::new(IndexFactory::new());
 
     let actor_name = format!("{}.ContinuousQuery", query_container_id);
-    let source_client = Arc::new(SourceClient::new(reqwest::Client::new()));
+    let bootstrap_page_size = env::var("BOOTSTRAP_PAGE_SIZE")
+        .ok()
+        .and_then(|v| v.parse::<usize>().ok());
+    let source_client =
+        Arc::new(SourceClient::new(reqwest::Client::new()).with_page_size(bootstrap_page_size));
     let publisher = Arc::new(ResultPublisher::new(
         dapr_host.into(),
         dapr_http_port,
@@ -135,7 +141,8 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
             .register_method("configure", QueryActor::configure)
             .register_method("getStatus", QueryActor::get_status)
             .register_method("deprovision", QueryActor::deprovision)
-            .register_method("reconcile", QueryActor::reconcile),
+            .register_method("reconcile", QueryActor::reconcile)
+            .register_method("rebuildView", QueryActor::rebuild_view),
         )
         .await;
 
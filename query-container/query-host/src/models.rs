@@ -19,6 +19,7 @@ use std::{
 };
 
 use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
 use thiserror::Error;
 use tokio::sync::watch;
 
@@ -95,6 +96,13 @@ impl QueryLifecycle {
     }
 }
 
+/// Property predicates by label that the source uses to return less bootstrap data
+#[derive(Debug, Clone, Default)]
+pub struct SubscriptionPredicates {
+    pub node_predicates: Map<String, Value>,
+    pub rel_predicates: Map<String, Value>,
+}
+
 pub struct ChangeStreamConfig {
     pub redis_url: String,
     pub buffer_size: usize,
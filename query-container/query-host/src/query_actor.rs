@@ -275,6 +275,25 @@ impl QueryActor {
         Json(())
     }
 
+    pub async fn rebuild_view(&self) -> impl IntoResponse {
+        log::info!("Query rebuild view - {}", self.query_id);
+        let worker = match self.worker.get().await {
+            Some(w) if !w.is_finished() => w,
+            _ => {
+                log::error!("Query {} worker not running", self.query_id);
+                return (StatusCode::CONFLICT, "Query worker not running").into_response();
+            }
+        };
+
+        match worker.rebuild_view().await {
+            Ok(_) => Json(()).into_response(),
+            Err(e) => {
+                log::error!("Query {} Error rebuilding view: {}", self.query_id, e);
+                (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()).into_response()
+            }
+        }
+    }
+
     pub async fn get_status(&self) -> Json<QueryStatus> {
         log::info!("Query get status - {}", self.query_id);
 
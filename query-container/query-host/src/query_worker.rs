@@ -21,6 +21,7 @@ use drasi_query_cypher::CypherParser;
 use drasi_query_gql::GQLParser;
 use futures::StreamExt;
 use std::{
+    collections::HashMap,
     error::Error,
     pin::pin,
     sync::Arc,
@@ -54,17 +55,26 @@ use crate::{
     change_stream::{
         self, redis_change_stream::RedisChangeStream, Message, SequentialChangeStream,
     },
+    element_sequences::ElementSequences,
     future_consumer::FutureConsumer,
     index_factory::IndexFactory,
-    models::{BootstrapError, ChangeStreamConfig, QueryError, QueryLifecycle, QueryState},
+    models::{
+        BootstrapError, ChangeStreamConfig, QueryError, QueryLifecycle, QueryState,
+        SubscriptionPredicates,
+    },
     result_publisher::ResultPublisher,
+    result_rows::{ResultRows, RowSnapshot},
     source_client::SourceClient,
 };
 
+/// Number of result lines buffered while streaming a rebuilt view to view-svc
+const REBUILD_BUFFER_SIZE: usize = 64;
+
 enum Command {
     Shutdown,
     Delete,
     Pause,
+    RebuildView(oneshot::Sender<Result<(), BootstrapError>>),
 }
 
 /// A background worker that runs a single query
@@ -101,31 +111,16 @@ impl QueryWorker {
 
             let view_spec = config.view.clone();
             let query_language = config.query_language.clone();
+            let predicates: HashMap<String, SubscriptionPredicates> = config
+                .sources
+                .subscriptions
+                .iter()
+                .map(|s| (s.id.clone(), s.predicates()))
+                .collect();
             let config: models::QueryConfig = config.into();
             let mut modified_config = config.clone();
 
-            let (parser, function_registry): (Arc<dyn QueryParser>, Arc<FunctionRegistry>) =
-                match query_language {
-                    Some(QueryLanguage::GQL) => {
-                        let function_registry =
-                            Arc::new(FunctionRegistry::new()).with_gql_function_set();
-                        let parser = Arc::new(GQLParser::new(function_registry.clone()))
-                            as Arc<dyn QueryParser>;
-                        (parser, function_registry)
-                    }
-                    Some(QueryLanguage::Cypher) | None => {
-                        let function_registry =
-                            Arc::new(FunctionRegistry::new()).with_cypher_function_set();
-                        let parser = Arc::new(CypherParser::new(function_registry.clone()))
-                            as Arc<dyn QueryParser>;
-                        (parser, function_registry)
-                    }
-                };
-
-            let mut builder =
-                QueryBuilder::new(&config.query, parser).with_function_registry(function_registry);
-
-            builder = builder.with_joins(config.sources.joins.clone());
+            let mut builder = query_builder(&config, &query_language, middleware_registry.clone());
 
             let index_set = match index_factory
                 .build(&modified_config.storage_profile, &query_id)
@@ -149,20 +144,6 @@ impl QueryWorker {
             builder = builder.with_result_index(result_index.clone());
             builder = builder.with_future_queue(future_queue.clone());
 
-            builder = builder.with_middleware_registry(middleware_registry);
-            for mw in modified_config.sources.middleware.clone() {
-                builder = builder.with_source_middleware(Arc::new(mw));
-            }
-
-            for subscription in &modified_config.sources.subscriptions {
-                let pipeline: Vec<String> = subscription
-                    .pipeline
-                    .iter()
-                    .map(|s| s.to_string())
-                    .collect();
-                builder = builder.with_source_pipeline(subscription.id.to_string(), &pipeline);
-            }
-
             let continuous_query = match builder.try_build().await {
                 Ok(cq) => cq,
                 Err(err) => {
@@ -213,6 +194,25 @@ impl QueryWorker {
                 lifecycle.change_state(QueryState::Configured);
             }
 
+            let element_sequences =
+                match ElementSequences::connect(&stream_config.redis_url, &query_id).await {
+                    Ok(es) => es,
+                    Err(err) => {
+                        log::error!("Error connecting to element sequence store: {}", err);
+                        lifecycle.change_state(QueryState::TransientError(err.to_string()));
+                        return;
+                    }
+                };
+
+            let result_rows = match ResultRows::connect(&stream_config.redis_url, &query_id).await {
+                Ok(rr) => rr,
+                Err(err) => {
+                    log::error!("Error connecting to result row store: {}", err);
+                    lifecycle.change_state(QueryState::TransientError(err.to_string()));
+                    return;
+                }
+            };
+
             let mut sequence_manager = match SequenceManager::new(result_index.clone()).await {
                 Ok(sm) => sm,
                 Err(err) => {
@@ -236,6 +236,8 @@ impl QueryWorker {
                     _ = element_index.clear().await;
                     _ = result_index.clear().await;
                     _ = archive_index.clear().await;
+                    _ = element_sequences.clear().await;
+                    _ = result_rows.clear().await;
 
                     if let Err(err) = bootstrap(
                         &query_container_id,
@@ -243,8 +245,10 @@ impl QueryWorker {
                         &modified_config,
                         &continuous_query,
                         &source_client,
+                        &predicates,
                         &mut sequence_manager,
                         &publisher,
+                        &result_rows,
                         element_index.clone(),
                         result_index.clone(),
                     )
@@ -326,6 +330,8 @@ impl QueryWorker {
                                     _ = element_index.clear().await;
                                     _ = result_index.clear().await;
                                     _ = archive_index.clear().await;
+                                    _ = element_sequences.clear().await;
+                                    _ = result_rows.clear().await;
                                     _ = change_stream.unsubscribe().await;
                                     // Iterate over the subscriptions and unsubscribe from each one
                                     for subscription in &modified_config.sources.subscriptions {
@@ -353,6 +359,28 @@ impl QueryWorker {
                                 Some(Command::Pause) => {
                                     todo!();
                                 },
+                                Some(Command::RebuildView(reply)) => {
+                                    log::info!("Query {} rebuilding result view", query_id);
+                                    // the rows are copied at the current sequence, then sent to view-svc while changes keep being processed
+                                    let sequence = sequence_manager.get().await.sequence;
+                                    match snapshot_result_rows(&view_spec, &result_rows, sequence).await {
+                                        Ok(snapshot) => {
+                                            let query_container_id = query_container_id.clone();
+                                            let query_id = query_id.clone();
+                                            tokio::spawn(async move {
+                                                let result = rebuild_result_view(&query_container_id, &query_id, snapshot, sequence).await;
+                                                if let Err(err) = &result {
+                                                    log::error!("Error rebuilding result view: {}", err);
+                                                }
+                                                _ = reply.send(result);
+                                            });
+                                        },
+                                        Err(err) => {
+                                            log::error!("Error rebuilding result view: {}", err);
+                                            _ = reply.send(Err(err));
+                                        },
+                                    }
+                                },
                                 None => {
                                     log::error!("Command channel closed unexpectedly");
                                     lifecycle.change_state(QueryState::TerminalError("Command channel closed unexpectedly".to_string()));
@@ -394,7 +422,7 @@ impl QueryWorker {
                                         span.set_attribute("query_id", query_id.clone());
 
                                         let evt_id = &evt.id.clone();
-                                        let process_future = process_change(&query_id, &continuous_query, &mut sequence_manager, &publisher, evt, enqueue_time, dequeue_time)
+                                        let process_future = process_change(&query_id, &continuous_query, &element_sequences, &result_rows, &mut sequence_manager, &publisher, evt, enqueue_time, dequeue_time)
                                             .instrument(span);
 
                                         match process_future.await {
@@ -475,6 +503,24 @@ impl QueryWorker {
         }
     }
 
+    /// Streams the current results of the query into its result view, pausing change processing until it is done
+    pub async fn rebuild_view(&self) -> Result<(), BootstrapError> {
+        let (reply_tx, reply_rx) = oneshot::channel();
+        if let Err(err) = self.commander.send(Command::RebuildView(reply_tx)) {
+            log::error!("Error sending rebuild view command: {}", err);
+            return Err(BootstrapError::other(Box::new(std::io::Error::other(
+                "Query worker is not running",
+            ))));
+        }
+
+        match reply_rx.await {
+            Ok(result) => result,
+            Err(_) => Err(BootstrapError::other(Box::new(std::io::Error::other(
+                "Query worker stopped before the view was rebuilt",
+            )))),
+        }
+    }
+
     pub fn shutdown(&self) {
         if self.handle.is_finished() {
             log::info!("Query worker already finished");
@@ -499,9 +545,12 @@ impl QueryWorker {
     }
 }
 
+#[allow(clippy::too_many_arguments)]
 async fn process_change(
     query_id: &str,
     continuous_query: &ContinuousQuery,
+    element_sequences: &ElementSequences,
+    result_rows: &ResultRows,
     seq_manager: &mut SequenceManager,
     publisher: &ResultPublisher,
     evt: Message<ChangeEvent>,
@@ -512,6 +561,7 @@ async fn process_change(
     let timestamp = evt.data.get_timestamp();
     let mut metadata = evt.data.get_metadata();
     let source_change_id = evt.id.clone();
+    let sequence = evt.data.get_sequence();
     let source_change: models::SourceChange = match evt.data.try_into() {
         Ok(sc) => sc,
         Err(err) => {
@@ -520,6 +570,25 @@ async fn process_change(
         }
     };
 
+    // changes without a sequence number, such as future changes, are applied in the order they arrive.
+    // The sequence is recorded before the change is applied; if applying it fails, the change is
+    // delivered again with the same sequence number and is not discarded.
+    if let (Some(sequence), Some(reference)) = (sequence, changed_element(&source_change)) {
+        let deleted = matches!(source_change, models::SourceChange::Delete { .. });
+        if !element_sequences
+            .advance(reference, sequence, deleted)
+            .await?
+        {
+            log::info!(
+                "Query {} discarding stale change {} (sequence {})",
+                query_id,
+                source_change_id,
+                sequence
+            );
+            return Ok(());
+        }
+    }
+
     let process_start_time = SystemTime::now();
     let changes = match continuous_query.process_source_change(source_change).await {
         Ok(c) => c,
@@ -573,6 +642,8 @@ async fn process_change(
         let output =
             ResultEvent::from_query_results(query_id, changes, seq, timestamp, Some(metadata));
 
+        result_rows.apply(&output).await?;
+
         match publisher.publish(query_id, output).await {
             Ok(_) => log::info!("Published result"),
             Err(err) => {
@@ -585,6 +656,21 @@ async fn process_change(
     Ok(())
 }
 
+/// The element a change applies to. Deletes are included, so the sequence of a deleted element is kept as a tombstone.
+fn changed_element(source_change: &models::SourceChange) -> Option<&models::ElementReference> {
+    let metadata = match source_change {
+        models::SourceChange::Insert { element } | models::SourceChange::Update { element } => {
+            match element {
+                models::Element::Node { metadata, .. } => metadata,
+                models::Element::Relation { metadata, .. } => metadata,
+            }
+        }
+        models::SourceChange::Delete { metadata } => metadata,
+        models::SourceChange::Future { .. } => return None,
+    };
+    Some(&metadata.reference)
+}
+
 #[allow(clippy::too_many_arguments)]
 #[instrument(skip_all, fields(query_id = query_id), err)]
 async fn bootstrap(
@@ -593,8 +679,10 @@ async fn bootstrap(
     config: &models::QueryConfig,
     query: &ContinuousQuery,
     source_client: &SourceClient,
+    predicates: &HashMap<String, SubscriptionPredicates>,
     seq_manager: &mut SequenceManager,
     publisher: &ResultPublisher,
+    result_rows: &ResultRows,
     element_index: Arc<dyn ElementIndex>,
     result_index: Arc<dyn ResultIndex>,
 ) -> Result<(), BootstrapError> {
@@ -631,6 +719,7 @@ async fn bootstrap(
                 query_container_id.to_string(),
                 query_id.to_string(),
                 source.clone(),
+                predicates.get(&*source.id).cloned().unwrap_or_default(),
             )
             .await
         {
@@ -675,6 +764,11 @@ async fn bootstrap(
                         },
                     );
 
+                    if let Err(err) = result_rows.apply(&output).await {
+                        log::error!("Error saving result rows: {}", err);
+                        return Err(BootstrapError::other(Box::new(err)));
+                    }
+
                     let result = {
                         let _guard = tracing::dispatcher::set_default(&Dispatch::none());
                         publisher.publish(query_id, output).await
@@ -721,6 +815,168 @@ async fn bootstrap(
     Ok(())
 }
 
+/// Copies the current result rows of the query, as the rows of its result view at `sequence`
+async fn snapshot_result_rows(
+    view_spec: &api::ViewSpec,
+    result_rows: &ResultRows,
+    sequence: u64,
+) -> Result<RowSnapshot, BootstrapError> {
+    if !view_spec.enabled {
+        return Err(BootstrapError::other(Box::new(std::io::Error::other(
+            "Result view is not enabled",
+        ))));
+    }
+
+    match result_rows.snapshot(sequence).await {
+        Ok(snapshot) => Ok(snapshot),
+        Err(err) => Err(BootstrapError::other(Box::new(err))),
+    }
+}
+
+/// Rebuilds the result view from a snapshot of the result rows, which are streamed to view-svc as the results at
+/// `sequence`. The changes after `sequence` are recorded in the view by view-svc as usual.
+#[instrument(skip_all, fields(query_id = query_id), err)]
+async fn rebuild_result_view(
+    query_container_id: &str,
+    query_id: &str,
+    mut snapshot: RowSnapshot,
+    sequence: u64,
+) -> Result<(), BootstrapError> {
+    let timestamp = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_millis() as u64;
+
+    let (tx, rx) = mpsc::channel::<Result<Vec<u8>, std::io::Error>>(REBUILD_BUFFER_SIZE);
+
+    let produce = async move {
+        let result = stream_rows(query_id, &mut snapshot, sequence, timestamp, &tx).await;
+
+        if let Err(err) = snapshot.release().await {
+            log::warn!("Error removing result row snapshot: {}", err);
+        }
+
+        // fail the request body, so view-svc discards the partial results
+        if let Err(err) = &result {
+            _ = tx.send(Err(std::io::Error::other(err.to_string()))).await;
+        }
+        result
+    };
+
+    let body = reqwest::Body::wrap_stream(futures::stream::unfold(rx, |mut rx| async move {
+        rx.recv().await.map(|line| (line, rx))
+    }));
+
+    let request = reqwest::Client::new()
+        .post(format!(
+            "http://{}-view-svc/{}/rebuild",
+            query_container_id, query_id
+        ))
+        .query(&[("sequence", sequence), ("timestamp", timestamp)])
+        .body(body)
+        .send();
+
+    let (produced, response) = tokio::join!(produce, request);
+    produced?;
+
+    match response {
+        Ok(resp) if resp.status().is_success() => {
+            log::info!(
+                "Query {} rebuilt result view at sequence {}",
+                query_id,
+                sequence
+            );
+            Ok(())
+        }
+        Ok(resp) => {
+            let status = resp.status();
+            let body = resp.text().await.unwrap_or_default();
+            Err(BootstrapError::other(Box::new(std::io::Error::other(
+                format!("view-svc rejected rebuild with {}: {}", status, body),
+            ))))
+        }
+        Err(err) => Err(BootstrapError::other(Box::new(err))),
+    }
+}
+
+/// Sends the rows of a snapshot as newline delimited result events
+async fn stream_rows(
+    query_id: &str,
+    snapshot: &mut RowSnapshot,
+    sequence: u64,
+    timestamp: u64,
+    tx: &mpsc::Sender<Result<Vec<u8>, std::io::Error>>,
+) -> Result<(), BootstrapError> {
+    loop {
+        let rows = match snapshot.next_batch().await {
+            Ok(Some(rows)) => rows,
+            Ok(None) => return Ok(()),
+            Err(err) => return Err(BootstrapError::other(Box::new(err))),
+        };
+
+        if rows.is_empty() {
+            continue;
+        }
+
+        let output = ResultEvent::from_rows(query_id, rows, sequence, timestamp);
+        let mut line = match serde_json::to_vec(&output) {
+            Ok(line) => line,
+            Err(e) => return Err(BootstrapError::other(Box::new(e))),
+        };
+        line.push(b'\n');
+
+        if tx.send(Ok(line)).await.is_err() {
+            return Err(BootstrapError::other(Box::new(std::io::Error::other(
+                "view-svc closed the rebuild request",
+            ))));
+        }
+    }
+}
+
+/// Builds a query from its config, without any indexes
+fn query_builder(
+    config: &models::QueryConfig,
+    query_language: &Option<QueryLanguage>,
+    middleware_registry: Arc<MiddlewareTypeRegistry>,
+) -> QueryBuilder {
+    let (parser, function_registry): (Arc<dyn QueryParser>, Arc<FunctionRegistry>) =
+        match query_language {
+            Some(QueryLanguage::GQL) => {
+                let function_registry = Arc::new(FunctionRegistry::new()).with_gql_function_set();
+                let parser =
+                    Arc::new(GQLParser::new(function_registry.clone())) as Arc<dyn QueryParser>;
+                (parser, function_registry)
+            }
+            Some(QueryLanguage::Cypher) | None => {
+                let function_registry =
+                    Arc::new(FunctionRegistry::new()).with_cypher_function_set();
+                let parser =
+                    Arc::new(CypherParser::new(function_registry.clone())) as Arc<dyn QueryParser>;
+                (parser, function_registry)
+            }
+        };
+
+    let mut builder =
+        QueryBuilder::new(&config.query, parser).with_function_registry(function_registry);
+
+    builder = builder.with_joins(config.sources.joins.clone());
+    builder = builder.with_middleware_registry(middleware_registry);
+    for mw in config.sources.middleware.clone() {
+        builder = builder.with_source_middleware(Arc::new(mw));
+    }
+
+    for subscription in &config.sources.subscriptions {
+        let pipeline: Vec<String> = subscription
+            .pipeline
+            .iter()
+            .map(|s| s.to_string())
+            .collect();
+        builder = builder.with_source_pipeline(subscription.id.to_string(), &pipeline);
+    }
+
+    builder
+}
+
 fn fill_default_source_labels(spec: &mut models::QueryConfig, ast: &Query) {
     for source in &mut spec.sources.subscriptions {
         if source.nodes.is_empty() && source.relations.is_empty() {
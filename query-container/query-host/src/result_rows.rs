@@ -0,0 +1,131 @@
+// Copyright 2024 The Drasi Authors.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use redis::{aio::MultiplexedConnection, AsyncCommands, RedisError};
+use serde_json::{Map, Value};
+
+use crate::api::ResultEvent;
+
+/// Number of rows read from a snapshot at a time
+const SCAN_BATCH_SIZE: usize = 500;
+
+/// Keeps the current result rows of a query, so its result view can be rebuilt without evaluating the query again.
+///
+/// The `ResultIndex` of a query only holds the accumulators of its aggregations, so the rows are kept in a Redis hash
+/// per query, next to the element sequences, and follow every result change the query publishes.
+/// They are cleared when the query is bootstrapped again or deleted.
+#[derive(Clone)]
+pub struct ResultRows {
+    key: String,
+    connection: MultiplexedConnection,
+}
+
+impl ResultRows {
+    pub async fn connect(url: &str, query_id: &str) -> Result<Self, RedisError> {
+        let client = redis::Client::open(url)?;
+        let connection = client.get_multiplexed_async_connection().await?;
+
+        Ok(Self {
+            key: format!("{}-result-rows", query_id),
+            connection,
+        })
+    }
+
+    /// Applies the rows added, updated and deleted by a published result event, in a single round trip
+    pub async fn apply(&self, event: &ResultEvent) -> Result<(), RedisError> {
+        let changes = match event {
+            ResultEvent::Change(change) => change.row_changes(),
+            ResultEvent::Control(_) => return Ok(()),
+        };
+        if changes.is_empty() {
+            return Ok(());
+        }
+
+        let mut pipe = redis::pipe();
+        pipe.atomic();
+        for (key, row) in changes {
+            match row {
+                Some(row) => pipe.hset(&self.key, key, Value::Object(row.clone()).to_string()),
+                None => pipe.hdel(&self.key, key),
+            }
+            .ignore();
+        }
+
+        let mut connection = self.connection.clone();
+        pipe.query_async(&mut connection).await
+    }
+
+    /// Copies the current rows, so they can be read while the query keeps changing them
+    pub async fn snapshot(&self, sequence: u64) -> Result<RowSnapshot, RedisError> {
+        let snapshot_key = format!("{}-{}", self.key, sequence);
+        let mut connection = self.connection.clone();
+        let _: i64 = redis::cmd("COPY")
+            .arg(&self.key)
+            .arg(&snapshot_key)
+            .arg("REPLACE")
+            .query_async(&mut connection)
+            .await?;
+
+        Ok(RowSnapshot {
+            key: snapshot_key,
+            connection,
+            cursor: Some(0),
+        })
+    }
+
+    pub async fn clear(&self) -> Result<(), RedisError> {
+        let mut connection = self.connection.clone();
+        connection.del(&self.key).await
+    }
+}
+
+/// The rows of a query as they were when the snapshot was taken
+pub struct RowSnapshot {
+    key: String,
+    connection: MultiplexedConnection,
+    cursor: Option<u64>,
+}
+
+impl RowSnapshot {
+    /// Reads the next batch of rows, or returns `None` once every row was read
+    pub async fn next_batch(&mut self) -> Result<Option<Vec<Map<String, Value>>>, RedisError> {
+        let cursor = match self.cursor {
+            Some(cursor) => cursor,
+            None => return Ok(None),
+        };
+
+        let (next, entries): (u64, Vec<(String, String)>) = redis::cmd("HSCAN")
+            .arg(&self.key)
+            .arg(cursor)
+            .arg("COUNT")
+            .arg(SCAN_BATCH_SIZE)
+            .query_async(&mut self.connection)
+            .await?;
+        self.cursor = if next == 0 { None } else { Some(next) };
+
+        let mut rows = Vec::with_capacity(entries.len());
+        for (key, data) in entries {
+            match serde_json::from_str(&data) {
+                Ok(row) => rows.push(row),
+                Err(e) => log::error!("Error parsing result row {}: {:?}", key, e),
+            }
+        }
+        Ok(Some(rows))
+    }
+
+    /// Removes the snapshot
+    pub async fn release(mut self) -> Result<(), RedisError> {
+        self.connection.del(&self.key).await
+    }
+}
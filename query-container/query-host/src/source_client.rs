@@ -18,16 +18,29 @@ use futures::{Stream, StreamExt};
 use reqwest_streams::JsonStreamResponse;
 use serde_json::json;
 
-use crate::models::{BootstrapError, UnsubscriptionError};
+use crate::models::{BootstrapError, SubscriptionPredicates, UnsubscriptionError};
+
+/// Set by query-api when there is another page of bootstrap data
+const CONTINUATION_TOKEN_HEADER: &str = "x-continuation-token";
 
 #[derive(Debug)]
 pub struct SourceClient {
     client: reqwest::Client,
+    page_size: Option<usize>,
 }
 
 impl SourceClient {
     pub fn new(client: reqwest::Client) -> SourceClient {
-        SourceClient { client }
+        SourceClient {
+            client,
+            page_size: None,
+        }
+    }
+
+    /// Fetches the bootstrap data in pages of this size, instead of in a single response
+    pub fn with_page_size(mut self, page_size: Option<usize>) -> SourceClient {
+        self.page_size = page_size;
+        self
     }
 
     pub async fn subscribe(
@@ -35,52 +48,54 @@ impl SourceClient {
         query_container_id: String,
         query_id: String,
         subscription: QuerySubscription,
+        predicates: SubscriptionPredicates,
     ) -> Result<impl Stream<Item = Result<SourceChange, BootstrapError>>, BootstrapError> {
         let app_id = format!("{}-query-api", subscription.id);
-        let data = json!({
+        let url = format!("http://{}/subscription", app_id);
+        let mut data = json!({
             "queryNodeId": query_container_id,
             "queryId": query_id,
             "nodeLabels": subscription.nodes.iter().map(|l| l.source_label.clone()).collect::<Vec<String>>(),
             "relLabels": subscription.relations.iter().map(|l| l.source_label.clone()).collect::<Vec<String>>(),
+            "nodePredicates": predicates.node_predicates,
+            "relPredicates": predicates.rel_predicates,
         });
-        let resp = match self
-            .client
-            .post(format!("http://{}/subscription", app_id))
-            .json(&data)
-            .send()
-            .await
-        {
-            Ok(resp) => resp,
-            Err(e) => {
-                return Err(BootstrapError::fetch_failed(
-                    subscription.id.to_string(),
-                    Box::new(e),
-                ))
-            }
-        };
-
-        if !resp.status().is_success() {
-            return Err(BootstrapError::fetch_failed(
-                subscription.id.to_string(),
-                Box::new(std::io::Error::new(
-                    std::io::ErrorKind::Other,
-                    format!(
-                        "{} {}",
-                        resp.status(),
-                        resp.text().await.unwrap_or_default()
-                    ),
-                )),
-            ));
+        if let Some(page_size) = self.page_size {
+            data["pageSize"] = json!(page_size);
         }
 
-        let mut stream = resp.json_nl_stream::<v2::BootstrapElement>(usize::MAX);
+        let client = self.client.clone();
+        let source_id = subscription.id.to_string();
+        let mut resp = request_page(&client, &url, &data, &source_id).await?;
 
         Ok(stream! {
-            while let Some(element) = stream.next().await {
-                match element {
-                    Ok(element) => yield Ok(element.into_source_change(subscription.id.as_ref())),
-                    Err(e) => yield Err(BootstrapError::fetch_failed(subscription.id.to_string(), Box::new(e))),
+            loop {
+                let continuation_token = resp
+                    .headers()
+                    .get(CONTINUATION_TOKEN_HEADER)
+                    .and_then(|v| v.to_str().ok())
+                    .map(|v| v.to_string());
+
+                let mut stream = resp.json_nl_stream::<v2::BootstrapElement>(usize::MAX);
+                while let Some(element) = stream.next().await {
+                    match element {
+                        Ok(element) => yield Ok(element.into_source_change(source_id.as_str())),
+                        Err(e) => yield Err(BootstrapError::fetch_failed(source_id.clone(), Box::new(e))),
+                    }
                 }
+
+                let Some(continuation_token) = continuation_token else {
+                    break;
+                };
+                log::info!("Fetching the next page of bootstrap data from source {}", source_id);
+                data["continuationToken"] = json!(continuation_token);
+                resp = match request_page(&client, &url, &data, &source_id).await {
+                    Ok(resp) => resp,
+                    Err(e) => {
+                        yield Err(e);
+                        break;
+                    }
+                };
             }
         })
     }
@@ -122,6 +137,39 @@ impl SourceClient {
     }
 }
 
+async fn request_page(
+    client: &reqwest::Client,
+    url: &str,
+    data: &serde_json::Value,
+    source_id: &str,
+) -> Result<reqwest::Response, BootstrapError> {
+    let resp = match client.post(url).json(data).send().await {
+        Ok(resp) => resp,
+        Err(e) => {
+            return Err(BootstrapError::fetch_failed(
+                source_id.to_string(),
+                Box::new(e),
+            ))
+        }
+    };
+
+    if !resp.status().is_success() {
+        return Err(BootstrapError::fetch_failed(
+            source_id.to_string(),
+            Box::new(std::io::Error::new(
+                std::io::ErrorKind::Other,
+                format!(
+                    "{} {}",
+                    resp.status(),
+                    resp.text().await.unwrap_or_default()
+                ),
+            )),
+        ));
+    }
+
+    Ok(resp)
+}
+
 mod v2 {
     use std::sync::Arc;
 
@@ -14,28 +14,73 @@
 
 use std::{
     collections::HashMap,
+    convert::Infallible,
     fmt::{Display, Formatter},
     net::SocketAddr,
     sync::Arc,
 };
 
+use async_stream::stream;
 use axum::{
-    extract::{Path, Query, State},
-    response::IntoResponse,
-    routing::get,
+    body::Body,
+    extract::{FromRef, Json, Path, Query, State},
+    response::{
+        sse::{Event, KeepAlive, Sse},
+        IntoResponse,
+    },
+    routing::{get, post},
     Router,
 };
 use axum_streams::*;
+use futures::StreamExt;
 
 use serde::{Deserialize, Serialize};
 use serde_json::{Map, Value};
 
-use crate::{models::ViewError, view_store::ViewStore};
+use crate::{
+    change_stream::redis_tail_stream::RedisTailStream,
+    exporter::{ExportFormat, ExportSchedule, ViewExporter},
+    models::{ChangeStreamConfig, ViewError},
+    view_store::{ChangeStream, ViewStore},
+};
 
 #[derive(Serialize, Deserialize, Debug, Clone)]
 #[serde(rename_all = "camelCase")]
 pub struct ViewSpec {
     pub retention_policy: RetentionPolicy,
+
+    /// Result fields to index for lookups
+    #[serde(default)]
+    pub index_fields: Vec<String>,
+
+    /// Result fields that together uniquely identify a row, used by `key` lookups
+    #[serde(default)]
+    pub key_fields: Vec<String>,
+
+    /// Periodic exports of the view to the export path
+    #[serde(default)]
+    pub exports: Vec<ExportSchedule>,
+
+    /// Makes this a composite view that joins the results of several queries, instead of the view of a single query
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub join: Option<JoinSpec>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct JoinSpec {
+    /// The queries to join. Every result of the first query appears in the view, enriched with the
+    /// result of each other query that has the same join key, or null if there is none.
+    pub queries: Vec<JoinedQuery>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct JoinedQuery {
+    pub query_id: String,
+
+    /// The result field holding the join key
+    pub key_field: String,
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone, Copy)]
@@ -54,7 +99,7 @@ pub enum RetentionPolicy {
     All,
 }
 
-#[derive(Deserialize, Debug, Clone)]
+#[derive(Serialize, Deserialize, Debug, Clone)]
 #[serde(tag = "kind")]
 pub enum ControlSignal {
     #[serde(rename = "bootstrapStarted")]
@@ -85,7 +130,7 @@ impl Display for ControlSignal {
     }
 }
 
-#[derive(Deserialize, Debug)]
+#[derive(Serialize, Deserialize, Debug)]
 #[serde(tag = "kind")]
 pub enum ResultEvent {
     #[serde(rename = "change")]
@@ -95,7 +140,7 @@ pub enum ResultEvent {
     Control(ResultControlEvent),
 }
 
-#[derive(Deserialize, Debug)]
+#[derive(Serialize, Deserialize, Debug)]
 #[serde(rename_all = "camelCase")]
 pub struct ResultChangeEvent {
     pub query_id: String,
@@ -107,7 +152,7 @@ pub struct ResultChangeEvent {
     pub metadata: Option<Map<String, Value>>,
 }
 
-#[derive(Deserialize, Debug)]
+#[derive(Serialize, Deserialize, Debug)]
 #[serde(rename_all = "camelCase")]
 pub struct ResultControlEvent {
     pub query_id: String,
@@ -117,7 +162,7 @@ pub struct ResultControlEvent {
     pub control_signal: ControlSignal,
 }
 
-#[derive(Deserialize, Debug)]
+#[derive(Serialize, Deserialize, Debug)]
 pub struct UpdatePayload {
     pub before: Option<Map<String, Value>>,
     pub after: Option<Map<String, Value>>,
@@ -135,10 +180,57 @@ pub enum ViewElement {
     Data(Map<String, Value>),
 }
 
-pub async fn start_view_service(view_store: Arc<dyn ViewStore>, port: u16) {
+impl ResultEvent {
+    pub fn sequence(&self) -> u64 {
+        match self {
+            ResultEvent::Change(c) => c.sequence,
+            ResultEvent::Control(c) => c.sequence,
+        }
+    }
+}
+
+#[derive(Clone)]
+struct AppState {
+    store: Arc<dyn ViewStore>,
+    stream_config: Arc<ChangeStreamConfig>,
+    exporter: Arc<ViewExporter>,
+}
+
+impl FromRef<AppState> for Arc<dyn ViewStore> {
+    fn from_ref(state: &AppState) -> Self {
+        state.store.clone()
+    }
+}
+
+impl FromRef<AppState> for Arc<ChangeStreamConfig> {
+    fn from_ref(state: &AppState) -> Self {
+        state.stream_config.clone()
+    }
+}
+
+impl FromRef<AppState> for Arc<ViewExporter> {
+    fn from_ref(state: &AppState) -> Self {
+        state.exporter.clone()
+    }
+}
+
+pub async fn start_view_service(
+    view_store: Arc<dyn ViewStore>,
+    stream_config: Arc<ChangeStreamConfig>,
+    exporter: Arc<ViewExporter>,
+    port: u16,
+) {
     let app = Router::new()
         .route("/:query_id", get(view_stream))
-        .with_state(view_store.clone());
+        .route("/:query_id/export", post(export_view))
+        .route("/:query_id/rebuild", post(rebuild_view))
+        .route("/:query_id/rows", get(view_rows))
+        .route("/:query_id/watch", get(watch_stream))
+        .with_state(AppState {
+            store: view_store.clone(),
+            stream_config,
+            exporter,
+        });
 
     // let addr = SocketAddr::from(([0, 0, 0, 0], port));
     let addr = format!("0.0.0.0:{}", port);
@@ -167,6 +259,198 @@ async fn view_stream(
                 let body = format!("View `{}` not found", query_id);
                 (axum::http::StatusCode::NOT_FOUND, body).into_response()
             }
+            ViewError::InvalidRequest(_) => {
+                (axum::http::StatusCode::BAD_REQUEST, e.to_string()).into_response()
+            }
+            _ => {
+                let body = format!("Error: {}", e);
+                (axum::http::StatusCode::INTERNAL_SERVER_ERROR, body).into_response()
+            }
+        },
+    }
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+struct ExportParams {
+    format: ExportFormat,
+    timestamp: Option<u64>,
+    from: Option<u64>,
+    to: Option<u64>,
+}
+
+/// Exports a view to a file in the export path, either as a snapshot at `timestamp`
+/// or as the full history between `from` and `to`.
+async fn export_view(
+    State(exporter): State<Arc<ViewExporter>>,
+    Path(query_id): Path<String>,
+    Query(params): Query<ExportParams>,
+) -> impl IntoResponse {
+    let result = match (params.from, params.to) {
+        (Some(from), Some(to)) => {
+            exporter
+                .export_history(&query_id, params.format, from, to)
+                .await
+        }
+        (None, None) => {
+            exporter
+                .export_snapshot(&query_id, params.format, params.timestamp)
+                .await
+        }
+        _ => {
+            return (
+                axum::http::StatusCode::BAD_REQUEST,
+                "Both `from` and `to` are required for a history export",
+            )
+                .into_response()
+        }
+    };
+
+    match result {
+        Ok(path) => Json(serde_json::json!({ "path": path })).into_response(),
+        Err(e) => match e {
+            ViewError::NotFound => {
+                let body = format!("View `{}` not found", query_id);
+                (axum::http::StatusCode::NOT_FOUND, body).into_response()
+            }
+            ViewError::InvalidRequest(_) => {
+                (axum::http::StatusCode::BAD_REQUEST, e.to_string()).into_response()
+            }
+            _ => {
+                let body = format!("Error: {}", e);
+                (axum::http::StatusCode::INTERNAL_SERVER_ERROR, body).into_response()
+            }
+        },
+    }
+}
+
+#[derive(Deserialize)]
+struct RebuildParams {
+    sequence: u64,
+    timestamp: u64,
+}
+
+/// Replaces the contents of a view with the current results of its query, streamed by query-host.
+/// The body holds one result change event per line, that together produce the results when applied to an empty view.
+/// The `sequence` and `timestamp` parameters set the result sequence and time the rebuilt view is valid from.
+async fn rebuild_view(
+    State(store): State<Arc<dyn ViewStore>>,
+    Path(query_id): Path<String>,
+    Query(params): Query<RebuildParams>,
+    body: Body,
+) -> impl IntoResponse {
+    log::info!(
+        "Rebuilding view {} at sequence {}",
+        query_id,
+        params.sequence
+    );
+
+    match store
+        .rebuild_view(
+            &query_id,
+            params.sequence,
+            params.timestamp,
+            read_result_lines(body),
+        )
+        .await
+    {
+        Ok(_) => axum::http::StatusCode::NO_CONTENT.into_response(),
+        Err(e) => match e {
+            ViewError::NotFound => {
+                let body = format!("View `{}` not found", query_id);
+                (axum::http::StatusCode::NOT_FOUND, body).into_response()
+            }
+            ViewError::InvalidRequest(_) => {
+                (axum::http::StatusCode::BAD_REQUEST, e.to_string()).into_response()
+            }
+            _ => {
+                let body = format!("Error: {}", e);
+                (axum::http::StatusCode::INTERNAL_SERVER_ERROR, body).into_response()
+            }
+        },
+    }
+}
+
+/// Reads a body of newline delimited result events as a stream of result changes
+fn read_result_lines(body: Body) -> ChangeStream {
+    stream! {
+        let mut data = body.into_data_stream();
+        let mut buffer = Vec::new();
+        loop {
+            let chunk = data.next().await;
+            let end = chunk.is_none();
+            match chunk {
+                Some(Ok(chunk)) => buffer.extend_from_slice(&chunk),
+                Some(Err(e)) => {
+                    yield Err(ViewError::InvalidRequest(e.to_string()));
+                    return;
+                }
+                None => buffer.push(b'\n'),
+            }
+
+            while let Some(pos) = buffer.iter().position(|b| *b == b'\n') {
+                let line: Vec<u8> = buffer.drain(..=pos).collect();
+                if line.iter().all(|b| b.is_ascii_whitespace()) {
+                    continue;
+                }
+                match serde_json::from_slice::<ResultEvent>(&line) {
+                    Ok(ResultEvent::Change(change)) => yield Ok(change),
+                    Ok(ResultEvent::Control(_)) => {}
+                    Err(e) => {
+                        yield Err(ViewError::InvalidRequest(e.to_string()));
+                        return;
+                    }
+                }
+            }
+
+            if end {
+                break;
+            }
+        }
+    }
+    .boxed()
+}
+
+/// Looks up rows of a view by key or by indexed field values.
+///
+/// The `key` parameter holds the key field values, either as a single value or as a JSON array
+/// for composite keys. Any other parameter, except `timestamp`, is matched against the indexed
+/// field of the same name. Values are parsed as JSON and fall back to a plain string, so numeric
+/// looking strings must be quoted.
+async fn view_rows(
+    State(store): State<Arc<dyn ViewStore>>,
+    Path(query_id): Path<String>,
+    Query(params): Query<HashMap<String, String>>,
+) -> impl IntoResponse {
+    let mut timestamp = None;
+    let mut key = None;
+    let mut filter = Map::new();
+
+    for (name, value) in params {
+        match name.as_str() {
+            "timestamp" => timestamp = value.parse::<u64>().ok(),
+            "key" => {
+                key = Some(match parse_param_value(&value) {
+                    Value::Array(values) => values,
+                    v => vec![v],
+                })
+            }
+            _ => {
+                filter.insert(name, parse_param_value(&value));
+            }
+        }
+    }
+
+    match store.get_rows(&query_id, key, filter, timestamp).await {
+        Ok(stream) => StreamBodyAs::json_array(stream).into_response(),
+        Err(e) => match e {
+            ViewError::NotFound => {
+                let body = format!("View `{}` not found", query_id);
+                (axum::http::StatusCode::NOT_FOUND, body).into_response()
+            }
+            ViewError::InvalidRequest(_) => {
+                (axum::http::StatusCode::BAD_REQUEST, e.to_string()).into_response()
+            }
             _ => {
                 let body = format!("Error: {}", e);
                 (axum::http::StatusCode::INTERNAL_SERVER_ERROR, body).into_response()
@@ -174,3 +458,102 @@ async fn view_stream(
         },
     }
 }
+
+fn parse_param_value(value: &str) -> Value {
+    match serde_json::from_str(value) {
+        Ok(v) => v,
+        Err(_) => Value::String(value.to_string()),
+    }
+}
+
+/// Streams the current view followed by all subsequent result events as server-sent events.
+///
+/// The results stream is positioned at the first message the view worker has not yet applied
+/// before the snapshot is read, so any event not reflected in the snapshot will be served.
+/// Events at or below the snapshot sequence are already in the snapshot and are skipped.
+async fn watch_stream(
+    State(store): State<Arc<dyn ViewStore>>,
+    State(stream_config): State<Arc<ChangeStreamConfig>>,
+    Path(query_id): Path<String>,
+) -> impl IntoResponse {
+    let topic = format!("{}-results", query_id);
+    let mut change_stream = match RedisTailStream::from_consumer_group(
+        &stream_config.redis_url,
+        &topic,
+        "view-svc",
+        stream_config.fetch_batch_size,
+        stream_config.watch_block_ms,
+    )
+    .await
+    {
+        Ok(cs) => cs,
+        Err(e) => {
+            let body = format!("Error: {}", e);
+            return (axum::http::StatusCode::INTERNAL_SERVER_ERROR, body).into_response();
+        }
+    };
+
+    let mut snapshot = match store.get_view(&query_id, None).await {
+        Ok(stream) => stream,
+        Err(ViewError::NotFound) => {
+            let body = format!("View `{}` not found", query_id);
+            return (axum::http::StatusCode::NOT_FOUND, body).into_response();
+        }
+        Err(e) => {
+            let body = format!("Error: {}", e);
+            return (axum::http::StatusCode::INTERNAL_SERVER_ERROR, body).into_response();
+        }
+    };
+
+    let events = stream! {
+        let mut snapshot_sequence = 0;
+        while let Some(item) = snapshot.next().await {
+            let event_type = match &item {
+                ViewElement::Header { sequence, .. } => {
+                    snapshot_sequence = *sequence;
+                    "header"
+                }
+                ViewElement::Data(_) => "data",
+            };
+            match Event::default().event(event_type).json_data(&item) {
+                Ok(evt) => yield Ok::<Event, Infallible>(evt),
+                Err(err) => log::error!("Error serializing view element: {}", err),
+            }
+        }
+
+        loop {
+            let msg = match change_stream.recv::<ResultEvent>().await {
+                Ok(Some(msg)) => msg,
+                Ok(None) => continue,
+                Err(err) => {
+                    log::error!("Error receiving message from change stream: {}", err);
+                    break;
+                }
+            };
+
+            if msg.data.sequence() <= snapshot_sequence {
+                continue;
+            }
+
+            let event_type = match &msg.data {
+                ResultEvent::Change(_) => "change",
+                ResultEvent::Control(_) => "control",
+            };
+            match Event::default()
+                .event(event_type)
+                .id(msg.id.as_str())
+                .json_data(&msg.data)
+            {
+                Ok(evt) => yield Ok(evt),
+                Err(err) => log::error!("Error serializing result event: {}", err),
+            }
+        }
+    };
+
+    Sse::new(events)
+        .keep_alive(KeepAlive::default())
+        .into_response()
+}
+
+#[cfg(test)]
+mod tests;
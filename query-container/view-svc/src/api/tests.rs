@@ -0,0 +1,248 @@
+// Copyright 2024 The Drasi Authors.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+#![allow(clippy::unwrap_used)]
+
+use std::{collections::HashMap, sync::Arc};
+
+use axum::{
+    body::Body,
+    extract::{Path, Query, State},
+    http::StatusCode,
+    response::IntoResponse,
+};
+use futures::StreamExt;
+use serde_json::{json, Value};
+
+use super::{rebuild_view, view_rows, RebuildParams, ViewElement, ViewSpec};
+use crate::{memory_view_store::MemoryViewStore, view_store::ViewStore};
+
+fn spec() -> ViewSpec {
+    serde_json::from_value(json!({ "retentionPolicy": "all" })).unwrap()
+}
+
+fn change_line(sequence: u64, added: Value, updated: Value) -> String {
+    json!({
+        "kind": "change",
+        "queryId": "q1",
+        "sequence": sequence,
+        "sourceTimeMs": 100,
+        "addedResults": added,
+        "updatedResults": updated,
+        "deletedResults": [],
+        "metadata": null
+    })
+    .to_string()
+}
+
+async fn rebuild(store: &Arc<MemoryViewStore>, sequence: u64, body: String) -> StatusCode {
+    let store: Arc<dyn ViewStore> = store.clone();
+    rebuild_view(
+        State(store),
+        Path("q1".to_string()),
+        Query(RebuildParams {
+            sequence,
+            timestamp: 2000,
+        }),
+        Body::from(body),
+    )
+    .await
+    .into_response()
+    .status()
+}
+
+async fn header(store: &Arc<MemoryViewStore>) -> (u64, u64) {
+    match store.get_view("q1", None).await.unwrap().next().await {
+        Some(ViewElement::Header {
+            sequence,
+            timestamp,
+            ..
+        }) => (sequence, timestamp),
+        _ => panic!("view did not start with a header"),
+    }
+}
+
+#[tokio::test]
+async fn rebuild_replaces_rows_with_streamed_results() {
+    let store = Arc::new(MemoryViewStore::default());
+    store.init_view("q1", &spec()).await.unwrap();
+    store.set_state("q1", 3, 1000, "running").await.unwrap();
+    let mut rebuilds = store.rebuilt_sequence("q1");
+
+    let body = [
+        change_line(9, json!([{ "id": "a" }, { "id": "b" }]), json!([])),
+        change_line(
+            9,
+            json!([]),
+            json!([{ "before": { "id": "b" }, "after": { "id": "c" }, "grouping_keys": null }]),
+        ),
+    ]
+    .join("\n");
+
+    assert_eq!(rebuild(&store, 9, body).await, StatusCode::NO_CONTENT);
+    assert_eq!(header(&store).await, (9, 2000));
+
+    let mut rows = store.rows("q1").await;
+    rows.sort_by_key(|r| r.to_string());
+    assert_eq!(rows, vec![json!({ "id": "a" }), json!({ "id": "c" })]);
+
+    assert!(rebuilds.has_changed().unwrap());
+    assert_eq!(*rebuilds.borrow_and_update(), 9);
+}
+
+#[tokio::test]
+async fn rebuild_with_invalid_results_leaves_view_unchanged() {
+    let store = Arc::new(MemoryViewStore::default());
+    store.init_view("q1", &spec()).await.unwrap();
+    store.set_state("q1", 3, 1000, "running").await.unwrap();
+
+    let body = format!(
+        "{}\n{{ not json",
+        change_line(9, json!([{ "id": "a" }]), json!([]))
+    );
+
+    assert_eq!(rebuild(&store, 9, body).await, StatusCode::BAD_REQUEST);
+    assert_eq!(header(&store).await, (3, 1000));
+    assert!(store.rows("q1").await.is_empty());
+}
+
+#[tokio::test]
+async fn rebuild_of_unknown_view_is_not_found() {
+    let store = Arc::new(MemoryViewStore::default());
+    assert_eq!(
+        rebuild(&store, 1, String::new()).await,
+        StatusCode::NOT_FOUND
+    );
+}
+
+async fn lookup(store: &Arc<MemoryViewStore>, params: &[(&str, &str)]) -> (StatusCode, Vec<Value>) {
+    let store: Arc<dyn ViewStore> = store.clone();
+    let params: HashMap<String, String> = params
+        .iter()
+        .map(|(k, v)| (k.to_string(), v.to_string()))
+        .collect();
+    let response = view_rows(State(store), Path("q1".to_string()), Query(params))
+        .await
+        .into_response();
+    let status = response.status();
+    if status != StatusCode::OK {
+        return (status, Vec::new());
+    }
+
+    let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+        .await
+        .unwrap();
+    let elements: Vec<Value> = serde_json::from_slice(&body).unwrap();
+    let rows = elements
+        .into_iter()
+        .filter_map(|mut e| e.get_mut("data").map(Value::take))
+        .collect();
+    (status, rows)
+}
+
+async fn lookup_subject(spec: Value, change: String) -> Arc<MemoryViewStore> {
+    let store = Arc::new(MemoryViewStore::default());
+    store
+        .init_view("q1", &serde_json::from_value(spec).unwrap())
+        .await
+        .unwrap();
+    let change = match serde_json::from_str(&change).unwrap() {
+        super::ResultEvent::Change(change) => change,
+        _ => panic!("expected a change event"),
+    };
+    store.record_change("q1", change).await.unwrap();
+    store
+}
+
+#[tokio::test]
+async fn rows_are_looked_up_by_key_and_index_fields() {
+    let store = lookup_subject(
+        json!({ "retentionPolicy": "latest", "keyFields": ["id"], "indexFields": ["category"] }),
+        change_line(
+            1,
+            json!([
+                { "id": "a", "category": "book" },
+                { "id": "b", "category": "book" },
+                { "id": "c", "category": "toy" }
+            ]),
+            json!([]),
+        ),
+    )
+    .await;
+
+    assert_eq!(
+        lookup(&store, &[("key", "\"c\"")]).await,
+        (
+            StatusCode::OK,
+            vec![json!({ "id": "c", "category": "toy" })]
+        )
+    );
+    assert_eq!(
+        lookup(&store, &[("category", "book")]).await,
+        (
+            StatusCode::OK,
+            vec![
+                json!({ "id": "a", "category": "book" }),
+                json!({ "id": "b", "category": "book" })
+            ]
+        )
+    );
+}
+
+#[tokio::test]
+async fn aggregation_rows_are_looked_up_by_grouping_keys() {
+    let store = lookup_subject(
+        json!({ "retentionPolicy": "latest" }),
+        change_line(
+            1,
+            json!([]),
+            json!([
+                { "before": null, "after": { "category": "book", "total": 2 }, "grouping_keys": ["category"] },
+                { "before": null, "after": { "category": "toy", "total": 1 }, "grouping_keys": ["category"] }
+            ]),
+        ),
+    )
+    .await;
+
+    assert_eq!(
+        lookup(&store, &[("key", "book")]).await,
+        (
+            StatusCode::OK,
+            vec![json!({ "category": "book", "total": 2 })]
+        )
+    );
+}
+
+#[tokio::test]
+async fn invalid_row_lookups_are_rejected() {
+    let store = lookup_subject(
+        json!({ "retentionPolicy": "latest", "keyFields": ["id"] }),
+        change_line(1, json!([{ "id": "a", "name": "x" }]), json!([])),
+    )
+    .await;
+
+    assert_eq!(
+        lookup(&store, &[("name", "x")]).await.0,
+        StatusCode::BAD_REQUEST
+    );
+    assert_eq!(
+        lookup(&store, &[("key", "[\"a\", \"b\"]")]).await.0,
+        StatusCode::BAD_REQUEST
+    );
+
+    let missing = Arc::new(MemoryViewStore::default());
+    assert_eq!(
+        lookup(&missing, &[("key", "a")]).await.0,
+        StatusCode::NOT_FOUND
+    );
+}
@@ -77,6 +77,7 @@ impl<T> opentelemetry::propagation::Extractor for Message<T> {
 }
 
 pub mod redis_change_stream;
+pub mod redis_tail_stream;
 
 #[cfg(test)]
 mod tests;
@@ -245,7 +245,7 @@ async fn process_batch(
     true
 }
 
-fn deserialize_message<T>(message: &StreamId) -> Result<Message<T>, ChangeStreamError>
+pub(super) fn deserialize_message<T>(message: &StreamId) -> Result<Message<T>, ChangeStreamError>
 where
     T: for<'de> Deserialize<'de>,
 {
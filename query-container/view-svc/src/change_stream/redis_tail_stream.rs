@@ -0,0 +1,182 @@
+// Copyright 2024 The Drasi Authors.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::collections::VecDeque;
+
+use redis::{
+    streams::{
+        StreamId, StreamInfoGroupsReply, StreamPendingReply, StreamReadOptions, StreamReadReply,
+    },
+    AsyncCommands,
+};
+use serde::Deserialize;
+
+use super::{redis_change_stream::deserialize_message, ChangeStreamError, Message};
+
+/// A read-only cursor over a Redis stream that does not participate in a consumer group.
+///
+/// The cursor is positioned relative to an existing consumer group, so that every message
+/// that the group has not yet finished processing will be served. This allows a reader to
+/// take a snapshot of state built by that group and then continue from the stream without a gap.
+pub struct RedisTailStream {
+    topic: String,
+    connection: redis::aio::Connection,
+    last_id: String,
+    buffer: VecDeque<StreamId>,
+    read_opts: StreamReadOptions,
+}
+
+impl RedisTailStream {
+    /// Creates a cursor that starts at the oldest message not yet acknowledged by `group_id`.
+    /// If the group does not exist, the cursor starts at the beginning of the stream.
+    pub async fn from_consumer_group(
+        url: &str,
+        topic: &str,
+        group_id: &str,
+        fetch_batch_size: usize,
+        block_ms: usize,
+    ) -> Result<Self, ChangeStreamError> {
+        let client = redis::Client::open(url)?;
+        let mut connection = client.get_async_connection().await?;
+
+        let last_id = unprocessed_position(&mut connection, topic, group_id).await?;
+
+        log::info!("Tailing {} after {}", topic, last_id);
+
+        Ok(RedisTailStream {
+            topic: topic.to_string(),
+            connection,
+            last_id,
+            buffer: VecDeque::new(),
+            read_opts: StreamReadOptions::default()
+                .count(fetch_batch_size)
+                .block(block_ms),
+        })
+    }
+
+    /// Receives the next message from the stream, waiting up to the configured block time.
+    /// Returns `None` if no message arrived within that time.
+    pub async fn recv<T>(&mut self) -> Result<Option<Message<T>>, ChangeStreamError>
+    where
+        T: for<'de> Deserialize<'de>,
+    {
+        if self.buffer.is_empty() {
+            let batch: StreamReadReply = self
+                .connection
+                .xread_options(&[&self.topic], &[&self.last_id], &self.read_opts)
+                .await?;
+
+            for k in batch.keys {
+                self.buffer.extend(k.ids);
+            }
+        }
+
+        match self.buffer.pop_front() {
+            Some(item) => {
+                self.last_id = item.id.clone();
+                Ok(Some(deserialize_message::<T>(&item)?))
+            }
+            None => Ok(None),
+        }
+    }
+}
+
+/// Returns the stream ID after which all messages that have not been acknowledged by `group_id` are found.
+/// If the group does not exist, this is the beginning of the stream.
+async fn unprocessed_position(
+    connection: &mut redis::aio::Connection,
+    topic: &str,
+    group_id: &str,
+) -> Result<String, ChangeStreamError> {
+    if !connection.exists::<&str, bool>(topic).await? {
+        return Ok("0".to_string());
+    }
+
+    let pending: StreamPendingReply = match connection.xpending(topic, group_id).await {
+        Ok(p) => p,
+        Err(err) => match err.kind() {
+            redis::ErrorKind::ExtensionError => StreamPendingReply::Empty,
+            _ => return Err(err.into()),
+        },
+    };
+
+    match pending {
+        StreamPendingReply::Data(p) if p.count > 0 => Ok(preceding_id(&p.start_id)),
+        _ => {
+            let groups: StreamInfoGroupsReply = connection.xinfo_groups(topic).await?;
+            match groups.groups.into_iter().find(|g| g.name == group_id) {
+                Some(g) => Ok(g.last_delivered_id),
+                None => Ok("0".to_string()),
+            }
+        }
+    }
+}
+
+/// Creates the consumer group `group_id`, positioned so that it will receive every message
+/// that `source_group_id` has not yet acknowledged.
+/// Returns false if the group already exists.
+pub async fn create_group_after(
+    url: &str,
+    topic: &str,
+    group_id: &str,
+    source_group_id: &str,
+) -> Result<bool, ChangeStreamError> {
+    let client = redis::Client::open(url)?;
+    let mut connection = client.get_async_connection().await?;
+
+    let start_id = unprocessed_position(&mut connection, topic, source_group_id).await?;
+
+    match connection
+        .xgroup_create_mkstream::<&str, &str, &str, String>(topic, group_id, &start_id)
+        .await
+    {
+        Ok(_) => {
+            log::info!(
+                "Created consumer group {} on {} after {}",
+                group_id,
+                topic,
+                start_id
+            );
+            Ok(true)
+        }
+        Err(err) => match err.kind() {
+            redis::ErrorKind::ExtensionError => Ok(false),
+            _ => Err(err.into()),
+        },
+    }
+}
+
+/// Deletes the consumer group `group_id`, if it exists.
+pub async fn delete_group(url: &str, topic: &str, group_id: &str) -> Result<(), ChangeStreamError> {
+    let client = redis::Client::open(url)?;
+    let mut connection = client.get_async_connection().await?;
+    connection
+        .xgroup_destroy::<&str, &str, bool>(topic, group_id)
+        .await?;
+    Ok(())
+}
+
+/// Returns the stream ID immediately before `id`, so that an exclusive read from it will include `id`.
+pub fn preceding_id(id: &str) -> String {
+    let (ms, seq) = match id.split_once('-') {
+        Some((ms, seq)) => (ms.parse::<u64>(), seq.parse::<u64>()),
+        None => (id.parse::<u64>(), Ok(0)),
+    };
+
+    match (ms, seq) {
+        (Ok(ms), Ok(seq)) if seq > 0 => format!("{}-{}", ms, seq - 1),
+        (Ok(ms), Ok(_)) if ms > 0 => format!("{}-{}", ms - 1, u64::MAX),
+        _ => "0".to_string(),
+    }
+}
@@ -22,7 +22,11 @@ use serde_json::{json, Value};
 use tokio::task;
 use uuid::Uuid;
 
-use crate::change_stream::{redis_change_stream::RedisChangeStream, SequentialChangeStream};
+use crate::change_stream::{
+    redis_change_stream::RedisChangeStream,
+    redis_tail_stream::{preceding_id, RedisTailStream},
+    SequentialChangeStream,
+};
 
 #[derive(Deserialize, Debug)]
 struct TestMessage {
@@ -280,6 +284,106 @@ async fn stops_buffering_on_drop() {
     assert_eq!(0, pending.count());
 }
 
+#[tokio::test]
+async fn tail_starts_at_first_unacked_message() {
+    let url = get_url();
+    let query_container_id = format!("test:{}", Uuid::new_v4());
+    let query_id = Uuid::new_v4().to_string();
+    let mut connection = redis::Client::open(url.as_str())
+        .unwrap()
+        .get_multiplexed_async_connection()
+        .await
+        .unwrap();
+
+    let consumer = RedisChangeStream::new(&url, &query_container_id, &query_id, "consumer", 5, 3)
+        .await
+        .unwrap();
+
+    for i in 1..=5 {
+        let _: redis::Value = connection
+            .xadd(
+                &query_container_id,
+                "*",
+                &build_redis_message(json!({"data": i})),
+            )
+            .await
+            .unwrap();
+    }
+
+    for _ in 1..=2 {
+        let msg = consumer.recv::<TestMessage>().await.unwrap().unwrap();
+        consumer.ack(&msg.id).await.unwrap();
+    }
+
+    tokio::time::sleep(Duration::from_millis(1000)).await;
+
+    let mut subject =
+        RedisTailStream::from_consumer_group(&url, &query_container_id, &query_id, 2, 100)
+            .await
+            .unwrap();
+
+    for i in 3..=5 {
+        let msg = subject.recv::<TestMessage>().await.unwrap().unwrap();
+        assert_eq!(msg.data.data, i);
+    }
+
+    assert!(subject.recv::<TestMessage>().await.unwrap().is_none());
+
+    let _: redis::Value = connection
+        .xadd(
+            &query_container_id,
+            "*",
+            &build_redis_message(json!({"data": 6})),
+        )
+        .await
+        .unwrap();
+
+    let msg = subject.recv::<TestMessage>().await.unwrap().unwrap();
+    assert_eq!(msg.data.data, 6);
+}
+
+#[tokio::test]
+async fn tail_without_group_starts_at_beginning() {
+    let url = get_url();
+    let query_container_id = format!("test:{}", Uuid::new_v4());
+    let mut connection = redis::Client::open(url.as_str())
+        .unwrap()
+        .get_multiplexed_async_connection()
+        .await
+        .unwrap();
+
+    for i in 1..=3 {
+        let _: redis::Value = connection
+            .xadd(
+                &query_container_id,
+                "*",
+                &build_redis_message(json!({"data": i})),
+            )
+            .await
+            .unwrap();
+    }
+
+    let mut subject =
+        RedisTailStream::from_consumer_group(&url, &query_container_id, "missing", 5, 100)
+            .await
+            .unwrap();
+
+    for i in 1..=3 {
+        let msg = subject.recv::<TestMessage>().await.unwrap().unwrap();
+        assert_eq!(msg.data.data, i);
+    }
+}
+
+#[test]
+fn preceding_id_steps_back_one_entry() {
+    assert_eq!(preceding_id("1700000000000-5"), "1700000000000-4");
+    assert_eq!(
+        preceding_id("1700000000000-0"),
+        format!("1699999999999-{}", u64::MAX)
+    );
+    assert_eq!(preceding_id("0-0"), "0");
+}
+
 fn build_redis_message(msg: Value) -> Vec<(String, String)> {
     let evt = EventBuilderV10::new()
         .id("test")
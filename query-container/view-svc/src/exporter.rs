@@ -0,0 +1,344 @@
+// Copyright 2024 The Drasi Authors.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::{
+    fs::File,
+    path::{Path, PathBuf},
+    sync::Arc,
+    time::Duration,
+};
+
+use arrow_schema::{Schema, SchemaRef};
+use futures::StreamExt;
+use parquet::arrow::ArrowWriter;
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+use tokio::{sync::mpsc, task::JoinHandle};
+
+use crate::{
+    api::ViewElement,
+    models::ViewError,
+    view_store::{RowStream, ViewStore, ViewStream},
+};
+
+const BATCH_SIZE: usize = 1000;
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub enum ExportFormat {
+    Parquet,
+    Csv,
+}
+
+impl ExportFormat {
+    fn extension(&self) -> &'static str {
+        match self {
+            ExportFormat::Parquet => "parquet",
+            ExportFormat::Csv => "csv",
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct ExportSchedule {
+    pub format: ExportFormat,
+    pub interval_seconds: u64,
+}
+
+enum ExportSchema {
+    Csv(Vec<String>),
+    Parquet(SchemaRef),
+}
+
+/// Writes views to local files, reading them through the `ViewStore` so that any store can be exported.
+pub struct ViewExporter {
+    store: Arc<dyn ViewStore>,
+    path: PathBuf,
+}
+
+impl ViewExporter {
+    pub fn new(store: Arc<dyn ViewStore>, path: PathBuf) -> Self {
+        Self { store, path }
+    }
+
+    /// Exports the view as it was at `timestamp`, or the current view if no timestamp is given.
+    pub async fn export_snapshot(
+        &self,
+        query_id: &str,
+        format: ExportFormat,
+        timestamp: Option<u64>,
+    ) -> Result<PathBuf, ViewError> {
+        // without a requested timestamp, the export is pinned to the timestamp of the current view,
+        // so that the schema scan and the data read see the same rows
+        let view_ts = match timestamp {
+            Some(ts) => ts,
+            None => match self.store.get_view(query_id, None).await?.next().await {
+                Some(ViewElement::Header { timestamp, .. }) => timestamp,
+                _ => return Err(ViewError::Other("View did not start with a header".into())),
+            },
+        };
+
+        let view = self.store.get_view(query_id, Some(view_ts)).await?;
+        let schema = scan_schema(format, data_rows(view)).await?;
+
+        let rows = data_rows(self.store.get_view(query_id, Some(view_ts)).await?);
+        let file_name = format!("{}-{}.{}", query_id, view_ts, format.extension());
+
+        self.write(&file_name, schema, rows).await
+    }
+
+    /// Exports every version of every row that was valid at some point between `from` and `to`.
+    /// Each row gets `_validFrom` and `_validTo` columns, `_validTo` is empty for rows that are still current.
+    pub async fn export_history(
+        &self,
+        query_id: &str,
+        format: ExportFormat,
+        from: u64,
+        to: u64,
+    ) -> Result<PathBuf, ViewError> {
+        let history = self.store.get_view_history(query_id, from, to).await?;
+        let schema = scan_schema(format, history).await?;
+
+        let rows = self.store.get_view_history(query_id, from, to).await?;
+        let file_name = format!("{}-{}-{}.{}", query_id, from, to, format.extension());
+
+        self.write(&file_name, schema, rows).await
+    }
+
+    /// Streams the rows to a blocking task that writes the file, in batches
+    async fn write(
+        &self,
+        file_name: &str,
+        schema: ExportSchema,
+        mut rows: RowStream,
+    ) -> Result<PathBuf, ViewError> {
+        if let Err(e) = tokio::fs::create_dir_all(&self.path).await {
+            return Err(ViewError::StoreError(Box::new(e)));
+        }
+
+        let target = self.path.join(file_name);
+        let tmp = self.path.join(format!(".{}.tmp", file_name));
+
+        let (tx, rx) = mpsc::channel::<Vec<Map<String, Value>>>(2);
+        let tmp_path = tmp.clone();
+        let writer = tokio::task::spawn_blocking(move || match schema {
+            ExportSchema::Csv(columns) => write_csv(&tmp_path, &columns, rx),
+            ExportSchema::Parquet(schema) => write_parquet(&tmp_path, schema, rx),
+        });
+
+        let mut batch = Vec::with_capacity(BATCH_SIZE);
+        while let Some(row) = rows.next().await {
+            batch.push(row);
+            if batch.len() >= BATCH_SIZE {
+                let full = std::mem::replace(&mut batch, Vec::with_capacity(BATCH_SIZE));
+                // the writer stopped early, its error is reported below
+                if tx.send(full).await.is_err() {
+                    break;
+                }
+            }
+        }
+        if !batch.is_empty() {
+            _ = tx.send(batch).await;
+        }
+        drop(tx);
+
+        let result = match writer.await {
+            Ok(r) => r,
+            Err(e) => Err(export_error(e)),
+        };
+
+        if let Err(e) = result {
+            _ = tokio::fs::remove_file(&tmp).await;
+            return Err(e);
+        }
+
+        if let Err(e) = tokio::fs::rename(&tmp, &target).await {
+            return Err(ViewError::StoreError(Box::new(e)));
+        }
+
+        log::info!("Exported view to {}", target.display());
+        Ok(target)
+    }
+}
+
+fn data_rows(view: ViewStream) -> RowStream {
+    view.filter_map(|item| async move {
+        match item {
+            ViewElement::Data(data) => Some(data),
+            ViewElement::Header { .. } => None,
+        }
+    })
+    .boxed()
+}
+
+async fn scan_schema(format: ExportFormat, mut rows: RowStream) -> Result<ExportSchema, ViewError> {
+    match format {
+        ExportFormat::Csv => {
+            let mut columns: Vec<String> = Vec::new();
+            while let Some(row) = rows.next().await {
+                for key in row.keys() {
+                    if !columns.contains(key) {
+                        columns.push(key.clone());
+                    }
+                }
+            }
+            Ok(ExportSchema::Csv(columns))
+        }
+        ExportFormat::Parquet => {
+            let mut schema = Schema::empty();
+            let mut batch = Vec::with_capacity(BATCH_SIZE);
+            loop {
+                let row = rows.next().await;
+                let end = row.is_none();
+                if let Some(row) = row {
+                    batch.push(Value::Object(row));
+                }
+
+                if batch.len() >= BATCH_SIZE || (end && !batch.is_empty()) {
+                    let batch_schema = arrow_json::reader::infer_json_schema_from_iterator(
+                        batch.drain(..).map(Ok),
+                    )
+                    .map_err(export_error)?;
+                    schema = Schema::try_merge(vec![schema, batch_schema]).map_err(export_error)?;
+                }
+
+                if end {
+                    break;
+                }
+            }
+            Ok(ExportSchema::Parquet(Arc::new(schema)))
+        }
+    }
+}
+
+fn write_csv(
+    path: &Path,
+    columns: &[String],
+    mut batches: mpsc::Receiver<Vec<Map<String, Value>>>,
+) -> Result<(), ViewError> {
+    let mut writer = csv::Writer::from_path(path).map_err(export_error)?;
+    writer.write_record(columns).map_err(export_error)?;
+
+    while let Some(batch) = batches.blocking_recv() {
+        for row in batch {
+            let record = columns.iter().map(|c| match row.get(c) {
+                None | Some(Value::Null) => String::new(),
+                Some(Value::String(s)) => s.clone(),
+                Some(v) => v.to_string(),
+            });
+            writer.write_record(record).map_err(export_error)?;
+        }
+    }
+
+    writer.flush().map_err(export_error)?;
+    Ok(())
+}
+
+fn write_parquet(
+    path: &Path,
+    schema: SchemaRef,
+    mut batches: mpsc::Receiver<Vec<Map<String, Value>>>,
+) -> Result<(), ViewError> {
+    let file = File::create(path).map_err(export_error)?;
+    let mut writer = ArrowWriter::try_new(file, schema.clone(), None).map_err(export_error)?;
+    let mut decoder = arrow_json::ReaderBuilder::new(schema)
+        .with_batch_size(BATCH_SIZE)
+        .build_decoder()
+        .map_err(export_error)?;
+
+    while let Some(batch) = batches.blocking_recv() {
+        decoder.serialize(&batch).map_err(export_error)?;
+        if let Some(record_batch) = decoder.flush().map_err(export_error)? {
+            writer.write(&record_batch).map_err(export_error)?;
+        }
+    }
+
+    writer.close().map_err(export_error)?;
+    Ok(())
+}
+
+fn export_error(e: impl std::error::Error + Send + Sync + 'static) -> ViewError {
+    ViewError::StoreError(Box::new(e))
+}
+
+/// Periodic exports of a view, which are stopped when this is dropped
+pub struct ScheduledExports {
+    exporter: Arc<ViewExporter>,
+    query_id: Arc<str>,
+    tasks: Vec<JoinHandle<()>>,
+}
+
+impl ScheduledExports {
+    pub fn start(
+        exporter: Arc<ViewExporter>,
+        query_id: Arc<str>,
+        schedules: &[ExportSchedule],
+    ) -> Self {
+        let mut result = Self {
+            exporter,
+            query_id,
+            tasks: Vec::new(),
+        };
+        result.reschedule(schedules);
+        result
+    }
+
+    /// Stops the current exports and starts the given ones
+    pub fn reschedule(&mut self, schedules: &[ExportSchedule]) {
+        self.stop();
+        for schedule in schedules {
+            if schedule.interval_seconds == 0 {
+                log::warn!(
+                    "Ignoring export schedule with zero interval for {}",
+                    self.query_id
+                );
+                continue;
+            }
+
+            let exporter = self.exporter.clone();
+            let query_id = self.query_id.clone();
+            let schedule = schedule.clone();
+            self.tasks.push(tokio::spawn(async move {
+                let mut interval =
+                    tokio::time::interval(Duration::from_secs(schedule.interval_seconds));
+                interval.tick().await;
+                loop {
+                    interval.tick().await;
+                    if let Err(err) = exporter
+                        .export_snapshot(&query_id, schedule.format, None)
+                        .await
+                    {
+                        log::error!("Error exporting view {}: {}", query_id, err);
+                    }
+                }
+            }));
+        }
+    }
+
+    fn stop(&mut self) {
+        for task in self.tasks.drain(..) {
+            task.abort();
+        }
+    }
+}
+
+impl Drop for ScheduledExports {
+    fn drop(&mut self) {
+        self.stop();
+    }
+}
+
+#[cfg(test)]
+mod tests;
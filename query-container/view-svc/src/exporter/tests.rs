@@ -0,0 +1,181 @@
+// Copyright 2024 The Drasi Authors.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+#![allow(clippy::unwrap_used)]
+
+use std::{fs::File, sync::Arc};
+
+use parquet::arrow::arrow_reader::ParquetRecordBatchReaderBuilder;
+use serde_json::{json, Value};
+use uuid::Uuid;
+
+use super::{ExportFormat, ViewExporter};
+use crate::{api::ResultChangeEvent, memory_view_store::MemoryViewStore, view_store::ViewStore};
+
+fn change(
+    sequence: u64,
+    ts: u64,
+    added: Value,
+    updated: Value,
+    deleted: Value,
+) -> ResultChangeEvent {
+    serde_json::from_value(json!({
+        "queryId": "q1",
+        "sequence": sequence,
+        "sourceTimeMs": ts,
+        "addedResults": added,
+        "updatedResults": updated,
+        "deletedResults": deleted,
+        "metadata": null
+    }))
+    .unwrap()
+}
+
+/// A view with two rows added at 1000, after which `a` is deleted and `b` updated at 2000
+async fn subject() -> (ViewExporter, std::path::PathBuf) {
+    let path = std::env::temp_dir().join(format!("view-export-{}", Uuid::new_v4()));
+    let store = Arc::new(MemoryViewStore::default());
+    store
+        .init_view(
+            "q1",
+            &serde_json::from_value(json!({ "retentionPolicy": "all" })).unwrap(),
+        )
+        .await
+        .unwrap();
+    store
+        .record_change(
+            "q1",
+            change(
+                1,
+                1000,
+                json!([{"id": "a", "count": 1}, {"id": "b", "count": 2, "tags": ["x", "y"]}]),
+                json!([]),
+                json!([]),
+            ),
+        )
+        .await
+        .unwrap();
+    (ViewExporter::new(store, path.clone()), path)
+}
+
+async fn advance(exporter: &ViewExporter) {
+    exporter
+        .store
+        .record_change(
+            "q1",
+            change(
+                2,
+                2000,
+                json!([]),
+                json!([{
+                    "before": {"id": "b", "count": 2, "tags": ["x", "y"]},
+                    "after": {"id": "b", "count": 3},
+                    "grouping_keys": null
+                }]),
+                json!([{"id": "a", "count": 1}]),
+            ),
+        )
+        .await
+        .unwrap();
+}
+
+#[tokio::test]
+async fn exports_snapshot_to_csv() {
+    let (exporter, path) = subject().await;
+
+    let file = exporter
+        .export_snapshot("q1", ExportFormat::Csv, None)
+        .await
+        .unwrap();
+
+    assert_eq!(file, path.join("q1-1000.csv"));
+    let content = std::fs::read_to_string(&file).unwrap();
+    assert_eq!(
+        content,
+        "id,count,tags\na,1,\nb,2,\"[\"\"x\"\",\"\"y\"\"]\"\n"
+    );
+
+    std::fs::remove_dir_all(path).unwrap();
+}
+
+#[tokio::test]
+async fn exports_snapshot_to_parquet() {
+    let (exporter, path) = subject().await;
+
+    let file = exporter
+        .export_snapshot("q1", ExportFormat::Parquet, None)
+        .await
+        .unwrap();
+
+    let reader = ParquetRecordBatchReaderBuilder::try_new(File::open(&file).unwrap())
+        .unwrap()
+        .build()
+        .unwrap();
+    let batches: Vec<_> = reader.map(|b| b.unwrap()).collect();
+    let schema = batches[0].schema();
+
+    assert_eq!(batches.iter().map(|b| b.num_rows()).sum::<usize>(), 2);
+    assert!(schema.field_with_name("id").is_ok());
+    assert!(schema.field_with_name("count").is_ok());
+    assert!(schema.field_with_name("tags").is_ok());
+
+    std::fs::remove_dir_all(path).unwrap();
+}
+
+#[tokio::test]
+async fn exports_history_with_validity_columns() {
+    let (exporter, path) = subject().await;
+
+    let file = exporter
+        .export_history("q1", ExportFormat::Csv, 0, 2000)
+        .await
+        .unwrap();
+
+    assert_eq!(file, path.join("q1-0-2000.csv"));
+    let content = std::fs::read_to_string(&file).unwrap();
+    let header = content.lines().next().unwrap();
+    assert_eq!(header, "id,count,_validFrom,_validTo,tags");
+
+    std::fs::remove_dir_all(path).unwrap();
+}
+
+#[tokio::test]
+async fn exports_snapshot_at_requested_timestamp() {
+    let (exporter, path) = subject().await;
+    advance(&exporter).await;
+
+    let past = exporter
+        .export_snapshot("q1", ExportFormat::Csv, Some(1500))
+        .await
+        .unwrap();
+
+    assert_eq!(past, path.join("q1-1500.csv"));
+    assert_eq!(
+        std::fs::read_to_string(&past).unwrap(),
+        "id,count,tags\na,1,\nb,2,\"[\"\"x\"\",\"\"y\"\"]\"\n"
+    );
+
+    let current = exporter
+        .export_snapshot("q1", ExportFormat::Csv, None)
+        .await
+        .unwrap();
+
+    assert_eq!(current, path.join("q1-2000.csv"));
+    assert_eq!(
+        std::fs::read_to_string(&current).unwrap(),
+        "id,count\nb,3\n"
+    );
+
+    std::fs::remove_dir_all(path).unwrap();
+}
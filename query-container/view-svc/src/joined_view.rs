@@ -0,0 +1,441 @@
+// Copyright 2024 The Drasi Authors.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::{collections::HashMap, sync::Arc};
+
+use futures::StreamExt;
+use serde_json::{Map, Value};
+
+use crate::{
+    api::{JoinSpec, JoinedQuery, ResultChangeEvent, RetentionPolicy, ViewElement, ViewSpec},
+    models::ViewError,
+    view_store::{ViewStore, ViewStream},
+};
+
+/// The field of a joined row that holds the join key, it is indexed so the rows of a key can be found
+pub const JOIN_KEY_FIELD: &str = "_joinKey";
+
+/// Maintains a view that joins the results of several queries on a common key.
+///
+/// The current results of each joined query are kept in a source view named `{view_id}.{query_id}`,
+/// which is fed from the results stream of that query. When a change arrives, the joined rows of
+/// every join key it touches are recomputed from the source views and the difference is recorded
+/// as a change to the joined view.
+///
+/// Each joined row holds the join key and one field per query, named after the query id,
+/// holding the result of that query for the key, or null if there is none.
+pub struct JoinedView {
+    view_id: Arc<str>,
+    spec: ViewSpec,
+    join: JoinSpec,
+    store: Arc<dyn ViewStore>,
+    sequence: u64,
+    /// The sequence each source view was seeded at, the results at or below it are already in the source view
+    seeded_sequences: HashMap<String, u64>,
+}
+
+impl JoinedView {
+    pub async fn init(
+        view_id: Arc<str>,
+        spec: ViewSpec,
+        store: Arc<dyn ViewStore>,
+    ) -> Result<Self, ViewError> {
+        let join = match &spec.join {
+            Some(join) if !join.queries.is_empty() => join.clone(),
+            _ => {
+                return Err(ViewError::InvalidRequest(
+                    "A joined view must reference at least one query".into(),
+                ))
+            }
+        };
+
+        store.init_view(&view_id, &joined_spec(&spec)).await?;
+        for query in &join.queries {
+            store
+                .init_view(
+                    &source_view_id(&view_id, &query.query_id),
+                    &source_spec(query),
+                )
+                .await?;
+        }
+
+        let sequence = match store.get_view(&view_id, None).await {
+            Ok(mut view) => match view.next().await {
+                Some(ViewElement::Header { sequence, .. }) => sequence,
+                _ => 0,
+            },
+            Err(ViewError::NotFound) => 0,
+            Err(err) => return Err(err),
+        };
+
+        Ok(Self {
+            view_id,
+            spec,
+            join,
+            store,
+            sequence,
+            seeded_sequences: HashMap::new(),
+        })
+    }
+
+    pub fn queries(&self) -> &[JoinedQuery] {
+        &self.join.queries
+    }
+
+    /// Applies a new spec to the joined view, the joined queries cannot be changed once the view exists
+    pub async fn reconfigure(&mut self, spec: ViewSpec) -> Result<(), ViewError> {
+        if spec.join.as_ref() != Some(&self.join) {
+            return Err(ViewError::InvalidRequest(
+                "The queries of a joined view cannot be changed, the view must be recreated".into(),
+            ));
+        }
+
+        self.store
+            .init_view(&self.view_id, &joined_spec(&spec))
+            .await?;
+        self.spec = spec;
+        Ok(())
+    }
+
+    /// Copies the current view of a query into its source view.
+    /// Returns the timestamp of the copied view, or `None` if the query has no view.
+    pub async fn seed_source(&mut self, query_id: &str) -> Result<Option<u64>, ViewError> {
+        let mut view = match self.store.get_view(query_id, None).await {
+            Ok(view) => view,
+            Err(ViewError::NotFound) => {
+                log::info!("No view of {} to seed {} from", query_id, self.view_id);
+                return Ok(None);
+            }
+            Err(err) => return Err(err),
+        };
+
+        let (sequence, timestamp) = match view.next().await {
+            Some(ViewElement::Header {
+                sequence,
+                timestamp,
+                ..
+            }) => (sequence, timestamp),
+            _ => return Err(ViewError::Other("View did not start with a header".into())),
+        };
+
+        let rows = data_rows(view).await;
+        log::info!(
+            "Seeding {} with {} results of {} at sequence {}",
+            self.view_id,
+            rows.len(),
+            query_id,
+            sequence
+        );
+
+        let seed = ResultChangeEvent {
+            query_id: query_id.to_string(),
+            sequence,
+            source_time_ms: timestamp,
+            added_results: rows,
+            updated_results: Vec::new(),
+            deleted_results: Vec::new(),
+            metadata: None,
+        };
+        self.store
+            .rebuild_view(
+                &source_view_id(&self.view_id, query_id),
+                sequence,
+                timestamp,
+                futures::stream::iter([Ok(seed)]).boxed(),
+            )
+            .await?;
+        self.seeded_sequences.insert(query_id.to_string(), sequence);
+
+        Ok(Some(timestamp))
+    }
+
+    /// Records a change to the results of one of the joined queries
+    pub async fn apply_change(
+        &mut self,
+        query_id: &str,
+        change: ResultChangeEvent,
+    ) -> Result<(), ViewError> {
+        let key_field = match self.join.queries.iter().find(|q| q.query_id == query_id) {
+            Some(query) => query.key_field.clone(),
+            None => return Err(ViewError::NotFound),
+        };
+
+        if self
+            .seeded_sequences
+            .get(query_id)
+            .is_some_and(|seeded| change.sequence <= *seeded)
+        {
+            return Ok(());
+        }
+
+        let mut keys: Vec<Value> = Vec::new();
+        let touched = change
+            .added_results
+            .iter()
+            .chain(change.deleted_results.iter())
+            .chain(
+                change
+                    .updated_results
+                    .iter()
+                    .flat_map(|u| u.before.iter().chain(u.after.iter())),
+            );
+        for row in touched {
+            let key = row.get(&key_field).cloned().unwrap_or(Value::Null);
+            if !keys.contains(&key) {
+                keys.push(key);
+            }
+        }
+
+        let ts = change.source_time_ms;
+        self.store
+            .record_change(&source_view_id(&self.view_id, query_id), change)
+            .await?;
+
+        let mut added = Vec::new();
+        let mut deleted = Vec::new();
+        for key in keys {
+            let current = self.read_rows(&self.view_id, JOIN_KEY_FIELD, &key).await?;
+            let joined = self.join_key(&key).await?;
+            deleted.extend(current.iter().filter(|r| !joined.contains(r)).cloned());
+            added.extend(joined.into_iter().filter(|r| !current.contains(r)));
+        }
+
+        self.record(ts, added, deleted).await
+    }
+
+    /// Clears the source view of a query, when it is bootstrapped again or deleted
+    pub async fn reset_source(&mut self, query_id: &str, ts: u64) -> Result<(), ViewError> {
+        let query = match self.join.queries.iter().find(|q| q.query_id == query_id) {
+            Some(query) => query.clone(),
+            None => return Err(ViewError::NotFound),
+        };
+
+        let source_id = source_view_id(&self.view_id, query_id);
+        self.seeded_sequences.remove(query_id);
+        self.store.delete_view(&source_id).await?;
+        self.store
+            .init_view(&source_id, &source_spec(&query))
+            .await?;
+
+        self.rebuild(ts).await
+    }
+
+    /// Recomputes every joined row from the source views and records the difference to the
+    /// current rows, so the history of the joined view is kept under `All` and `Expire` retention
+    pub async fn rebuild(&mut self, ts: u64) -> Result<(), ViewError> {
+        let primary = &self.join.queries[0];
+        let primary_rows = match self
+            .store
+            .get_view(&source_view_id(&self.view_id, &primary.query_id), None)
+            .await
+        {
+            Ok(view) => data_rows(view).await,
+            Err(ViewError::NotFound) => Vec::new(),
+            Err(err) => return Err(err),
+        };
+
+        let mut keys: Vec<Value> = Vec::new();
+        for row in primary_rows {
+            let key = row.get(&primary.key_field).cloned().unwrap_or(Value::Null);
+            if !keys.contains(&key) {
+                keys.push(key);
+            }
+        }
+
+        let mut joined = Vec::new();
+        for key in keys {
+            joined.extend(self.join_key(&key).await?);
+        }
+
+        let current = match self.store.get_view(&self.view_id, None).await {
+            Ok(view) => data_rows(view).await,
+            Err(ViewError::NotFound) => Vec::new(),
+            Err(err) => return Err(err),
+        };
+
+        let deleted: Vec<_> = current
+            .iter()
+            .filter(|r| !joined.contains(r))
+            .cloned()
+            .collect();
+        let added: Vec<_> = joined
+            .into_iter()
+            .filter(|r| !current.contains(r))
+            .collect();
+
+        log::info!(
+            "Rebuilt {}, {} rows added and {} removed",
+            self.view_id,
+            added.len(),
+            deleted.len()
+        );
+        if added.is_empty() && deleted.is_empty() {
+            // there is no change to record, but the view should still exist
+            self.sequence += 1;
+            return self
+                .store
+                .set_state(&self.view_id, self.sequence, ts, "running")
+                .await;
+        }
+
+        self.record(ts, added, deleted).await
+    }
+
+    /// Returns a joined row for each result of the primary query with the given key
+    async fn join_key(&self, key: &Value) -> Result<Vec<Map<String, Value>>, ViewError> {
+        let primary = &self.join.queries[0];
+        let primary_rows = self
+            .read_rows(
+                &source_view_id(&self.view_id, &primary.query_id),
+                &primary.key_field,
+                key,
+            )
+            .await?;
+
+        if primary_rows.is_empty() {
+            return Ok(Vec::new());
+        }
+
+        let mut matches = Vec::new();
+        for query in &self.join.queries[1..] {
+            let matched = match key {
+                Value::Null => Value::Null,
+                _ => {
+                    let rows = self
+                        .read_rows(
+                            &source_view_id(&self.view_id, &query.query_id),
+                            &query.key_field,
+                            key,
+                        )
+                        .await?;
+                    if rows.len() > 1 {
+                        log::warn!(
+                            "{} has {} results with join key {}, joining the first",
+                            query.query_id,
+                            rows.len(),
+                            key
+                        );
+                    }
+                    match rows.into_iter().next() {
+                        Some(row) => Value::Object(row),
+                        None => Value::Null,
+                    }
+                }
+            };
+            matches.push((query.query_id.clone(), matched));
+        }
+
+        Ok(primary_rows
+            .into_iter()
+            .map(|row| {
+                let mut joined = Map::new();
+                joined.insert(JOIN_KEY_FIELD.to_string(), key.clone());
+                joined.insert(primary.query_id.clone(), Value::Object(row));
+                for (query_id, matched) in &matches {
+                    joined.insert(query_id.clone(), matched.clone());
+                }
+                joined
+            })
+            .collect())
+    }
+
+    async fn read_rows(
+        &self,
+        view_id: &str,
+        field: &str,
+        key: &Value,
+    ) -> Result<Vec<Map<String, Value>>, ViewError> {
+        let mut filter = Map::new();
+        filter.insert(field.to_string(), key.clone());
+
+        match self.store.get_rows(view_id, None, filter, None).await {
+            Ok(view) => Ok(data_rows(view).await),
+            Err(ViewError::NotFound) => Ok(Vec::new()),
+            Err(err) => Err(err),
+        }
+    }
+
+    async fn record(
+        &mut self,
+        ts: u64,
+        added: Vec<Map<String, Value>>,
+        deleted: Vec<Map<String, Value>>,
+    ) -> Result<(), ViewError> {
+        if added.is_empty() && deleted.is_empty() {
+            return Ok(());
+        }
+
+        self.sequence += 1;
+        self.store
+            .record_change(
+                &self.view_id,
+                ResultChangeEvent {
+                    query_id: self.view_id.to_string(),
+                    sequence: self.sequence,
+                    source_time_ms: ts,
+                    added_results: added,
+                    updated_results: Vec::new(),
+                    deleted_results: deleted,
+                    metadata: None,
+                },
+            )
+            .await
+    }
+}
+
+/// The id of the view that holds the current results of a query, for a joined view
+pub fn source_view_id(view_id: &str, query_id: &str) -> String {
+    format!("{}.{}", view_id, query_id)
+}
+
+fn joined_spec(spec: &ViewSpec) -> ViewSpec {
+    let mut index_fields = vec![JOIN_KEY_FIELD.to_string()];
+    for field in &spec.index_fields {
+        if !index_fields.contains(field) {
+            index_fields.push(field.clone());
+        }
+    }
+
+    ViewSpec {
+        retention_policy: spec.retention_policy,
+        index_fields,
+        key_fields: spec.key_fields.clone(),
+        exports: spec.exports.clone(),
+        join: None,
+    }
+}
+
+fn source_spec(query: &JoinedQuery) -> ViewSpec {
+    ViewSpec {
+        retention_policy: RetentionPolicy::Latest,
+        index_fields: vec![query.key_field.clone()],
+        key_fields: Vec::new(),
+        exports: Vec::new(),
+        join: None,
+    }
+}
+
+async fn data_rows(view: ViewStream) -> Vec<Map<String, Value>> {
+    view.filter_map(|item| async move {
+        match item {
+            ViewElement::Data(data) => Some(data),
+            ViewElement::Header { .. } => None,
+        }
+    })
+    .collect()
+    .await
+}
+
+#[cfg(test)]
+mod tests;
@@ -0,0 +1,409 @@
+// Copyright 2024 The Drasi Authors.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+#![allow(clippy::unwrap_used)]
+
+use std::sync::Arc;
+
+use futures::StreamExt;
+use serde_json::{json, Map, Value};
+
+use super::{source_view_id, JoinedView};
+use crate::{
+    api::{
+        JoinSpec, JoinedQuery, ResultChangeEvent, RetentionPolicy, UpdatePayload, ViewElement,
+        ViewSpec,
+    },
+    memory_view_store::MemoryViewStore,
+    view_store::ViewStore,
+};
+
+fn spec() -> ViewSpec {
+    ViewSpec {
+        retention_policy: RetentionPolicy::Latest,
+        index_fields: Vec::new(),
+        key_fields: vec!["_joinKey".to_string()],
+        exports: Vec::new(),
+        join: Some(JoinSpec {
+            queries: vec![
+                JoinedQuery {
+                    query_id: "orders".to_string(),
+                    key_field: "driverId".to_string(),
+                },
+                JoinedQuery {
+                    query_id: "drivers".to_string(),
+                    key_field: "id".to_string(),
+                },
+            ],
+        }),
+    }
+}
+
+fn row(value: Value) -> Map<String, Value> {
+    value.as_object().unwrap().clone()
+}
+
+fn change(
+    query_id: &str,
+    sequence: u64,
+    added: Vec<Value>,
+    deleted: Vec<Value>,
+) -> ResultChangeEvent {
+    ResultChangeEvent {
+        query_id: query_id.to_string(),
+        sequence,
+        source_time_ms: sequence * 100,
+        added_results: added.into_iter().map(row).collect(),
+        updated_results: Vec::new(),
+        deleted_results: deleted.into_iter().map(row).collect(),
+        metadata: None,
+    }
+}
+
+async fn subject() -> (JoinedView, Arc<MemoryViewStore>) {
+    subject_with(spec()).await
+}
+
+async fn subject_with(spec: ViewSpec) -> (JoinedView, Arc<MemoryViewStore>) {
+    let store = Arc::new(MemoryViewStore::default());
+    let view = JoinedView::init("v1".into(), spec, store.clone())
+        .await
+        .unwrap();
+    (view, store)
+}
+
+#[tokio::test]
+async fn joins_results_on_key() {
+    let (mut view, store) = subject().await;
+
+    view.apply_change(
+        "orders",
+        change(
+            "orders",
+            1,
+            vec![json!({"id": "o1", "driverId": "d1"})],
+            vec![],
+        ),
+    )
+    .await
+    .unwrap();
+
+    assert_eq!(
+        store.rows("v1").await,
+        vec![json!({"_joinKey": "d1", "orders": {"id": "o1", "driverId": "d1"}, "drivers": null})]
+    );
+
+    view.apply_change(
+        "drivers",
+        change(
+            "drivers",
+            1,
+            vec![json!({"id": "d1", "name": "Ann"})],
+            vec![],
+        ),
+    )
+    .await
+    .unwrap();
+
+    assert_eq!(
+        store.rows("v1").await,
+        vec![json!({
+            "_joinKey": "d1",
+            "orders": {"id": "o1", "driverId": "d1"},
+            "drivers": {"id": "d1", "name": "Ann"}
+        })]
+    );
+}
+
+#[tokio::test]
+async fn updates_every_row_with_the_key() {
+    let (mut view, store) = subject().await;
+
+    view.apply_change(
+        "orders",
+        change(
+            "orders",
+            1,
+            vec![
+                json!({"id": "o1", "driverId": "d1"}),
+                json!({"id": "o2", "driverId": "d1"}),
+                json!({"id": "o3", "driverId": "d2"}),
+            ],
+            vec![],
+        ),
+    )
+    .await
+    .unwrap();
+
+    view.apply_change(
+        "drivers",
+        ResultChangeEvent {
+            updated_results: vec![UpdatePayload {
+                before: None,
+                after: Some(row(json!({"id": "d1", "name": "Ann"}))),
+                grouping_keys: None,
+            }],
+            ..change("drivers", 1, vec![], vec![])
+        },
+    )
+    .await
+    .unwrap();
+
+    let rows = store.rows("v1").await;
+    assert_eq!(rows.len(), 3);
+    for row in rows {
+        let expected = match row["_joinKey"].as_str().unwrap() {
+            "d1" => json!({"id": "d1", "name": "Ann"}),
+            _ => Value::Null,
+        };
+        assert_eq!(row["drivers"], expected);
+    }
+}
+
+#[tokio::test]
+async fn removes_rows_when_primary_result_is_deleted() {
+    let (mut view, store) = subject().await;
+
+    view.apply_change(
+        "drivers",
+        change(
+            "drivers",
+            1,
+            vec![json!({"id": "d1", "name": "Ann"})],
+            vec![],
+        ),
+    )
+    .await
+    .unwrap();
+    view.apply_change(
+        "orders",
+        change(
+            "orders",
+            1,
+            vec![json!({"id": "o1", "driverId": "d1"})],
+            vec![],
+        ),
+    )
+    .await
+    .unwrap();
+    assert_eq!(store.rows("v1").await.len(), 1);
+
+    view.apply_change(
+        "orders",
+        change(
+            "orders",
+            2,
+            vec![],
+            vec![json!({"id": "o1", "driverId": "d1"})],
+        ),
+    )
+    .await
+    .unwrap();
+
+    assert!(store.rows("v1").await.is_empty());
+}
+
+#[tokio::test]
+async fn ignores_changes_already_in_the_source_view() {
+    let (mut view, store) = subject().await;
+    let evt = || {
+        change(
+            "orders",
+            1,
+            vec![json!({"id": "o1", "driverId": "d1"})],
+            vec![],
+        )
+    };
+
+    view.apply_change("orders", evt()).await.unwrap();
+    view.apply_change("orders", evt()).await.unwrap();
+
+    assert_eq!(store.rows("v1").await.len(), 1);
+}
+
+#[tokio::test]
+async fn reset_source_clears_its_results_from_the_join() {
+    let (mut view, store) = subject().await;
+
+    view.apply_change(
+        "drivers",
+        change(
+            "drivers",
+            1,
+            vec![json!({"id": "d1", "name": "Ann"})],
+            vec![],
+        ),
+    )
+    .await
+    .unwrap();
+    view.apply_change(
+        "orders",
+        change(
+            "orders",
+            1,
+            vec![json!({"id": "o1", "driverId": "d1"})],
+            vec![],
+        ),
+    )
+    .await
+    .unwrap();
+
+    view.reset_source("drivers", 500).await.unwrap();
+
+    assert_eq!(
+        store.rows("v1").await,
+        vec![json!({"_joinKey": "d1", "orders": {"id": "o1", "driverId": "d1"}, "drivers": null})]
+    );
+    assert!(store
+        .rows(&source_view_id("v1", "drivers"))
+        .await
+        .is_empty());
+}
+
+#[tokio::test]
+async fn seeds_source_from_query_view() {
+    let (mut view, store) = subject().await;
+    store.init_view("orders", &spec()).await.unwrap();
+    store
+        .record_change(
+            "orders",
+            change(
+                "orders",
+                7,
+                vec![json!({"id": "o1", "driverId": "d1"})],
+                vec![],
+            ),
+        )
+        .await
+        .unwrap();
+
+    let ts = view.seed_source("orders").await.unwrap();
+    assert_eq!(ts, Some(700));
+    assert_eq!(view.seed_source("drivers").await.unwrap(), None);
+
+    view.rebuild(700).await.unwrap();
+    assert_eq!(store.rows("v1").await.len(), 1);
+
+    // changes up to the sequence of the seeded view are already included
+    view.apply_change(
+        "orders",
+        change(
+            "orders",
+            7,
+            vec![json!({"id": "o2", "driverId": "d1"})],
+            vec![],
+        ),
+    )
+    .await
+    .unwrap();
+    assert_eq!(store.rows("v1").await.len(), 1);
+}
+
+#[tokio::test]
+async fn joined_view_keeps_key_fields_of_spec() {
+    let (mut view, store) = subject().await;
+
+    view.apply_change(
+        "orders",
+        change(
+            "orders",
+            1,
+            vec![
+                json!({"id": "o1", "driverId": "d1"}),
+                json!({"id": "o2", "driverId": "d2"}),
+            ],
+            vec![],
+        ),
+    )
+    .await
+    .unwrap();
+
+    let rows: Vec<_> = store
+        .get_rows("v1", Some(vec![json!("d2")]), Map::new(), None)
+        .await
+        .unwrap()
+        .filter_map(|item| async move {
+            match item {
+                ViewElement::Data(data) => Some(Value::Object(data)),
+                ViewElement::Header { .. } => None,
+            }
+        })
+        .collect()
+        .await;
+
+    assert_eq!(
+        rows,
+        vec![json!({"_joinKey": "d2", "orders": {"id": "o2", "driverId": "d2"}, "drivers": null})]
+    );
+}
+
+#[tokio::test]
+async fn rebuild_keeps_history_of_joined_view() {
+    let (mut view, store) = subject_with(ViewSpec {
+        retention_policy: RetentionPolicy::All,
+        ..spec()
+    })
+    .await;
+
+    view.apply_change(
+        "orders",
+        change(
+            "orders",
+            1,
+            vec![json!({"id": "o1", "driverId": "d1"})],
+            vec![],
+        ),
+    )
+    .await
+    .unwrap();
+    view.apply_change(
+        "drivers",
+        change(
+            "drivers",
+            2,
+            vec![json!({"id": "d1", "name": "Ann"})],
+            vec![],
+        ),
+    )
+    .await
+    .unwrap();
+
+    view.reset_source("drivers", 500).await.unwrap();
+
+    let history: Vec<Map<String, Value>> = store
+        .get_view_history("v1", 0, 1000)
+        .await
+        .unwrap()
+        .collect()
+        .await;
+    let validity: Vec<(Value, Value, Value)> = history
+        .iter()
+        .map(|r| {
+            (
+                r["drivers"].clone(),
+                r["_validFrom"].clone(),
+                r["_validTo"].clone(),
+            )
+        })
+        .collect();
+
+    assert_eq!(
+        validity,
+        vec![
+            (Value::Null, json!(100), json!(199)),
+            (json!({"id": "d1", "name": "Ann"}), json!(200), json!(499)),
+            (Value::Null, json!(500), Value::Null),
+        ]
+    );
+}
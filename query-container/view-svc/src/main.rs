@@ -12,7 +12,7 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
-use std::{env, sync::Arc, time::Duration};
+use std::{env, path::PathBuf, sync::Arc, time::Duration};
 
 use opentelemetry::{metrics, sdk::Resource, trace::TraceError, KeyValue};
 use opentelemetry_otlp::{ExportConfig, WithExportConfig};
@@ -28,10 +28,14 @@ use tracing_subscriber::{
     Layer, Registry,
 };
 
-use crate::view_actor::ViewActor;
+use crate::{exporter::ViewExporter, view_actor::ViewActor};
 
 mod api;
 mod change_stream;
+mod exporter;
+mod joined_view;
+#[cfg(test)]
+mod memory_view_store;
 mod models;
 mod mongo_view_store;
 mod view_actor;
@@ -65,13 +69,25 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
         },
         buffer_size: 20,
         fetch_batch_size: 5,
+        watch_block_ms: 5000,
     });
 
     let actor_name = format!("{}.View", query_container_id);
 
     let view_store = view_store_factory::from_env().await?;
 
-    tokio::spawn(api::start_view_service(view_store.clone(), 80));
+    let export_path = env::var("EXPORT_PATH").unwrap_or("/exports".to_string());
+    let exporter = Arc::new(ViewExporter::new(
+        view_store.clone(),
+        PathBuf::from(export_path),
+    ));
+
+    tokio::spawn(api::start_view_service(
+        view_store.clone(),
+        stream_config.clone(),
+        exporter.clone(),
+        80,
+    ));
 
     dapr_server
         .register_actor(
@@ -83,6 +99,7 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
                         view_store.clone(),
                         dapr_client,
                         stream_config.clone(),
+                        exporter.clone(),
                     ))
                 }),
             )
@@ -0,0 +1,310 @@
+// Copyright 2024 The Drasi Authors.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+#![allow(clippy::unwrap_used)]
+
+use std::collections::HashMap;
+
+use async_trait::async_trait;
+use futures::StreamExt;
+use serde_json::{Map, Value};
+use tokio::sync::{watch, Mutex};
+
+use crate::{
+    api::{ResultChangeEvent, RetentionPolicy, ViewElement, ViewSpec},
+    models::ViewError,
+    view_store::{lookup_filter, ChangeStream, RowStream, ViewStore, ViewStream},
+};
+
+/// A view store that keeps every version of the rows of its views in memory, used by tests
+#[derive(Default)]
+pub struct MemoryViewStore {
+    views: Mutex<HashMap<String, MemoryView>>,
+    rebuilds: std::sync::Mutex<HashMap<String, watch::Sender<u64>>>,
+}
+
+#[derive(Default)]
+struct MemoryView {
+    spec: Option<ViewSpec>,
+    sequence: Option<u64>,
+    timestamp: u64,
+    state: Option<String>,
+    versions: Vec<Version>,
+    /// The grouping keys of the first aggregation update, used as key fields when the spec declares none
+    grouping_keys: Option<Vec<String>>,
+}
+
+#[derive(Clone)]
+struct Version {
+    row: Map<String, Value>,
+    valid_from: u64,
+    valid_to: Option<u64>,
+}
+
+impl Version {
+    fn is_valid_at(&self, ts: u64) -> bool {
+        self.valid_from <= ts && self.valid_to.is_none_or(|to| to >= ts)
+    }
+
+    fn matches(&self, row: &Map<String, Value>, grouping_keys: &Option<Vec<String>>) -> bool {
+        match grouping_keys {
+            Some(keys) => keys.iter().all(|k| self.row.get(k) == row.get(k)),
+            None => &self.row == row,
+        }
+    }
+}
+
+impl MemoryView {
+    fn apply(&mut self, change: ResultChangeEvent, ts: u64) {
+        for del in change.deleted_results {
+            self.close(&del, &None, ts);
+        }
+
+        for update in change.updated_results {
+            if self.grouping_keys.is_none() {
+                self.grouping_keys = update.grouping_keys.clone();
+            }
+            if let Some(before) = &update.before {
+                self.close(before, &update.grouping_keys, ts);
+            }
+            if let Some(after) = update.after {
+                self.close(&after, &update.grouping_keys, ts);
+                self.versions.push(Version {
+                    row: after,
+                    valid_from: ts,
+                    valid_to: None,
+                });
+            }
+        }
+
+        for add in change.added_results {
+            self.close(&add, &None, ts);
+            self.versions.push(Version {
+                row: add,
+                valid_from: ts,
+                valid_to: None,
+            });
+        }
+    }
+
+    fn close(&mut self, row: &Map<String, Value>, grouping_keys: &Option<Vec<String>>, ts: u64) {
+        for version in &mut self.versions {
+            if version.valid_to.is_none() && version.matches(row, grouping_keys) {
+                version.valid_to = Some(ts.saturating_sub(1));
+            }
+        }
+        if matches!(
+            self.spec.as_ref().map(|s| s.retention_policy),
+            Some(RetentionPolicy::Latest)
+        ) {
+            self.versions.retain(|v| v.valid_to.is_none());
+        }
+    }
+
+    fn elements(
+        &self,
+        timestamp: Option<u64>,
+        filter: impl Fn(&Map<String, Value>) -> bool,
+    ) -> Result<ViewStream, ViewError> {
+        let sequence = self.sequence.ok_or(ViewError::NotFound)?;
+        let effective_at = timestamp.map_or(self.timestamp, |ts| ts.min(self.timestamp));
+        let mut items = vec![ViewElement::Header {
+            sequence,
+            timestamp: self.timestamp,
+            state: self.state.clone(),
+        }];
+        items.extend(
+            self.versions
+                .iter()
+                .filter(|v| v.is_valid_at(effective_at) && filter(&v.row))
+                .map(|v| ViewElement::Data(v.row.clone())),
+        );
+        Ok(futures::stream::iter(items).boxed())
+    }
+}
+
+impl MemoryViewStore {
+    /// The current rows of a view, or an empty list if the view does not exist
+    pub async fn rows(&self, query_id: &str) -> Vec<Value> {
+        let views = self.views.lock().await;
+        views
+            .get(query_id)
+            .map(|v| {
+                v.versions
+                    .iter()
+                    .filter(|v| v.valid_to.is_none())
+                    .map(|v| Value::Object(v.row.clone()))
+                    .collect()
+            })
+            .unwrap_or_default()
+    }
+
+    fn rebuild_sender<T>(&self, query_id: &str, f: impl FnOnce(&watch::Sender<u64>) -> T) -> T {
+        let mut rebuilds = self.rebuilds.lock().unwrap();
+        f(rebuilds
+            .entry(query_id.to_string())
+            .or_insert_with(|| watch::channel(0).0))
+    }
+}
+
+#[async_trait]
+impl ViewStore for MemoryViewStore {
+    async fn init_view(&self, query_id: &str, spec: &ViewSpec) -> Result<(), ViewError> {
+        let mut views = self.views.lock().await;
+        views.entry(query_id.to_string()).or_default().spec = Some(spec.clone());
+        Ok(())
+    }
+
+    async fn set_retention_policy(
+        &self,
+        query_id: &str,
+        policy: RetentionPolicy,
+    ) -> Result<(), ViewError> {
+        let mut views = self.views.lock().await;
+        let view = views.get_mut(query_id).ok_or(ViewError::NotFound)?;
+        if let Some(spec) = &mut view.spec {
+            spec.retention_policy = policy;
+        }
+        Ok(())
+    }
+
+    async fn delete_view(&self, query_id: &str) -> Result<(), ViewError> {
+        self.views.lock().await.remove(query_id);
+        Ok(())
+    }
+
+    async fn record_change(
+        &self,
+        query_id: &str,
+        change: ResultChangeEvent,
+    ) -> Result<(), ViewError> {
+        let mut views = self.views.lock().await;
+        let view = views.get_mut(query_id).ok_or(ViewError::NotFound)?;
+        let ts = change.source_time_ms;
+        view.sequence = Some(change.sequence);
+        view.timestamp = ts;
+        view.apply(change, ts);
+        Ok(())
+    }
+
+    async fn get_view(
+        &self,
+        query_id: &str,
+        timestamp: Option<u64>,
+    ) -> Result<ViewStream, ViewError> {
+        let views = self.views.lock().await;
+        let view = views.get(query_id).ok_or(ViewError::NotFound)?;
+        view.elements(timestamp, |_| true)
+    }
+
+    async fn get_view_history(
+        &self,
+        query_id: &str,
+        from: u64,
+        to: u64,
+    ) -> Result<RowStream, ViewError> {
+        let views = self.views.lock().await;
+        let view = views.get(query_id).ok_or(ViewError::NotFound)?;
+        let rows: Vec<Map<String, Value>> = view
+            .versions
+            .iter()
+            .filter(|v| v.valid_from <= to && v.valid_to.is_none_or(|t| t >= from))
+            .map(|v| {
+                let mut row = v.row.clone();
+                row.insert("_validFrom".to_string(), Value::from(v.valid_from));
+                row.insert(
+                    "_validTo".to_string(),
+                    v.valid_to.map_or(Value::Null, Value::from),
+                );
+                row
+            })
+            .collect();
+        Ok(futures::stream::iter(rows).boxed())
+    }
+
+    async fn get_rows(
+        &self,
+        query_id: &str,
+        key: Option<Vec<Value>>,
+        filter: Map<String, Value>,
+        timestamp: Option<u64>,
+    ) -> Result<ViewStream, ViewError> {
+        let views = self.views.lock().await;
+        let view = views.get(query_id).ok_or(ViewError::NotFound)?;
+        let spec = view.spec.clone().unwrap_or_else(|| ViewSpec {
+            retention_policy: RetentionPolicy::Latest,
+            index_fields: Vec::new(),
+            key_fields: Vec::new(),
+            exports: Vec::new(),
+            join: None,
+        });
+        let key_fields = match &view.grouping_keys {
+            Some(grouping_keys) if spec.key_fields.is_empty() => grouping_keys.clone(),
+            _ => spec.key_fields,
+        };
+        let values = lookup_filter(query_id, &key_fields, &spec.index_fields, key, filter)?;
+        view.elements(timestamp, |row| {
+            values.iter().all(|(f, v)| row.get(f) == Some(v))
+        })
+    }
+
+    async fn rebuild_view(
+        &self,
+        query_id: &str,
+        sequence: u64,
+        ts: u64,
+        mut changes: ChangeStream,
+    ) -> Result<(), ViewError> {
+        let mut rebuilt = MemoryView::default();
+        while let Some(change) = changes.next().await {
+            rebuilt.apply(change?, ts);
+        }
+
+        let mut views = self.views.lock().await;
+        let view = views.get_mut(query_id).ok_or(ViewError::NotFound)?;
+        for version in &mut view.versions {
+            if version.valid_to.is_none() {
+                version.valid_to = Some(ts.saturating_sub(1));
+            }
+        }
+        view.versions.extend(rebuilt.versions);
+        if view.grouping_keys.is_none() {
+            view.grouping_keys = rebuilt.grouping_keys;
+        }
+        view.sequence = Some(sequence);
+        view.timestamp = ts;
+        drop(views);
+
+        self.rebuild_sender(query_id, |tx| tx.send_replace(sequence));
+        Ok(())
+    }
+
+    fn rebuilt_sequence(&self, query_id: &str) -> watch::Receiver<u64> {
+        self.rebuild_sender(query_id, |tx| tx.subscribe())
+    }
+
+    async fn set_state(
+        &self,
+        query_id: &str,
+        sequence: u64,
+        ts: u64,
+        state: &str,
+    ) -> Result<(), ViewError> {
+        let mut views = self.views.lock().await;
+        let view = views.entry(query_id.to_string()).or_default();
+        view.sequence = Some(sequence);
+        view.timestamp = ts;
+        view.state = Some(state.to_string());
+        Ok(())
+    }
+}
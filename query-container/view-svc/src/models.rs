@@ -19,6 +19,7 @@ pub struct ChangeStreamConfig {
     pub redis_url: String,
     pub buffer_size: usize,
     pub fetch_batch_size: usize,
+    pub watch_block_ms: usize,
 }
 
 #[derive(Debug, Error)]
@@ -31,4 +32,7 @@ pub enum ViewError {
 
     #[error("Not Found")]
     NotFound,
+
+    #[error("Invalid request: {0}")]
+    InvalidRequest(String),
 }
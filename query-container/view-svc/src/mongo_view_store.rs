@@ -23,28 +23,72 @@ use async_stream::stream;
 use async_trait::async_trait;
 use futures::StreamExt;
 use mongodb::{
-    bson::{doc, Uuid},
+    bson::{doc, Document, Uuid},
     options, IndexModel,
 };
 use serde::{Deserialize, Serialize};
 use serde_json::{Map, Value};
 use siphasher::sip128::Hasher128;
-use tokio::sync::RwLock;
+use tokio::sync::{watch, RwLock};
 
 use crate::{
-    api::{ResultChangeEvent, RetentionPolicy, ViewElement},
+    api::{ResultChangeEvent, RetentionPolicy, ViewElement, ViewSpec},
     models::ViewError,
-    view_store::{ViewStore, ViewStream},
+    view_store::{lookup_filter, ChangeStream, RowStream, ViewStore, ViewStream},
 };
 
 const MAX_TIMESTAMP: i64 = 253402300799999;
+/// The validity of the rows of a view that is being rebuilt, which no view or history query selects
+const STAGED_TIMESTAMP: i64 = MAX_TIMESTAMP + 1;
+const REBUILD_BATCH_SIZE: usize = 1000;
 
 pub struct MongoViewStore {
     database: mongodb::Database,
     retention_policy: RwLock<HashMap<String, RetentionPolicy>>,
+    lookup_fields: RwLock<HashMap<String, LookupFields>>,
+    rebuilds: Mutex<HashMap<String, watch::Sender<u64>>>,
     gc_task: Mutex<Option<tokio::task::JoinHandle<()>>>,
 }
 
+#[derive(Clone)]
+struct LookupFields {
+    index_fields: Vec<String>,
+    key_fields: Vec<String>,
+    /// The grouping keys of an aggregation, used as key fields when none are declared
+    grouping_keys: Option<Vec<String>>,
+}
+
+impl LookupFields {
+    fn key_fields(&self) -> &[String] {
+        match &self.grouping_keys {
+            Some(grouping_keys) if self.key_fields.is_empty() => grouping_keys,
+            _ => &self.key_fields,
+        }
+    }
+
+    /// The indexes on result fields, with the names Mongo gives them
+    fn indexes(&self) -> Vec<(String, Document)> {
+        let key_fields = self.key_fields();
+        let mut indexes = Vec::new();
+
+        if !key_fields.is_empty() {
+            indexes.push(result_index_keys(key_fields));
+        }
+
+        for field in &self.index_fields {
+            if key_fields.len() == 1 && key_fields[0] == *field {
+                continue;
+            }
+            indexes.push(result_index_keys(std::slice::from_ref(field)));
+        }
+
+        indexes
+            .into_iter()
+            .map(|keys| (index_name(&keys), keys))
+            .collect()
+    }
+}
+
 impl MongoViewStore {
     pub async fn connect(
         mongo_uri: &str,
@@ -56,6 +100,8 @@ impl MongoViewStore {
         let result = Arc::new(MongoViewStore {
             database,
             retention_policy: RwLock::new(HashMap::new()),
+            lookup_fields: RwLock::new(HashMap::new()),
+            rebuilds: Mutex::new(HashMap::new()),
             gc_task: Mutex::new(None),
         });
 
@@ -70,6 +116,199 @@ impl MongoViewStore {
         result.gc_task.lock().unwrap().replace(gc_task);
         Ok(result)
     }
+
+    async fn query_view(
+        &self,
+        query_id: &str,
+        timestamp: Option<u64>,
+        mut filter: Document,
+    ) -> Result<ViewStream, ViewError> {
+        let ret_policy = self.retention_policy.read().await;
+        let policy = match ret_policy.get(query_id) {
+            Some(p) => *p,
+            None => return Err(ViewError::NotFound),
+        };
+        drop(ret_policy);
+
+        let collection = self.database.collection::<ViewItem>(query_id);
+
+        let metadata = collection
+            .find_one(
+                doc! {
+                    "_id": "$metadata"
+                },
+                None,
+            )
+            .await;
+
+        let metadata = match metadata {
+            Ok(Some(ViewItem::Metadata(m))) => m,
+            Ok(_) => return Err(ViewError::NotFound),
+            Err(err) => return Err(ViewError::StoreError(Box::new(err))),
+        };
+
+        let now = SystemTime::now()
+            .duration_since(SystemTime::UNIX_EPOCH)
+            .unwrap()
+            .as_millis() as u64;
+        let timestamp = timestamp.unwrap_or(now) as i64;
+        let effective_at = std::cmp::min(metadata.ts, timestamp);
+
+        if let Some(gap) = metadata
+            .gaps
+            .iter()
+            .find(|g| g.from < effective_at && effective_at < g.to)
+        {
+            return Err(ViewError::InvalidRequest(format!(
+                "View history is unavailable between {} and {}",
+                gap.from, gap.to
+            )));
+        }
+
+        if let RetentionPolicy::Expire { after_seconds } = policy {
+            if ((now - (after_seconds * 1000)) as i64) > timestamp {
+                return Err(ViewError::NotFound);
+            }
+        }
+
+        filter.insert("validFrom", doc! { "$lte": effective_at });
+        filter.insert("validTo", doc! { "$gte": effective_at });
+
+        let data = collection.find(filter, None).await;
+
+        let mut data = match data {
+            Ok(d) => d,
+            Err(err) => return Err(ViewError::StoreError(Box::new(err))),
+        };
+
+        Ok(stream! {
+            yield ViewElement::Header{
+                sequence: metadata.seq as u64,
+                timestamp: metadata.ts as u64,
+                state: metadata.state.clone(),
+            };
+
+            while let Some(doc) = data.next().await {
+                match doc {
+                    Ok(ViewItem::View(v)) => {
+                        yield ViewElement::Data(v.result);
+                    },
+                    Ok(ViewItem::Metadata(_)) => {},
+                    Err(err) => {
+                        log::error!("error reading from view: {:?}", err);
+                    }
+                }
+            }
+        }
+        .boxed())
+    }
+
+    /// Uses the grouping keys of an aggregation as the key fields of a view that declares none.
+    /// They are recorded in the view metadata, so they are known again after a restart.
+    /// Writes the rows produced by `changes` to the view as staged rows, in batches, replacing the staged rows
+    /// left by a rebuild that did not complete
+    async fn stage_rows(
+        &self,
+        query_id: &str,
+        collection: &mongodb::Collection<ViewItem>,
+        changes: &mut ChangeStream,
+    ) -> Result<(), ViewError> {
+        if let Err(e) = collection
+            .delete_many(doc! { "validTo": STAGED_TIMESTAMP }, None)
+            .await
+        {
+            return Err(ViewError::StoreError(Box::new(e)));
+        }
+
+        let mut rows = HashMap::new();
+        while let Some(change) = changes.next().await {
+            let change = change?;
+            self.derive_key_fields(query_id, collection, &change)
+                .await?;
+
+            let removed = apply_to_rows(&mut rows, change);
+            if !removed.is_empty() {
+                let hashes: Vec<Uuid> = removed.into_iter().map(Uuid::from_bytes).collect();
+                if let Err(e) = collection
+                    .delete_many(
+                        doc! { "hash": { "$in": hashes }, "validTo": STAGED_TIMESTAMP },
+                        None,
+                    )
+                    .await
+                {
+                    return Err(ViewError::StoreError(Box::new(e)));
+                }
+            }
+
+            if rows.len() >= REBUILD_BATCH_SIZE {
+                write_staged_rows(collection, std::mem::take(&mut rows)).await?;
+            }
+        }
+
+        write_staged_rows(collection, rows).await
+    }
+
+    async fn derive_key_fields(
+        &self,
+        query_id: &str,
+        collection: &mongodb::Collection<ViewItem>,
+        change: &ResultChangeEvent,
+    ) -> Result<(), ViewError> {
+        let grouping_keys = match change
+            .updated_results
+            .iter()
+            .find_map(|u| u.grouping_keys.as_ref())
+        {
+            Some(grouping_keys) if !grouping_keys.is_empty() => grouping_keys,
+            _ => return Ok(()),
+        };
+
+        let needed = self
+            .lookup_fields
+            .read()
+            .await
+            .get(query_id)
+            .is_some_and(|l| l.key_fields.is_empty() && l.grouping_keys.is_none());
+        if !needed {
+            return Ok(());
+        }
+
+        let mut lookup_fields = self.lookup_fields.write().await;
+        let indexes = match lookup_fields.get_mut(query_id) {
+            Some(lookup) => {
+                lookup.grouping_keys = Some(grouping_keys.clone());
+                lookup.indexes()
+            }
+            None => return Ok(()),
+        };
+        drop(lookup_fields);
+
+        log::info!(
+            "Using grouping keys {:?} as the key fields of {}",
+            grouping_keys,
+            query_id
+        );
+
+        let indexes = indexes
+            .into_iter()
+            .map(|(_, keys)| IndexModel::builder().keys(keys).build());
+        if let Err(e) = collection.create_indexes(indexes, None).await {
+            return Err(ViewError::StoreError(Box::new(e)));
+        }
+
+        let result = collection
+            .update_one(
+                doc! { "_id": "$metadata" },
+                doc! { "$set": doc! { "groupingKeys": grouping_keys } },
+                options::UpdateOptions::builder().upsert(true).build(),
+            )
+            .await;
+
+        match result {
+            Ok(_) => Ok(()),
+            Err(e) => Err(ViewError::StoreError(Box::new(e))),
+        }
+    }
 }
 
 impl Drop for MongoViewStore {
@@ -83,11 +322,29 @@ impl Drop for MongoViewStore {
 
 #[async_trait]
 impl ViewStore for MongoViewStore {
-    async fn init_view(&self, query_id: &str, policy: RetentionPolicy) -> Result<(), ViewError> {
-        self.set_retention_policy(query_id, policy).await?;
+    async fn init_view(&self, query_id: &str, spec: &ViewSpec) -> Result<(), ViewError> {
+        self.set_retention_policy(query_id, spec.retention_policy)
+            .await?;
 
         let collection = self.database.collection::<ViewItem>(query_id);
 
+        let grouping_keys = match collection.find_one(doc! { "_id": "$metadata" }, None).await {
+            Ok(Some(ViewItem::Metadata(m))) => m.grouping_keys,
+            Ok(_) => None,
+            Err(e) => return Err(ViewError::StoreError(Box::new(e))),
+        };
+
+        let lookup = LookupFields {
+            index_fields: spec.index_fields.clone(),
+            key_fields: spec.key_fields.clone(),
+            grouping_keys,
+        };
+        let result_indexes = lookup.indexes();
+
+        let mut lookup_fields = self.lookup_fields.write().await;
+        lookup_fields.insert(query_id.to_string(), lookup);
+        drop(lookup_fields);
+
         let index1 = IndexModel::builder()
             .keys(doc! {
                 "validFrom": 1,
@@ -102,13 +359,32 @@ impl ViewStore for MongoViewStore {
             })
             .build();
 
-        match collection.create_indexes(vec![index1, index2], None).await {
-            Ok(r) => {
-                log::debug!("created indexes: {:?}", r.index_names);
-                Ok(())
-            }
+        let mut indexes = vec![index1, index2];
+        indexes.extend(
+            result_indexes
+                .iter()
+                .map(|(_, keys)| IndexModel::builder().keys(keys.clone()).build()),
+        );
+
+        match collection.create_indexes(indexes, None).await {
+            Ok(r) => log::debug!("created indexes: {:?}", r.index_names),
             Err(e) => return Err(ViewError::StoreError(Box::new(e))),
         }
+
+        let existing = match collection.list_index_names().await {
+            Ok(names) => names,
+            Err(e) => return Err(ViewError::StoreError(Box::new(e))),
+        };
+        let wanted: Vec<String> = result_indexes.into_iter().map(|(name, _)| name).collect();
+
+        for name in stale_indexes(&existing, &wanted) {
+            log::info!("Dropping index {} of {}", name, query_id);
+            if let Err(e) = collection.drop_index(name, None).await {
+                return Err(ViewError::StoreError(Box::new(e)));
+            }
+        }
+
+        Ok(())
     }
 
     async fn set_retention_policy(
@@ -126,6 +402,10 @@ impl ViewStore for MongoViewStore {
         retention_policy.remove(query_id);
         drop(retention_policy);
 
+        let mut lookup_fields = self.lookup_fields.write().await;
+        lookup_fields.remove(query_id);
+        drop(lookup_fields);
+
         let collection = self.database.collection::<ViewDocument>(query_id);
 
         match collection
@@ -151,6 +431,8 @@ impl ViewStore for MongoViewStore {
         drop(ret_policy);
 
         let collection = self.database.collection::<ViewItem>(query_id);
+        self.derive_key_fields(query_id, &collection, &change)
+            .await?;
 
         let ts = change.source_time_ms as i64;
 
@@ -322,6 +604,15 @@ impl ViewStore for MongoViewStore {
         query_id: &str,
         timestamp: Option<u64>,
     ) -> Result<ViewStream, ViewError> {
+        self.query_view(query_id, timestamp, Document::new()).await
+    }
+
+    async fn get_view_history(
+        &self,
+        query_id: &str,
+        from: u64,
+        to: u64,
+    ) -> Result<RowStream, ViewError> {
         let ret_policy = self.retention_policy.read().await;
         let policy = match ret_policy.get(query_id) {
             Some(p) => *p,
@@ -329,43 +620,24 @@ impl ViewStore for MongoViewStore {
         };
         drop(ret_policy);
 
-        let collection = self.database.collection::<ViewItem>(query_id);
-
-        let metadata = collection
-            .find_one(
-                doc! {
-                    "_id": "$metadata"
-                },
-                None,
-            )
-            .await;
-
-        let metadata = match metadata {
-            Ok(Some(ViewItem::Metadata(m))) => m,
-            Ok(_) => return Err(ViewError::NotFound),
-            Err(err) => return Err(ViewError::StoreError(Box::new(err))),
-        };
-
-        let now = SystemTime::now()
-            .duration_since(SystemTime::UNIX_EPOCH)
-            .unwrap()
-            .as_millis() as u64;
-        let timestamp = timestamp.unwrap_or(now) as i64;
-        let effective_at = std::cmp::min(metadata.ts, timestamp);
-
-        if let RetentionPolicy::Expire { after_seconds } = policy {
-            if ((now - (after_seconds * 1000)) as i64) > timestamp {
-                return Err(ViewError::NotFound);
-            }
+        if let RetentionPolicy::Latest = policy {
+            return Err(ViewError::InvalidRequest(format!(
+                "View `{}` does not retain history",
+                query_id
+            )));
         }
 
+        let collection = self.database.collection::<ViewItem>(query_id);
+
         let data = collection
             .find(
                 doc! {
-                    "validFrom": { "$lte": effective_at },
-                    "validTo": { "$gte": effective_at },
+                    "validFrom": { "$lte": to as i64 },
+                    "validTo": { "$gte": from as i64 },
                 },
-                None,
+                options::FindOptions::builder()
+                    .sort(doc! { "validFrom": 1 })
+                    .build(),
             )
             .await;
 
@@ -375,16 +647,19 @@ impl ViewStore for MongoViewStore {
         };
 
         Ok(stream! {
-            yield ViewElement::Header{
-                sequence: metadata.seq as u64,
-                timestamp: metadata.ts as u64,
-                state: metadata.state.clone(),
-            };
-
             while let Some(doc) = data.next().await {
                 match doc {
                     Ok(ViewItem::View(v)) => {
-                        yield ViewElement::Data(v.result);
+                        let mut row = v.result;
+                        row.insert("_validFrom".to_string(), Value::from(v.valid_from));
+                        row.insert(
+                            "_validTo".to_string(),
+                            match v.valid_to {
+                                MAX_TIMESTAMP => Value::Null,
+                                valid_to => Value::from(valid_to),
+                            },
+                        );
+                        yield row;
                     },
                     Ok(ViewItem::Metadata(_)) => {},
                     Err(err) => {
@@ -396,6 +671,149 @@ impl ViewStore for MongoViewStore {
         .boxed())
     }
 
+    async fn get_rows(
+        &self,
+        query_id: &str,
+        key: Option<Vec<Value>>,
+        filter: Map<String, Value>,
+        timestamp: Option<u64>,
+    ) -> Result<ViewStream, ViewError> {
+        let lookup_fields = self.lookup_fields.read().await;
+        let lookup = match lookup_fields.get(query_id) {
+            Some(l) => l.clone(),
+            None => return Err(ViewError::NotFound),
+        };
+        drop(lookup_fields);
+
+        let values = lookup_filter(
+            query_id,
+            lookup.key_fields(),
+            &lookup.index_fields,
+            key,
+            filter,
+        )?;
+
+        let mut query = Document::new();
+        for (field, value) in values {
+            query.insert(format!("result.{}", field), to_bson(&value)?);
+        }
+
+        self.query_view(query_id, timestamp, query).await
+    }
+
+    async fn rebuild_view(
+        &self,
+        query_id: &str,
+        sequence: u64,
+        ts: u64,
+        mut changes: ChangeStream,
+    ) -> Result<(), ViewError> {
+        let ret_policy = self.retention_policy.read().await;
+        let policy = match ret_policy.get(query_id) {
+            Some(p) => *p,
+            None => return Err(ViewError::NotFound),
+        };
+        drop(ret_policy);
+
+        // the rebuilt rows are staged next to the current rows and only replace them once every row is written,
+        // so readers keep seeing the current rows and the view is left as it was if the rebuild fails
+        let collection = self.database.collection::<ViewItem>(query_id);
+        let ts = ts as i64;
+
+        if let Err(e) = self.stage_rows(query_id, &collection, &mut changes).await {
+            if let Err(e) = collection
+                .delete_many(doc! { "validTo": STAGED_TIMESTAMP }, None)
+                .await
+            {
+                log::error!("Error removing the staged rows of {}: {:?}", query_id, e);
+            }
+            return Err(e);
+        }
+
+        let last_ts = match collection.find_one(doc! { "_id": "$metadata" }, None).await {
+            Ok(Some(ViewItem::Metadata(m))) => Some(m.ts),
+            Ok(_) => None,
+            Err(e) => return Err(ViewError::StoreError(Box::new(e))),
+        };
+
+        let staged = doc! { "$eq": ["$validTo", STAGED_TIMESTAMP] };
+        let response = collection
+            .update_many(
+                doc! { "validTo": { "$in": [MAX_TIMESTAMP, STAGED_TIMESTAMP] } },
+                vec![doc! {
+                    "$set": doc! {
+                        "validFrom": { "$cond": [staged.clone(), ts, "$validFrom"] },
+                        "validTo": { "$cond": [staged, MAX_TIMESTAMP, ts - 1] },
+                    }
+                }],
+                None,
+            )
+            .await
+            .map(|r| log::debug!("rebuild swap: {:?}", r));
+
+        if let Err(e) = response {
+            return Err(ViewError::StoreError(Box::new(e)));
+        }
+
+        if let RetentionPolicy::Latest = policy {
+            let response = collection
+                .delete_many(doc! { "validTo": { "$lt": MAX_TIMESTAMP } }, None)
+                .await
+                .map(|r| log::debug!("rebuild clear: {:?}", r));
+
+            if let Err(e) = response {
+                return Err(ViewError::StoreError(Box::new(e)));
+            }
+        }
+
+        let mut update = doc! {
+            "$set": doc!{
+                "seq": sequence as i64,
+                "ts": ts,
+            }
+        };
+
+        if let Some(last_ts) = last_ts {
+            if last_ts < ts {
+                update.insert("$push", doc! { "gaps": doc! { "from": last_ts, "to": ts } });
+            }
+        }
+
+        let seq_result = collection
+            .find_one_and_update(
+                doc! {
+                    "_id": "$metadata"
+                },
+                update,
+                options::FindOneAndUpdateOptions::builder()
+                    .upsert(true)
+                    .build(),
+            )
+            .await;
+
+        if let Err(err) = seq_result {
+            return Err(ViewError::StoreError(Box::new(err)));
+        }
+
+        self.rebuilds
+            .lock()
+            .unwrap()
+            .entry(query_id.to_string())
+            .or_insert_with(|| watch::channel(0).0)
+            .send_replace(sequence);
+
+        Ok(())
+    }
+
+    fn rebuilt_sequence(&self, query_id: &str) -> watch::Receiver<u64> {
+        self.rebuilds
+            .lock()
+            .unwrap()
+            .entry(query_id.to_string())
+            .or_insert_with(|| watch::channel(0).0)
+            .subscribe()
+    }
+
     async fn set_state(
         &self,
         query_id: &str,
@@ -446,6 +864,17 @@ struct MetadataDocument {
     seq: i64,
     ts: i64,
     state: Option<String>,
+    #[serde(default)]
+    gaps: Vec<HistoryGap>,
+    #[serde(default)]
+    grouping_keys: Option<Vec<String>>,
+}
+
+/// A period for which the view history is unknown, because the view was rebuilt
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct HistoryGap {
+    from: i64,
+    to: i64,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -455,6 +884,112 @@ enum ViewItem {
     Metadata(MetadataDocument),
 }
 
+fn result_index_keys(fields: &[String]) -> Document {
+    let mut keys = Document::new();
+    for field in fields {
+        keys.insert(format!("result.{}", field), 1);
+    }
+    keys.insert("validTo", 1);
+    keys
+}
+
+/// The name Mongo gives an index that is created without one
+fn index_name(keys: &Document) -> String {
+    keys.iter()
+        .map(|(field, order)| format!("{}_{}", field, order))
+        .collect::<Vec<_>>()
+        .join("_")
+}
+
+/// The result field indexes of a view that are no longer wanted, the other indexes are always kept
+fn stale_indexes(existing: &[String], wanted: &[String]) -> Vec<String> {
+    existing
+        .iter()
+        .filter(|name| name.starts_with("result.") && !wanted.contains(name))
+        .cloned()
+        .collect()
+}
+
+/// Inserts a batch of rows as staged rows, replacing the staged rows written earlier with the same keys
+async fn write_staged_rows(
+    collection: &mongodb::Collection<ViewItem>,
+    rows: HashMap<[u8; 16], Map<String, Value>>,
+) -> Result<(), ViewError> {
+    if rows.is_empty() {
+        return Ok(());
+    }
+
+    let hashes: Vec<Uuid> = rows.keys().map(|key| Uuid::from_bytes(*key)).collect();
+    if let Err(e) = collection
+        .delete_many(
+            doc! { "hash": { "$in": hashes }, "validTo": STAGED_TIMESTAMP },
+            None,
+        )
+        .await
+    {
+        return Err(ViewError::StoreError(Box::new(e)));
+    }
+
+    let items = rows.into_iter().map(|(key, row)| {
+        ViewItem::View(ViewDocument {
+            hash: Uuid::from_bytes(key),
+            result: row,
+            valid_from: STAGED_TIMESTAMP,
+            valid_to: STAGED_TIMESTAMP,
+        })
+    });
+
+    match collection.insert_many(items, None).await {
+        Ok(_) => Ok(()),
+        Err(e) => Err(ViewError::StoreError(Box::new(e))),
+    }
+}
+
+/// Applies a change to a set of rows, keyed the same way `record_change` keys the documents of a view.
+/// Returns the keys of the rows the change removes.
+fn apply_to_rows(
+    rows: &mut HashMap<[u8; 16], Map<String, Value>>,
+    change: ResultChangeEvent,
+) -> Vec<[u8; 16]> {
+    let mut removed = Vec::new();
+
+    for del in change.deleted_results {
+        let key = hash_values(&del);
+        rows.remove(&key);
+        removed.push(key);
+    }
+
+    for update in change.updated_results {
+        let key = |row: &Map<String, Value>| match &update.grouping_keys {
+            Some(grouping_keys) => hash_grouping_values(row, grouping_keys),
+            None => hash_values(row),
+        };
+
+        if let Some(before) = &update.before {
+            let before_key = key(before);
+            rows.remove(&before_key);
+            removed.push(before_key);
+        }
+
+        if let Some(after) = update.after {
+            rows.insert(key(&after), after);
+        }
+    }
+
+    for add in change.added_results {
+        rows.insert(hash_values(&add), add);
+    }
+
+    removed
+}
+
+fn to_bson(value: &Value) -> Result<mongodb::bson::Bson, ViewError> {
+    match mongodb::bson::to_bson(value) {
+        Ok(b) => Ok(b),
+        Err(e) => Err(ViewError::InvalidRequest(e.to_string())),
+    }
+}
+
 fn hash_grouping_values(values: &Map<String, Value>, grouping_keys: &Vec<String>) -> [u8; 16] {
     let mut h = siphasher::sip128::SipHasher::new();
 
@@ -546,3 +1081,6 @@ async fn collect_garbage(store: Arc<MongoViewStore>) {
         };
     }
 }
+
+#[cfg(test)]
+mod tests;
@@ -0,0 +1,177 @@
+// Copyright 2024 The Drasi Authors.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+#![allow(clippy::unwrap_used)]
+
+use std::collections::HashMap;
+
+use serde_json::{json, Map, Value};
+
+use super::{apply_to_rows, hash_grouping_values, hash_values, stale_indexes, LookupFields};
+use crate::api::ResultChangeEvent;
+
+fn row(value: Value) -> Map<String, Value> {
+    value.as_object().unwrap().clone()
+}
+
+fn change(value: Value) -> ResultChangeEvent {
+    serde_json::from_value(value).unwrap()
+}
+
+fn sorted_rows(rows: HashMap<[u8; 16], Map<String, Value>>) -> Vec<Value> {
+    let mut rows: Vec<Value> = rows.into_values().map(Value::Object).collect();
+    rows.sort_by_key(|r| r.to_string());
+    rows
+}
+
+#[test]
+fn rebuilt_rows_follow_added_updated_and_deleted_results() {
+    let mut rows = HashMap::new();
+    apply_to_rows(
+        &mut rows,
+        change(json!({
+            "queryId": "q1",
+            "sequence": 1,
+            "sourceTimeMs": 100,
+            "addedResults": [{ "id": "a" }, { "id": "b" }],
+            "updatedResults": [],
+            "deletedResults": [],
+            "metadata": null
+        })),
+    );
+    let removed = apply_to_rows(
+        &mut rows,
+        change(json!({
+            "queryId": "q1",
+            "sequence": 1,
+            "sourceTimeMs": 100,
+            "addedResults": [],
+            "updatedResults": [{ "before": { "id": "a" }, "after": { "id": "c" }, "grouping_keys": null }],
+            "deletedResults": [{ "id": "b" }],
+            "metadata": null
+        })),
+    );
+
+    // the removed rows are also removed from the batches of staged rows that were already written
+    assert_eq!(
+        removed,
+        vec![
+            hash_values(&row(json!({ "id": "b" }))),
+            hash_values(&row(json!({ "id": "a" })))
+        ]
+    );
+    assert_eq!(sorted_rows(rows), vec![json!({ "id": "c" })]);
+}
+
+#[test]
+fn rebuilt_aggregation_rows_are_keyed_by_grouping_keys() {
+    let aggregation = |before: Value, after: Value| {
+        change(json!({
+            "queryId": "q1",
+            "sequence": 1,
+            "sourceTimeMs": 100,
+            "addedResults": [],
+            "updatedResults": [{ "before": before, "after": after, "grouping_keys": ["region"] }],
+            "deletedResults": [],
+            "metadata": null
+        }))
+    };
+
+    let mut rows = HashMap::new();
+    apply_to_rows(
+        &mut rows,
+        aggregation(Value::Null, json!({ "region": "EU", "total": 1 })),
+    );
+    apply_to_rows(
+        &mut rows,
+        aggregation(
+            json!({ "region": "EU", "total": 1 }),
+            json!({ "region": "EU", "total": 3 }),
+        ),
+    );
+    apply_to_rows(
+        &mut rows,
+        aggregation(Value::Null, json!({ "region": "US", "total": 2 })),
+    );
+
+    // later aggregation changes recorded on top of the rebuilt view find the rows by the same key
+    let eu = row(json!({ "region": "EU", "total": 3 }));
+    let key = hash_grouping_values(&eu, &vec!["region".to_string()]);
+    assert_eq!(rows.get(&key), Some(&eu));
+    assert_ne!(key, hash_values(&eu));
+
+    assert_eq!(
+        sorted_rows(rows),
+        vec![
+            json!({ "region": "EU", "total": 3 }),
+            json!({ "region": "US", "total": 2 })
+        ]
+    );
+}
+
+fn strings(values: &[&str]) -> Vec<String> {
+    values.iter().map(|v| v.to_string()).collect()
+}
+
+fn index_names(lookup: &LookupFields) -> Vec<String> {
+    lookup.indexes().into_iter().map(|(name, _)| name).collect()
+}
+
+#[test]
+fn result_indexes_follow_key_and_index_fields() {
+    let lookup = LookupFields {
+        index_fields: strings(&["category", "id"]),
+        key_fields: strings(&["id"]),
+        grouping_keys: Some(strings(&["region"])),
+    };
+
+    assert_eq!(lookup.key_fields(), strings(&["id"]).as_slice());
+    assert_eq!(
+        index_names(&lookup),
+        strings(&["result.id_1_validTo_1", "result.category_1_validTo_1"])
+    );
+}
+
+#[test]
+fn grouping_keys_are_key_fields_when_none_are_declared() {
+    let lookup = LookupFields {
+        index_fields: Vec::new(),
+        key_fields: Vec::new(),
+        grouping_keys: Some(strings(&["category", "region"])),
+    };
+
+    assert_eq!(
+        lookup.key_fields(),
+        strings(&["category", "region"]).as_slice()
+    );
+    assert_eq!(
+        index_names(&lookup),
+        strings(&["result.category_1_result.region_1_validTo_1"])
+    );
+}
+
+#[test]
+fn result_indexes_removed_from_the_spec_are_stale() {
+    let existing = strings(&[
+        "_id_",
+        "validFrom_1_validTo_1",
+        "hash_1_validTo_1",
+        "result.id_1_validTo_1",
+        "result.category_1_validTo_1",
+    ]);
+
+    assert_eq!(
+        stale_indexes(&existing, &strings(&["result.id_1_validTo_1"])),
+        strings(&["result.category_1_validTo_1"])
+    );
+}
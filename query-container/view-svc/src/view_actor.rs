@@ -30,6 +30,8 @@ use dapr::{
 
 use crate::{
     api::ViewSpec,
+    exporter::ViewExporter,
+    joined_view::source_view_id,
     models::{ChangeStreamConfig, ViewError},
     view_store::ViewStore,
     view_worker::{ShutdownReason, ViewWorker, WorkerState},
@@ -41,6 +43,7 @@ pub struct ViewActor {
     store: Arc<dyn ViewStore>,
     dapr_client: ActorContextClient,
     stream_config: Arc<ChangeStreamConfig>,
+    exporter: Arc<ViewExporter>,
     config: OptionalValue<ViewSpec>,
     worker: OptionalValue<Arc<ViewWorker>>,
 }
@@ -110,12 +113,14 @@ impl ViewActor {
         store: Arc<dyn ViewStore>,
         dapr_client: ActorContextClient,
         stream_config: Arc<ChangeStreamConfig>,
+        exporter: Arc<ViewExporter>,
     ) -> Self {
         Self {
             query_id,
             store,
             dapr_client,
             stream_config,
+            exporter,
             config: OptionalValue::new(),
             worker: OptionalValue::new(),
         }
@@ -177,6 +182,15 @@ impl ViewActor {
                 .into_response();
         }
 
+        if let Some(join) = self.config.get().await.and_then(|c| c.join) {
+            for query in join.queries {
+                let source_id = source_view_id(&self.query_id, &query.query_id);
+                if let Err(err) = self.store.delete_view(&source_id).await {
+                    log::error!("Error deleting view {}: {}", source_id, err);
+                }
+            }
+        }
+
         Json(()).into_response()
     }
 
@@ -204,6 +218,7 @@ impl ViewActor {
             config.clone(),
             self.stream_config.clone(),
             self.store.clone(),
+            self.exporter.clone(),
         ));
 
         self.worker.set(worker.clone()).await;
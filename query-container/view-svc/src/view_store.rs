@@ -16,18 +16,22 @@ use std::pin::Pin;
 
 use async_trait::async_trait;
 use futures::Stream;
+use serde_json::{Map, Value};
+use tokio::sync::watch;
 
 use crate::{
-    api::{ResultChangeEvent, RetentionPolicy, ViewElement},
+    api::{ResultChangeEvent, RetentionPolicy, ViewElement, ViewSpec},
     models::ViewError,
 };
 
 pub type ViewStream = Pin<Box<dyn Stream<Item = ViewElement> + Send>>;
+pub type RowStream = Pin<Box<dyn Stream<Item = Map<String, Value>> + Send>>;
+pub type ChangeStream = Pin<Box<dyn Stream<Item = Result<ResultChangeEvent, ViewError>> + Send>>;
 //pub type ViewStream = dyn Stream<Item = Result<ViewElement, ViewError>>;
 
 #[async_trait]
 pub trait ViewStore: Send + Sync {
-    async fn init_view(&self, query_id: &str, policy: RetentionPolicy) -> Result<(), ViewError>;
+    async fn init_view(&self, query_id: &str, spec: &ViewSpec) -> Result<(), ViewError>;
     async fn set_retention_policy(
         &self,
         query_id: &str,
@@ -44,6 +48,37 @@ pub trait ViewStore: Send + Sync {
         query_id: &str,
         timestamp: Option<u64>,
     ) -> Result<ViewStream, ViewError>;
+    /// Returns every version of every row that was valid at some point between `from` and `to`,
+    /// with `_validFrom` and `_validTo` fields added. `_validTo` is null for rows that are still current.
+    async fn get_view_history(
+        &self,
+        query_id: &str,
+        from: u64,
+        to: u64,
+    ) -> Result<RowStream, ViewError>;
+    /// Returns the rows of the view that match the given key and field values.
+    /// `key` holds values for the key fields of the view, in the order they were declared, or in the
+    /// order of the grouping keys of an aggregation view that declares no key fields.
+    /// Every field in `filter` must be a key or index field.
+    async fn get_rows(
+        &self,
+        query_id: &str,
+        key: Option<Vec<Value>>,
+        filter: Map<String, Value>,
+        timestamp: Option<u64>,
+    ) -> Result<ViewStream, ViewError>;
+    /// Replaces the current contents of a view with the rows produced by applying `changes` to an empty view,
+    /// as of the given sequence and time. The period between the last recorded change and `ts` is recorded
+    /// as a gap in the view history. The view is left as it was if reading the changes or writing the rows fails.
+    async fn rebuild_view(
+        &self,
+        query_id: &str,
+        sequence: u64,
+        ts: u64,
+        changes: ChangeStream,
+    ) -> Result<(), ViewError>;
+    /// Returns the sequence the view was last rebuilt at. The changes at or below it are included in the rebuilt view.
+    fn rebuilt_sequence(&self, query_id: &str) -> watch::Receiver<u64>;
     async fn set_state(
         &self,
         query_id: &str,
@@ -52,3 +87,46 @@ pub trait ViewStore: Send + Sync {
         state: &str,
     ) -> Result<(), ViewError>;
 }
+
+/// Resolves a row lookup into the result field values to match, checking that `key` has a value for
+/// every key field and that every field in `filter` is a key or index field
+pub fn lookup_filter(
+    query_id: &str,
+    key_fields: &[String],
+    index_fields: &[String],
+    key: Option<Vec<Value>>,
+    filter: Map<String, Value>,
+) -> Result<Map<String, Value>, ViewError> {
+    let mut result = Map::new();
+
+    if let Some(key) = key {
+        if key_fields.is_empty() {
+            return Err(ViewError::InvalidRequest(format!(
+                "View `{}` has no key fields",
+                query_id
+            )));
+        }
+        if key.len() != key_fields.len() {
+            return Err(ViewError::InvalidRequest(format!(
+                "Expected {} key values, got {}",
+                key_fields.len(),
+                key.len()
+            )));
+        }
+        for (field, value) in key_fields.iter().zip(key) {
+            result.insert(field.clone(), value);
+        }
+    }
+
+    for (field, value) in filter {
+        if !key_fields.contains(&field) && !index_fields.contains(&field) {
+            return Err(ViewError::InvalidRequest(format!(
+                "`{}` is not an indexed field",
+                field
+            )));
+        }
+        result.insert(field, value);
+    }
+
+    Ok(result)
+}
@@ -14,11 +14,15 @@
 
 use std::sync::Arc;
 
+use futures::future::select_all;
 use opentelemetry::{propagation::TextMapPropagator, KeyValue};
 use opentelemetry_sdk::propagation::TraceContextPropagator;
 use tokio::{
     select,
-    sync::{mpsc, oneshot, watch, Mutex},
+    sync::{
+        mpsc::{self, UnboundedReceiver},
+        oneshot, watch, Mutex,
+    },
     task::JoinHandle,
     time::Instant,
 };
@@ -26,7 +30,13 @@ use tracing_opentelemetry::OpenTelemetrySpanExt;
 
 use crate::{
     api::{ControlSignal, ResultEvent, ViewSpec},
-    change_stream::{redis_change_stream::RedisChangeStream, SequentialChangeStream},
+    change_stream::{
+        redis_change_stream::RedisChangeStream,
+        redis_tail_stream::{create_group_after, delete_group},
+        SequentialChangeStream,
+    },
+    exporter::{ScheduledExports, ViewExporter},
+    joined_view::JoinedView,
     models::ChangeStreamConfig,
     view_store::ViewStore,
 };
@@ -61,6 +71,7 @@ impl ViewWorker {
         config: ViewSpec,
         stream_config: Arc<ChangeStreamConfig>,
         store: Arc<dyn ViewStore>,
+        exporter: Arc<ViewExporter>,
     ) -> Self {
         let (command_tx, mut command_rx) = mpsc::unbounded_channel();
         let (is_complete_tx, is_complete_rx) = oneshot::channel::<()>();
@@ -71,11 +82,26 @@ impl ViewWorker {
         let inner_handle = tokio::spawn(async move {
             log::info!("View {} worker starting", query_id);
 
-            if let Err(err) = store.init_view(&query_id, config.retention_policy).await {
+            if config.join.is_some() {
+                return run_joined_view(
+                    query_id,
+                    config,
+                    stream_config,
+                    store,
+                    exporter,
+                    &mut command_rx,
+                )
+                .await;
+            }
+
+            if let Err(err) = store.init_view(&query_id, &config).await {
                 log::error!("Error initializing view: {}", err);
                 return ShutdownReason::Error;
             }
 
+            let mut scheduled_exports =
+                ScheduledExports::start(exporter, query_id.clone(), &config.exports);
+
             let topic = format!("{}-results", query_id);
 
             let change_stream = match RedisChangeStream::new(
@@ -106,6 +132,10 @@ impl ViewWorker {
 
             let metric_attributes = [KeyValue::new("query_id", query_id.to_string())];
 
+            // results at or below the sequence the view was last rebuilt at are already included in the view
+            let mut rebuilds = store.rebuilt_sequence(&query_id);
+            let mut rebuilt_sequence = *rebuilds.borrow_and_update();
+
             loop {
                 select! {
                     cmd = command_rx.recv() => {
@@ -116,9 +146,10 @@ impl ViewWorker {
                                 },
                                 Some(Command::Reconfigure(new_config)) => {
                                     log::info!("View {} worker reconfigure", query_id);
-                                    if let Err(err) = store.set_retention_policy(&query_id, new_config.retention_policy).await {
-                                        log::error!("Error setting retention policy: {}", err);
+                                    if let Err(err) = store.init_view(&query_id, &new_config).await {
+                                        log::error!("Error reconfiguring view: {}", err);
                                     }
+                                    scheduled_exports.reschedule(&new_config.exports);
                                 },
                                 None => {
                                     log::error!("Command channel closed unexpectedly");
@@ -143,6 +174,30 @@ impl ViewWorker {
                                         span.set_parent(parent_context);
                                         span.set_attribute("query_id", query_id.clone());
 
+                                        if rebuilds.has_changed().unwrap_or_default() {
+                                            rebuilt_sequence = *rebuilds.borrow_and_update();
+                                        }
+
+                                        let bootstrap_started = matches!(
+                                            &evt.data,
+                                            ResultEvent::Control(c) if matches!(c.control_signal, ControlSignal::BootstrapStarted)
+                                        );
+                                        if bootstrap_started {
+                                            // sequences start over when a query is bootstrapped again
+                                            rebuilt_sequence = 0;
+                                        } else if evt.data.sequence() <= rebuilt_sequence {
+                                            log::debug!(
+                                                "skipping result {} for {}, view was rebuilt at {}",
+                                                evt.data.sequence(),
+                                                query_id,
+                                                rebuilt_sequence
+                                            );
+                                            if let Err(err) = change_stream.ack(&evt_id).await {
+                                                log::error!("Error acknowledging message: {}", err);
+                                            }
+                                            continue;
+                                        }
+
                                         match evt.data {
                                             ResultEvent::Change(change_evt) => {
                                                 if let Err(err) = store.record_change(&query_id, change_evt).await {
@@ -242,3 +297,149 @@ impl ViewWorker {
         *self.state.borrow()
     }
 }
+
+/// Runs a view that joins several queries, consuming the results stream of each of them
+async fn run_joined_view(
+    view_id: Arc<str>,
+    config: ViewSpec,
+    stream_config: Arc<ChangeStreamConfig>,
+    store: Arc<dyn ViewStore>,
+    exporter: Arc<ViewExporter>,
+    command_rx: &mut UnboundedReceiver<Command>,
+) -> ShutdownReason {
+    let mut view = match JoinedView::init(view_id.clone(), config.clone(), store).await {
+        Ok(v) => v,
+        Err(err) => {
+            log::error!("Error initializing joined view: {}", err);
+            return ShutdownReason::Error;
+        }
+    };
+
+    let mut scheduled_exports = ScheduledExports::start(exporter, view_id.clone(), &config.exports);
+
+    // each joined view has its own consumer group on the results of every query it joins,
+    // when the group is new the source view is seeded from the view of that query
+    let group_id = format!("view-svc.{}", view_id);
+    let query_ids: Vec<String> = view.queries().iter().map(|q| q.query_id.clone()).collect();
+    let mut streams = Vec::new();
+    let mut seeded_ts = None;
+
+    for query_id in &query_ids {
+        let topic = format!("{}-results", query_id);
+
+        let created =
+            match create_group_after(&stream_config.redis_url, &topic, &group_id, "view-svc").await
+            {
+                Ok(c) => c,
+                Err(err) => {
+                    log::error!("Error creating consumer group for {}: {}", topic, err);
+                    return ShutdownReason::Error;
+                }
+            };
+
+        if created {
+            match view.seed_source(query_id).await {
+                Ok(ts) => seeded_ts = seeded_ts.max(ts),
+                Err(err) => {
+                    log::error!("Error seeding joined view from {}: {}", query_id, err);
+                    // the group is removed so that seeding is attempted again on restart
+                    if let Err(err) =
+                        delete_group(&stream_config.redis_url, &topic, &group_id).await
+                    {
+                        log::error!("Error deleting consumer group for {}: {}", topic, err);
+                    }
+                    return ShutdownReason::Error;
+                }
+            }
+        }
+
+        match RedisChangeStream::new(
+            &stream_config.redis_url,
+            &topic,
+            &group_id,
+            "view-svc",
+            stream_config.buffer_size,
+            stream_config.fetch_batch_size,
+        )
+        .await
+        {
+            Ok(cs) => streams.push(cs),
+            Err(err) => {
+                log::error!("Error creating change stream: {}", err);
+                return ShutdownReason::Error;
+            }
+        };
+    }
+
+    if let Some(ts) = seeded_ts {
+        if let Err(err) = view.rebuild(ts).await {
+            log::error!("Error building joined view: {}", err);
+            return ShutdownReason::Error;
+        }
+    }
+
+    loop {
+        let next_msg = select_all(
+            streams
+                .iter()
+                .enumerate()
+                .map(|(i, cs)| Box::pin(async move { (i, cs.recv::<ResultEvent>().await) })),
+        );
+
+        select! {
+            cmd = command_rx.recv() => {
+                match cmd {
+                    Some(Command::Shutdown) => {
+                        log::info!("View {} worker shutting down", view_id);
+                        return ShutdownReason::Deactivated;
+                    },
+                    Some(Command::Reconfigure(new_config)) => {
+                        log::info!("View {} worker reconfigure", view_id);
+                        let exports = new_config.exports.clone();
+                        match view.reconfigure(new_config).await {
+                            Ok(_) => scheduled_exports.reschedule(&exports),
+                            Err(err) => log::error!("Error reconfiguring view: {}", err),
+                        }
+                    },
+                    None => {
+                        log::error!("Command channel closed unexpectedly");
+                        return ShutdownReason::Error;
+                    },
+                }
+            },
+            ((i, msg), _, _) = next_msg => {
+                let evt = match msg {
+                    Err(err) => {
+                        log::error!("Error polling stream consumer: {}", err);
+                        return ShutdownReason::Error;
+                    },
+                    Ok(None) => continue,
+                    Ok(Some(evt)) => evt,
+                };
+
+                let query_id = &query_ids[i];
+                let result = match evt.data {
+                    ResultEvent::Change(change_evt) => view.apply_change(query_id, change_evt).await,
+                    ResultEvent::Control(control_evt) => {
+                        log::info!("Control event for {} from {}: {:?}", view_id, query_id, control_evt);
+                        match control_evt.control_signal {
+                            ControlSignal::BootstrapStarted | ControlSignal::QueryDeleted => {
+                                view.reset_source(query_id, control_evt.source_time_ms).await
+                            },
+                            _ => Ok(()),
+                        }
+                    },
+                };
+
+                if let Err(err) = result {
+                    log::error!("Error updating joined view {} from {}: {}", view_id, query_id, err);
+                    return ShutdownReason::Error;
+                }
+
+                if let Err(err) = streams[i].ack(&evt.id).await {
+                    log::error!("Error acknowledging message: {}", err);
+                }
+            }
+        };
+    }
+}
@@ -1,6 +1,9 @@
 use std::{
     env,
-    sync::Arc,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc,
+    },
     time::{SystemTime, UNIX_EPOCH},
 };
 
@@ -36,7 +39,8 @@ async fn main() {
         .with_deprovision_handler(deprovision)
         .without_context()
         .build()
-        .await;
+        .await
+        .expect("failed to build reactivator");
 
     reactivator.start().await;
 }
@@ -171,13 +175,9 @@ where
                                     bookmark.metadata.resource_version,
                                     K::KIND
                                 );
+                                // the bookmark is only used to resume this watch, the saved cursor
+                                // advances with the changes once they are published
                                 cursor = bookmark.metadata.resource_version.clone();
-                                if let Err(err) = state_store
-                                    .put(K::KIND, cursor.to_string().as_bytes().to_vec())
-                                    .await
-                                {
-                                    log::error!("failed to store cursor for {}: {}", K::KIND, err);
-                                }
                                 continue;
                             }
                             kube::api::WatchEvent::Error(error_response) => {
@@ -260,15 +260,35 @@ where
 
                 // Since we do not have a timestamp for the source change, we will use the reactivator start time
                 // as the timestamp for the source change
-                match tx.send(SourceChange::new(op, node,reactivator_start_ns,  reactivator_start_ns,0, None)).await {
-                    Ok(_) => log::info!("sent node change for {} {}", label, id),
-                    Err(e) => log::error!("failed to send node change for {} {}: {}", label, id, e),
+                let mut changes = std::iter::once(node)
+                    .chain(owners)
+                    .map(|element| {
+                        SourceChange::new(
+                            op,
+                            element,
+                            reactivator_start_ns,
+                            reactivator_start_ns,
+                            next_sequence(),
+                            None,
+                        )
+                    })
+                    .collect::<Vec<SourceChange>>();
+
+                // the cursor is saved once the last change of the event is published
+                if let Some(last) = changes.pop() {
+                    changes.push(last.with_checkpoint(K::KIND, cursor.as_bytes().to_vec()));
                 }
-                for owner in owners {
-                    match tx.send(SourceChange::new(op, owner,reactivator_start_ns, reactivator_start_ns, 0, None)).await {
-                        Ok(_) => log::info!("sent relation change for {} {}", label, id),
+
+                for change in changes {
+                    let kind = match change.element() {
+                        SourceElement::Node { .. } => "node",
+                        SourceElement::Relation { .. } => "relation",
+                    };
+                    match tx.send(change).await {
+                        Ok(_) => log::info!("sent {} change for {} {}", kind, label, id),
                         Err(e) => log::error!(
-                            "failed to send relation change for {} {}: {}",
+                            "failed to send {} change for {} {}: {}",
+                            kind,
                             label,
                             id,
                             e
@@ -279,3 +299,20 @@ where
         }
     })
 }
+
+static LAST_SEQUENCE: AtomicU64 = AtomicU64::new(0);
+
+/// Resource versions are opaque, so changes are sequenced by the time they are read, kept strictly
+/// increasing across the watches of every kind, and across restarts as long as the clock does not go back
+fn next_sequence() -> u64 {
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_nanos() as u64)
+        .unwrap_or_default();
+    let previous = LAST_SEQUENCE
+        .fetch_update(Ordering::SeqCst, Ordering::SeqCst, |last| {
+            Some(now.max(last + 1))
+        })
+        .unwrap_or_default();
+    now.max(previous + 1)
+}
@@ -0,0 +1,318 @@
+// Copyright 2024 The Drasi Authors.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Derive macros that map Rust structs to `SourceElement`s, re-exported by `drasi-source-sdk`.
+//!
+//! Struct attributes:
+//! - `#[drasi(label = "...")]` adds a label, it can be repeated. The struct name is the label when none are given.
+//!
+//! Field attributes:
+//! - `#[drasi(id)]` the element id, required.
+//! - `#[drasi(start)]`, `#[drasi(end)]` the ids of the start and end nodes, required on relations.
+//! - `#[drasi(rename = "...")]` the property name, the field name is used otherwise.
+//! - `#[drasi(skip)]` leaves the field out of the properties.
+//!
+//! Every field that is not skipped becomes a property, including the id and endpoint fields.
+//! Property values are serialized with serde, so field types must implement `Serialize`,
+//! and the id and endpoint fields must implement `Display`.
+
+use proc_macro::TokenStream;
+use proc_macro2::TokenStream as TokenStream2;
+use quote::quote;
+use syn::{parse_macro_input, Data, DeriveInput, Fields, Ident, LitStr};
+
+#[proc_macro_derive(DrasiNode, attributes(drasi))]
+pub fn derive_node(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+    expand(input, ElementKind::Node)
+        .unwrap_or_else(syn::Error::into_compile_error)
+        .into()
+}
+
+#[proc_macro_derive(DrasiRelation, attributes(drasi))]
+pub fn derive_relation(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+    expand(input, ElementKind::Relation)
+        .unwrap_or_else(syn::Error::into_compile_error)
+        .into()
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum ElementKind {
+    Node,
+    Relation,
+}
+
+#[derive(Default)]
+struct FieldAttrs {
+    id: bool,
+    start: bool,
+    end: bool,
+    skip: bool,
+    rename: Option<String>,
+}
+
+fn parse_field_attrs(field: &syn::Field) -> syn::Result<FieldAttrs> {
+    let mut attrs = FieldAttrs::default();
+    for attr in field.attrs.iter().filter(|a| a.path().is_ident("drasi")) {
+        attr.parse_nested_meta(|meta| {
+            if meta.path.is_ident("id") {
+                attrs.id = true;
+            } else if meta.path.is_ident("start") {
+                attrs.start = true;
+            } else if meta.path.is_ident("end") {
+                attrs.end = true;
+            } else if meta.path.is_ident("skip") {
+                attrs.skip = true;
+            } else if meta.path.is_ident("rename") {
+                attrs.rename = Some(meta.value()?.parse::<LitStr>()?.value());
+            } else {
+                return Err(meta.error("expected `id`, `start`, `end`, `skip` or `rename`"));
+            }
+            Ok(())
+        })?;
+    }
+    Ok(attrs)
+}
+
+fn parse_labels(input: &DeriveInput) -> syn::Result<Vec<String>> {
+    let mut labels = Vec::new();
+    for attr in input.attrs.iter().filter(|a| a.path().is_ident("drasi")) {
+        attr.parse_nested_meta(|meta| {
+            if meta.path.is_ident("label") {
+                labels.push(meta.value()?.parse::<LitStr>()?.value());
+                Ok(())
+            } else {
+                Err(meta.error("expected `label`"))
+            }
+        })?;
+    }
+    if labels.is_empty() {
+        labels.push(input.ident.to_string());
+    }
+    Ok(labels)
+}
+
+/// Sets `slot` to the field, failing if another field already has the same role
+fn assign<'a>(slot: &mut Option<&'a Ident>, ident: &'a Ident, role: &str) -> syn::Result<()> {
+    match slot {
+        Some(_) => Err(syn::Error::new_spanned(
+            ident,
+            format!("only one field can be the {}", role),
+        )),
+        None => {
+            *slot = Some(ident);
+            Ok(())
+        }
+    }
+}
+
+fn expand(input: DeriveInput, kind: ElementKind) -> syn::Result<TokenStream2> {
+    let fields = match &input.data {
+        Data::Struct(data) => match &data.fields {
+            Fields::Named(fields) => &fields.named,
+            _ => {
+                return Err(syn::Error::new_spanned(
+                    &input.ident,
+                    "only structs with named fields can be mapped to elements",
+                ))
+            }
+        },
+        _ => {
+            return Err(syn::Error::new_spanned(
+                &input.ident,
+                "only structs can be mapped to elements",
+            ))
+        }
+    };
+
+    let labels = parse_labels(&input)?;
+    let mut id = None;
+    let mut start = None;
+    let mut end = None;
+    let mut properties = Vec::new();
+
+    for field in fields {
+        let ident = field.ident.as_ref().expect("named field");
+        let attrs = parse_field_attrs(field)?;
+
+        if attrs.id {
+            assign(&mut id, ident, "id")?;
+        }
+        if attrs.start || attrs.end {
+            if kind == ElementKind::Node {
+                return Err(syn::Error::new_spanned(
+                    ident,
+                    "`start` and `end` can only be used on relations",
+                ));
+            }
+            if attrs.start {
+                assign(&mut start, ident, "start")?;
+            }
+            if attrs.end {
+                assign(&mut end, ident, "end")?;
+            }
+        }
+        if !attrs.skip {
+            let name = attrs.rename.unwrap_or_else(|| ident.to_string());
+            properties.push((name, ident));
+        }
+    }
+
+    let missing = |role: &str| {
+        syn::Error::new_spanned(
+            &input.ident,
+            format!("a field must be marked with `#[drasi({})]`", role),
+        )
+    };
+    let id = id.ok_or_else(|| missing("id"))?;
+
+    let element = match kind {
+        ElementKind::Node => quote! {
+            ::drasi_source_sdk::SourceElement::Node {
+                id: ::std::string::ToString::to_string(&self.#id),
+                labels,
+                properties,
+            }
+        },
+        ElementKind::Relation => {
+            let start = start.ok_or_else(|| missing("start"))?;
+            let end = end.ok_or_else(|| missing("end"))?;
+            quote! {
+                ::drasi_source_sdk::SourceElement::Relation {
+                    id: ::std::string::ToString::to_string(&self.#id),
+                    labels,
+                    properties,
+                    start_id: ::std::string::ToString::to_string(&self.#start),
+                    end_id: ::std::string::ToString::to_string(&self.#end),
+                }
+            }
+        }
+    };
+
+    let name = &input.ident;
+    let (impl_generics, ty_generics, where_clause) = input.generics.split_for_impl();
+    let inserts = properties.iter().map(|(name, ident)| {
+        quote! {
+            properties.insert(
+                ::std::string::String::from(#name),
+                ::drasi_source_sdk::__private::serde_json::to_value(&self.#ident)?,
+            );
+        }
+    });
+
+    Ok(quote! {
+        impl #impl_generics ::drasi_source_sdk::ToSourceElement for #name #ty_generics #where_clause {
+            fn to_source_element(
+                &self,
+            ) -> ::std::result::Result<
+                ::drasi_source_sdk::SourceElement,
+                ::drasi_source_sdk::__private::serde_json::Error,
+            > {
+                let labels = ::std::vec![#(::std::string::String::from(#labels)),*];
+                let mut properties = ::drasi_source_sdk::__private::serde_json::Map::new();
+                #(#inserts)*
+                ::std::result::Result::Ok(#element)
+            }
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn expand_error(input: TokenStream2, kind: ElementKind) -> String {
+        let input: DeriveInput = syn::parse2(input).unwrap();
+        match expand(input, kind) {
+            Ok(_) => panic!("expected an error"),
+            Err(e) => e.to_string(),
+        }
+    }
+
+    #[test]
+    fn test_node_requires_id() {
+        let error = expand_error(
+            quote! {
+                struct Vehicle {
+                    name: String,
+                }
+            },
+            ElementKind::Node,
+        );
+        assert_eq!(error, "a field must be marked with `#[drasi(id)]`");
+    }
+
+    #[test]
+    fn test_relation_requires_endpoints() {
+        let error = expand_error(
+            quote! {
+                struct LocatedAt {
+                    #[drasi(id)]
+                    id: String,
+                    #[drasi(start)]
+                    vehicle: String,
+                }
+            },
+            ElementKind::Relation,
+        );
+        assert_eq!(error, "a field must be marked with `#[drasi(end)]`");
+    }
+
+    #[test]
+    fn test_node_rejects_endpoints() {
+        let error = expand_error(
+            quote! {
+                struct Vehicle {
+                    #[drasi(id)]
+                    id: String,
+                    #[drasi(start)]
+                    location: String,
+                }
+            },
+            ElementKind::Node,
+        );
+        assert_eq!(error, "`start` and `end` can only be used on relations");
+    }
+
+    #[test]
+    fn test_rejects_duplicate_id() {
+        let error = expand_error(
+            quote! {
+                struct Vehicle {
+                    #[drasi(id)]
+                    id: String,
+                    #[drasi(id)]
+                    vin: String,
+                }
+            },
+            ElementKind::Node,
+        );
+        assert_eq!(error, "only one field can be the id");
+    }
+
+    #[test]
+    fn test_rejects_unknown_attribute() {
+        let error = expand_error(
+            quote! {
+                struct Vehicle {
+                    #[drasi(id, key)]
+                    id: String,
+                }
+            },
+            ElementKind::Node,
+        );
+        assert_eq!(error, "expected `id`, `start`, `end`, `skip` or `rename`");
+    }
+}
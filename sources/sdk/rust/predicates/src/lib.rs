@@ -0,0 +1,190 @@
+// Copyright 2024 The Drasi Authors.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::{cmp::Ordering, collections::HashMap};
+
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+
+/// Predicates on the properties of elements, keyed by label.
+/// An element with a label only matches the predicates if it satisfies every predicate of the label.
+pub type LabelPredicates = HashMap<String, Vec<PropertyPredicate>>;
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PropertyPredicate {
+    pub property: String,
+
+    #[serde(flatten)]
+    pub condition: PredicateCondition,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum PredicateCondition {
+    Eq(Value),
+    In(Vec<Value>),
+    Range(RangeBounds),
+}
+
+#[derive(Debug, Clone, PartialEq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RangeBounds {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub gt: Option<Value>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub gte: Option<Value>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub lt: Option<Value>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub lte: Option<Value>,
+}
+
+impl PropertyPredicate {
+    /// Missing properties are treated as null
+    pub fn matches(&self, properties: &Map<String, Value>) -> bool {
+        let value = properties.get(&self.property).unwrap_or(&Value::Null);
+
+        match &self.condition {
+            PredicateCondition::Eq(expected) => values_equal(value, expected),
+            PredicateCondition::In(expected) => expected.iter().any(|e| values_equal(value, e)),
+            PredicateCondition::Range(range) => {
+                let within = |bound: &Option<Value>, accept: fn(Ordering) -> bool| match bound {
+                    Some(bound) => compare(value, bound).is_some_and(accept),
+                    None => true,
+                };
+
+                within(&range.gt, |o| o == Ordering::Greater)
+                    && within(&range.gte, |o| o != Ordering::Less)
+                    && within(&range.lt, |o| o == Ordering::Less)
+                    && within(&range.lte, |o| o != Ordering::Greater)
+            }
+        }
+    }
+}
+
+pub fn matches_all(predicates: &[PropertyPredicate], properties: &Map<String, Value>) -> bool {
+    predicates.iter().all(|p| p.matches(properties))
+}
+
+/// Reads the predicates of a subscription, which are optional
+pub fn parse_label_predicates(value: &Value) -> Result<LabelPredicates, serde_json::Error> {
+    match value {
+        Value::Null => Ok(LabelPredicates::new()),
+        value => serde_json::from_value(value.clone()),
+    }
+}
+
+fn values_equal(a: &Value, b: &Value) -> bool {
+    match (a, b) {
+        (Value::Number(_), Value::Number(_)) => compare(a, b) == Some(Ordering::Equal),
+        _ => a == b,
+    }
+}
+
+/// Numbers and strings are ordered, values of other or different types are not comparable
+fn compare(a: &Value, b: &Value) -> Option<Ordering> {
+    match (a, b) {
+        (Value::Number(a), Value::Number(b)) => match (a.as_i64(), b.as_i64()) {
+            (Some(a), Some(b)) => Some(a.cmp(&b)),
+            _ => a.as_f64()?.partial_cmp(&b.as_f64()?),
+        },
+        (Value::String(a), Value::String(b)) => Some(a.cmp(b)),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn predicate(value: Value) -> PropertyPredicate {
+        serde_json::from_value(value).unwrap()
+    }
+
+    fn properties(value: Value) -> Map<String, Value> {
+        value.as_object().unwrap().clone()
+    }
+
+    #[test]
+    fn test_deserialize_predicates() {
+        let predicates: LabelPredicates = serde_json::from_value(json!({
+            "Order": [
+                { "property": "region", "eq": "EU" },
+                { "property": "status", "in": ["open", "late"] },
+                { "property": "total", "range": { "gte": 100, "lt": 500 } }
+            ]
+        }))
+        .unwrap();
+
+        assert_eq!(
+            predicates["Order"],
+            vec![
+                PropertyPredicate {
+                    property: "region".to_string(),
+                    condition: PredicateCondition::Eq(json!("EU")),
+                },
+                PropertyPredicate {
+                    property: "status".to_string(),
+                    condition: PredicateCondition::In(vec![json!("open"), json!("late")]),
+                },
+                PropertyPredicate {
+                    property: "total".to_string(),
+                    condition: PredicateCondition::Range(RangeBounds {
+                        gte: Some(json!(100)),
+                        lt: Some(json!(500)),
+                        ..Default::default()
+                    }),
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_eq() {
+        let p = predicate(json!({ "property": "region", "eq": "EU" }));
+        assert!(p.matches(&properties(json!({ "region": "EU" }))));
+        assert!(!p.matches(&properties(json!({ "region": "US" }))));
+        assert!(!p.matches(&properties(json!({}))));
+
+        let p = predicate(json!({ "property": "count", "eq": 1 }));
+        assert!(p.matches(&properties(json!({ "count": 1.0 }))));
+
+        let p = predicate(json!({ "property": "region", "eq": null }));
+        assert!(p.matches(&properties(json!({}))));
+    }
+
+    #[test]
+    fn test_in() {
+        let p = predicate(json!({ "property": "status", "in": ["open", "late"] }));
+        assert!(p.matches(&properties(json!({ "status": "late" }))));
+        assert!(!p.matches(&properties(json!({ "status": "closed" }))));
+    }
+
+    #[test]
+    fn test_range() {
+        let p = predicate(json!({ "property": "total", "range": { "gt": 10, "lte": 20.5 } }));
+        assert!(!p.matches(&properties(json!({ "total": 10 }))));
+        assert!(p.matches(&properties(json!({ "total": 10.1 }))));
+        assert!(p.matches(&properties(json!({ "total": 20.5 }))));
+        assert!(!p.matches(&properties(json!({ "total": 21 }))));
+        assert!(!p.matches(&properties(json!({ "total": "15" }))));
+        assert!(!p.matches(&properties(json!({}))));
+
+        let p = predicate(json!({ "property": "name", "range": { "gte": "m" } }));
+        assert!(p.matches(&properties(json!({ "name": "maria" }))));
+        assert!(!p.matches(&properties(json!({ "name": "anna" }))));
+    }
+}
@@ -0,0 +1,142 @@
+// Copyright 2024 The Drasi Authors.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Replays a journal recorded by the source SDK with `ReactivatorBuilder::with_journal` or
+//! `PUBLISH_JOURNAL_PATH`, sending each recorded batch to a source's change topic or straight
+//! to a change router, at the original pace or faster.
+
+mod target;
+
+use std::{fs::File, io::BufReader, path::PathBuf, time::Duration};
+
+use clap::{Parser, Subcommand};
+use drasi_source_sdk::JournalReader;
+
+use crate::target::Target;
+
+#[derive(Parser)]
+#[command(
+    name = "drasi-replay",
+    version,
+    about = "Replays a journal of source changes"
+)]
+struct Args {
+    /// The journal file to replay
+    journal: PathBuf,
+
+    /// How many times faster than recorded to replay, 0 replays without waiting
+    #[arg(long, default_value_t = 1.0)]
+    speed: f64,
+
+    #[command(subcommand)]
+    target: TargetArgs,
+}
+
+#[derive(Subcommand)]
+enum TargetArgs {
+    /// Publishes to the `{source}-change` topic through a Dapr sidecar
+    Dapr {
+        #[arg(long)]
+        source_id: String,
+        #[arg(long, default_value_t = 3500)]
+        dapr_port: u16,
+        #[arg(long, default_value = "drasi-pubsub")]
+        pubsub: String,
+    },
+    /// Appends to the `{source}-change` Redis stream
+    Redis {
+        #[arg(long)]
+        source_id: String,
+        #[arg(long, default_value = "redis://127.0.0.1:6379")]
+        url: String,
+    },
+    /// Posts straight to a change router, for example one that a debug query subscribes through
+    Router {
+        /// The base URL of the change router, such as a port forwarded `{source}-change-router`
+        #[arg(long)]
+        url: String,
+    },
+}
+
+/// How long to wait before replaying an entry that was recorded `next` nanoseconds after the epoch
+fn delay(previous: u64, next: u64, speed: f64) -> Duration {
+    if speed <= 0.0 || next <= previous {
+        return Duration::ZERO;
+    }
+    Duration::from_secs_f64(Duration::from_nanos(next - previous).as_secs_f64() / speed)
+}
+
+#[tokio::main]
+async fn main() -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    env_logger::Builder::from_env(env_logger::Env::default().default_filter_or("info")).init();
+    let args = Args::parse();
+
+    let target = match &args.target {
+        TargetArgs::Dapr {
+            source_id,
+            dapr_port,
+            pubsub,
+        } => Target::dapr(source_id, *dapr_port, pubsub),
+        TargetArgs::Redis { source_id, url } => Target::redis(source_id, url).await?,
+        TargetArgs::Router { url } => Target::router(url),
+    };
+
+    let journal = JournalReader::new(BufReader::new(File::open(&args.journal)?));
+    let mut previous = None;
+    let mut entries = 0;
+    let mut changes = 0;
+
+    for entry in journal {
+        let entry = entry?;
+        if let Some(previous) = previous {
+            tokio::time::sleep(delay(previous, entry.recorded_at, args.speed)).await;
+        }
+        previous = Some(entry.recorded_at);
+
+        if let Err(e) = target.send(&entry.changes).await {
+            log::error!(
+                "Error replaying entry {} of the journal, stopping: {}",
+                entries + 1,
+                e
+            );
+            return Err(e);
+        }
+        entries += 1;
+        changes += entry.changes.len();
+        log::debug!(
+            "Replayed entry {} ({} changes)",
+            entries,
+            entry.changes.len()
+        );
+    }
+
+    log::info!("Replayed {} changes in {} entries", changes, entries);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_delay() {
+        assert_eq!(delay(1_000, 3_000_001_000, 1.0), Duration::from_secs(3));
+        assert_eq!(
+            delay(1_000, 3_000_001_000, 2.0),
+            Duration::from_millis(1500)
+        );
+        assert_eq!(delay(1_000, 3_000_001_000, 0.0), Duration::ZERO);
+        assert_eq!(delay(3_000, 1_000, 1.0), Duration::ZERO);
+    }
+}
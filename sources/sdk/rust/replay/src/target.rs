@@ -0,0 +1,101 @@
+// Copyright 2024 The Drasi Authors.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::{error::Error, time::SystemTime};
+
+use redis::{aio::MultiplexedConnection, AsyncCommands};
+use serde_json::{json, Value};
+
+/// Where replayed changes are sent
+pub enum Target {
+    /// The `{source}-change` topic, through a Dapr sidecar
+    Dapr {
+        client: reqwest::Client,
+        url: String,
+    },
+
+    /// The `{source}-change` Redis stream, read by change routers whose change ingest is `redis`
+    Redis {
+        connection: MultiplexedConnection,
+        topic: String,
+    },
+
+    /// The `/receive` endpoint of a change router, bypassing the topic
+    Router {
+        client: reqwest::Client,
+        url: String,
+    },
+}
+
+impl Target {
+    pub fn dapr(source_id: &str, dapr_port: u16, pubsub: &str) -> Self {
+        Target::Dapr {
+            client: reqwest::Client::new(),
+            url: format!(
+                "http://127.0.0.1:{}/v1.0/publish/{}/{}-change",
+                dapr_port, pubsub, source_id
+            ),
+        }
+    }
+
+    pub async fn redis(source_id: &str, url: &str) -> Result<Self, redis::RedisError> {
+        let client = redis::Client::open(url)?;
+        Ok(Target::Redis {
+            connection: client.get_multiplexed_async_connection().await?,
+            topic: format!("{}-change", source_id),
+        })
+    }
+
+    pub fn router(url: &str) -> Self {
+        Target::Router {
+            client: reqwest::Client::new(),
+            url: format!("{}/receive", url.trim_end_matches('/')),
+        }
+    }
+
+    /// Sends the changes of one journal entry, in a single call like the publisher that recorded them
+    pub async fn send(&self, changes: &[Value]) -> Result<(), Box<dyn Error + Send + Sync>> {
+        match self {
+            Target::Dapr { client, url } => {
+                client
+                    .post(url)
+                    .json(changes)
+                    .send()
+                    .await?
+                    .error_for_status()?;
+            }
+            Target::Redis { connection, topic } => {
+                let enqueue_time = SystemTime::now()
+                    .duration_since(SystemTime::UNIX_EPOCH)?
+                    .as_nanos()
+                    .to_string();
+                let items = [
+                    ("data", serde_json::to_string(changes)?),
+                    ("enqueue_time", enqueue_time),
+                ];
+                let mut connection = connection.clone();
+                let _: String = connection.xadd(topic, "*", &items).await?;
+            }
+            Target::Router { client, url } => {
+                client
+                    .post(url)
+                    .json(&json!({ "data": changes }))
+                    .send()
+                    .await?
+                    .error_for_status()?;
+            }
+        }
+        Ok(())
+    }
+}
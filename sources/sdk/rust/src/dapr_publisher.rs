@@ -50,6 +50,13 @@ impl Publisher for DaprPublisher {
     async fn publish(
         &self,
         change: SourceChange,
+    ) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+        self.publish_batch(vec![change]).await
+    }
+
+    async fn publish_batch(
+        &self,
+        changes: Vec<SourceChange>,
     ) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
         let topic = format!("{}-change", self.source_id);
 
@@ -59,7 +66,7 @@ impl Publisher for DaprPublisher {
                 "http://{}:{}/v1.0/publish/{}/{}",
                 self.dapr_host, self.dapr_port, self.pubsub, topic
             ))
-            .json(&vec![change]);
+            .json(&changes);
 
         let ctx = Span::current().context();
         let span = ctx.span();
@@ -81,7 +88,7 @@ impl Publisher for DaprPublisher {
     }
 }
 
-fn create_traceparent_header(span_context: &SpanContext) -> String {
+pub(crate) fn create_traceparent_header(span_context: &SpanContext) -> String {
     format!(
         "{:02x}-{:032x}-{:016x}-{:02x}",
         0,
@@ -0,0 +1,159 @@
+// Copyright 2024 The Drasi Authors.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::{
+    io,
+    path::{Path, PathBuf},
+};
+
+use axum::async_trait;
+use tokio::{fs, io::AsyncWriteExt, sync::Mutex};
+
+use crate::StateStore;
+
+/// Keeps each key in its own file under a directory, so state survives restarts without Dapr.
+/// Values are written to a temporary file that is then renamed over the old one,
+/// so a crash never leaves a partially written value behind.
+pub struct FileStateStore {
+    dir: PathBuf,
+    write_lock: Mutex<()>,
+}
+
+impl FileStateStore {
+    /// Opens the store, creating the directory if needed
+    pub async fn open(dir: impl Into<PathBuf>) -> io::Result<Self> {
+        let dir = dir.into();
+        fs::create_dir_all(&dir).await?;
+        Ok(FileStateStore {
+            dir,
+            write_lock: Mutex::new(()),
+        })
+    }
+
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    fn path(&self, id: &str) -> PathBuf {
+        self.dir.join(file_name(id))
+    }
+}
+
+/// Escapes everything but ASCII letters, digits, `-` and `_`, so any key is a safe file name.
+/// Escaped names never contain `.`, which leaves the `.tmp` suffix free for temporary files.
+fn file_name(id: &str) -> String {
+    let mut name = String::with_capacity(id.len());
+    for byte in id.bytes() {
+        match byte {
+            b'a'..=b'z' | b'A'..=b'Z' | b'0'..=b'9' | b'-' | b'_' => name.push(byte as char),
+            _ => name.push_str(&format!("%{:02X}", byte)),
+        }
+    }
+    name
+}
+
+#[async_trait]
+impl StateStore for FileStateStore {
+    async fn get(
+        &self,
+        id: &str,
+    ) -> Result<Option<Vec<u8>>, Box<dyn std::error::Error + Send + Sync>> {
+        match fs::read(self.path(id)).await {
+            Ok(data) => Ok(Some(data)),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(Box::new(e)),
+        }
+    }
+
+    async fn put(
+        &self,
+        id: &str,
+        value: Vec<u8>,
+    ) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+        let _guard = self.write_lock.lock().await;
+        let path = self.path(id);
+        let tmp_path = path.with_extension("tmp");
+
+        let mut file = fs::File::create(&tmp_path).await?;
+        file.write_all(&value).await?;
+        file.sync_all().await?;
+        drop(file);
+
+        fs::rename(&tmp_path, &path).await?;
+
+        // the rename is only durable once the directory itself is synced
+        #[cfg(unix)]
+        fs::File::open(&self.dir).await?.sync_all().await?;
+
+        Ok(())
+    }
+
+    async fn delete(&self, id: &str) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+        let _guard = self.write_lock.lock().await;
+        match fs::remove_file(self.path(id)).await {
+            Ok(_) => Ok(()),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(()),
+            Err(e) => Err(Box::new(e)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::SystemTime;
+
+    fn test_dir() -> PathBuf {
+        let now = SystemTime::now()
+            .duration_since(SystemTime::UNIX_EPOCH)
+            .unwrap()
+            .as_nanos();
+        std::env::temp_dir().join(format!("drasi-file-state-{}", now))
+    }
+
+    #[test]
+    fn test_file_name() {
+        assert_eq!(file_name("cursor_1-a"), "cursor_1-a");
+        assert_eq!(file_name("../etc/passwd"), "%2E%2E%2Fetc%2Fpasswd");
+        assert_eq!(file_name("a b"), "a%20b");
+    }
+
+    #[tokio::test]
+    async fn test_values_survive_reopening() {
+        let dir = test_dir();
+
+        let store = FileStateStore::open(&dir).await.unwrap();
+        assert_eq!(store.get("cursor").await.unwrap(), None);
+        store.put("cursor", b"1".to_vec()).await.unwrap();
+        store.put("cursor", b"2".to_vec()).await.unwrap();
+        store.put("other/key", b"x".to_vec()).await.unwrap();
+
+        let store = FileStateStore::open(&dir).await.unwrap();
+        assert_eq!(store.get("cursor").await.unwrap(), Some(b"2".to_vec()));
+        assert_eq!(store.get("other/key").await.unwrap(), Some(b"x".to_vec()));
+
+        store.delete("cursor").await.unwrap();
+        store.delete("cursor").await.unwrap();
+        assert_eq!(store.get("cursor").await.unwrap(), None);
+
+        let mut names = std::fs::read_dir(&dir)
+            .unwrap()
+            .map(|e| e.unwrap().file_name().into_string().unwrap())
+            .collect::<Vec<_>>();
+        names.sort();
+        assert_eq!(names, vec!["other%2Fkey"]);
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,189 @@
+// Copyright 2024 The Drasi Authors.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::{
+    io::{self, BufRead},
+    path::Path,
+    time::SystemTime,
+};
+
+use axum::async_trait;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use tokio::{fs, io::AsyncWriteExt, sync::Mutex};
+
+use crate::{models::SourceChange, Publisher};
+
+/// A line of a journal, holding the changes published in one call
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct JournalEntry {
+    /// When the changes were published, in nanoseconds since the Unix epoch
+    pub recorded_at: u64,
+    pub changes: Vec<Value>,
+}
+
+/// Publishes changes to another publisher and appends the ones it accepted to a journal file,
+/// one JSON line per published batch, so the exact sequence can be replayed later
+pub struct JournalPublisher {
+    inner: Box<dyn Publisher>,
+    journal: Mutex<fs::File>,
+}
+
+impl JournalPublisher {
+    /// Opens the journal for appending, creating it if needed
+    pub async fn open(inner: Box<dyn Publisher>, path: impl AsRef<Path>) -> io::Result<Self> {
+        let journal = fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(path)
+            .await?;
+
+        Ok(JournalPublisher {
+            inner,
+            journal: Mutex::new(journal),
+        })
+    }
+
+    async fn record(
+        &self,
+        changes: &[SourceChange],
+    ) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+        let recorded_at = SystemTime::now()
+            .duration_since(SystemTime::UNIX_EPOCH)?
+            .as_nanos() as u64;
+        let entry = serde_json::json!({
+            "recordedAt": recorded_at,
+            "changes": changes,
+        });
+        let mut line = serde_json::to_vec(&entry)?;
+        line.push(b'\n');
+
+        let mut journal = self.journal.lock().await;
+        journal.write_all(&line).await?;
+        journal.flush().await?;
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl Publisher for JournalPublisher {
+    async fn publish(
+        &self,
+        change: SourceChange,
+    ) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+        self.publish_batch(vec![change]).await
+    }
+
+    async fn publish_batch(
+        &self,
+        changes: Vec<SourceChange>,
+    ) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+        if changes.is_empty() {
+            return Ok(());
+        }
+
+        // the journal only holds changes that were actually published, and a failure to
+        // record them must not hold back the source
+        self.inner.publish_batch(changes.clone()).await?;
+        if let Err(e) = self.record(&changes).await {
+            log::error!("Error writing to the publish journal: {}", e);
+        }
+        Ok(())
+    }
+}
+
+/// Reads the entries of a journal in the order they were recorded
+pub struct JournalReader<R: BufRead> {
+    lines: io::Lines<R>,
+}
+
+impl<R: BufRead> JournalReader<R> {
+    pub fn new(reader: R) -> Self {
+        JournalReader {
+            lines: reader.lines(),
+        }
+    }
+}
+
+impl<R: BufRead> Iterator for JournalReader<R> {
+    type Item = io::Result<JournalEntry>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            let line = match self.lines.next()? {
+                Ok(line) => line,
+                Err(e) => return Some(Err(e)),
+            };
+            if line.trim().is_empty() {
+                continue;
+            }
+            return Some(serde_json::from_str(&line).map_err(io::Error::from));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{ChangeOp, DebugPublisher, SourceElement};
+    use serde_json::Map;
+
+    fn change(id: &str) -> SourceChange {
+        let node = SourceElement::Node {
+            id: id.to_string(),
+            labels: vec!["Item".to_string()],
+            properties: Map::new(),
+        };
+        SourceChange::new(ChangeOp::Create, node, 0, 0, 0, None)
+    }
+
+    #[tokio::test]
+    async fn test_journal_round_trip() {
+        let now = SystemTime::now()
+            .duration_since(SystemTime::UNIX_EPOCH)
+            .unwrap()
+            .as_nanos();
+        let path = std::env::temp_dir().join(format!("drasi-journal-{}.jsonl", now));
+
+        let publisher = JournalPublisher::open(Box::new(DebugPublisher::new()), &path)
+            .await
+            .unwrap();
+        publisher
+            .publish_batch(vec![change("1"), change("2")])
+            .await
+            .unwrap();
+        publisher.publish(change("3")).await.unwrap();
+
+        let file = std::fs::File::open(&path).unwrap();
+        let entries = JournalReader::new(io::BufReader::new(file))
+            .collect::<io::Result<Vec<_>>>()
+            .unwrap();
+
+        assert_eq!(entries.len(), 2);
+        assert!(entries[0].recorded_at <= entries[1].recorded_at);
+        let ids: Vec<Vec<&str>> = entries
+            .iter()
+            .map(|e| {
+                e.changes
+                    .iter()
+                    .map(|c| c["payload"]["after"]["id"].as_str().unwrap())
+                    .collect()
+            })
+            .collect();
+        assert_eq!(ids, vec![vec!["1", "2"], vec!["3"]]);
+
+        std::fs::remove_file(&path).unwrap();
+    }
+}
@@ -12,7 +12,7 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
-use std::{env, error::Error};
+use std::{env, error::Error, time::Duration};
 
 pub use async_stream::stream;
 use axum::async_trait;
@@ -20,22 +20,57 @@ use axum::async_trait;
 mod dapr_publisher;
 mod dapr_statestore;
 mod debug_publisher;
+mod file_statestore;
+mod journal;
 mod memory_statestore;
 mod models;
 mod proxy;
 mod reactivator;
+mod redis_publisher;
+mod redis_statestore;
+mod retry;
+mod schema;
+mod sql;
 mod telemetry;
 
 pub use debug_publisher::DebugPublisher;
+use drasi_source_predicates as predicates;
+pub use drasi_source_sdk_derive::{DrasiNode, DrasiRelation};
+pub use file_statestore::FileStateStore;
+pub use journal::{JournalEntry, JournalPublisher, JournalReader};
 pub use memory_statestore::MemoryStateStore;
 pub use models::*;
+pub use predicates::*;
 pub use proxy::*;
 pub use reactivator::*;
+pub use redis_publisher::RedisPublisher;
+pub use redis_statestore::RedisStateStore;
+pub use retry::RetryPolicy;
+pub use schema::*;
+pub use sql::*;
 use tokio::signal;
+pub use tokio_util::sync::CancellationToken;
+
+#[doc(hidden)]
+pub mod __private {
+    // used by the code generated by the derive macros
+    pub use serde_json;
+}
 
 #[async_trait]
-pub trait Publisher {
+pub trait Publisher: Send + Sync {
     async fn publish(&self, change: SourceChange) -> Result<(), Box<dyn Error + Send + Sync>>;
+
+    /// Publishes several changes at once, in order. Publishers that can send a batch in a single call should override this.
+    async fn publish_batch(
+        &self,
+        changes: Vec<SourceChange>,
+    ) -> Result<(), Box<dyn Error + Send + Sync>> {
+        for change in changes {
+            self.publish(change).await?;
+        }
+        Ok(())
+    }
 }
 
 #[async_trait]
@@ -52,6 +87,16 @@ pub fn get_config_value(key: &str) -> Option<String> {
     }
 }
 
+/// How long to keep publishing pending changes, or serving bootstrap streams, after a shutdown signal.
+/// Read from `SHUTDOWN_DRAIN_TIMEOUT_MS`, by default 20 seconds so draining finishes within the default pod termination grace period.
+fn drain_timeout_from_config() -> Duration {
+    Duration::from_millis(
+        get_config_value("SHUTDOWN_DRAIN_TIMEOUT_MS")
+            .and_then(|v| v.parse().ok())
+            .unwrap_or(20_000),
+    )
+}
+
 async fn shutdown_signal() {
     let ctrl_c = async {
         signal::ctrl_c()
@@ -19,6 +19,8 @@ use tokio::sync::Mutex;
 
 use crate::StateStore;
 
+/// Keeps state in memory, clones share the same state
+#[derive(Clone)]
 pub struct MemoryStateStore {
     data: Arc<Mutex<HashMap<String, Vec<u8>>>>,
 }
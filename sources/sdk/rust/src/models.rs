@@ -16,14 +16,67 @@ use serde::{ser::SerializeStruct, Deserialize, Serialize, Serializer};
 use serde_json::{Map, Value};
 use std::env;
 
-#[derive(Deserialize, Debug)]
+use crate::{
+    predicates::{matches_all, LabelPredicates},
+    BootstrapError,
+};
+
+#[derive(Deserialize, Debug, Clone)]
 #[serde(rename_all = "camelCase")]
 pub struct BootstrapRequest {
     pub node_labels: Vec<String>,
     pub rel_labels: Vec<String>,
+    /// Property predicates by node label, sources may use these to fetch fewer nodes
+    #[serde(default)]
+    pub node_predicates: LabelPredicates,
+    /// Property predicates by relation label, sources may use these to fetch fewer relations
+    #[serde(default)]
+    pub rel_predicates: LabelPredicates,
+    /// The maximum number of elements to return, all of them are returned when not set
+    #[serde(default)]
+    pub page_size: Option<usize>,
+    /// Returned by the proxy with the previous page, to request the next page
+    #[serde(default)]
+    pub continuation_token: Option<String>,
+}
+
+impl BootstrapRequest {
+    /// The number of elements returned by the previous pages, sources that page natively should skip these
+    pub fn offset(&self) -> Result<usize, BootstrapError> {
+        match &self.continuation_token {
+            Some(token) => token.parse().map_err(|_| {
+                BootstrapError::InvalidRequest(format!("Invalid continuation token: {}", token))
+            }),
+            None => Ok(0),
+        }
+    }
+
+    /// Returns false if the element should not be part of the bootstrap data, because none of its
+    /// requested labels have predicates that it satisfies.
+    /// Elements without any requested labels are not filtered.
+    pub fn matches(&self, element: &SourceElement) -> bool {
+        let (labels, properties, requested, predicates) = match element {
+            SourceElement::Node {
+                labels, properties, ..
+            } => (labels, properties, &self.node_labels, &self.node_predicates),
+            SourceElement::Relation {
+                labels, properties, ..
+            } => (labels, properties, &self.rel_labels, &self.rel_predicates),
+        };
+
+        let mut relevant = labels.iter().filter(|l| requested.contains(l)).peekable();
+        if relevant.peek().is_none() {
+            return true;
+        }
+
+        relevant.any(|label| match predicates.get(label) {
+            Some(predicates) => matches_all(predicates, properties),
+            None => true,
+        })
+    }
 }
 
-#[derive(Serialize, Debug)]
+#[derive(Serialize, Debug, Clone, PartialEq)]
 #[serde(rename_all = "camelCase")]
 #[serde(untagged)]
 pub enum SourceElement {
@@ -43,7 +96,12 @@ pub enum SourceElement {
     },
 }
 
-#[derive(Serialize, Debug, Clone, Copy)]
+/// Maps a type to a source element, usually implemented with `#[derive(DrasiNode)]` or `#[derive(DrasiRelation)]`
+pub trait ToSourceElement {
+    fn to_source_element(&self) -> Result<SourceElement, serde_json::Error>;
+}
+
+#[derive(Serialize, Debug, Clone, Copy, PartialEq, Eq)]
 pub enum ChangeOp {
     #[serde(rename = "i")]
     Create,
@@ -55,7 +113,15 @@ pub enum ChangeOp {
     Delete,
 }
 
-#[derive(Debug)]
+/// A position in the source, that the reactivator saves to the state store once the change carrying it
+/// has been published, so the stream producer can resume from it after a restart
+#[derive(Debug, Clone, PartialEq)]
+pub struct Checkpoint {
+    pub key: String,
+    pub value: Vec<u8>,
+}
+
+#[derive(Debug, Clone)]
 pub struct SourceChange {
     op: ChangeOp,
     element: SourceElement,
@@ -64,6 +130,7 @@ pub struct SourceChange {
     reactivator_end_ns: u128,
     source_ns: u128,
     seq: u64,
+    checkpoint: Option<Checkpoint>,
 }
 
 impl SourceChange {
@@ -83,9 +150,31 @@ impl SourceChange {
             reactivator_end_ns: 0,
             source_ns,
             seq,
+            checkpoint: None,
         }
     }
 
+    /// Attaches a checkpoint, which is saved under `key` in the state store after the change is published
+    pub fn with_checkpoint(mut self, key: &str, value: Vec<u8>) -> Self {
+        self.checkpoint = Some(Checkpoint {
+            key: key.to_string(),
+            value,
+        });
+        self
+    }
+
+    pub fn checkpoint(&self) -> Option<&Checkpoint> {
+        self.checkpoint.as_ref()
+    }
+
+    pub fn op(&self) -> ChangeOp {
+        self.op
+    }
+
+    pub fn element(&self) -> &SourceElement {
+        &self.element
+    }
+
     pub fn set_reactivator_end_ns(&mut self, reactivator_end_ns: u128) {
         self.reactivator_end_ns = reactivator_end_ns;
     }
@@ -350,4 +439,71 @@ mod tests {
             expected
         );
     }
+
+    #[test]
+    fn test_bootstrap_request_without_predicates() {
+        let request: BootstrapRequest = serde_json::from_value(json!({
+            "nodeLabels": ["Order"],
+            "relLabels": []
+        }))
+        .unwrap();
+
+        assert!(request.node_predicates.is_empty());
+        assert!(request.rel_predicates.is_empty());
+        assert_eq!(request.page_size, None);
+        assert_eq!(request.offset().unwrap(), 0);
+    }
+
+    #[test]
+    fn test_bootstrap_request_continuation() {
+        let request: BootstrapRequest = serde_json::from_value(json!({
+            "nodeLabels": ["Order"],
+            "relLabels": [],
+            "pageSize": 100,
+            "continuationToken": "200"
+        }))
+        .unwrap();
+
+        assert_eq!(request.page_size, Some(100));
+        assert_eq!(request.offset().unwrap(), 200);
+
+        let request = BootstrapRequest {
+            continuation_token: Some("abc".to_string()),
+            ..request
+        };
+        assert!(matches!(
+            request.offset(),
+            Err(BootstrapError::InvalidRequest(_))
+        ));
+    }
+
+    #[test]
+    fn test_bootstrap_request_matches() {
+        let request: BootstrapRequest = serde_json::from_value(json!({
+            "nodeLabels": ["Order", "Customer"],
+            "relLabels": ["PLACED"],
+            "nodePredicates": {
+                "Order": [{ "property": "region", "eq": "EU" }]
+            }
+        }))
+        .unwrap();
+
+        let node = |labels: Vec<&str>, region: &str| SourceElement::Node {
+            id: "1".to_string(),
+            labels: labels.into_iter().map(String::from).collect(),
+            properties: json!({ "region": region }).as_object().unwrap().clone(),
+        };
+
+        assert!(request.matches(&node(vec!["Order"], "EU")));
+        assert!(!request.matches(&node(vec!["Order"], "US")));
+        assert!(request.matches(&node(vec!["Order", "Customer"], "US")));
+        assert!(request.matches(&node(vec!["Unknown"], "US")));
+        assert!(request.matches(&SourceElement::Relation {
+            id: "2".to_string(),
+            labels: vec!["PLACED".to_string()],
+            properties: Map::new(),
+            start_id: "1".to_string(),
+            end_id: "3".to_string(),
+        }));
+    }
 }
@@ -13,26 +13,44 @@
 // limitations under the License.
 
 use std::{
-    env, fs::OpenOptions, future::Future, io::Write, net::SocketAddr, panic, pin::Pin, sync::Arc,
+    env,
+    fs::OpenOptions,
+    future::{Future, IntoFuture},
+    io::Write,
+    net::SocketAddr,
+    panic,
+    pin::Pin,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    },
+    task::{Context as TaskContext, Poll},
+    time::Duration,
 };
 
 use axum::{
     extract::State,
+    http::{HeaderMap, HeaderValue},
     response::IntoResponse,
     routing::{get, post},
     Json, Router,
 };
 use axum_streams::StreamBodyAs;
-use futures::Stream;
+use futures::{future, stream, Stream, StreamExt};
 use thiserror::Error;
 use tokio::net::TcpListener;
 
 use crate::{
+    drain_timeout_from_config,
     models::{BootstrapRequest, SourceElement},
+    schema::SourceSchema,
     shutdown_signal,
     telemetry::init_tracer,
 };
 
+/// Response header holding the token to request the next page of bootstrap data with
+pub const CONTINUATION_TOKEN_HEADER: &str = "x-continuation-token";
+
 pub type BootstrapStream = Pin<Box<dyn Stream<Item = Result<SourceElement, BootstrapError>> + Send + Sync>>;
 
 #[derive(Error, Debug)]
@@ -50,6 +68,13 @@ impl Into<axum::Error> for BootstrapError {
     }
 }
 
+/// The bootstrap data the proxy returns for a request
+pub struct BootstrapResponse {
+    pub elements: BootstrapStream,
+    /// Requests the next page, when a page was requested and there are more elements
+    pub continuation_token: Option<String>,
+}
+
 pub struct SourceProxy<Response, Context>
 where
     Response: Future<Output = Result<BootstrapStream, BootstrapError>> + Send + Sync,
@@ -57,7 +82,10 @@ where
 {
     stream_producer: fn(Context, BootstrapRequest) -> Response,
     port: u16,
-    context: Context
+    context: Context,
+    source_paging: bool,
+    schema: Option<SourceSchema>,
+    drain_timeout: Duration,
 }
 
 impl<Response, Context> SourceProxy<Response, Context>
@@ -65,6 +93,20 @@ where
     Response: Future<Output = Result<BootstrapStream, BootstrapError>> + Send + Sync + 'static,
     Context: Send + Sync + Clone + 'static,
 {
+    /// Handles a bootstrap request in-process, the same way as a request to `/acquire-stream`
+    pub async fn bootstrap(
+        &self,
+        request: BootstrapRequest,
+    ) -> Result<BootstrapResponse, BootstrapError> {
+        acquire(
+            self.stream_producer,
+            self.context.clone(),
+            self.source_paging,
+            request,
+        )
+        .await
+    }
+
     pub async fn start(&self) {
         panic::set_hook(Box::new(|info| {
             if let Some(message) = info.payload().downcast_ref::<String>() {
@@ -97,11 +139,16 @@ where
 
         let app_state = Arc::new(AppState::<Response, Context> {
             stream_producer: self.stream_producer,
-            context: self.context.clone()
+            context: self.context.clone(),
+            source_paging: self.source_paging,
+            schema: self.schema.clone(),
+            active_streams: Arc::new(AtomicUsize::new(0)),
         });
+        let active_streams = app_state.active_streams.clone();
 
         let app = Router::new()
             .route("/acquire-stream", post(proxy_stream))
+            .route("/schema", get(get_schema))
             .route(
                 "/supports-stream",
                 get(|| async { (axum::http::StatusCode::NO_CONTENT, "") })
@@ -118,9 +165,37 @@ where
             }
         };
 
-        let final_result = axum::serve(listener, app.into_make_service())
-            .with_graceful_shutdown(shutdown_signal())
-            .await;
+        // after the shutdown signal the server stops accepting connections, and waits for active bootstrap streams
+        // to complete until the drain timeout
+        let (stopping_tx, stopping_rx) = tokio::sync::oneshot::channel();
+        let server = axum::serve(listener, app.into_make_service())
+            .with_graceful_shutdown(async move {
+                shutdown_signal().await;
+                _ = stopping_tx.send(());
+            })
+            .into_future();
+        let drain_timeout = self.drain_timeout;
+        let drain_deadline = async move {
+            match stopping_rx.await {
+                Ok(()) => {
+                    log::info!(
+                        "Shutting down, waiting for {} active bootstrap streams",
+                        active_streams.load(Ordering::SeqCst)
+                    );
+                    tokio::time::sleep(drain_timeout).await;
+                    active_streams.load(Ordering::SeqCst)
+                }
+                Err(_) => future::pending().await,
+            }
+        };
+
+        let final_result = tokio::select! {
+            result = server => result,
+            active = drain_deadline => {
+                log::warn!("Drain timeout elapsed, aborting {} active bootstrap streams", active);
+                Ok(())
+            }
+        };
 
         log::info!("Http server shutting down");
         if let Err(err) = final_result {
@@ -140,6 +215,9 @@ where
     stream_producer: Option<fn(Context, BootstrapRequest) -> Response>,
     port: Option<u16>,
     context: Option<Context>,
+    source_paging: bool,
+    schema: Option<SourceSchema>,
+    drain_timeout: Option<Duration>,
 }
 
 impl<Response, Context> SourceProxyBuilder<Response, Context>
@@ -152,6 +230,9 @@ where
             stream_producer: None,
             port: None,
             context: None,
+            source_paging: false,
+            schema: None,
+            drain_timeout: None,
         }
     }
 
@@ -168,6 +249,16 @@ where
         self
     }
 
+    /// The stream producer starts its stream at `BootstrapRequest::offset` when a page is requested,
+    /// instead of the proxy skipping the elements of the previous pages.
+    /// The offset counts the elements that match the predicates of the request, so a source paged producer must
+    /// apply the predicates exactly, for example with `BootstrapRequest::sql_filter`. An element that does not match
+    /// fails the page rather than shifting the pages that follow.
+    pub fn with_source_paging(mut self) -> Self {
+        self.source_paging = true;
+        self
+    }
+
     pub fn with_context(
         mut self,
         context: Context
@@ -176,6 +267,19 @@ where
         self
     }
 
+    /// Declares the labels and properties the source produces, served at `/schema`
+    pub fn with_schema(mut self, schema: SourceSchema) -> Self {
+        self.schema = Some(schema);
+        self
+    }
+
+    /// Sets how long active bootstrap streams may still complete after a shutdown signal, by default 20 seconds.
+    /// Can also be set with the `SHUTDOWN_DRAIN_TIMEOUT_MS` environment variable.
+    pub fn with_drain_timeout(mut self, drain_timeout: Duration) -> Self {
+        self.drain_timeout = Some(drain_timeout);
+        self
+    }
+
     pub fn build(self) -> SourceProxy<Response, Context> {
         SourceProxy {
             stream_producer: self.stream_producer.unwrap(),
@@ -184,6 +288,9 @@ where
                 Some(s) => s,
                 None => panic!("context not defined"),
             },
+            source_paging: self.source_paging,
+            schema: self.schema,
+            drain_timeout: self.drain_timeout.unwrap_or_else(drain_timeout_from_config),
         }
     }
 }
@@ -207,6 +314,56 @@ where
 {
     stream_producer: fn(Context, BootstrapRequest) -> Response,
     context: Context,
+    source_paging: bool,
+    schema: Option<SourceSchema>,
+    active_streams: Arc<AtomicUsize>,
+}
+
+/// A bootstrap stream being served, counted in `active_streams` until it completes or is dropped
+struct TrackedStream {
+    elements: BootstrapStream,
+    active_streams: Arc<AtomicUsize>,
+}
+
+impl TrackedStream {
+    fn new(elements: BootstrapStream, active_streams: Arc<AtomicUsize>) -> Self {
+        active_streams.fetch_add(1, Ordering::SeqCst);
+        TrackedStream {
+            elements,
+            active_streams,
+        }
+    }
+}
+
+impl Stream for TrackedStream {
+    type Item = Result<SourceElement, BootstrapError>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut TaskContext<'_>) -> Poll<Option<Self::Item>> {
+        self.elements.as_mut().poll_next(cx)
+    }
+}
+
+impl Drop for TrackedStream {
+    fn drop(&mut self) {
+        self.active_streams.fetch_sub(1, Ordering::SeqCst);
+    }
+}
+
+async fn get_schema<Response, Context>(
+    State(state): State<Arc<AppState<Response, Context>>>,
+) -> impl IntoResponse
+where
+    Response: Future<Output = Result<BootstrapStream, BootstrapError>> + Send + Sync,
+    Context: Send + Sync + Clone + 'static,
+{
+    match &state.schema {
+        Some(schema) => Json(schema.clone()).into_response(),
+        None => (
+            axum::http::StatusCode::NOT_FOUND,
+            "The source does not declare a schema",
+        )
+            .into_response(),
+    }
 }
 
 async fn proxy_stream<Response, Context>(
@@ -217,8 +374,24 @@ where
     Response: Future<Output = Result<BootstrapStream, BootstrapError>> + Send + Sync,
     Context: Send + Sync + Clone + 'static,
 {
-    match (state.stream_producer)(state.context.clone(), request).await {
-        Ok(stream) => StreamBodyAs::json_nl_with_errors(stream).into_response(),
+    match acquire(
+        state.stream_producer,
+        state.context.clone(),
+        state.source_paging,
+        request,
+    )
+    .await
+    {
+        Ok(response) => {
+            let mut headers = HeaderMap::new();
+            if let Some(token) = response.continuation_token {
+                if let Ok(token) = HeaderValue::from_str(&token) {
+                    headers.insert(CONTINUATION_TOKEN_HEADER, token);
+                }
+            }
+            let elements = TrackedStream::new(response.elements, state.active_streams.clone());
+            (headers, StreamBodyAs::json_nl_with_errors(elements)).into_response()
+        }
         Err(e) => match e {
             BootstrapError::InvalidRequest(e) => {
                 (axum::http::StatusCode::BAD_REQUEST, e.to_string()).into_response()
@@ -229,3 +402,233 @@ where
         },
     }
 }
+
+/// Runs the stream producer, removing the elements that do not match the predicates of the request,
+/// and reads the requested page.
+///
+/// Without source paging every page runs the stream producer again and skips the elements of the previous pages,
+/// so reading a whole bootstrap a page at a time reads O(n²) elements. Sources that can seek should page natively.
+async fn acquire<Response, Context>(
+    stream_producer: fn(Context, BootstrapRequest) -> Response,
+    context: Context,
+    source_paging: bool,
+    request: BootstrapRequest,
+) -> Result<BootstrapResponse, BootstrapError>
+where
+    Response: Future<Output = Result<BootstrapStream, BootstrapError>> + Send + Sync,
+{
+    let filter = request.clone();
+    let paging = match request.page_size {
+        Some(page_size) => Some((page_size, request.offset()?)),
+        None => None,
+    };
+
+    let stream = stream_producer(context, request).await?;
+
+    match paging {
+        // the source skipped the matching elements of the previous pages, so anything it returns has to match
+        Some((page_size, offset)) if source_paging => {
+            let stream = stream.map(move |item| match item {
+                Ok(element) if !filter.matches(&element) => Err(BootstrapError::InternalError(
+                    "A source paged stream producer returned an element that does not match the predicates of the request".to_string(),
+                )),
+                item => item,
+            });
+            let page = read_page(stream, page_size, offset).await;
+            Ok(BootstrapResponse {
+                elements: Box::pin(stream::iter(page.elements)),
+                continuation_token: page.continuation_token,
+            })
+        }
+        Some((page_size, offset)) => {
+            let page = read_page(matching(stream, filter).skip(offset), page_size, offset).await;
+            Ok(BootstrapResponse {
+                elements: Box::pin(stream::iter(page.elements)),
+                continuation_token: page.continuation_token,
+            })
+        }
+        None => Ok(BootstrapResponse {
+            elements: Box::pin(matching(stream, filter)),
+            continuation_token: None,
+        }),
+    }
+}
+
+/// Sources are not required to apply the predicates, so the proxy removes anything that does not match
+fn matching(
+    stream: BootstrapStream,
+    filter: BootstrapRequest,
+) -> impl Stream<Item = Result<SourceElement, BootstrapError>> {
+    stream.filter(move |item| {
+        future::ready(match item {
+            Ok(element) => filter.matches(element),
+            Err(_) => true,
+        })
+    })
+}
+
+struct BootstrapPage {
+    elements: Vec<Result<SourceElement, BootstrapError>>,
+    continuation_token: Option<String>,
+}
+
+/// Reads one element more than the page holds, to find out if there is a next page
+async fn read_page(
+    stream: impl Stream<Item = Result<SourceElement, BootstrapError>>,
+    page_size: usize,
+    offset: usize,
+) -> BootstrapPage {
+    let mut elements: Vec<_> = stream.take(page_size + 1).collect().await;
+    let mut continuation_token = None;
+    if elements.len() > page_size && elements.iter().all(|e| e.is_ok()) {
+        elements.truncate(page_size);
+        continuation_token = Some((offset + page_size).to_string());
+    }
+
+    BootstrapPage {
+        elements,
+        continuation_token,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::{json, Map};
+
+    fn elements(count: usize) -> impl Stream<Item = Result<SourceElement, BootstrapError>> {
+        stream::iter((0..count).map(|i| {
+            Ok(SourceElement::Node {
+                id: i.to_string(),
+                labels: vec!["Item".to_string()],
+                properties: Map::new(),
+            })
+        }))
+    }
+
+    fn ids(page: &BootstrapPage) -> Vec<String> {
+        page.elements
+            .iter()
+            .map(|e| match e {
+                Ok(SourceElement::Node { id, .. }) => id.clone(),
+                _ => panic!("unexpected element"),
+            })
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn test_tracked_stream_is_counted_until_dropped() {
+        let active_streams = Arc::new(AtomicUsize::new(0));
+        let mut tracked = TrackedStream::new(Box::pin(elements(2)), active_streams.clone());
+        assert_eq!(active_streams.load(Ordering::SeqCst), 1);
+
+        assert!(tracked.next().await.is_some());
+        drop(tracked);
+        assert_eq!(active_streams.load(Ordering::SeqCst), 0);
+    }
+
+    #[tokio::test]
+    async fn test_read_page() {
+        let page = read_page(elements(5).skip(2), 2, 2).await;
+        assert_eq!(ids(&page), vec!["2", "3"]);
+        assert_eq!(page.continuation_token, Some("4".to_string()));
+
+        let page = read_page(elements(5).skip(4), 2, 4).await;
+        assert_eq!(ids(&page), vec!["4"]);
+        assert_eq!(page.continuation_token, None);
+
+        let page = read_page(elements(4).skip(2), 2, 2).await;
+        assert_eq!(ids(&page), vec!["2", "3"]);
+        assert_eq!(page.continuation_token, None);
+    }
+
+    fn region_node(id: usize, region: &str) -> Result<SourceElement, BootstrapError> {
+        let mut properties = Map::new();
+        properties.insert("region".to_string(), json!(region));
+        Ok(SourceElement::Node {
+            id: id.to_string(),
+            labels: vec!["Order".to_string()],
+            properties,
+        })
+    }
+
+    /// Returns orders alternating between EU and US, ignoring the predicates and page of the request
+    async fn all_orders(
+        _context: (),
+        _request: BootstrapRequest,
+    ) -> Result<BootstrapStream, BootstrapError> {
+        Ok(Box::pin(stream::iter((0..6).map(|i| {
+            region_node(i, if i % 2 == 0 { "EU" } else { "US" })
+        }))))
+    }
+
+    /// Returns only the EU orders, starting at the offset of the request
+    async fn eu_orders(
+        _context: (),
+        request: BootstrapRequest,
+    ) -> Result<BootstrapStream, BootstrapError> {
+        let offset = request.offset()?;
+        Ok(Box::pin(stream::iter(
+            (0..6).step_by(2).skip(offset).map(|i| region_node(i, "EU")),
+        )))
+    }
+
+    fn eu_request(continuation_token: Option<&str>) -> BootstrapRequest {
+        serde_json::from_value(json!({
+            "nodeLabels": ["Order"],
+            "relLabels": [],
+            "nodePredicates": {
+                "Order": [{ "property": "region", "eq": "EU" }]
+            },
+            "pageSize": 2,
+            "continuationToken": continuation_token,
+        }))
+        .unwrap()
+    }
+
+    async fn read(response: BootstrapResponse) -> (Vec<String>, Option<String>) {
+        let page = BootstrapPage {
+            elements: response.elements.collect().await,
+            continuation_token: response.continuation_token,
+        };
+        (ids(&page), page.continuation_token)
+    }
+
+    #[tokio::test]
+    async fn test_proxy_pages_over_matching_elements() {
+        let first = acquire(all_orders, (), false, eu_request(None))
+            .await
+            .unwrap();
+        assert_eq!(
+            read(first).await,
+            (vec!["0".into(), "2".into()], Some("2".into()))
+        );
+
+        let last = acquire(all_orders, (), false, eu_request(Some("2")))
+            .await
+            .unwrap();
+        assert_eq!(read(last).await, (vec!["4".into()], None));
+    }
+
+    #[tokio::test]
+    async fn test_source_paging_reads_from_offset() {
+        let last = acquire(eu_orders, (), true, eu_request(Some("2")))
+            .await
+            .unwrap();
+        assert_eq!(read(last).await, (vec!["4".into()], None));
+    }
+
+    #[tokio::test]
+    async fn test_source_paging_fails_on_unfiltered_elements() {
+        let response = acquire(all_orders, (), true, eu_request(None))
+            .await
+            .unwrap();
+        let elements: Vec<_> = response.elements.collect().await;
+
+        assert!(matches!(
+            elements.as_slice(),
+            [Ok(_), Err(BootstrapError::InternalError(_)), ..]
+        ));
+        assert_eq!(response.continuation_token, None);
+    }
+}
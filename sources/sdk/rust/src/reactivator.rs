@@ -14,6 +14,8 @@
 
 use std::fs::OpenOptions;
 use std::net::SocketAddr;
+use std::path::PathBuf;
+use std::time::Duration;
 use std::{env, io::Write, panic};
 use std::{future::Future, pin::Pin, sync::Arc};
 
@@ -21,18 +23,29 @@ use axum::extract::State;
 use axum::response::IntoResponse;
 use axum::routing::post;
 use axum::Router;
-use futures::{select, FutureExt, Stream, StreamExt};
+use futures::{select_biased, stream::FusedStream, FutureExt, Stream, StreamExt};
 use thiserror::Error;
 use tokio::net::TcpListener;
 use tokio::task;
+use tokio::time::Instant;
+use tokio_util::sync::CancellationToken;
 use tracing::Instrument;
 use tracing_opentelemetry::OpenTelemetrySpanExt;
 
 use crate::dapr_publisher::DaprPublisher;
 use crate::dapr_statestore::DaprStateStore;
-use crate::shutdown_signal;
+use crate::file_statestore::FileStateStore;
+use crate::journal::JournalPublisher;
+use crate::memory_statestore::MemoryStateStore;
+use crate::redis_publisher::RedisPublisher;
+use crate::redis_statestore::RedisStateStore;
+use crate::retry::RetryPolicy;
 use crate::telemetry::init_tracer;
-use crate::{models::SourceChange, Publisher, StateStore};
+use crate::{drain_timeout_from_config, get_config_value, shutdown_signal};
+use crate::{
+    models::{Checkpoint, SourceChange},
+    Publisher, StateStore,
+};
 
 #[derive(Error, Debug)]
 pub enum ReactivatorError {
@@ -41,22 +54,82 @@ pub enum ReactivatorError {
 
     #[error("Error from state store: {0}")]
     StateStoreError(Box<dyn std::error::Error>),
+
+    #[error("Error publishing change: {0}")]
+    PublishError(Box<dyn std::error::Error + Send + Sync>),
 }
 
 pub type ChangeStream = Pin<Box<dyn Stream<Item = SourceChange> + Send>>;
 
+/// How the reactivator accumulates changes into batches before publishing them
+#[derive(Debug, Clone)]
+pub struct PublishBatching {
+    /// A batch is published as soon as it holds this many changes
+    pub max_size: usize,
+    /// A batch is published once its first change has waited this long, even if it is not full
+    pub linger: Duration,
+}
+
+impl Default for PublishBatching {
+    /// Reads `PUBLISH_BATCH_SIZE` and `PUBLISH_BATCH_LINGER_MS`, by default every change is published on its own
+    fn default() -> Self {
+        PublishBatching {
+            max_size: env::var("PUBLISH_BATCH_SIZE")
+                .ok()
+                .and_then(|v| v.parse().ok())
+                .unwrap_or(1),
+            linger: Duration::from_millis(
+                env::var("PUBLISH_BATCH_LINGER_MS")
+                    .ok()
+                    .and_then(|v| v.parse().ok())
+                    .unwrap_or(0),
+            ),
+        }
+    }
+}
+
+/// The function that starts the change stream, optionally given a token that is cancelled when the reactivator shuts down
+enum StreamProducer<Context, Response> {
+    Plain(fn(Context, Arc<dyn StateStore + Send + Sync>) -> Response),
+    Cancellable(fn(Context, Arc<dyn StateStore + Send + Sync>, CancellationToken) -> Response),
+}
+
+impl<Context, Response> StreamProducer<Context, Response> {
+    /// Calls the producer, returning the token to cancel if it takes one
+    fn produce(
+        self,
+        context: Context,
+        state_store: Arc<dyn StateStore + Send + Sync>,
+    ) -> (Response, Option<CancellationToken>) {
+        match self {
+            StreamProducer::Plain(producer) => (producer(context, state_store), None),
+            StreamProducer::Cancellable(producer) => {
+                let cancellation = CancellationToken::new();
+                (
+                    producer(context, state_store, cancellation.clone()),
+                    Some(cancellation),
+                )
+            }
+        }
+    }
+}
+
 pub struct ReactivatorBuilder<Response, DeprovisionResponse, Context = ()>
 where
     Context: Send + Sync + 'static,
     Response: Future<Output = Result<ChangeStream, ReactivatorError>> + Send + 'static,
     DeprovisionResponse: Future<Output = ()> + Send + 'static,
 {
-    stream_producer: Option<fn(Context, Arc<dyn StateStore + Send + Sync>) -> Response>,
+    stream_producer: Option<StreamProducer<Context, Response>>,
     publisher: Option<Box<dyn Publisher>>,
     state_store: Option<Arc<dyn StateStore + Send + Sync>>,
     context: Option<Context>,
     deprovision_handler: Option<fn(Arc<dyn StateStore + Send + Sync>) -> DeprovisionResponse>,
     port: Option<u16>,
+    retry_policy: Option<RetryPolicy>,
+    batching: Option<PublishBatching>,
+    journal_path: Option<PathBuf>,
+    drain_timeout: Option<Duration>,
 }
 
 impl<Response, DeprovisionResponse, Context> ReactivatorBuilder<Response, DeprovisionResponse, Context>
@@ -73,6 +146,10 @@ where
             context: None,
             deprovision_handler: None,
             port: None,
+            retry_policy: None,
+            batching: None,
+            journal_path: None,
+            drain_timeout: None,
         }
     }
 
@@ -80,7 +157,22 @@ where
         mut self,
         stream_producer: fn(Context, Arc<dyn StateStore + Send + Sync>) -> Response,
     ) -> Self {
-        self.stream_producer = Some(stream_producer);
+        self.stream_producer = Some(StreamProducer::Plain(stream_producer));
+        self
+    }
+
+    /// Like `with_stream_producer`, but the producer is also given a token that is cancelled when the reactivator shuts down.
+    /// Once it is cancelled the producer should close its upstream connections, yield any changes it still holds and end the
+    /// stream, all of which are published before the reactivator stops.
+    pub fn with_cancellable_stream_producer(
+        mut self,
+        stream_producer: fn(
+            Context,
+            Arc<dyn StateStore + Send + Sync>,
+            CancellationToken,
+        ) -> Response,
+    ) -> Self {
+        self.stream_producer = Some(StreamProducer::Cancellable(stream_producer));
         self
     }
 
@@ -118,6 +210,34 @@ where
         self
     }
 
+    /// Sets how publishing changes and saving their checkpoints is retried, by default it is retried until it succeeds
+    pub fn with_retry_policy(mut self, retry_policy: RetryPolicy) -> Self {
+        self.retry_policy = Some(retry_policy);
+        self
+    }
+
+    /// Publishes changes in batches of up to `max_size`, waiting at most `linger` for a batch to fill up
+    pub fn with_batching(mut self, max_size: usize, linger: Duration) -> Self {
+        self.batching = Some(PublishBatching {
+            max_size: max_size.max(1),
+            linger,
+        });
+        self
+    }
+
+    /// Appends every published batch to a journal file, which can be replayed with `drasi-replay`.
+    /// Can also be set with the `PUBLISH_JOURNAL_PATH` environment variable.
+    pub fn with_journal(mut self, path: impl Into<PathBuf>) -> Self {
+        self.journal_path = Some(path.into());
+        self
+    }
+
+    /// Sets how long pending changes are still published after a shutdown signal, by default 20 seconds.
+    /// Can also be set with the `SHUTDOWN_DRAIN_TIMEOUT_MS` environment variable.
+    pub fn with_drain_timeout(mut self, drain_timeout: Duration) -> Self {
+        self.drain_timeout = Some(drain_timeout);
+        self
+    }
 }
 
 impl<Response, DeprovisionResponse, Context> ReactivatorBuilder<Response, DeprovisionResponse, Context>
@@ -126,26 +246,95 @@ where
     Response: Future<Output = Result<ChangeStream, ReactivatorError>> + Send,
     DeprovisionResponse: Future<Output = ()> + Send + 'static,
 { 
-    pub async fn build(self) -> Reactivator<Context, Response, DeprovisionResponse> {
-        Reactivator {
-            stream_fn: self.stream_producer.expect("Stream producer is required"),
-            publisher: self
-                .publisher
-                .unwrap_or_else(|| Box::new(DaprPublisher::new())),
-            state_store: match self.state_store {
-                Some(ss) => ss,
-                None => Arc::new(DaprStateStore::connect().await.unwrap()),
-            },
-            context: match self.context {
-                Some(s) => s,
-                None => panic!("context not defined"),
-            },
+    /// Fails if a required part is missing, or if the publisher or state store configured by the
+    /// environment cannot connect
+    pub async fn build(
+        self,
+    ) -> Result<Reactivator<Context, Response, DeprovisionResponse>, ReactivatorError> {
+        let stream_producer = self.stream_producer.ok_or_else(|| {
+            ReactivatorError::InternalError("Stream producer is required".to_string())
+        })?;
+        let context = self
+            .context
+            .ok_or_else(|| ReactivatorError::InternalError("context not defined".to_string()))?;
+
+        let mut publisher = match self.publisher {
+            Some(p) => p,
+            None => publisher_from_config().await?,
+        };
+        let journal_path = self
+            .journal_path
+            .or_else(|| get_config_value("PUBLISH_JOURNAL_PATH").map(PathBuf::from));
+        if let Some(path) = journal_path {
+            publisher = Box::new(
+                JournalPublisher::open(publisher, &path)
+                    .await
+                    .map_err(|e| ReactivatorError::PublishError(Box::new(e)))?,
+            );
+        }
+        let state_store = match self.state_store {
+            Some(ss) => ss,
+            None => state_store_from_config().await?,
+        };
+
+        Ok(Reactivator {
+            stream_producer,
+            publisher,
+            state_store,
+            context,
             deprovision_handler: self.deprovision_handler,
             port: self.port,
-        }
+            retry_policy: self.retry_policy.unwrap_or_default(),
+            batching: self.batching.unwrap_or_default(),
+            drain_timeout: self.drain_timeout.unwrap_or_else(drain_timeout_from_config),
+        })
+    }
+}
+
+/// Picks the publisher with `PUBLISHER_TYPE`, either `dapr` (the default) or `redis`
+async fn publisher_from_config() -> Result<Box<dyn Publisher>, ReactivatorError> {
+    match get_config_value("PUBLISHER_TYPE").as_deref() {
+        Some("redis") => match RedisPublisher::connect().await {
+            Ok(p) => Ok(Box::new(p)),
+            Err(e) => Err(ReactivatorError::PublishError(Box::new(e))),
+        },
+        _ => Ok(Box::new(DaprPublisher::new())),
     }
 }
 
+/// Picks the state store with `STATE_STORE_TYPE`, either `dapr` (the default), `file`, `redis` or `memory`.
+/// The file store keeps its files under `STATE_STORE_PATH`, `./state` by default.
+async fn state_store_from_config() -> Result<Arc<dyn StateStore + Send + Sync>, ReactivatorError> {
+    let state_store: Arc<dyn StateStore + Send + Sync> =
+        match get_config_value("STATE_STORE_TYPE").as_deref() {
+            None | Some("dapr") => Arc::new(
+                DaprStateStore::connect()
+                    .await
+                    .map_err(|e| ReactivatorError::StateStoreError(Box::new(e)))?,
+            ),
+            Some("file") => Arc::new(
+                FileStateStore::open(
+                    get_config_value("STATE_STORE_PATH").unwrap_or_else(|| "state".to_string()),
+                )
+                .await
+                .map_err(|e| ReactivatorError::StateStoreError(Box::new(e)))?,
+            ),
+            Some("redis") => Arc::new(
+                RedisStateStore::connect()
+                    .await
+                    .map_err(|e| ReactivatorError::StateStoreError(Box::new(e)))?,
+            ),
+            Some("memory") => Arc::new(MemoryStateStore::new()),
+            Some(other) => {
+                return Err(ReactivatorError::InternalError(format!(
+                    "Unknown state store type: {}",
+                    other
+                )))
+            }
+        };
+    Ok(state_store)
+}
+
 impl<Response, DeprovisionResponse> ReactivatorBuilder<Response, DeprovisionResponse, ()>
 where
     Response: Future<Output = Result<ChangeStream, ReactivatorError>> + Send,
@@ -165,12 +354,15 @@ where
     Response: Future<Output = Result<ChangeStream, ReactivatorError>> + Send + 'static,
     DeprovisionResponse: Future<Output = ()> + Send + 'static,
 {
-    stream_fn: fn(Context, Arc<dyn StateStore + Send + Sync>) -> Response,
+    stream_producer: StreamProducer<Context, Response>,
     publisher: Box<dyn Publisher>,
     state_store: Arc<dyn StateStore + Send + Sync>,
     context: Context,
     deprovision_handler: Option<fn(Arc<dyn StateStore + Send + Sync>) -> DeprovisionResponse>,
     port: Option<u16>,
+    retry_policy: RetryPolicy,
+    batching: PublishBatching,
+    drain_timeout: Duration,
 }
 
 impl<Context, Response, DeprovisionResponse> Reactivator<Context, Response, DeprovisionResponse>
@@ -207,9 +399,11 @@ where
 
         log::info!("Initialized tracing");
 
-        let producer = self.stream_fn;        
         let state_store = self.state_store.clone();
-        let mut stream = producer(self.context, state_store.clone()).await.unwrap().fuse();
+        let (stream, cancellation) = self
+            .stream_producer
+            .produce(self.context, state_store.clone());
+        let mut stream = stream.await.unwrap().fuse();
         let port = self.port.unwrap_or(80);
         let deprovision_handler = self.deprovision_handler;
 
@@ -242,43 +436,252 @@ where
             }
         });
 
-        let shutdown = shutdown_signal().fuse();
-        futures::pin_mut!(shutdown);
-
-        loop {
-            select! {
-                data = stream.next() => {
-                    match data {
-                        Some(mut data) => {
-                            let span = tracing::span!(tracing::Level::INFO, "publish_change");
-                            span.set_attribute("drasi.source.id", source_id.clone());
-                            let reactivator_end_ns = {
-                                let now = std::time::SystemTime::now();
-                                now.duration_since(std::time::UNIX_EPOCH)
-                                    .expect("Time went backwards")
-                                    .as_nanos() as u128
-                            };
-                            data.set_reactivator_end_ns(reactivator_end_ns);
-                            if let Err(err) = self.publisher.publish(data).instrument(span).await {
-                                panic!("Error publishing: {}", err)
-                            }
-                        },
-                        None => {
-                            log::info!("Change stream ended");
-                            break;
+        let result = publish_stream(
+            &mut stream,
+            Shutdown {
+                signal: shutdown_signal(),
+                cancellation,
+                drain_timeout: self.drain_timeout,
+            },
+            self.publisher.as_ref(),
+            self.state_store.as_ref(),
+            &self.retry_policy,
+            &self.batching,
+            &source_id,
+        )
+        .await;
+
+        opentelemetry::global::shutdown_tracer_provider();
+        tokio::task::yield_now().await;
+
+        // changes that could not be published are read again from the last checkpoint after a restart
+        if let Err(err) = result {
+            panic!("Stopping reactivator: {}", err);
+        }
+    }
+
+    /// Publishes the changes of the stream producer until the stream ends or `shutdown` completes, then drains it as `start` does.
+    /// Unlike `start`, this does not set up tracing, the panic hook or the deprovision endpoint, so it can be used to
+    /// run the reactivator in-process, for example in tests.
+    pub async fn run_until(
+        self,
+        shutdown: impl Future<Output = ()>,
+    ) -> Result<(), ReactivatorError> {
+        let source_id = env::var("SOURCE_ID").unwrap_or_default();
+        let (stream, cancellation) = self
+            .stream_producer
+            .produce(self.context, self.state_store.clone());
+        let mut stream = stream.await?.fuse();
+
+        publish_stream(
+            &mut stream,
+            Shutdown {
+                signal: shutdown,
+                cancellation,
+                drain_timeout: self.drain_timeout,
+            },
+            self.publisher.as_ref(),
+            self.state_store.as_ref(),
+            &self.retry_policy,
+            &self.batching,
+            &source_id,
+        )
+        .await
+    }
+}
+
+/// How the reactivator stops once `signal` completes
+struct Shutdown<F> {
+    signal: F,
+    /// Cancelled to ask the stream producer to stop, if it takes a token
+    cancellation: Option<CancellationToken>,
+    /// How long pending changes are still published after the signal
+    drain_timeout: Duration,
+}
+
+/// Publishes the changes from the stream until it ends or the reactivator shuts down.
+/// Changes are accumulated into batches, a batch is published when it is full or when its first change
+/// has waited for the linger duration.
+///
+/// On shutdown, a publish in progress is finished, the stream producer is cancelled and the pending batch is published along
+/// with any changes a cancellable producer still yields, so the last checkpoint is saved. This all has to complete within the
+/// drain timeout, anything left unpublished is read again from the last checkpoint after a restart.
+///
+/// Fails if a batch cannot be published once its retries run out, so the reactivator can restart from the last checkpoint.
+async fn publish_stream(
+    stream: &mut (impl FusedStream<Item = SourceChange> + Unpin),
+    shutdown: Shutdown<impl Future<Output = ()>>,
+    publisher: &dyn Publisher,
+    state_store: &(dyn StateStore + Send + Sync),
+    retry_policy: &RetryPolicy,
+    batching: &PublishBatching,
+    source_id: &str,
+) -> Result<(), ReactivatorError> {
+    let signal = shutdown.signal.fuse();
+    futures::pin_mut!(signal);
+
+    let mut batch = Vec::new();
+    let mut deadline = None;
+    let mut drain_deadline = None;
+
+    loop {
+        let linger = async move {
+            match deadline {
+                Some(deadline) => tokio::time::sleep_until(deadline).await,
+                None => futures::future::pending().await,
+            }
+        }
+        .fuse();
+        futures::pin_mut!(linger);
+
+        // shutdown is checked first, so no more changes are taken from the stream once it completes
+        let flush = select_biased! {
+            _ = signal => {
+                log::info!("Terminating");
+                drain_deadline = Some(Instant::now() + shutdown.drain_timeout);
+                break;
+            },
+            data = stream.next() => {
+                match data {
+                    Some(data) => {
+                        if batch.is_empty() {
+                            deadline = Some(Instant::now() + batching.linger);
                         }
+                        batch.push(data);
+                        batch.len() >= batching.max_size
+                    },
+                    None => {
+                        log::info!("Change stream ended");
+                        break;
                     }
-                },
-                _ = shutdown => {
-                    log::info!("Terminating");
-                    break;
                 }
+            },
+            _ = linger => true,
+        };
+
+        if flush {
+            deadline = None;
+            let span = tracing::span!(tracing::Level::INFO, "publish_change");
+            span.set_attribute("drasi.source.id", source_id.to_string());
+            let publish = publish_batch(
+                publisher,
+                state_store,
+                retry_policy,
+                std::mem::take(&mut batch),
+            )
+            .instrument(span)
+            .fuse();
+            futures::pin_mut!(publish);
+
+            let published = select_biased! {
+                published = publish => Some(published),
+                _ = signal => None,
+            };
+            let published = match published {
+                Some(published) => published,
+                None => {
+                    // the batch was already taken from the stream, so it is finished before terminating
+                    log::info!("Terminating, finishing the publish in progress");
+                    let deadline = Instant::now() + shutdown.drain_timeout;
+                    drain_deadline = Some(deadline);
+                    match tokio::time::timeout_at(deadline, publish.as_mut()).await {
+                        Ok(published) => published,
+                        Err(_) => {
+                            log::warn!(
+                                "Drain timeout elapsed while publishing, unpublished changes will be read again from the last checkpoint"
+                            );
+                            return Ok(());
+                        }
+                    }
+                }
+            };
+            published?;
+            if drain_deadline.is_some() {
+                break;
+            }
+        }
+    }
+
+    let drain_deadline = drain_deadline.unwrap_or_else(|| Instant::now() + shutdown.drain_timeout);
+    let drain = async {
+        if let Some(cancellation) = &shutdown.cancellation {
+            cancellation.cancel();
+        }
+        if !batch.is_empty() {
+            let pending = std::mem::take(&mut batch);
+            publish_batch(publisher, state_store, retry_policy, pending).await?;
+        }
+        // only a cancellable producer is expected to end its stream after shutdown
+        if shutdown.cancellation.is_some() {
+            while let Some(data) = stream.next().await {
+                batch.push(data);
+                if batch.len() >= batching.max_size {
+                    let full = std::mem::take(&mut batch);
+                    publish_batch(publisher, state_store, retry_policy, full).await?;
+                }
+            }
+            if !batch.is_empty() {
+                publish_batch(publisher, state_store, retry_policy, batch).await?;
             }
         }
+        Ok::<(), ReactivatorError>(())
+    };
+
+    match tokio::time::timeout_at(drain_deadline, drain).await {
+        Ok(Ok(())) => log::info!("Drained pending changes"),
+        Ok(Err(err)) => {
+            log::error!("Error publishing pending changes, {}", err);
+            return Err(err);
+        }
+        Err(_) => log::warn!(
+            "Drain timeout elapsed, unpublished changes will be read again from the last checkpoint"
+        ),
+    }
 
-        opentelemetry::global::shutdown_tracer_provider();
-        tokio::task::yield_now().await;
+    Ok(())
+}
+
+/// Publishes a batch of changes and then saves their checkpoints, so a checkpoint never gets ahead of the published changes.
+/// If a checkpoint cannot be saved the changes have still been published, and may be published again after a restart.
+async fn publish_batch(
+    publisher: &dyn Publisher,
+    state_store: &(dyn StateStore + Send + Sync),
+    retry_policy: &RetryPolicy,
+    mut changes: Vec<SourceChange>,
+) -> Result<(), ReactivatorError> {
+    let reactivator_end_ns = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .expect("Time went backwards")
+        .as_nanos();
+    for change in &mut changes {
+        change.set_reactivator_end_ns(reactivator_end_ns);
     }
+
+    retry_policy
+        .run("publishing changes", || {
+            publisher.publish_batch(changes.clone())
+        })
+        .await
+        .map_err(ReactivatorError::PublishError)?;
+
+    // only the latest checkpoint under each key needs to be saved
+    let mut checkpoints: Vec<&Checkpoint> = Vec::new();
+    for checkpoint in changes.iter().rev().filter_map(|c| c.checkpoint()) {
+        if !checkpoints.iter().any(|c| c.key == checkpoint.key) {
+            checkpoints.push(checkpoint);
+        }
+    }
+
+    for checkpoint in checkpoints {
+        retry_policy
+            .run("saving checkpoint", || {
+                state_store.put(&checkpoint.key, checkpoint.value.clone())
+            })
+            .await
+            .map_err(|err| ReactivatorError::StateStoreError(err))?;
+    }
+
+    Ok(())
 }
 
 struct AppState<DeprovisionResponse>
@@ -304,3 +707,312 @@ where
         None => (axum::http::StatusCode::NO_CONTENT, "".to_string()).into_response(),
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{models::ChangeOp, MemoryStateStore, SourceElement};
+    use serde_json::Map;
+    use std::{sync::Mutex, time::Duration};
+
+    struct FlakyPublisher {
+        failures: Mutex<u32>,
+        published: Mutex<Vec<SourceChange>>,
+    }
+
+    impl FlakyPublisher {
+        fn new(failures: u32) -> Self {
+            FlakyPublisher {
+                failures: Mutex::new(failures),
+                published: Mutex::new(Vec::new()),
+            }
+        }
+    }
+
+    #[axum::async_trait]
+    impl Publisher for FlakyPublisher {
+        async fn publish(
+            &self,
+            change: SourceChange,
+        ) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+            let mut failures = self.failures.lock().unwrap();
+            if *failures > 0 {
+                *failures -= 1;
+                return Err(Box::from("publish failed"));
+            }
+            self.published.lock().unwrap().push(change);
+            Ok(())
+        }
+    }
+
+    #[derive(Default)]
+    struct BatchRecorder {
+        batches: Mutex<Vec<Vec<u64>>>,
+        notify: tokio::sync::Notify,
+    }
+
+    #[axum::async_trait]
+    impl Publisher for BatchRecorder {
+        async fn publish(
+            &self,
+            change: SourceChange,
+        ) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+            self.publish_batch(vec![change]).await
+        }
+
+        async fn publish_batch(
+            &self,
+            changes: Vec<SourceChange>,
+        ) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+            let cursors = changes
+                .iter()
+                .map(|c| {
+                    let checkpoint = c.checkpoint().unwrap();
+                    u64::from_be_bytes(checkpoint.value.clone().try_into().unwrap())
+                })
+                .collect();
+            self.batches.lock().unwrap().push(cursors);
+            self.notify.notify_one();
+            Ok(())
+        }
+    }
+
+    fn retry_policy(max_attempts: u32) -> RetryPolicy {
+        RetryPolicy {
+            max_attempts: Some(max_attempts),
+            initial_backoff: Duration::from_millis(1),
+            max_backoff: Duration::from_millis(1),
+        }
+    }
+
+    fn change(cursor: u64) -> SourceChange {
+        let node = SourceElement::Node {
+            id: format!("node-{}", cursor),
+            labels: vec!["Item".to_string()],
+            properties: Map::new(),
+        };
+        SourceChange::new(ChangeOp::Create, node, 0, 0, cursor, None)
+            .with_checkpoint("cursor", cursor.to_be_bytes().to_vec())
+    }
+
+    #[tokio::test]
+    async fn test_checkpoint_is_saved_after_publishing() {
+        let publisher = FlakyPublisher::new(2);
+        let state_store = MemoryStateStore::new();
+
+        publish_batch(&publisher, &state_store, &retry_policy(3), vec![change(7)])
+            .await
+            .unwrap();
+
+        assert_eq!(publisher.published.lock().unwrap().len(), 1);
+        assert_eq!(
+            state_store.get("cursor").await.unwrap(),
+            Some(7u64.to_be_bytes().to_vec())
+        );
+    }
+
+    #[tokio::test]
+    async fn test_checkpoint_is_not_saved_when_publishing_fails() {
+        let publisher = FlakyPublisher::new(5);
+        let state_store = MemoryStateStore::new();
+
+        let result =
+            publish_batch(&publisher, &state_store, &retry_policy(3), vec![change(7)]).await;
+
+        assert!(matches!(result, Err(ReactivatorError::PublishError(_))));
+        assert!(publisher.published.lock().unwrap().is_empty());
+        assert_eq!(state_store.get("cursor").await.unwrap(), None);
+    }
+
+    #[tokio::test]
+    async fn test_changes_are_published_in_batches_of_max_size() {
+        let publisher = BatchRecorder::default();
+        let state_store = MemoryStateStore::new();
+        let batching = PublishBatching {
+            max_size: 2,
+            linger: Duration::from_secs(3600),
+        };
+        let mut stream = futures::stream::iter((1..=5).map(change)).fuse();
+
+        publish_stream(
+            &mut stream,
+            Shutdown {
+                signal: futures::future::pending(),
+                cancellation: None,
+                drain_timeout: Duration::from_secs(5),
+            },
+            &publisher,
+            &state_store,
+            &retry_policy(1),
+            &batching,
+            "test",
+        )
+        .await
+        .unwrap();
+
+        assert_eq!(
+            *publisher.batches.lock().unwrap(),
+            vec![vec![1, 2], vec![3, 4], vec![5]]
+        );
+        assert_eq!(
+            state_store.get("cursor").await.unwrap(),
+            Some(5u64.to_be_bytes().to_vec())
+        );
+    }
+
+    #[tokio::test]
+    async fn test_partial_batch_is_published_after_linger() {
+        let publisher = BatchRecorder::default();
+        let state_store = MemoryStateStore::new();
+        let batching = PublishBatching {
+            max_size: 10,
+            linger: Duration::from_millis(10),
+        };
+        let mut stream = futures::stream::iter((1..=3).map(change))
+            .chain(futures::stream::pending())
+            .fuse();
+
+        // the stream never ends, so only the linger can publish the batch and stop the test
+        tokio::time::timeout(
+            Duration::from_secs(5),
+            publish_stream(
+                &mut stream,
+                Shutdown {
+                    signal: publisher.notify.notified(),
+                    cancellation: None,
+                    drain_timeout: Duration::from_secs(5),
+                },
+                &publisher,
+                &state_store,
+                &retry_policy(1),
+                &batching,
+                "test",
+            ),
+        )
+        .await
+        .expect("batch was not published after the linger")
+        .unwrap();
+
+        assert_eq!(*publisher.batches.lock().unwrap(), vec![vec![1, 2, 3]]);
+    }
+
+    #[tokio::test]
+    async fn test_cancellable_producer_is_drained_on_shutdown() {
+        let publisher = BatchRecorder::default();
+        let state_store = MemoryStateStore::new();
+        let batching = PublishBatching {
+            max_size: 10,
+            linger: Duration::from_secs(3600),
+        };
+        let cancellation = CancellationToken::new();
+        let producer_token = cancellation.clone();
+        let mut stream = Box::pin(async_stream::stream! {
+            yield change(1);
+            yield change(2);
+            // the producer holds on to its last change until it is asked to stop
+            producer_token.cancelled().await;
+            yield change(3);
+        })
+        .fuse();
+
+        tokio::time::timeout(
+            Duration::from_secs(5),
+            publish_stream(
+                &mut stream,
+                Shutdown {
+                    signal: tokio::time::sleep(Duration::from_millis(10)),
+                    cancellation: Some(cancellation),
+                    drain_timeout: Duration::from_secs(5),
+                },
+                &publisher,
+                &state_store,
+                &retry_policy(1),
+                &batching,
+                "test",
+            ),
+        )
+        .await
+        .expect("stream was not drained")
+        .unwrap();
+
+        // the pending batch is published before the changes the producer yields once it is cancelled
+        assert_eq!(
+            *publisher.batches.lock().unwrap(),
+            vec![vec![1, 2], vec![3]]
+        );
+        assert_eq!(
+            state_store.get("cursor").await.unwrap(),
+            Some(3u64.to_be_bytes().to_vec())
+        );
+    }
+
+    #[tokio::test]
+    async fn test_drain_stops_at_drain_timeout() {
+        let publisher = BatchRecorder::default();
+        let state_store = MemoryStateStore::new();
+        let batching = PublishBatching {
+            max_size: 10,
+            linger: Duration::from_secs(3600),
+        };
+        // the producer ignores cancellation and never ends its stream
+        let mut stream = futures::stream::iter((1..=2).map(change))
+            .chain(futures::stream::pending())
+            .fuse();
+
+        tokio::time::timeout(
+            Duration::from_secs(5),
+            publish_stream(
+                &mut stream,
+                Shutdown {
+                    signal: tokio::time::sleep(Duration::from_millis(10)),
+                    cancellation: Some(CancellationToken::new()),
+                    drain_timeout: Duration::from_millis(10),
+                },
+                &publisher,
+                &state_store,
+                &retry_policy(1),
+                &batching,
+                "test",
+            ),
+        )
+        .await
+        .expect("drain did not stop at the drain timeout")
+        .unwrap();
+
+        // the pending batch is still published before waiting on the producer
+        assert_eq!(*publisher.batches.lock().unwrap(), vec![vec![1, 2]]);
+        assert_eq!(
+            state_store.get("cursor").await.unwrap(),
+            Some(2u64.to_be_bytes().to_vec())
+        );
+    }
+
+    #[tokio::test]
+    async fn test_publish_failure_stops_with_error() {
+        let publisher = FlakyPublisher::new(5);
+        let state_store = MemoryStateStore::new();
+        let batching = PublishBatching {
+            max_size: 1,
+            linger: Duration::from_secs(3600),
+        };
+        let mut stream = futures::stream::iter((1..=3).map(change)).fuse();
+
+        let result = publish_stream(
+            &mut stream,
+            Shutdown {
+                signal: futures::future::pending(),
+                cancellation: None,
+                drain_timeout: Duration::from_secs(5),
+            },
+            &publisher,
+            &state_store,
+            &retry_policy(2),
+            &batching,
+            "test",
+        )
+        .await;
+
+        assert!(matches!(result, Err(ReactivatorError::PublishError(_))));
+        assert_eq!(state_store.get("cursor").await.unwrap(), None);
+    }
+}
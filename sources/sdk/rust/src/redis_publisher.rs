@@ -0,0 +1,103 @@
+// Copyright 2024 The Drasi Authors.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::{env, time::SystemTime};
+
+use axum::async_trait;
+use opentelemetry::trace::TraceContextExt;
+use redis::{aio::MultiplexedConnection, AsyncCommands};
+use tracing::Span;
+use tracing_opentelemetry::OpenTelemetrySpanExt;
+
+use super::Publisher;
+use crate::{dapr_publisher::create_traceparent_header, models::SourceChange};
+
+/// Publishes changes straight to the `{source}-change` Redis stream, without a Dapr sidecar.
+/// Each call appends a single entry whose `data` field holds the JSON array of changes,
+/// which the change router reads when its change ingest is set to `redis`.
+pub struct RedisPublisher {
+    connection: MultiplexedConnection,
+    topic: String,
+}
+
+impl RedisPublisher {
+    /// Connects using the `REDIS_BROKER` and `SOURCE_ID` environment variables
+    pub async fn connect() -> Result<Self, redis::RedisError> {
+        let url =
+            env::var("REDIS_BROKER").unwrap_or_else(|_| "redis://drasi-redis:6379".to_string());
+        let source_id = env::var("SOURCE_ID").map_err(|_| {
+            redis::RedisError::from((
+                redis::ErrorKind::InvalidClientConfig,
+                "Source ID not specified",
+            ))
+        })?;
+        Self::connect_to(&url, &source_id).await
+    }
+
+    pub async fn connect_to(url: &str, source_id: &str) -> Result<Self, redis::RedisError> {
+        let client = redis::Client::open(url)?;
+        let connection = client.get_multiplexed_async_connection().await?;
+
+        Ok(RedisPublisher {
+            connection,
+            topic: format!("{}-change", source_id),
+        })
+    }
+}
+
+#[async_trait]
+impl Publisher for RedisPublisher {
+    async fn publish(
+        &self,
+        change: SourceChange,
+    ) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+        self.publish_batch(vec![change]).await
+    }
+
+    async fn publish_batch(
+        &self,
+        changes: Vec<SourceChange>,
+    ) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+        if changes.is_empty() {
+            return Ok(());
+        }
+
+        let data = serde_json::to_string(&changes)?;
+        let enqueue_time = SystemTime::now()
+            .duration_since(SystemTime::UNIX_EPOCH)?
+            .as_nanos()
+            .to_string();
+
+        let (traceparent, tracestate) = {
+            let ctx = Span::current().context();
+            let span = ctx.span();
+            let span_context = span.span_context();
+            (
+                create_traceparent_header(span_context),
+                span_context.trace_state().header(),
+            )
+        };
+
+        let items = [
+            ("data", data),
+            ("enqueue_time", enqueue_time),
+            ("traceparent", traceparent),
+            ("tracestate", tracestate),
+        ];
+
+        let mut connection = self.connection.clone();
+        let _: String = connection.xadd(&self.topic, "*", &items).await?;
+        Ok(())
+    }
+}
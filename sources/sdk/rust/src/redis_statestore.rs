@@ -0,0 +1,81 @@
+// Copyright 2024 The Drasi Authors.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::env;
+
+use axum::async_trait;
+use redis::{aio::MultiplexedConnection, AsyncCommands};
+
+use crate::StateStore;
+
+/// Keeps state in Redis, without a Dapr sidecar.
+/// Keys are prefixed with the source id, so several sources can share one Redis.
+pub struct RedisStateStore {
+    connection: MultiplexedConnection,
+    prefix: String,
+}
+
+impl RedisStateStore {
+    /// Connects using the `STATE_STORE_REDIS_URL` environment variable, or `REDIS_BROKER` when it is not set,
+    /// and prefixes keys with `SOURCE_ID`
+    pub async fn connect() -> Result<Self, redis::RedisError> {
+        let url = env::var("STATE_STORE_REDIS_URL")
+            .or_else(|_| env::var("REDIS_BROKER"))
+            .unwrap_or_else(|_| "redis://drasi-redis:6379".to_string());
+        let source_id = env::var("SOURCE_ID").unwrap_or_default();
+        Self::connect_to(&url, &source_id).await
+    }
+
+    pub async fn connect_to(url: &str, source_id: &str) -> Result<Self, redis::RedisError> {
+        let client = redis::Client::open(url)?;
+        let connection = client.get_multiplexed_async_connection().await?;
+
+        Ok(RedisStateStore {
+            connection,
+            prefix: format!("{}-state:", source_id),
+        })
+    }
+
+    fn key(&self, id: &str) -> String {
+        format!("{}{}", self.prefix, id)
+    }
+}
+
+#[async_trait]
+impl StateStore for RedisStateStore {
+    async fn get(
+        &self,
+        id: &str,
+    ) -> Result<Option<Vec<u8>>, Box<dyn std::error::Error + Send + Sync>> {
+        let mut connection = self.connection.clone();
+        let value: Option<Vec<u8>> = connection.get(self.key(id)).await?;
+        Ok(value)
+    }
+
+    async fn put(
+        &self,
+        id: &str,
+        value: Vec<u8>,
+    ) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+        let mut connection = self.connection.clone();
+        let _: () = connection.set(self.key(id), value).await?;
+        Ok(())
+    }
+
+    async fn delete(&self, id: &str) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+        let mut connection = self.connection.clone();
+        let _: () = connection.del(self.key(id)).await?;
+        Ok(())
+    }
+}
@@ -0,0 +1,135 @@
+// Copyright 2024 The Drasi Authors.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use serde::{Deserialize, Serialize};
+
+/// The labels a source can produce and the properties of each, served by the proxy at `/schema`
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct SourceSchema {
+    pub nodes: Vec<ElementSchema>,
+    pub relations: Vec<ElementSchema>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct ElementSchema {
+    pub label: String,
+    #[serde(default)]
+    pub properties: Vec<PropertySchema>,
+    /// The label of the start node, for relations
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub start_label: Option<String>,
+    /// The label of the end node, for relations
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub end_label: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct PropertySchema {
+    pub name: String,
+    #[serde(rename = "type")]
+    pub property_type: PropertyType,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub enum PropertyType {
+    String,
+    Integer,
+    Float,
+    Boolean,
+    List,
+    Object,
+    Any,
+}
+
+impl SourceSchema {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_node(mut self, node: ElementSchema) -> Self {
+        self.nodes.push(node);
+        self
+    }
+
+    pub fn with_relation(mut self, relation: ElementSchema) -> Self {
+        self.relations.push(relation);
+        self
+    }
+}
+
+impl ElementSchema {
+    pub fn new(label: &str) -> Self {
+        ElementSchema {
+            label: label.to_string(),
+            properties: Vec::new(),
+            start_label: None,
+            end_label: None,
+        }
+    }
+
+    pub fn with_property(mut self, name: &str, property_type: PropertyType) -> Self {
+        self.properties.push(PropertySchema {
+            name: name.to_string(),
+            property_type,
+        });
+        self
+    }
+
+    /// Sets the labels of the nodes a relation connects
+    pub fn with_endpoints(mut self, start_label: &str, end_label: &str) -> Self {
+        self.start_label = Some(start_label.to_string());
+        self.end_label = Some(end_label.to_string());
+        self
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_serialize_schema() {
+        let schema = SourceSchema::new()
+            .with_node(
+                ElementSchema::new("Vehicle")
+                    .with_property("vin", PropertyType::String)
+                    .with_property("mileage", PropertyType::Integer),
+            )
+            .with_relation(ElementSchema::new("LOCATED_AT").with_endpoints("Vehicle", "Location"));
+
+        assert_eq!(
+            serde_json::to_value(&schema).unwrap(),
+            json!({
+                "nodes": [{
+                    "label": "Vehicle",
+                    "properties": [
+                        { "name": "vin", "type": "string" },
+                        { "name": "mileage", "type": "integer" }
+                    ]
+                }],
+                "relations": [{
+                    "label": "LOCATED_AT",
+                    "properties": [],
+                    "startLabel": "Vehicle",
+                    "endLabel": "Location"
+                }]
+            })
+        );
+    }
+}
@@ -0,0 +1,226 @@
+// Copyright 2024 The Drasi Authors.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use serde_json::Value;
+
+use crate::{
+    predicates::{PredicateCondition, PropertyPredicate},
+    BootstrapError, BootstrapRequest,
+};
+
+/// The SQL flavour that filters and page clauses are written in
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SqlDialect {
+    /// `"column"` and `$1` placeholders
+    Postgres,
+    /// `` `column` `` and `?` placeholders
+    MySql,
+    /// `[column]` and `@p1` placeholders
+    SqlServer,
+}
+
+/// A where clause, without the `WHERE` keyword, and the values to bind to its placeholders in order
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct SqlFilter {
+    pub clause: String,
+    pub params: Vec<Value>,
+}
+
+impl SqlDialect {
+    fn quote(&self, identifier: &str) -> String {
+        match self {
+            SqlDialect::Postgres => format!("\"{}\"", identifier.replace('"', "\"\"")),
+            SqlDialect::MySql => format!("`{}`", identifier.replace('`', "``")),
+            SqlDialect::SqlServer => format!("[{}]", identifier.replace(']', "]]")),
+        }
+    }
+
+    fn placeholder(&self, index: usize) -> String {
+        match self {
+            SqlDialect::Postgres => format!("${}", index),
+            SqlDialect::MySql => "?".to_string(),
+            SqlDialect::SqlServer => format!("@p{}", index),
+        }
+    }
+}
+
+/// Translates predicates into a where clause, using the property names as column names.
+/// Returns None when there are no predicates.
+pub fn sql_filter(predicates: &[PropertyPredicate], dialect: SqlDialect) -> Option<SqlFilter> {
+    if predicates.is_empty() {
+        return None;
+    }
+
+    let mut params = Vec::new();
+    let mut bind = |value: &Value| {
+        params.push(value.clone());
+        dialect.placeholder(params.len())
+    };
+
+    let mut conditions = Vec::new();
+    for predicate in predicates {
+        let column = dialect.quote(&predicate.property);
+        match &predicate.condition {
+            PredicateCondition::Eq(Value::Null) => conditions.push(format!("{} IS NULL", column)),
+            PredicateCondition::Eq(value) => {
+                conditions.push(format!("{} = {}", column, bind(value)))
+            }
+            PredicateCondition::In(values) if values.is_empty() => {
+                conditions.push("1 = 0".to_string())
+            }
+            PredicateCondition::In(values) => {
+                let placeholders: Vec<String> = values.iter().map(&mut bind).collect();
+                conditions.push(format!("{} IN ({})", column, placeholders.join(", ")));
+            }
+            PredicateCondition::Range(range) => {
+                let bounds = [
+                    (&range.gt, ">"),
+                    (&range.gte, ">="),
+                    (&range.lt, "<"),
+                    (&range.lte, "<="),
+                ];
+                for (bound, operator) in bounds {
+                    if let Some(value) = bound {
+                        conditions.push(format!("{} {} {}", column, operator, bind(value)));
+                    }
+                }
+            }
+        }
+    }
+
+    Some(SqlFilter {
+        clause: conditions.join(" AND "),
+        params,
+    })
+}
+
+impl BootstrapRequest {
+    /// The where clause for the predicates of a node or relation label, None when the label has no predicates
+    pub fn sql_filter(&self, label: &str, dialect: SqlDialect) -> Option<SqlFilter> {
+        let predicates = self
+            .node_predicates
+            .get(label)
+            .or_else(|| self.rel_predicates.get(label))?;
+        sql_filter(predicates, dialect)
+    }
+
+    /// The clause that reads the requested page, None when no page was requested.
+    /// One row more than the page size is read, so the proxy can tell whether there is a next page.
+    /// Only use this with `SourceProxyBuilder::with_source_paging`, together with `sql_filter` for the predicates,
+    /// and with an `ORDER BY` that is stable between pages, which SQL Server requires.
+    pub fn sql_page(&self, dialect: SqlDialect) -> Result<Option<String>, BootstrapError> {
+        let Some(page_size) = self.page_size else {
+            return Ok(None);
+        };
+        let offset = self.offset()?;
+        let limit = page_size + 1;
+
+        Ok(Some(match dialect {
+            SqlDialect::Postgres | SqlDialect::MySql => {
+                format!("LIMIT {} OFFSET {}", limit, offset)
+            }
+            SqlDialect::SqlServer => {
+                format!("OFFSET {} ROWS FETCH NEXT {} ROWS ONLY", offset, limit)
+            }
+        }))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn request(value: Value) -> BootstrapRequest {
+        serde_json::from_value(value).unwrap()
+    }
+
+    #[test]
+    fn test_sql_filter() {
+        let request = request(json!({
+            "nodeLabels": ["Order"],
+            "relLabels": [],
+            "nodePredicates": {
+                "Order": [
+                    { "property": "region", "eq": "EU" },
+                    { "property": "status", "in": ["open", "late"] },
+                    { "property": "total", "range": { "gte": 100, "lt": 500 } },
+                    { "property": "deleted", "eq": null }
+                ]
+            }
+        }));
+
+        let filter = request.sql_filter("Order", SqlDialect::Postgres).unwrap();
+        assert_eq!(
+            filter.clause,
+            r#""region" = $1 AND "status" IN ($2, $3) AND "total" >= $4 AND "total" < $5 AND "deleted" IS NULL"#
+        );
+        assert_eq!(
+            filter.params,
+            vec![
+                json!("EU"),
+                json!("open"),
+                json!("late"),
+                json!(100),
+                json!(500)
+            ]
+        );
+
+        let filter = request.sql_filter("Order", SqlDialect::MySql).unwrap();
+        assert_eq!(
+            filter.clause,
+            "`region` = ? AND `status` IN (?, ?) AND `total` >= ? AND `total` < ? AND `deleted` IS NULL"
+        );
+
+        let filter = request.sql_filter("Order", SqlDialect::SqlServer).unwrap();
+        assert!(filter
+            .clause
+            .starts_with("[region] = @p1 AND [status] IN (@p2, @p3)"));
+
+        assert_eq!(request.sql_filter("Customer", SqlDialect::Postgres), None);
+    }
+
+    #[test]
+    fn test_sql_filter_quotes_columns() {
+        let predicates: Vec<PropertyPredicate> = serde_json::from_value(json!([
+            { "property": "a\"b", "in": [] }
+        ]))
+        .unwrap();
+
+        let filter = sql_filter(&predicates, SqlDialect::Postgres).unwrap();
+        assert_eq!(filter.clause, "1 = 0");
+        assert_eq!(SqlDialect::Postgres.quote("a\"b"), r#""a""b""#);
+    }
+
+    #[test]
+    fn test_sql_page() {
+        let paged = request(json!({
+            "nodeLabels": [],
+            "relLabels": [],
+            "pageSize": 50,
+            "continuationToken": "100"
+        }));
+        assert_eq!(
+            paged.sql_page(SqlDialect::Postgres).unwrap(),
+            Some("LIMIT 51 OFFSET 100".to_string())
+        );
+        assert_eq!(
+            paged.sql_page(SqlDialect::SqlServer).unwrap(),
+            Some("OFFSET 100 ROWS FETCH NEXT 51 ROWS ONLY".to_string())
+        );
+
+        let unpaged = request(json!({ "nodeLabels": [], "relLabels": [] }));
+        assert_eq!(unpaged.sql_page(SqlDialect::Postgres).unwrap(), None);
+    }
+}
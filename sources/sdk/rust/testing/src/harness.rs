@@ -0,0 +1,239 @@
+// Copyright 2024 The Drasi Authors.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::{
+    future::{self, Future, Ready},
+    sync::Arc,
+    time::Duration,
+};
+
+use drasi_source_sdk::{
+    BootstrapError, BootstrapRequest, BootstrapStream, ChangeStream, MemoryStateStore,
+    ReactivatorBuilder, ReactivatorError, SourceChange, SourceElement, SourceProxy, StateStore,
+};
+use futures::StreamExt;
+use thiserror::Error;
+
+use crate::CapturingPublisher;
+
+#[derive(Error, Debug)]
+pub enum HarnessError {
+    #[error("Reactivator error: {0}")]
+    Reactivator(ReactivatorError),
+
+    #[error("Timed out after {published} changes were published")]
+    Timeout { published: usize },
+
+    #[error("The change stream ended after {published} of {expected} changes were published")]
+    StreamEnded { expected: usize, published: usize },
+}
+
+/// Runs the stream producer of a reactivator in-process.
+/// Cursors saved through checkpoints are kept in a `MemoryStateStore` across runs, so stopping a run
+/// and starting another one behaves like a restart of the source.
+pub struct ReactivatorHarness<Context, Response>
+where
+    Context: Clone + Send + Sync + 'static,
+    Response: Future<Output = Result<ChangeStream, ReactivatorError>> + Send + 'static,
+{
+    stream_producer: fn(Context, Arc<dyn StateStore + Send + Sync>) -> Response,
+    context: Context,
+    state_store: MemoryStateStore,
+    publisher: CapturingPublisher,
+    timeout: Duration,
+}
+
+impl<Context, Response> ReactivatorHarness<Context, Response>
+where
+    Context: Clone + Send + Sync + 'static,
+    Response: Future<Output = Result<ChangeStream, ReactivatorError>> + Send + 'static,
+{
+    pub fn new(
+        stream_producer: fn(Context, Arc<dyn StateStore + Send + Sync>) -> Response,
+        context: Context,
+    ) -> Self {
+        ReactivatorHarness {
+            stream_producer,
+            context,
+            state_store: MemoryStateStore::new(),
+            publisher: CapturingPublisher::new(),
+            timeout: Duration::from_secs(10),
+        }
+    }
+
+    /// How long a run may take before it fails, 10 seconds by default
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    /// Starts from the given state, for example to seed a cursor
+    pub fn with_state_store(mut self, state_store: MemoryStateStore) -> Self {
+        self.state_store = state_store;
+        self
+    }
+
+    pub fn state_store(&self) -> &MemoryStateStore {
+        &self.state_store
+    }
+
+    /// Every change published so far, across runs
+    pub fn published(&self) -> Vec<SourceChange> {
+        self.publisher.changes()
+    }
+
+    /// Runs the reactivator until `count` more changes have been published and then stops it,
+    /// returning the changes published by this run
+    pub async fn run_until_published(
+        &self,
+        count: usize,
+    ) -> Result<Vec<SourceChange>, HarnessError> {
+        let changes = self.run(Some(count)).await?;
+        if changes.len() < count {
+            return Err(HarnessError::StreamEnded {
+                expected: count,
+                published: changes.len(),
+            });
+        }
+        Ok(changes)
+    }
+
+    /// Runs the reactivator until its change stream ends, returning the changes published by this run
+    pub async fn run_to_end(&self) -> Result<Vec<SourceChange>, HarnessError> {
+        self.run(None).await
+    }
+
+    async fn run(&self, count: Option<usize>) -> Result<Vec<SourceChange>, HarnessError> {
+        let start = self.publisher.len();
+
+        // changes are published one at a time, so a run stops right after the requested change
+        let reactivator = ReactivatorBuilder::<Response, Ready<()>, Context>::new()
+            .with_stream_producer(self.stream_producer)
+            .with_context(self.context.clone())
+            .with_publisher(self.publisher.clone())
+            .with_state_store(self.state_store.clone())
+            .with_batching(1, Duration::ZERO)
+            .build()
+            .await
+            .map_err(HarnessError::Reactivator)?;
+
+        let publisher = self.publisher.clone();
+        let shutdown = async move {
+            match count {
+                Some(count) => publisher.wait_for(start + count).await,
+                None => future::pending().await,
+            }
+        };
+
+        match tokio::time::timeout(self.timeout, reactivator.run_until(shutdown)).await {
+            Ok(result) => result.map_err(HarnessError::Reactivator)?,
+            Err(_) => {
+                return Err(HarnessError::Timeout {
+                    published: self.publisher.len() - start,
+                })
+            }
+        }
+
+        Ok(self.publisher.changes().split_off(start))
+    }
+}
+
+impl<Response> ReactivatorHarness<(), Response>
+where
+    Response: Future<Output = Result<ChangeStream, ReactivatorError>> + Send + 'static,
+{
+    pub fn without_context(
+        stream_producer: fn((), Arc<dyn StateStore + Send + Sync>) -> Response,
+    ) -> Self {
+        Self::new(stream_producer, ())
+    }
+}
+
+/// Sends bootstrap requests to a source proxy in-process
+pub struct ProxyHarness<Response, Context>
+where
+    Response: Future<Output = Result<BootstrapStream, BootstrapError>> + Send + Sync + 'static,
+    Context: Send + Sync + Clone + 'static,
+{
+    proxy: SourceProxy<Response, Context>,
+}
+
+impl<Response, Context> ProxyHarness<Response, Context>
+where
+    Response: Future<Output = Result<BootstrapStream, BootstrapError>> + Send + Sync + 'static,
+    Context: Send + Sync + Clone + 'static,
+{
+    pub fn new(proxy: SourceProxy<Response, Context>) -> Self {
+        ProxyHarness { proxy }
+    }
+
+    /// Returns the elements of a bootstrap request, or of a single page when the request has a page size
+    pub async fn bootstrap(
+        &self,
+        request: BootstrapRequest,
+    ) -> Result<Vec<SourceElement>, BootstrapError> {
+        let response = self.proxy.bootstrap(request).await?;
+        response
+            .elements
+            .collect::<Vec<_>>()
+            .await
+            .into_iter()
+            .collect()
+    }
+
+    /// Requests pages of the given size until there are no more, returning the elements of each page
+    pub async fn bootstrap_pages(
+        &self,
+        request: BootstrapRequest,
+        page_size: usize,
+    ) -> Result<Vec<Vec<SourceElement>>, BootstrapError> {
+        let mut pages = Vec::new();
+        let mut continuation_token = None;
+
+        loop {
+            let response = self
+                .proxy
+                .bootstrap(BootstrapRequest {
+                    page_size: Some(page_size),
+                    continuation_token,
+                    ..request.clone()
+                })
+                .await?;
+            let page = response
+                .elements
+                .collect::<Vec<_>>()
+                .await
+                .into_iter()
+                .collect::<Result<Vec<_>, _>>()?;
+            pages.push(page);
+
+            match response.continuation_token {
+                Some(token) => continuation_token = Some(token),
+                None => return Ok(pages),
+            }
+        }
+    }
+}
+
+/// A request for every element with the given labels, without predicates or paging
+pub fn bootstrap_request(node_labels: &[&str], rel_labels: &[&str]) -> BootstrapRequest {
+    BootstrapRequest {
+        node_labels: node_labels.iter().map(|l| l.to_string()).collect(),
+        rel_labels: rel_labels.iter().map(|l| l.to_string()).collect(),
+        node_predicates: Default::default(),
+        rel_predicates: Default::default(),
+        page_size: None,
+        continuation_token: None,
+    }
+}
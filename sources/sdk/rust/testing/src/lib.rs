@@ -0,0 +1,25 @@
+// Copyright 2024 The Drasi Authors.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Runs sources built with the SDK in-process, without Dapr or the rest of the platform.
+//! The reactivator publishes to a `CapturingPublisher` and keeps its cursors in a `MemoryStateStore`,
+//! and bootstrap requests are sent straight to the `SourceProxy`.
+
+mod harness;
+mod matchers;
+mod publisher;
+
+pub use harness::*;
+pub use matchers::*;
+pub use publisher::CapturingPublisher;
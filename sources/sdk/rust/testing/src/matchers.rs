@@ -0,0 +1,189 @@
+// Copyright 2024 The Drasi Authors.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::fmt::{self, Display};
+
+use drasi_source_sdk::{ChangeOp, SourceChange, SourceElement};
+use serde_json::{Map, Value};
+
+/// Describes the parts of an element a test cares about, labels and properties not mentioned are not checked
+#[derive(Debug, Clone)]
+pub struct ElementMatcher {
+    id: String,
+    relation: Option<(String, String)>,
+    labels: Vec<String>,
+    properties: Map<String, Value>,
+}
+
+impl ElementMatcher {
+    pub fn node(id: &str) -> Self {
+        ElementMatcher {
+            id: id.to_string(),
+            relation: None,
+            labels: Vec::new(),
+            properties: Map::new(),
+        }
+    }
+
+    pub fn relation(id: &str, start_id: &str, end_id: &str) -> Self {
+        ElementMatcher {
+            relation: Some((start_id.to_string(), end_id.to_string())),
+            ..Self::node(id)
+        }
+    }
+
+    pub fn with_label(mut self, label: &str) -> Self {
+        self.labels.push(label.to_string());
+        self
+    }
+
+    pub fn with_property(mut self, name: &str, value: impl Into<Value>) -> Self {
+        self.properties.insert(name.to_string(), value.into());
+        self
+    }
+
+    pub fn matches(&self, element: &SourceElement) -> bool {
+        let (id, labels, properties) = match (element, &self.relation) {
+            (
+                SourceElement::Node {
+                    id,
+                    labels,
+                    properties,
+                },
+                None,
+            ) => (id, labels, properties),
+            (
+                SourceElement::Relation {
+                    id,
+                    labels,
+                    properties,
+                    start_id,
+                    end_id,
+                },
+                Some((expected_start, expected_end)),
+            ) if start_id == expected_start && end_id == expected_end => (id, labels, properties),
+            _ => return false,
+        };
+
+        *id == self.id
+            && self.labels.iter().all(|l| labels.contains(l))
+            && self
+                .properties
+                .iter()
+                .all(|(name, value)| properties.get(name) == Some(value))
+    }
+}
+
+impl Display for ElementMatcher {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.relation {
+            None => write!(f, "node {}", self.id)?,
+            Some((start_id, end_id)) => {
+                write!(f, "relation {} ({} -> {})", self.id, start_id, end_id)?
+            }
+        }
+        if !self.labels.is_empty() {
+            write!(f, " labels {:?}", self.labels)?;
+        }
+        if !self.properties.is_empty() {
+            write!(f, " properties {}", Value::Object(self.properties.clone()))?;
+        }
+        Ok(())
+    }
+}
+
+/// Describes a change by its operation and element
+#[derive(Debug, Clone)]
+pub struct ChangeMatcher {
+    op: ChangeOp,
+    element: ElementMatcher,
+}
+
+impl ChangeMatcher {
+    pub fn insert(element: ElementMatcher) -> Self {
+        ChangeMatcher {
+            op: ChangeOp::Create,
+            element,
+        }
+    }
+
+    pub fn update(element: ElementMatcher) -> Self {
+        ChangeMatcher {
+            op: ChangeOp::Update,
+            element,
+        }
+    }
+
+    pub fn delete(element: ElementMatcher) -> Self {
+        ChangeMatcher {
+            op: ChangeOp::Delete,
+            element,
+        }
+    }
+
+    pub fn matches(&self, change: &SourceChange) -> bool {
+        change.op() == self.op && self.element.matches(change.element())
+    }
+}
+
+impl Display for ChangeMatcher {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:?} of {}", self.op, self.element)
+    }
+}
+
+/// Asserts that the elements match the expected ones, in any order
+#[track_caller]
+pub fn assert_elements(actual: &[SourceElement], expected: &[ElementMatcher]) {
+    if actual.len() != expected.len() {
+        panic!(
+            "expected {} elements but got {}: {:#?}",
+            expected.len(),
+            actual.len(),
+            actual
+        );
+    }
+
+    let mut remaining: Vec<&SourceElement> = actual.iter().collect();
+    for matcher in expected {
+        match remaining.iter().position(|e| matcher.matches(e)) {
+            Some(index) => {
+                remaining.remove(index);
+            }
+            None => panic!(
+                "no element matches {}, left unmatched: {:#?}",
+                matcher, remaining
+            ),
+        }
+    }
+}
+
+/// Asserts that the changes match the expected ones, in order
+#[track_caller]
+pub fn assert_changes(actual: &[SourceChange], expected: &[ChangeMatcher]) {
+    if actual.len() != expected.len() {
+        panic!(
+            "expected {} changes but got {}: {:#?}",
+            expected.len(),
+            actual.len(),
+            actual
+        );
+    }
+
+    for (index, (change, matcher)) in actual.iter().zip(expected).enumerate() {
+        if !matcher.matches(change) {
+            panic!("change {} is not a {}: {:#?}", index, matcher, change);
+        }
+    }
+}
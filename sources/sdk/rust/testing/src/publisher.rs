@@ -0,0 +1,78 @@
+// Copyright 2024 The Drasi Authors.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::sync::{Arc, Mutex};
+
+use async_trait::async_trait;
+use drasi_source_sdk::{Publisher, SourceChange};
+use tokio::sync::watch;
+
+/// Records the changes published to it, clones share the same record
+#[derive(Clone)]
+pub struct CapturingPublisher {
+    changes: Arc<Mutex<Vec<SourceChange>>>,
+    count: Arc<watch::Sender<usize>>,
+}
+
+impl CapturingPublisher {
+    pub fn new() -> Self {
+        CapturingPublisher {
+            changes: Arc::new(Mutex::new(Vec::new())),
+            count: Arc::new(watch::Sender::new(0)),
+        }
+    }
+
+    pub fn changes(&self) -> Vec<SourceChange> {
+        self.changes
+            .lock()
+            .expect("publisher lock poisoned")
+            .clone()
+    }
+
+    pub fn len(&self) -> usize {
+        self.changes.lock().expect("publisher lock poisoned").len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Waits until at least `count` changes have been published in total
+    pub async fn wait_for(&self, count: usize) {
+        let mut rx = self.count.subscribe();
+        _ = rx.wait_for(|published| *published >= count).await;
+    }
+}
+
+impl Default for CapturingPublisher {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[async_trait]
+impl Publisher for CapturingPublisher {
+    async fn publish(
+        &self,
+        change: SourceChange,
+    ) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+        let count = {
+            let mut changes = self.changes.lock().expect("publisher lock poisoned");
+            changes.push(change);
+            changes.len()
+        };
+        self.count.send_replace(count);
+        Ok(())
+    }
+}
@@ -0,0 +1,165 @@
+// Copyright 2024 The Drasi Authors.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::sync::Arc;
+
+use drasi_source_sdk::{
+    stream, BootstrapError, BootstrapRequest, BootstrapStream, ChangeOp, ChangeStream,
+    PredicateCondition, PropertyPredicate, ReactivatorError, SourceChange, SourceElement,
+    SourceProxyBuilder, StateStore,
+};
+use drasi_source_sdk_testing::{
+    assert_changes, assert_elements, bootstrap_request, ChangeMatcher, ElementMatcher,
+    HarnessError, ProxyHarness, ReactivatorHarness,
+};
+use serde_json::{json, Map};
+
+const CURSOR_KEY: &str = "cursor";
+
+fn item(id: u64) -> SourceElement {
+    let mut properties = Map::new();
+    properties.insert("value".to_string(), json!(id));
+    properties.insert(
+        "parity".to_string(),
+        json!(if id.is_multiple_of(2) { "even" } else { "odd" }),
+    );
+    SourceElement::Node {
+        id: id.to_string(),
+        labels: vec!["Item".to_string()],
+        properties,
+    }
+}
+
+/// Inserts items up to `last`, resuming after the cursor saved by the previous run
+async fn counter(
+    last: u64,
+    state_store: Arc<dyn StateStore + Send + Sync>,
+) -> Result<ChangeStream, ReactivatorError> {
+    let cursor = match state_store.get(CURSOR_KEY).await {
+        Ok(Some(cursor)) => String::from_utf8(cursor)
+            .ok()
+            .and_then(|c| c.parse().ok())
+            .unwrap_or(0),
+        Ok(None) => 0,
+        Err(e) => return Err(ReactivatorError::InternalError(e.to_string())),
+    };
+
+    let changes = ((cursor + 1)..=last).map(|id| {
+        SourceChange::new(ChangeOp::Create, item(id), 0, 0, id, None)
+            .with_checkpoint(CURSOR_KEY, id.to_string().into_bytes())
+    });
+
+    Ok(Box::pin(futures::stream::iter(changes)))
+}
+
+fn inserts(ids: std::ops::RangeInclusive<u64>) -> Vec<ChangeMatcher> {
+    ids.map(|id| {
+        ChangeMatcher::insert(
+            ElementMatcher::node(&id.to_string())
+                .with_label("Item")
+                .with_property("value", id),
+        )
+    })
+    .collect()
+}
+
+#[tokio::test]
+async fn test_reactivator_resumes_from_checkpoint() {
+    let harness = ReactivatorHarness::new(counter, 5);
+
+    let first = harness.run_until_published(3).await.unwrap();
+    assert_changes(&first, &inserts(1..=3));
+    assert_eq!(
+        harness.state_store().get(CURSOR_KEY).await.unwrap(),
+        Some(b"3".to_vec())
+    );
+
+    let second = harness.run_to_end().await.unwrap();
+    assert_changes(&second, &inserts(4..=5));
+    assert_eq!(harness.published().len(), 5);
+}
+
+#[tokio::test]
+async fn test_reactivator_reports_stream_end() {
+    let harness = ReactivatorHarness::new(counter, 2);
+
+    match harness.run_until_published(3).await {
+        Err(HarnessError::StreamEnded {
+            expected: 3,
+            published: 2,
+        }) => {}
+        other => panic!("unexpected result: {:?}", other.map(|c| c.len())),
+    }
+}
+
+async fn items(count: u64, request: BootstrapRequest) -> Result<BootstrapStream, BootstrapError> {
+    let offset = request.offset()? as u64;
+    Ok(Box::pin(stream! {
+        for id in (offset + 1)..=count {
+            yield Ok(item(id));
+        }
+    }))
+}
+
+#[tokio::test]
+async fn test_proxy_filters_by_predicates() {
+    let proxy = SourceProxyBuilder::new()
+        .with_stream_producer(items)
+        .with_context(6)
+        .build();
+    let harness = ProxyHarness::new(proxy);
+
+    let mut request = bootstrap_request(&["Item"], &[]);
+    request.node_predicates.insert(
+        "Item".to_string(),
+        vec![PropertyPredicate {
+            property: "parity".to_string(),
+            condition: PredicateCondition::Eq(json!("even")),
+        }],
+    );
+
+    let elements = harness.bootstrap(request).await.unwrap();
+    assert_elements(
+        &elements,
+        &[
+            ElementMatcher::node("6").with_property("parity", "even"),
+            ElementMatcher::node("2").with_property("parity", "even"),
+            ElementMatcher::node("4").with_property("parity", "even"),
+        ],
+    );
+}
+
+#[tokio::test]
+async fn test_proxy_pages_bootstrap() {
+    let proxy = SourceProxyBuilder::new()
+        .with_stream_producer(items)
+        .with_context(5)
+        .with_source_paging()
+        .build();
+    let harness = ProxyHarness::new(proxy);
+
+    let pages = harness
+        .bootstrap_pages(bootstrap_request(&["Item"], &[]), 2)
+        .await
+        .unwrap();
+
+    let sizes: Vec<usize> = pages.iter().map(|p| p.len()).collect();
+    assert_eq!(sizes, vec![2, 2, 1]);
+    assert_elements(
+        &pages.concat(),
+        &(1..=5)
+            .map(|id| ElementMatcher::node(&id.to_string()))
+            .collect::<Vec<_>>(),
+    );
+}
@@ -0,0 +1,113 @@
+// Copyright 2024 The Drasi Authors.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use drasi_source_sdk::{DrasiNode, DrasiRelation, SourceElement, ToSourceElement};
+use serde_json::json;
+
+#[derive(DrasiNode)]
+#[drasi(label = "Vehicle", label = "Asset")]
+struct Vehicle {
+    #[drasi(id)]
+    vin: String,
+    #[drasi(rename = "displayName")]
+    name: String,
+    mileage: Option<u32>,
+    #[drasi(skip)]
+    #[allow(dead_code)]
+    etag: String,
+}
+
+#[derive(DrasiNode)]
+struct Location {
+    #[drasi(id, skip)]
+    id: u64,
+    tags: Vec<String>,
+}
+
+#[derive(DrasiRelation)]
+#[drasi(label = "LOCATED_AT")]
+struct LocatedAt {
+    #[drasi(id, skip)]
+    id: String,
+    #[drasi(start)]
+    vehicle: String,
+    #[drasi(end, rename = "locationId")]
+    location: u64,
+}
+
+#[test]
+fn test_node_mapping() {
+    let vehicle = Vehicle {
+        vin: "v1".to_string(),
+        name: "Truck".to_string(),
+        mileage: None,
+        etag: "abc".to_string(),
+    };
+
+    match vehicle.to_source_element().unwrap() {
+        SourceElement::Node {
+            id,
+            labels,
+            properties,
+        } => {
+            assert_eq!(id, "v1");
+            assert_eq!(labels, vec!["Vehicle", "Asset"]);
+            assert_eq!(
+                serde_json::Value::Object(properties),
+                json!({ "vin": "v1", "displayName": "Truck", "mileage": null })
+            );
+        }
+        other => panic!("expected a node, got {:?}", other),
+    }
+}
+
+#[test]
+fn test_node_label_defaults_to_struct_name() {
+    let location = Location {
+        id: 7,
+        tags: vec!["depot".to_string()],
+    };
+
+    assert_eq!(
+        location.to_source_element().unwrap(),
+        SourceElement::Node {
+            id: "7".to_string(),
+            labels: vec!["Location".to_string()],
+            properties: json!({ "tags": ["depot"] }).as_object().unwrap().clone(),
+        }
+    );
+}
+
+#[test]
+fn test_relation_mapping() {
+    let located_at = LocatedAt {
+        id: "r1".to_string(),
+        vehicle: "v1".to_string(),
+        location: 7,
+    };
+
+    assert_eq!(
+        located_at.to_source_element().unwrap(),
+        SourceElement::Relation {
+            id: "r1".to_string(),
+            labels: vec!["LOCATED_AT".to_string()],
+            properties: json!({ "vehicle": "v1", "locationId": 7 })
+                .as_object()
+                .unwrap()
+                .clone(),
+            start_id: "v1".to_string(),
+            end_id: "7".to_string(),
+        }
+    );
+}
@@ -0,0 +1,104 @@
+// Copyright 2024 The Drasi Authors.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+// These tests need a local Redis, run them with `cargo test -- --ignored`
+
+use std::{env, time::SystemTime};
+
+use drasi_source_sdk::{ChangeOp, Publisher, RedisPublisher, SourceChange, SourceElement};
+use redis::{streams::StreamRangeReply, AsyncCommands};
+use serde_json::{Map, Value};
+
+fn get_url() -> String {
+    match env::var("REDIS_URL") {
+        Ok(url) => url,
+        Err(_) => "redis://127.0.0.1:6379".to_string(),
+    }
+}
+
+fn test_source_id() -> String {
+    let now = SystemTime::now()
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .unwrap()
+        .as_nanos();
+    format!("test-{}", now)
+}
+
+fn change(id: &str) -> SourceChange {
+    let node = SourceElement::Node {
+        id: id.to_string(),
+        labels: vec!["Item".to_string()],
+        properties: Map::new(),
+    };
+    SourceChange::new(ChangeOp::Create, node, 0, 0, 0, None)
+}
+
+async fn read_stream(topic: &str) -> Vec<Value> {
+    let mut connection = redis::Client::open(get_url())
+        .unwrap()
+        .get_multiplexed_async_connection()
+        .await
+        .unwrap();
+    let reply: StreamRangeReply = connection.xrange_all(topic).await.unwrap();
+    let _: () = connection.del(topic).await.unwrap();
+
+    reply
+        .ids
+        .iter()
+        .map(|entry| {
+            let data: String = entry.get("data").unwrap();
+            serde_json::from_str(&data).unwrap()
+        })
+        .collect()
+}
+
+#[tokio::test]
+#[ignore]
+async fn publishes_to_the_source_change_stream() {
+    let source_id = test_source_id();
+    let publisher = RedisPublisher::connect_to(&get_url(), &source_id)
+        .await
+        .unwrap();
+
+    publisher.publish(change("n1")).await.unwrap();
+
+    let entries = read_stream(&format!("{}-change", source_id)).await;
+    assert_eq!(entries.len(), 1);
+    assert_eq!(entries[0][0]["op"], "i");
+    assert_eq!(entries[0][0]["payload"]["after"]["id"], "n1");
+}
+
+#[tokio::test]
+#[ignore]
+async fn publishes_a_batch_as_a_single_entry() {
+    let source_id = test_source_id();
+    let publisher = RedisPublisher::connect_to(&get_url(), &source_id)
+        .await
+        .unwrap();
+
+    publisher
+        .publish_batch(vec![change("n1"), change("n2"), change("n3")])
+        .await
+        .unwrap();
+
+    let entries = read_stream(&format!("{}-change", source_id)).await;
+    assert_eq!(entries.len(), 1);
+    let ids: Vec<&str> = entries[0]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|c| c["payload"]["after"]["id"].as_str().unwrap())
+        .collect();
+    assert_eq!(ids, vec!["n1", "n2", "n3"]);
+}
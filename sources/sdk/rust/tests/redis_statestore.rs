@@ -0,0 +1,54 @@
+// Copyright 2024 The Drasi Authors.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+// These tests need a local Redis, run them with `cargo test -- --ignored`
+
+use std::{env, time::SystemTime};
+
+use drasi_source_sdk::{RedisStateStore, StateStore};
+
+fn get_url() -> String {
+    match env::var("REDIS_URL") {
+        Ok(url) => url,
+        Err(_) => "redis://127.0.0.1:6379".to_string(),
+    }
+}
+
+fn test_source_id() -> String {
+    let now = SystemTime::now()
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .unwrap()
+        .as_nanos();
+    format!("test-{}", now)
+}
+
+#[tokio::test]
+#[ignore]
+async fn keeps_values_per_source() {
+    let url = get_url();
+    let source_a = RedisStateStore::connect_to(&url, &test_source_id())
+        .await
+        .unwrap();
+    let source_b = RedisStateStore::connect_to(&url, &test_source_id())
+        .await
+        .unwrap();
+
+    assert_eq!(source_a.get("cursor").await.unwrap(), None);
+    source_a.put("cursor", b"42".to_vec()).await.unwrap();
+    assert_eq!(source_a.get("cursor").await.unwrap(), Some(b"42".to_vec()));
+    assert_eq!(source_b.get("cursor").await.unwrap(), None);
+
+    source_a.delete("cursor").await.unwrap();
+    assert_eq!(source_a.get("cursor").await.unwrap(), None);
+}
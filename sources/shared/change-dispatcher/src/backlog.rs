@@ -0,0 +1,580 @@
+// Copyright 2024 The Drasi Authors.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::{
+    collections::{HashMap, VecDeque},
+    error::Error,
+    ops::Range,
+    sync::Arc,
+};
+
+use async_trait::async_trait;
+use drasi_comms_abstractions::comms::Invoker;
+use serde_json::{json, Value};
+use tokio::sync::Mutex;
+
+use crate::delivery::{deliver, RetryPolicy};
+
+/// A numbered part of the backlog of a query node
+#[derive(Debug, Clone)]
+pub struct Chunk {
+    pub seq: u64,
+    pub changes: Vec<Value>,
+}
+
+/// Durable storage for the changes that could not be delivered to a query node.
+/// The backlog of a query node is kept as numbered chunks, along with the range of chunk numbers it holds,
+/// so parking changes only writes the new chunk instead of the whole backlog.
+#[async_trait]
+pub trait BacklogStore: Send + Sync {
+    async fn load(&self) -> Result<HashMap<String, Vec<Chunk>>, Box<dyn Error + Send + Sync>>;
+
+    /// Saves a new chunk, and the range of chunks the query node holds with it
+    async fn save_chunk(
+        &self,
+        query_node_id: &str,
+        chunk: &Chunk,
+        range: Range<u64>,
+    ) -> Result<(), Box<dyn Error + Send + Sync>>;
+
+    /// Removes a delivered chunk, and saves the range of chunks that remain
+    async fn remove_chunk(
+        &self,
+        query_node_id: &str,
+        seq: u64,
+        range: Range<u64>,
+    ) -> Result<(), Box<dyn Error + Send + Sync>>;
+}
+
+#[derive(Default)]
+struct NodeBacklog {
+    chunks: VecDeque<Chunk>,
+    next_seq: u64,
+    len: usize,
+}
+
+impl NodeBacklog {
+    fn range(&self) -> Range<u64> {
+        match self.chunks.front() {
+            Some(first) => first.seq..self.next_seq,
+            None => self.next_seq..self.next_seq,
+        }
+    }
+
+    /// The chunks at the front of the backlog that fit in a batch, at least one
+    fn next_batch(&self, batch_size: usize) -> Vec<Chunk> {
+        let mut batch = Vec::new();
+        let mut size = 0;
+        for chunk in &self.chunks {
+            if !batch.is_empty() && size + chunk.changes.len() > batch_size {
+                break;
+            }
+            size += chunk.changes.len();
+            batch.push(chunk.clone());
+        }
+        batch
+    }
+}
+
+/// Holds the changes parked for query nodes that were unavailable, in the order they were dispatched.
+/// While a query node has parked changes, new changes for it are parked behind them, so it
+/// receives every change in order once it recovers.
+/// A query node holds at most `max_changes` parked changes, parking more fails so that pubsub redelivers them later,
+/// and parked changes are stored and delivered in batches of at most `batch_size`.
+pub struct Backlog {
+    store: Arc<dyn BacklogStore>,
+    pending: Mutex<HashMap<String, NodeBacklog>>,
+    max_changes: usize,
+    batch_size: usize,
+}
+
+impl Backlog {
+    pub async fn load(
+        store: Arc<dyn BacklogStore>,
+        max_changes: usize,
+        batch_size: usize,
+    ) -> Result<Self, Box<dyn Error + Send + Sync>> {
+        let mut pending = HashMap::new();
+        for (query_node_id, mut chunks) in store.load().await? {
+            chunks.sort_by_key(|c| c.seq);
+            let backlog = NodeBacklog {
+                next_seq: chunks.last().map_or(0, |c| c.seq + 1),
+                len: chunks.iter().map(|c| c.changes.len()).sum(),
+                chunks: chunks.into(),
+            };
+            if backlog.chunks.is_empty() {
+                continue;
+            }
+            log::info!(
+                "Loaded {} parked changes for query node {}",
+                backlog.len,
+                query_node_id
+            );
+            pending.insert(query_node_id, backlog);
+        }
+
+        Ok(Backlog {
+            store,
+            pending: Mutex::new(pending),
+            max_changes,
+            batch_size: batch_size.max(1),
+        })
+    }
+
+    /// Parks changes for a query node
+    pub async fn park(
+        &self,
+        query_node_id: &str,
+        changes: &[Value],
+    ) -> Result<(), Box<dyn Error + Send + Sync>> {
+        let mut pending = self.pending.lock().await;
+        self.append(&mut pending, query_node_id, changes).await
+    }
+
+    /// Parks changes for a query node only if it already has parked changes, returning whether they were parked
+    pub async fn park_if_pending(
+        &self,
+        query_node_id: &str,
+        changes: &[Value],
+    ) -> Result<bool, Box<dyn Error + Send + Sync>> {
+        let mut pending = self.pending.lock().await;
+        if !pending.contains_key(query_node_id) {
+            return Ok(false);
+        }
+        self.append(&mut pending, query_node_id, changes).await?;
+        Ok(true)
+    }
+
+    /// Tries to deliver the parked changes of every query node in batches, removing each batch once delivered.
+    /// The backlog is not locked while delivering, changes parked meanwhile stay in the backlog.
+    pub async fn drain(&self, invoker: &dyn Invoker, policy: &RetryPolicy) {
+        let query_node_ids: Vec<String> = self.pending.lock().await.keys().cloned().collect();
+
+        for query_node_id in query_node_ids {
+            loop {
+                let batch = match self.pending.lock().await.get(&query_node_id) {
+                    Some(backlog) => backlog.next_batch(self.batch_size),
+                    None => break,
+                };
+                let changes: Vec<Value> = batch
+                    .iter()
+                    .flat_map(|c| c.changes.iter().cloned())
+                    .collect();
+
+                if let Err(e) = deliver(invoker, &query_node_id, &changes, "", policy).await {
+                    log::warn!(
+                        "Query node {} is still unavailable, its changes remain parked: {}",
+                        query_node_id,
+                        e
+                    );
+                    break;
+                }
+
+                log::info!(
+                    "Delivered {} parked changes to query node {}",
+                    changes.len(),
+                    query_node_id
+                );
+
+                if let Err(e) = self.remove(&query_node_id, &batch).await {
+                    // the changes will be delivered again, which query nodes tolerate
+                    log::error!(
+                        "Error updating the backlog of query node {}: {:?}",
+                        query_node_id,
+                        e
+                    );
+                    break;
+                }
+            }
+        }
+    }
+
+    async fn append(
+        &self,
+        pending: &mut HashMap<String, NodeBacklog>,
+        query_node_id: &str,
+        changes: &[Value],
+    ) -> Result<(), Box<dyn Error + Send + Sync>> {
+        let parked = pending.get(query_node_id).map_or(0, |b| b.len);
+        if parked + changes.len() > self.max_changes {
+            return Err(Box::from(format!(
+                "The backlog of query node {} is full, {} changes are parked",
+                query_node_id, parked
+            )));
+        }
+
+        let backlog = pending.entry(query_node_id.to_string()).or_default();
+        for part in changes.chunks(self.batch_size) {
+            let chunk = Chunk {
+                seq: backlog.next_seq,
+                changes: part.to_vec(),
+            };
+            let range = backlog.range().start..chunk.seq + 1;
+            let saved = self.store.save_chunk(query_node_id, &chunk, range).await;
+            if let Err(e) = saved {
+                if backlog.chunks.is_empty() {
+                    pending.remove(query_node_id);
+                }
+                return Err(e);
+            }
+            backlog.next_seq += 1;
+            backlog.len += chunk.changes.len();
+            backlog.chunks.push_back(chunk);
+        }
+
+        log::warn!(
+            "Parked {} changes for query node {}, {} are now pending",
+            changes.len(),
+            query_node_id,
+            parked + changes.len()
+        );
+        Ok(())
+    }
+
+    /// Removes delivered chunks from the front of the backlog of a query node
+    async fn remove(
+        &self,
+        query_node_id: &str,
+        delivered: &[Chunk],
+    ) -> Result<(), Box<dyn Error + Send + Sync>> {
+        let mut pending = self.pending.lock().await;
+        let backlog = match pending.get_mut(query_node_id) {
+            Some(backlog) => backlog,
+            None => return Ok(()),
+        };
+
+        for chunk in delivered {
+            if backlog.chunks.front().map(|c| c.seq) != Some(chunk.seq) {
+                break;
+            }
+            let range = chunk.seq + 1..backlog.next_seq;
+            self.store
+                .remove_chunk(query_node_id, chunk.seq, range)
+                .await?;
+            if let Some(removed) = backlog.chunks.pop_front() {
+                backlog.len -= removed.changes.len();
+            }
+        }
+
+        if backlog.chunks.is_empty() {
+            pending.remove(query_node_id);
+        }
+        Ok(())
+    }
+}
+
+/// Stores the backlog of each query node as chunks under their own keys in a Dapr state store.
+/// A key per query node holds the range of its chunks, and an index key lists the query nodes that have a backlog.
+pub struct DaprBacklogStore {
+    client: reqwest::Client,
+    dapr_port: u16,
+    store_name: String,
+    key_prefix: String,
+}
+
+impl DaprBacklogStore {
+    pub fn new(dapr_port: u16, store_name: &str, source_id: &str) -> Self {
+        DaprBacklogStore {
+            client: reqwest::Client::new(),
+            dapr_port,
+            store_name: store_name.to_string(),
+            key_prefix: format!("{}-dispatch-backlog", source_id),
+        }
+    }
+
+    fn url(&self) -> String {
+        format!(
+            "http://127.0.0.1:{}/v1.0/state/{}",
+            self.dapr_port, self.store_name
+        )
+    }
+
+    fn node_key(&self, query_node_id: &str) -> String {
+        format!("{}-{}", self.key_prefix, query_node_id)
+    }
+
+    fn chunk_key(&self, query_node_id: &str, seq: u64) -> String {
+        format!("{}-{}-{}", self.key_prefix, query_node_id, seq)
+    }
+
+    async fn get(&self, key: &str) -> Result<Option<Value>, Box<dyn Error + Send + Sync>> {
+        let resp = self
+            .client
+            .get(format!("{}/{}", self.url(), key))
+            .send()
+            .await?;
+        if !resp.status().is_success() {
+            return Err(Box::from(format!(
+                "State get request failed with status: {}",
+                resp.status()
+            )));
+        }
+
+        let body = resp.bytes().await?;
+        if body.is_empty() {
+            return Ok(None);
+        }
+        Ok(Some(serde_json::from_slice(&body)?))
+    }
+
+    async fn save(&self, entries: Value) -> Result<(), Box<dyn Error + Send + Sync>> {
+        let resp = self.client.post(self.url()).json(&entries).send().await?;
+        if !resp.status().is_success() {
+            return Err(Box::from(format!(
+                "State save request failed with status: {} and body: {}",
+                resp.status(),
+                resp.text().await.unwrap_or_default()
+            )));
+        }
+        Ok(())
+    }
+
+    async fn delete(&self, key: &str) -> Result<(), Box<dyn Error + Send + Sync>> {
+        let resp = self
+            .client
+            .delete(format!("{}/{}", self.url(), key))
+            .send()
+            .await?;
+        if !resp.status().is_success() {
+            return Err(Box::from(format!(
+                "State delete request failed with status: {}",
+                resp.status()
+            )));
+        }
+        Ok(())
+    }
+
+    async fn index(&self) -> Result<Vec<String>, Box<dyn Error + Send + Sync>> {
+        match self.get(&self.key_prefix).await? {
+            Some(index) => Ok(serde_json::from_value(index)?),
+            None => Ok(Vec::new()),
+        }
+    }
+}
+
+#[async_trait]
+impl BacklogStore for DaprBacklogStore {
+    async fn load(&self) -> Result<HashMap<String, Vec<Chunk>>, Box<dyn Error + Send + Sync>> {
+        let mut result = HashMap::new();
+        for query_node_id in self.index().await? {
+            let range = match self.get(&self.node_key(&query_node_id)).await? {
+                Some(range) => range,
+                None => continue,
+            };
+            let first = range["first"].as_u64().unwrap_or_default();
+            let next = range["next"].as_u64().unwrap_or_default();
+
+            let mut chunks = Vec::new();
+            for seq in first..next {
+                if let Some(changes) = self.get(&self.chunk_key(&query_node_id, seq)).await? {
+                    chunks.push(Chunk {
+                        seq,
+                        changes: serde_json::from_value(changes)?,
+                    });
+                }
+            }
+            result.insert(query_node_id, chunks);
+        }
+        Ok(result)
+    }
+
+    async fn save_chunk(
+        &self,
+        query_node_id: &str,
+        chunk: &Chunk,
+        range: Range<u64>,
+    ) -> Result<(), Box<dyn Error + Send + Sync>> {
+        // the chunk is saved before the range that includes it
+        self.save(json!([
+            { "key": self.chunk_key(query_node_id, chunk.seq), "value": chunk.changes }
+        ]))
+        .await?;
+
+        let mut entries = vec![json!({
+            "key": self.node_key(query_node_id),
+            "value": { "first": range.start, "next": range.end }
+        })];
+        if range.start == chunk.seq {
+            let mut index = self.index().await?;
+            if !index.iter().any(|id| id == query_node_id) {
+                index.push(query_node_id.to_string());
+                entries.push(json!({ "key": self.key_prefix, "value": index }));
+            }
+        }
+        self.save(Value::Array(entries)).await
+    }
+
+    async fn remove_chunk(
+        &self,
+        query_node_id: &str,
+        seq: u64,
+        range: Range<u64>,
+    ) -> Result<(), Box<dyn Error + Send + Sync>> {
+        // the range is saved before the chunk is deleted, so it never refers to a missing chunk
+        if range.is_empty() {
+            let mut index = self.index().await?;
+            index.retain(|id| id != query_node_id);
+            self.save(json!([{ "key": self.key_prefix, "value": index }]))
+                .await?;
+            self.delete(&self.node_key(query_node_id)).await?;
+        } else {
+            self.save(json!([{
+                "key": self.node_key(query_node_id),
+                "value": { "first": range.start, "next": range.end }
+            }]))
+            .await?;
+        }
+        self.delete(&self.chunk_key(query_node_id, seq)).await
+    }
+}
+
+#[cfg(test)]
+#[derive(Default)]
+pub struct MemoryBacklogStore {
+    pub backlogs: std::sync::Mutex<HashMap<String, std::collections::BTreeMap<u64, Vec<Value>>>>,
+}
+
+#[cfg(test)]
+#[async_trait]
+impl BacklogStore for MemoryBacklogStore {
+    async fn load(&self) -> Result<HashMap<String, Vec<Chunk>>, Box<dyn Error + Send + Sync>> {
+        let backlogs = self.backlogs.lock().unwrap();
+        Ok(backlogs
+            .iter()
+            .map(|(id, chunks)| {
+                let chunks = chunks
+                    .iter()
+                    .map(|(seq, changes)| Chunk {
+                        seq: *seq,
+                        changes: changes.clone(),
+                    })
+                    .collect();
+                (id.clone(), chunks)
+            })
+            .collect())
+    }
+
+    async fn save_chunk(
+        &self,
+        query_node_id: &str,
+        chunk: &Chunk,
+        _range: Range<u64>,
+    ) -> Result<(), Box<dyn Error + Send + Sync>> {
+        self.backlogs
+            .lock()
+            .unwrap()
+            .entry(query_node_id.to_string())
+            .or_default()
+            .insert(chunk.seq, chunk.changes.clone());
+        Ok(())
+    }
+
+    async fn remove_chunk(
+        &self,
+        query_node_id: &str,
+        seq: u64,
+        range: Range<u64>,
+    ) -> Result<(), Box<dyn Error + Send + Sync>> {
+        let mut backlogs = self.backlogs.lock().unwrap();
+        if let Some(chunks) = backlogs.get_mut(query_node_id) {
+            chunks.remove(&seq);
+        }
+        if range.is_empty() {
+            backlogs.remove(query_node_id);
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use drasi_comms_abstractions::comms::{Headers, Payload};
+    use std::time::Duration;
+
+    #[derive(Default)]
+    struct BatchRecorder {
+        batches: std::sync::Mutex<Vec<Vec<Value>>>,
+    }
+
+    #[async_trait]
+    impl Invoker for BatchRecorder {
+        async fn invoke(
+            &self,
+            data: Payload,
+            _app_id: &str,
+            _method: &str,
+            _headers: Option<Headers>,
+        ) -> Result<bytes::Bytes, Box<dyn std::error::Error>> {
+            if let Payload::Json(Value::Array(batch)) = data {
+                self.batches.lock().unwrap().push(batch);
+            }
+            Ok(bytes::Bytes::new())
+        }
+    }
+
+    fn policy() -> RetryPolicy {
+        RetryPolicy {
+            max_attempts: 1,
+            initial_backoff: Duration::from_millis(1),
+            max_backoff: Duration::from_millis(1),
+        }
+    }
+
+    fn changes(ids: std::ops::Range<u64>) -> Vec<Value> {
+        ids.map(|id| json!({ "id": id })).collect()
+    }
+
+    #[tokio::test]
+    async fn test_parks_chunks_and_drains_in_bounded_batches() {
+        let store = Arc::new(MemoryBacklogStore::default());
+        let backlog = Backlog::load(store.clone(), 100, 3).await.unwrap();
+
+        backlog.park("node1", &changes(0..5)).await.unwrap();
+        backlog.park("node1", &changes(5..6)).await.unwrap();
+        // a batch larger than the batch size is split, and each park only adds chunks
+        assert_eq!(store.backlogs.lock().unwrap()["node1"].len(), 3);
+
+        // the parked chunks are restored in order after a restart
+        let backlog = Backlog::load(store.clone(), 100, 3).await.unwrap();
+        let invoker = BatchRecorder::default();
+        backlog.drain(&invoker, &policy()).await;
+
+        let batches = invoker.batches.lock().unwrap().clone();
+        assert_eq!(batches, vec![changes(0..3), changes(3..6)]);
+        assert!(store.backlogs.lock().unwrap().is_empty());
+        assert!(!backlog
+            .park_if_pending("node1", &changes(6..7))
+            .await
+            .unwrap());
+    }
+
+    #[tokio::test]
+    async fn test_parking_fails_when_the_backlog_is_full() {
+        let store = Arc::new(MemoryBacklogStore::default());
+        let backlog = Backlog::load(store.clone(), 4, 10).await.unwrap();
+
+        backlog.park("node1", &changes(0..3)).await.unwrap();
+        assert!(backlog.park("node1", &changes(3..5)).await.is_err());
+        assert!(backlog
+            .park_if_pending("node1", &changes(3..5))
+            .await
+            .is_err());
+        backlog.park("node1", &changes(3..4)).await.unwrap();
+
+        // other query nodes have their own limit
+        backlog.park("node2", &changes(0..4)).await.unwrap();
+        assert_eq!(store.backlogs.lock().unwrap()["node1"].len(), 2);
+    }
+}
@@ -21,6 +21,15 @@ pub struct ChangeDispatcherConfig {
     pub pubsub_name: String,
     pub dapr_http_port: String,
     pub app_port: String,
+    pub max_attempts: u32,
+    pub retry_backoff_ms: u64,
+    pub max_backoff_ms: u64,
+    pub backlog_enabled: bool,
+    pub backlog_store: String,
+    pub backlog_drain_interval_ms: u64,
+    pub backlog_max_changes: usize,
+    pub backlog_batch_size: usize,
+    pub ordering_capacity: usize,
 }
 
 impl ChangeDispatcherConfig {
@@ -32,12 +41,44 @@ impl ChangeDispatcherConfig {
         let dapr_http_port = env::var("DAPR_HTTP_PORT").unwrap_or_else(|_| "3500".to_string());
         let app_port = env::var("APP_PORT").unwrap_or_else(|_| "3000".to_string());
 
+        // delivery to each query node is retried with exponential backoff, after which the changes
+        // are parked in the backlog if it is enabled, or the whole batch is redelivered by pubsub
+        let max_attempts = parse_env("DISPATCH_MAX_ATTEMPTS", 3);
+        let retry_backoff_ms = parse_env("DISPATCH_RETRY_BACKOFF_MS", 200);
+        let max_backoff_ms = parse_env("DISPATCH_MAX_BACKOFF_MS", 5000);
+        let backlog_enabled = parse_env("DISPATCH_BACKLOG_ENABLED", false);
+        let backlog_store = env::var("BACKLOG_STORE").unwrap_or_else(|_| "drasi-state".to_string());
+        let backlog_drain_interval_ms = parse_env("DISPATCH_BACKLOG_DRAIN_INTERVAL_MS", 10000);
+        let backlog_max_changes = parse_env("DISPATCH_BACKLOG_MAX_CHANGES", 100_000);
+        let backlog_batch_size = parse_env("DISPATCH_BACKLOG_BATCH_SIZE", 500);
+        // number of elements whose last dispatched sequence is kept to discard stale changes
+        let ordering_capacity = parse_env("DISPATCH_ORDERING_CAPACITY", 100_000);
+
         ChangeDispatcherConfig {
             source_id,
             subscriber_store,
             pubsub_name,
             app_port,
             dapr_http_port,
+            max_attempts,
+            retry_backoff_ms,
+            max_backoff_ms,
+            backlog_enabled,
+            backlog_store,
+            backlog_drain_interval_ms,
+            backlog_max_changes,
+            backlog_batch_size,
+            ordering_capacity,
         }
     }
 }
+
+fn parse_env<T: std::str::FromStr>(name: &str, default: T) -> T {
+    match env::var(name) {
+        Ok(value) => value.parse().unwrap_or_else(|_| {
+            log::warn!("Invalid value for {}: {}", name, value);
+            default
+        }),
+        Err(_) => default,
+    }
+}
@@ -0,0 +1,224 @@
+// Copyright 2024 The Drasi Authors.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::{
+    collections::{HashMap, HashSet, VecDeque},
+    sync::Mutex,
+    time::Duration,
+};
+
+use drasi_comms_abstractions::comms::{Headers, Invoker, Payload};
+use drasi_comms_dapr::comms::InvocationError;
+use serde_json::Value;
+
+#[derive(Debug, Clone)]
+pub struct RetryPolicy {
+    pub max_attempts: u32,
+    pub initial_backoff: Duration,
+    pub max_backoff: Duration,
+}
+
+impl RetryPolicy {
+    fn backoff(&self, attempt: u32) -> Duration {
+        let backoff = self
+            .initial_backoff
+            .saturating_mul(2u32.saturating_pow(attempt.saturating_sub(1)));
+        backoff.min(self.max_backoff)
+    }
+}
+
+/// Sends a batch of changes to the publish-api of a query node, retrying with exponential backoff
+pub async fn deliver(
+    invoker: &dyn Invoker,
+    query_node_id: &str,
+    batch: &[Value],
+    traceparent: &str,
+    policy: &RetryPolicy,
+) -> Result<(), String> {
+    let app_id = format!("{}-publish-api", query_node_id);
+    let mut attempt = 0;
+
+    loop {
+        attempt += 1;
+
+        let result = invoke_changes(invoker, &app_id, batch, traceparent)
+            .await
+            .map_err(|e| e.to_string());
+
+        match result {
+            Ok(_) => return Ok(()),
+            Err(e) => {
+                if attempt >= policy.max_attempts {
+                    return Err(format!(
+                        "Error invoking app {} after {} attempts: {}",
+                        app_id, attempt, e
+                    ));
+                }
+                let backoff = policy.backoff(attempt);
+                log::warn!(
+                    "Error invoking app {} (attempt {}), retrying in {:?}: {}",
+                    app_id,
+                    attempt,
+                    backoff,
+                    e
+                );
+                tokio::time::sleep(backoff).await;
+            }
+        }
+    }
+}
+
+/// Sends the batch to the `changes` method of the publish-api. A publish-api from before that
+/// method existed answers with a 404, for example during a rolling upgrade, and is sent each change
+/// on its own to the `change` method instead.
+async fn invoke_changes(
+    invoker: &dyn Invoker,
+    app_id: &str,
+    batch: &[Value],
+    traceparent: &str,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let headers = || {
+        let mut headers = HashMap::new();
+        if !traceparent.is_empty() {
+            headers.insert("traceparent".to_string(), traceparent.to_string());
+        }
+        Some(Headers::new(headers))
+    };
+
+    // the error is not Send, so it must be dropped before the next await
+    match invoker
+        .invoke(
+            Payload::Json(Value::Array(batch.to_vec())),
+            app_id,
+            "changes",
+            headers(),
+        )
+        .await
+    {
+        Ok(_) => return Ok(()),
+        Err(e) if !is_not_found(e.as_ref()) => return Err(e),
+        Err(_) => {}
+    };
+
+    log::info!(
+        "{} does not accept batches, sending {} changes one at a time",
+        app_id,
+        batch.len()
+    );
+    for change in batch {
+        invoker
+            .invoke(Payload::Json(change.clone()), app_id, "change", headers())
+            .await?;
+    }
+    Ok(())
+}
+
+fn is_not_found(error: &(dyn std::error::Error + 'static)) -> bool {
+    error
+        .downcast_ref::<InvocationError>()
+        .is_some_and(|e| e.status == reqwest::StatusCode::NOT_FOUND)
+}
+
+/// Remembers which query nodes have received each pubsub event, so that when an event is
+/// redelivered because some query nodes failed, the others do not receive it again.
+/// Only the most recent events are tracked, and only in memory: after a restart, or on another
+/// replica, a redelivered event is sent again to every query node it is for. Query nodes already
+/// tolerate those duplicates, the tracker only avoids them in the common case.
+pub struct DeliveryTracker {
+    capacity: usize,
+    inner: Mutex<TrackerState>,
+}
+
+#[derive(Default)]
+struct TrackerState {
+    delivered: HashMap<String, HashSet<String>>,
+    order: VecDeque<String>,
+}
+
+impl DeliveryTracker {
+    pub fn new(capacity: usize) -> Self {
+        DeliveryTracker {
+            capacity,
+            inner: Mutex::new(TrackerState::default()),
+        }
+    }
+
+    pub fn is_delivered(&self, event_id: &str, query_node_id: &str) -> bool {
+        let inner = self.inner.lock().expect("delivery tracker lock poisoned");
+        inner
+            .delivered
+            .get(event_id)
+            .is_some_and(|nodes| nodes.contains(query_node_id))
+    }
+
+    pub fn mark_delivered(&self, event_id: &str, query_node_id: &str) {
+        let mut inner = self.inner.lock().expect("delivery tracker lock poisoned");
+        if !inner.delivered.contains_key(event_id) {
+            inner.order.push_back(event_id.to_string());
+            while inner.order.len() > self.capacity {
+                if let Some(oldest) = inner.order.pop_front() {
+                    inner.delivered.remove(&oldest);
+                }
+            }
+        }
+        inner
+            .delivered
+            .entry(event_id.to_string())
+            .or_default()
+            .insert(query_node_id.to_string());
+    }
+
+    /// Stops tracking an event once every query node has received it
+    pub fn forget(&self, event_id: &str) {
+        let mut inner = self.inner.lock().expect("delivery tracker lock poisoned");
+        if inner.delivered.remove(event_id).is_some() {
+            inner.order.retain(|id| id != event_id);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_backoff_is_exponential_and_capped() {
+        let policy = RetryPolicy {
+            max_attempts: 5,
+            initial_backoff: Duration::from_millis(100),
+            max_backoff: Duration::from_millis(300),
+        };
+        assert_eq!(policy.backoff(1), Duration::from_millis(100));
+        assert_eq!(policy.backoff(2), Duration::from_millis(200));
+        assert_eq!(policy.backoff(3), Duration::from_millis(300));
+        assert_eq!(policy.backoff(30), Duration::from_millis(300));
+    }
+
+    #[test]
+    fn test_tracker_evicts_oldest_events() {
+        let tracker = DeliveryTracker::new(2);
+        tracker.mark_delivered("e1", "node1");
+        tracker.mark_delivered("e2", "node1");
+        tracker.mark_delivered("e2", "node2");
+        assert!(tracker.is_delivered("e1", "node1"));
+        assert!(!tracker.is_delivered("e1", "node2"));
+
+        tracker.mark_delivered("e3", "node1");
+        assert!(!tracker.is_delivered("e1", "node1"));
+        assert!(tracker.is_delivered("e2", "node2"));
+
+        tracker.forget("e2");
+        assert!(!tracker.is_delivered("e2", "node1"));
+    }
+}
@@ -13,18 +13,17 @@
 // limitations under the License.
 
 use change_dispatcher_config::ChangeDispatcherConfig;
-use drasi_comms_abstractions::comms::Payload;
 use log::info;
 use serde_json::Value;
 
 use serde_json::json;
-use std::{
-    collections::{HashMap, HashSet},
-    sync::Arc,
-};
+use std::{collections::HashSet, sync::Arc, time::Duration};
 
-use drasi_comms_abstractions::comms::{Headers, Invoker};
+use backlog::{Backlog, DaprBacklogStore};
+use delivery::{deliver, DeliveryTracker, RetryPolicy};
+use drasi_comms_abstractions::comms::Invoker;
 use drasi_comms_dapr::comms::DaprHttpInvoker;
+use ordering::{ElementChange, ElementOrdering};
 
 use axum::{
     extract::State,
@@ -34,7 +33,10 @@ use axum::{
     Json, Router,
 };
 
+mod backlog;
 mod change_dispatcher_config;
+mod delivery;
+mod ordering;
 
 #[tokio::main]
 async fn main() -> Result<(), Box<dyn std::error::Error>> {
@@ -51,10 +53,64 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
         }
     };
 
-    let invoker = DaprHttpInvoker::new("127.0.0.1".to_string(), dapr_http_port);
+    let invoker = Arc::new(DaprHttpInvoker::new(
+        "127.0.0.1".to_string(),
+        dapr_http_port,
+    ));
+
+    let retry_policy = RetryPolicy {
+        max_attempts: config.max_attempts.max(1),
+        initial_backoff: Duration::from_millis(config.retry_backoff_ms),
+        max_backoff: Duration::from_millis(config.max_backoff_ms),
+    };
+
+    let backlog = match config.backlog_enabled {
+        true => {
+            let store = Arc::new(DaprBacklogStore::new(
+                dapr_http_port,
+                &config.backlog_store,
+                &config.source_id,
+            ));
+            let backlog =
+                match Backlog::load(store, config.backlog_max_changes, config.backlog_batch_size)
+                    .await
+                {
+                    Ok(backlog) => Arc::new(backlog),
+                    Err(e) => {
+                        return Err(Box::<dyn std::error::Error>::from(format!(
+                            "Error loading the dispatch backlog: {}",
+                            e
+                        )));
+                    }
+                };
+
+            let drain_backlog = backlog.clone();
+            let drain_invoker = invoker.clone();
+            let drain_policy = retry_policy.clone();
+            let drain_interval = Duration::from_millis(config.backlog_drain_interval_ms);
+            tokio::spawn(async move {
+                loop {
+                    tokio::time::sleep(drain_interval).await;
+                    drain_backlog
+                        .drain(drain_invoker.as_ref(), &drain_policy)
+                        .await;
+                }
+            });
+
+            Some(backlog)
+        }
+        false => None,
+    };
+
     let shared_state = Arc::new(AppState {
         config: config.clone(),
         invoker,
+        delivery: Delivery {
+            retry_policy,
+            tracker: DeliveryTracker::new(10_000),
+            ordering: ElementOrdering::new(64, config.ordering_capacity),
+            backlog,
+        },
     });
 
     let app = Router::new()
@@ -84,7 +140,15 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
 
 struct AppState {
     config: ChangeDispatcherConfig,
-    invoker: DaprHttpInvoker,
+    invoker: Arc<DaprHttpInvoker>,
+    delivery: Delivery,
+}
+
+struct Delivery {
+    retry_policy: RetryPolicy,
+    tracker: DeliveryTracker,
+    ordering: ElementOrdering,
+    backlog: Option<Arc<Backlog>>,
 }
 
 #[axum::debug_handler]
@@ -118,9 +182,21 @@ async fn receive(
     };
 
     let config = state.config.clone();
-    let invoker = &state.invoker;
+    let invoker = state.invoker.as_ref();
     let json_data = body["data"].clone();
-    match process_changes(invoker, json_data, config, traceparent, receive_time).await {
+    // pubsub redelivers an event with the same id
+    let event_id = body["id"].as_str();
+    match process_changes(
+        invoker,
+        json_data,
+        config,
+        traceparent,
+        receive_time,
+        event_id,
+        &state.delivery,
+    )
+    .await
+    {
         Ok(_) => StatusCode::OK.into_response(),
         Err(e) => {
             log::error!("Error processing changes: {:?}", e);
@@ -133,12 +209,20 @@ async fn receive(
     }
 }
 
+/// Groups the changes in a batch by the query nodes subscribed to them, and sends each query node
+/// its changes in a single request, in the order they appear in the batch.
+/// Query nodes are sent their changes concurrently, and a query node that is unavailable does not
+/// prevent the others from receiving theirs.
+/// Changes to the same element are dispatched in sequence order, and a query node is not sent
+/// a change older than one it already received for that element.
 async fn process_changes(
-    invoker: &DaprHttpInvoker,
+    invoker: &dyn Invoker,
     changes: Value,
     _config: ChangeDispatcherConfig,
     traceparent: String,
     receive_time: i64,
+    event_id: Option<&str>,
+    delivery: &Delivery,
 ) -> Result<(), Box<dyn std::error::Error>> {
     let mut start_time = receive_time;
 
@@ -146,7 +230,18 @@ async fn process_changes(
         .as_array()
         .ok_or_else(|| Box::<dyn std::error::Error>::from("Changes must be an array"))?;
 
-    for (index, change_event) in changes.iter().enumerate() {
+    let element_changes: Vec<Option<ElementChange>> =
+        changes.iter().map(ElementChange::from_change).collect();
+    let _partitions = delivery
+        .ordering
+        .lock(element_changes.iter().flatten().map(|c| c.key.as_str()))
+        .await;
+
+    let mut node_batches: Vec<(String, Vec<Value>, Vec<&ElementChange>)> = Vec::new();
+
+    for (index, (change_event, element_change)) in
+        changes.iter().zip(element_changes.iter()).enumerate()
+    {
         // For the first change, we will use the receive_time from the pubsub
         // For the rest of the changes, we will use the time when the change event is processed
         if index > 0 {
@@ -182,7 +277,15 @@ async fn process_changes(
             .collect();
 
         for query_node_id in query_nodes {
-            let app_id = format!("{}-publish-api", query_node_id);
+            if let Some(element_change) = element_change {
+                if delivery.ordering.is_stale(query_node_id, element_change) {
+                    info!(
+                        "Skipping stale change {} to {} for {}",
+                        change_event["id"], element_change.key, query_node_id
+                    );
+                    continue;
+                }
+            }
 
             let queries: Vec<_> = subscriptions
                 .iter()
@@ -198,12 +301,6 @@ async fn process_changes(
                 }
             };
 
-            let mut headers = HashMap::new();
-            if !traceparent.is_empty() {
-                headers.insert("traceparent".to_string(), traceparent.clone());
-            }
-            let headers = Headers::new(headers);
-
             // End time, measured in nanoseconds
             dispatch_event["metadata"]["tracking"]["source"]["changeDispatcherEnd_ns"] =
                 match serde_json::to_value(
@@ -216,24 +313,396 @@ async fn process_changes(
                         ));
                     }
                 };
-            match invoker
-                .invoke(
-                    Payload::Json(dispatch_event.clone()),
-                    &app_id,
-                    "change",
-                    Some(headers),
-                )
-                .await
+
+            match node_batches
+                .iter_mut()
+                .find(|(id, _, _)| id == query_node_id)
             {
-                Ok(_) => {}
-                Err(e) => {
-                    return Err(Box::<dyn std::error::Error>::from(format!(
-                        "Error invoking app: {}",
-                        e
-                    )));
+                Some((_, batch, dispatched)) => {
+                    batch.push(dispatch_event.clone());
+                    dispatched.extend(element_change);
                 }
+                None => node_batches.push((
+                    query_node_id.to_string(),
+                    vec![dispatch_event.clone()],
+                    element_change.iter().collect(),
+                )),
             }
         }
     }
+
+    let results =
+        futures::future::join_all(node_batches.iter().map(|(query_node_id, batch, _)| {
+            dispatch_to_node(
+                invoker,
+                delivery,
+                query_node_id,
+                batch,
+                &traceparent,
+                event_id,
+            )
+        }))
+        .await;
+
+    let mut errors = Vec::new();
+    for ((query_node_id, _, dispatched), result) in node_batches.iter().zip(results) {
+        match result {
+            Ok(_) => {
+                for element_change in dispatched {
+                    delivery
+                        .ordering
+                        .mark_dispatched(query_node_id, element_change);
+                }
+            }
+            Err(e) => errors.push(e),
+        }
+    }
+    if !errors.is_empty() {
+        return Err(Box::<dyn std::error::Error>::from(errors.join("; ")));
+    }
+
+    if let Some(event_id) = event_id {
+        delivery.tracker.forget(event_id);
+    }
+    Ok(())
+}
+
+/// Sends a query node its changes, unless it already received them in an earlier delivery of the event.
+/// If the query node has a backlog, the changes are parked behind it, and if the query node
+/// cannot be reached they are parked in its backlog when the backlog is enabled.
+async fn dispatch_to_node(
+    invoker: &dyn Invoker,
+    delivery: &Delivery,
+    query_node_id: &str,
+    batch: &[Value],
+    traceparent: &str,
+    event_id: Option<&str>,
+) -> Result<(), String> {
+    if let Some(event_id) = event_id {
+        if delivery.tracker.is_delivered(event_id, query_node_id) {
+            info!(
+                "Skipping {} changes already delivered to {}",
+                batch.len(),
+                query_node_id
+            );
+            return Ok(());
+        }
+    }
+
+    let parked = match &delivery.backlog {
+        Some(backlog) => backlog
+            .park_if_pending(query_node_id, batch)
+            .await
+            .map_err(|e| e.to_string())?,
+        None => false,
+    };
+
+    if !parked {
+        info!(
+            "Dispatching {} changes to {}-publish-api",
+            batch.len(),
+            query_node_id
+        );
+        if let Err(e) = deliver(
+            invoker,
+            query_node_id,
+            batch,
+            traceparent,
+            &delivery.retry_policy,
+        )
+        .await
+        {
+            match &delivery.backlog {
+                Some(backlog) => {
+                    log::error!("{}", e);
+                    backlog
+                        .park(query_node_id, batch)
+                        .await
+                        .map_err(|e| e.to_string())?;
+                }
+                None => return Err(e),
+            }
+        }
+    }
+
+    if let Some(event_id) = event_id {
+        delivery.tracker.mark_delivered(event_id, query_node_id);
+    }
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use async_trait::async_trait;
+    use backlog::MemoryBacklogStore;
+    use drasi_comms_abstractions::comms::{Headers, Payload};
+    use drasi_comms_dapr::comms::InvocationError;
+    use std::sync::Mutex;
+
+    #[derive(Default)]
+    struct RecordingInvoker {
+        invocations: Mutex<Vec<(String, String, Value)>>,
+        unavailable: Mutex<HashSet<String>>,
+        // apps that have no `changes` method yet
+        legacy: Mutex<HashSet<String>>,
+    }
+
+    impl RecordingInvoker {
+        fn set_available(&self, app_id: &str, available: bool) {
+            let mut unavailable = self.unavailable.lock().unwrap();
+            match available {
+                true => unavailable.remove(app_id),
+                false => unavailable.insert(app_id.to_string()),
+            };
+        }
+
+        fn take(&self) -> Vec<(String, String, Value)> {
+            let mut invocations = std::mem::take(&mut *self.invocations.lock().unwrap());
+            invocations.sort_by(|a, b| a.0.cmp(&b.0));
+            invocations
+        }
+    }
+
+    #[async_trait]
+    impl Invoker for RecordingInvoker {
+        async fn invoke(
+            &self,
+            data: Payload,
+            app_id: &str,
+            method: &str,
+            _headers: Option<Headers>,
+        ) -> Result<bytes::Bytes, Box<dyn std::error::Error>> {
+            if self.unavailable.lock().unwrap().contains(app_id) {
+                return Err(Box::from(format!("{} is unavailable", app_id)));
+            }
+            if method == "changes" && self.legacy.lock().unwrap().contains(app_id) {
+                return Err(Box::new(InvocationError {
+                    status: reqwest::StatusCode::NOT_FOUND,
+                    body: String::new(),
+                }));
+            }
+            let data = match data {
+                Payload::Json(data) => data,
+                _ => panic!("expected a json payload"),
+            };
+            self.invocations
+                .lock()
+                .unwrap()
+                .push((app_id.to_string(), method.to_string(), data));
+            Ok(bytes::Bytes::new())
+        }
+    }
+
+    fn change(id: &str, subscriptions: Value) -> Value {
+        json!({
+            "id": id,
+            "subscriptions": subscriptions,
+            "metadata": { "tracking": { "source": {} } }
+        })
+    }
+
+    fn two_node_changes() -> Value {
+        json!([change(
+            "c1",
+            json!([
+                { "queryNodeId": "node1", "queryId": "q1" },
+                { "queryNodeId": "node2", "queryId": "q2" }
+            ])
+        )])
+    }
+
+    fn delivery(backlog: Option<Arc<Backlog>>) -> Delivery {
+        Delivery {
+            retry_policy: RetryPolicy {
+                max_attempts: 2,
+                initial_backoff: Duration::from_millis(1),
+                max_backoff: Duration::from_millis(1),
+            },
+            tracker: DeliveryTracker::new(100),
+            ordering: ElementOrdering::new(4, 100),
+            backlog,
+        }
+    }
+
+    async fn process(
+        invoker: &RecordingInvoker,
+        changes: Value,
+        event_id: &str,
+        delivery: &Delivery,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        process_changes(
+            invoker,
+            changes,
+            ChangeDispatcherConfig::default(),
+            String::new(),
+            0,
+            Some(event_id),
+            delivery,
+        )
+        .await
+    }
+
+    fn ids(batch: &Value) -> Vec<&Value> {
+        batch.as_array().unwrap().iter().map(|c| &c["id"]).collect()
+    }
+
+    #[tokio::test]
+    async fn test_batches_changes_per_query_node() {
+        let invoker = RecordingInvoker::default();
+        let changes = json!([
+            change(
+                "c1",
+                json!([
+                    { "queryNodeId": "node1", "queryId": "q1" },
+                    { "queryNodeId": "node2", "queryId": "q2" }
+                ])
+            ),
+            change("c2", json!([{ "queryNodeId": "node1", "queryId": "q3" }])),
+            change(
+                "c3",
+                json!([
+                    { "queryNodeId": "node1", "queryId": "q1" },
+                    { "queryNodeId": "node1", "queryId": "q3" }
+                ])
+            ),
+        ]);
+
+        process(&invoker, changes, "e1", &delivery(None))
+            .await
+            .unwrap();
+
+        let invocations = invoker.take();
+        assert_eq!(invocations.len(), 2);
+
+        let (app_id, method, batch) = &invocations[0];
+        assert_eq!(app_id, "node1-publish-api");
+        assert_eq!(method, "changes");
+        assert_eq!(ids(batch), vec!["c1", "c2", "c3"]);
+        assert_eq!(batch[0]["queries"], json!(["q1"]));
+        assert_eq!(batch[2]["queries"], json!(["q1", "q3"]));
+
+        let (app_id, _, batch) = &invocations[1];
+        assert_eq!(app_id, "node2-publish-api");
+        assert_eq!(batch.as_array().unwrap().len(), 1);
+        assert_eq!(batch[0]["queries"], json!(["q2"]));
+    }
+
+    #[tokio::test]
+    async fn test_falls_back_to_single_changes_for_older_publish_api() {
+        let invoker = RecordingInvoker::default();
+        invoker
+            .legacy
+            .lock()
+            .unwrap()
+            .insert("node1-publish-api".to_string());
+        let changes = json!([
+            change("c1", json!([{ "queryNodeId": "node1", "queryId": "q1" }])),
+            change("c2", json!([{ "queryNodeId": "node1", "queryId": "q1" }])),
+        ]);
+
+        process(&invoker, changes, "e1", &delivery(None))
+            .await
+            .unwrap();
+
+        let invocations = invoker.take();
+        assert_eq!(invocations.len(), 2);
+        for ((app_id, method, change), id) in invocations.iter().zip(["c1", "c2"]) {
+            assert_eq!(app_id, "node1-publish-api");
+            assert_eq!(method, "change");
+            assert_eq!(change["id"], id);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_redelivery_skips_nodes_that_received_the_event() {
+        let invoker = RecordingInvoker::default();
+        let delivery = delivery(None);
+        invoker.set_available("node2-publish-api", false);
+
+        assert!(process(&invoker, two_node_changes(), "e1", &delivery)
+            .await
+            .is_err());
+        let invocations = invoker.take();
+        assert_eq!(invocations.len(), 1);
+        assert_eq!(invocations[0].0, "node1-publish-api");
+
+        invoker.set_available("node2-publish-api", true);
+        process(&invoker, two_node_changes(), "e1", &delivery)
+            .await
+            .unwrap();
+        let invocations = invoker.take();
+        assert_eq!(invocations.len(), 1);
+        assert_eq!(invocations[0].0, "node2-publish-api");
+
+        // once every node received it, the event is no longer tracked
+        assert!(!delivery.tracker.is_delivered("e1", "node1"));
+    }
+
+    #[tokio::test]
+    async fn test_unavailable_node_is_parked_and_drained_in_order() {
+        let store = Arc::new(MemoryBacklogStore::default());
+        let backlog = Arc::new(Backlog::load(store.clone(), 100, 10).await.unwrap());
+        let invoker = RecordingInvoker::default();
+        let delivery = delivery(Some(backlog.clone()));
+        invoker.set_available("node2-publish-api", false);
+
+        process(&invoker, two_node_changes(), "e1", &delivery)
+            .await
+            .unwrap();
+        assert_eq!(invoker.take().len(), 1);
+        assert_eq!(store.backlogs.lock().unwrap()["node2"].len(), 1);
+
+        // the node has recovered, but new changes queue behind its backlog
+        invoker.set_available("node2-publish-api", true);
+        let changes = json!([change(
+            "c2",
+            json!([{ "queryNodeId": "node2", "queryId": "q2" }])
+        )]);
+        process(&invoker, changes, "e2", &delivery).await.unwrap();
+        assert!(invoker.take().is_empty());
+        assert_eq!(store.backlogs.lock().unwrap()["node2"].len(), 2);
+
+        backlog.drain(&invoker, &delivery.retry_policy).await;
+        let invocations = invoker.take();
+        assert_eq!(invocations.len(), 1);
+        assert_eq!(invocations[0].0, "node2-publish-api");
+        assert_eq!(ids(&invocations[0].2), vec!["c1", "c2"]);
+        assert!(store.backlogs.lock().unwrap().is_empty());
+
+        // with the backlog drained, changes are delivered directly again
+        process(&invoker, two_node_changes(), "e3", &delivery)
+            .await
+            .unwrap();
+        assert_eq!(invoker.take().len(), 2);
+    }
+
+    #[tokio::test]
+    async fn test_stale_changes_to_an_element_are_not_dispatched() {
+        let invoker = RecordingInvoker::default();
+        let delivery = delivery(None);
+        let element_change = |id: &str, sequence: u64| {
+            let mut change = change(id, json!([{ "queryNodeId": "node1", "queryId": "q1" }]));
+            change["sequence"] = json!(sequence);
+            change["elementType"] = json!("node");
+            change["after"] = json!({ "id": "n1" });
+            change
+        };
+
+        process(&invoker, json!([element_change("c2", 2)]), "e2", &delivery)
+            .await
+            .unwrap();
+        // a redelivered older change arrives after the newer one
+        process(&invoker, json!([element_change("c1", 1)]), "e1", &delivery)
+            .await
+            .unwrap();
+        process(&invoker, json!([element_change("c3", 3)]), "e3", &delivery)
+            .await
+            .unwrap();
+
+        let invocations = invoker.take();
+        assert_eq!(invocations.len(), 2);
+        assert_eq!(ids(&invocations[0].2), vec!["c2"]);
+        assert_eq!(ids(&invocations[1].2), vec!["c3"]);
+    }
+}
@@ -0,0 +1,157 @@
+// Copyright 2024 The Drasi Authors.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::{
+    collections::{hash_map::DefaultHasher, BTreeSet, HashMap, VecDeque},
+    hash::{Hash, Hasher},
+    sync::Mutex,
+};
+
+use serde_json::Value;
+use tokio::sync::{Mutex as AsyncMutex, MutexGuard};
+
+/// Keeps the changes to each element in the order of the sequence numbers stamped by the change router.
+///
+/// Elements are hashed into partitions, and the changes of a partition are dispatched one event at a time,
+/// so two changes to the same element are never in flight together.
+/// A change that arrives after a later change to the same element was dispatched to a query node,
+/// for example when pubsub redelivers a failed event, is stale and is not dispatched to that query node.
+///
+/// The sequences are kept in memory only, for the most recently changed elements, up to a capacity set with
+/// `DISPATCH_ORDERING_CAPACITY`. A stale change to an element that was evicted, or that arrives after the dispatcher
+/// restarted, is dispatched anyway; the query host keeps the sequence of every element it applied and discards it there.
+pub struct ElementOrdering {
+    partitions: Vec<AsyncMutex<()>>,
+    capacity: usize,
+    inner: Mutex<SequenceState>,
+}
+
+#[derive(Default)]
+struct SequenceState {
+    sequences: HashMap<(String, String), u64>,
+    order: VecDeque<(String, String)>,
+}
+
+/// Identifies the element a change applies to, along with the sequence number of the change
+pub struct ElementChange {
+    pub key: String,
+    pub sequence: u64,
+}
+
+impl ElementChange {
+    pub fn from_change(change: &Value) -> Option<Self> {
+        let sequence = change["sequence"].as_u64()?;
+        let id = change["after"]["id"]
+            .as_str()
+            .or_else(|| change["before"]["id"].as_str())?;
+        let element_type = change["elementType"].as_str().unwrap_or_default();
+
+        Some(ElementChange {
+            key: format!("{}/{}", element_type, id),
+            sequence,
+        })
+    }
+}
+
+impl ElementOrdering {
+    pub fn new(partitions: usize, capacity: usize) -> Self {
+        ElementOrdering {
+            partitions: (0..partitions.max(1))
+                .map(|_| AsyncMutex::new(()))
+                .collect(),
+            capacity,
+            inner: Mutex::new(SequenceState::default()),
+        }
+    }
+
+    /// Waits until no other event is being dispatched for the partitions of the given elements.
+    /// Partitions are locked in a consistent order, so events sharing partitions cannot deadlock.
+    pub async fn lock<'a>(&self, keys: impl Iterator<Item = &'a str>) -> Vec<MutexGuard<'_, ()>> {
+        let partitions: BTreeSet<usize> = keys.map(|key| self.partition(key)).collect();
+
+        let mut guards = Vec::with_capacity(partitions.len());
+        for partition in partitions {
+            guards.push(self.partitions[partition].lock().await);
+        }
+        guards
+    }
+
+    pub fn is_stale(&self, query_node_id: &str, change: &ElementChange) -> bool {
+        let inner = self.inner.lock().expect("element ordering lock poisoned");
+        inner
+            .sequences
+            .get(&(query_node_id.to_string(), change.key.clone()))
+            .is_some_and(|last| *last > change.sequence)
+    }
+
+    pub fn mark_dispatched(&self, query_node_id: &str, change: &ElementChange) {
+        let mut inner = self.inner.lock().expect("element ordering lock poisoned");
+        let key = (query_node_id.to_string(), change.key.clone());
+
+        match inner.sequences.get_mut(&key) {
+            Some(last) => *last = (*last).max(change.sequence),
+            None => {
+                inner.sequences.insert(key.clone(), change.sequence);
+                inner.order.push_back(key);
+                while inner.order.len() > self.capacity {
+                    if let Some(oldest) = inner.order.pop_front() {
+                        inner.sequences.remove(&oldest);
+                    }
+                }
+            }
+        }
+    }
+
+    fn partition(&self, key: &str) -> usize {
+        let mut hasher = DefaultHasher::new();
+        key.hash(&mut hasher);
+        (hasher.finish() % self.partitions.len() as u64) as usize
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn change(id: &str, sequence: u64) -> ElementChange {
+        ElementChange::from_change(&json!({
+            "sequence": sequence,
+            "elementType": "node",
+            "after": { "id": id },
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn test_older_changes_are_stale_per_query_node() {
+        let ordering = ElementOrdering::new(4, 100);
+        ordering.mark_dispatched("node1", &change("e1", 10));
+
+        assert!(ordering.is_stale("node1", &change("e1", 9)));
+        assert!(!ordering.is_stale("node1", &change("e1", 10)));
+        assert!(!ordering.is_stale("node1", &change("e1", 11)));
+        assert!(!ordering.is_stale("node1", &change("e2", 9)));
+        assert!(!ordering.is_stale("node2", &change("e1", 9)));
+    }
+
+    #[test]
+    fn test_changes_without_sequence_are_not_tracked() {
+        assert!(ElementChange::from_change(&json!({
+            "elementType": "node",
+            "after": { "id": "e1" },
+        }))
+        .is_none());
+    }
+}
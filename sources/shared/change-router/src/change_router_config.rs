@@ -24,6 +24,10 @@ pub struct ChangeRouterConfig {
     pub otel_endpoint: String,
     pub dapr_port: String,
     pub app_port: String,
+    pub subscription_store_type: String,
+    pub redis_url: String,
+    pub change_ingest: String,
+    pub sequence_from_lsn: bool,
 }
 
 impl ChangeRouterConfig {
@@ -39,6 +43,20 @@ impl ChangeRouterConfig {
         let dapr_port = std::env::var("DAPR_HTTP_PORT").unwrap_or_else(|_| "3000".to_string());
 
         let app_port = std::env::var("APP_PORT").unwrap_or_else(|_| "3000".to_string());
+
+        // dapr, redis or memory
+        let subscription_store_type =
+            env::var("SUBSCRIPTION_STORE_TYPE").unwrap_or_else(|_| "dapr".to_string());
+        let redis_url =
+            env::var("REDIS_BROKER").unwrap_or_else(|_| "redis://drasi-redis:6379".to_string());
+
+        // dapr, or redis to read the changes that sources publish directly to the Redis stream
+        let change_ingest = env::var("CHANGE_INGEST").unwrap_or_else(|_| "dapr".to_string());
+
+        // only for sources whose lsn increases with every change, otherwise the router numbers the changes itself
+        let sequence_from_lsn = env::var("SEQUENCE_FROM_LSN")
+            .map(|value| value == "true")
+            .unwrap_or(false);
         Self {
             source_id,
             subscriber_store,
@@ -46,6 +64,10 @@ impl ChangeRouterConfig {
             otel_endpoint,
             dapr_port,
             app_port,
+            subscription_store_type,
+            redis_url,
+            change_ingest,
+            sequence_from_lsn,
         }
     }
 }
@@ -15,8 +15,11 @@
 use std::sync::Arc;
 
 use change_router_config::ChangeRouterConfig;
+use drasi_source_predicates::parse_label_predicates;
 use log::{debug, info};
-use serde_json::{json, Value};
+use redis_ingest::RedisIngest;
+use sequencer::Sequencer;
+use serde_json::{json, Map, Value};
 use subscribers::Subscriber;
 use uuid::Uuid;
 
@@ -30,12 +33,18 @@ use axum::{
 
 use drasi_comms_abstractions::comms::{Headers, Publisher};
 use drasi_comms_dapr::comms::DaprHttpPublisher;
-use state_manager::{DaprStateManager, StateEntry};
+use subscription_store::SubscriptionStore;
 
 mod change_router_config;
+mod redis_ingest;
+mod sequencer;
 mod state_manager;
 mod subscriber_map;
 mod subscribers;
+mod subscription_store;
+
+const INGEST_BATCH_SIZE: usize = 100;
+const INGEST_RETRY_DELAY: std::time::Duration = std::time::Duration::from_secs(1);
 
 #[tokio::main]
 async fn main() -> Result<(), Box<dyn std::error::Error>> {
@@ -47,13 +56,6 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
     let node_subscriber = Subscriber::new();
     let rel_subscriber = Subscriber::new();
 
-    let query_condition = json!({
-        "filter": {
-            "EQ": { "type": "SourceSubscription" }
-        }
-    });
-    let mut metadata = std::collections::HashMap::new();
-
     let topic = format!("{}-dispatch", config.source_id.clone()).to_string();
     let dapr_port = match config.dapr_port.parse::<u16>() {
         Ok(port) => port,
@@ -72,20 +74,83 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
         config.pubsub_name.clone(),
         topic,
     );
-    let state_manager = DaprStateManager::new("127.0.0.1", dapr_port, &config.subscriber_store);
+    let subscription_store = match subscription_store::from_config(&config, dapr_port).await {
+        Ok(store) => store,
+        Err(e) => {
+            return Err(Box::<dyn std::error::Error>::from(format!(
+                "Error creating the subscription store: {:?}",
+                e
+            )));
+        }
+    };
 
-    metadata.insert("contentType".to_string(), "application/json".to_string());
-    let response = match state_manager
-        .query_state(query_condition, Some(metadata))
-        .await
-    {
-        Ok(response) => response,
+    load_subscriptions(
+        subscription_store.as_ref(),
+        &node_subscriber,
+        &rel_subscriber,
+    )
+    .await;
+    let sequencer = Sequencer::new(subscription_store.clone(), config.sequence_from_lsn);
+    let shared_state = Arc::new(AppState {
+        node_subscriber,
+        rel_subscriber,
+        config: config.clone(),
+        publisher,
+        subscription_store,
+        sequencer,
+    });
+
+    if config.change_ingest == "redis" {
+        let ingest =
+            match RedisIngest::connect(&config.redis_url, &config.source_id, INGEST_BATCH_SIZE)
+                .await
+            {
+                Ok(ingest) => ingest,
+                Err(e) => {
+                    return Err(Box::<dyn std::error::Error>::from(format!(
+                        "Error connecting to the change stream: {:?}",
+                        e
+                    )));
+                }
+            };
+        tokio::spawn(ingest_from_redis(shared_state.clone(), ingest));
+    }
+
+    let subscriber_server = Router::new()
+        .route("/dapr/subscribe", get(subscribe))
+        .route("/receive", post(receive))
+        .route("/subscriptions", get(list_subscriptions))
+        .with_state(shared_state);
+
+    let addr = format!("0.0.0.0:{}", config.app_port);
+    let listener = match tokio::net::TcpListener::bind(addr).await {
+        Ok(listener) => listener,
         Err(e) => {
-            log::error!("Error querying the Dapr state store: {:?}", e);
+            return Err(Box::<dyn std::error::Error>::from(format!(
+                "Error binding to address: {:?}",
+                e
+            )));
+        }
+    };
+    axum::serve(listener, subscriber_server).await.unwrap();
+
+    Ok(())
+}
+
+/// Restores the subscriptions saved in the store, so changes are routed to them after a restart
+async fn load_subscriptions(
+    store: &dyn SubscriptionStore,
+    node_subscriber: &Subscriber,
+    rel_subscriber: &Subscriber,
+) {
+    let subscriptions = match store.get_all().await {
+        Ok(subscriptions) => subscriptions,
+        Err(e) => {
+            log::error!("Error loading subscriptions from the store: {:?}", e);
             vec![]
         }
     };
-    for data in response {
+    for data in subscriptions {
         // if the data is corrupt for this subscription, this will cause a panic and stop loading all the others... we should probably log an error but continue to load the rest of the subscriptions
         let node_labels: Vec<&str> = match data["nodeLabels"].as_array() {
             Some(labels) => labels
@@ -97,8 +162,16 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
                 vec![]
             }
         };
-        node_subscriber.add_labels(
+        let node_predicates = match parse_label_predicates(&data["nodePredicates"]) {
+            Ok(predicates) => predicates,
+            Err(e) => {
+                log::error!("Error loading nodePredicates for subscription: {:?}", e);
+                continue;
+            }
+        };
+        node_subscriber.update_subscription(
             node_labels,
+            node_predicates,
             match data["queryNodeId"].as_str() {
                 Some(query_node_id) => query_node_id,
                 None => {
@@ -129,8 +202,16 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
                 vec![]
             }
         };
-        rel_subscriber.add_labels(
+        let rel_predicates = match parse_label_predicates(&data["relPredicates"]) {
+            Ok(predicates) => predicates,
+            Err(e) => {
+                log::error!("Error loading relPredicates for subscription: {:?}", e);
+                continue;
+            }
+        };
+        rel_subscriber.update_subscription(
             rel_labels,
+            rel_predicates,
             match data["queryNodeId"].as_str() {
                 Some(query_node_id) => query_node_id,
                 None => {
@@ -158,32 +239,6 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
         "Forwarding Relation types: {:?}",
         rel_subscriber.get_label_map()
     );
-
-    let shared_state = Arc::new(AppState {
-        node_subscriber,
-        rel_subscriber,
-        config: config.clone(),
-        publisher,
-        state_manager,
-    });
-    let subscriber_server = Router::new()
-        .route("/dapr/subscribe", get(subscribe))
-        .route("/receive", post(receive))
-        .with_state(shared_state);
-
-    let addr = format!("0.0.0.0:{}", config.app_port);
-    let listener = match tokio::net::TcpListener::bind(addr).await {
-        Ok(listener) => listener,
-        Err(e) => {
-            return Err(Box::<dyn std::error::Error>::from(format!(
-                "Error binding to address: {:?}",
-                e
-            )));
-        }
-    };
-    axum::serve(listener, subscriber_server).await.unwrap();
-
-    Ok(())
 }
 
 struct AppState {
@@ -191,11 +246,17 @@ struct AppState {
     rel_subscriber: Subscriber,
     config: ChangeRouterConfig,
     publisher: DaprHttpPublisher,
-    state_manager: DaprStateManager,
+    subscription_store: Arc<dyn SubscriptionStore>,
+    sequencer: Sequencer,
 }
 
 async fn subscribe() -> impl IntoResponse {
     let config = ChangeRouterConfig::from_env();
+    // changes are read from the Redis stream instead, see ingest_from_redis
+    if config.change_ingest == "redis" {
+        return Json(vec![]);
+    }
+
     let subscriptions = vec![json! {
         {
             "pubsubname": config.pubsub_name.clone(),
@@ -207,6 +268,34 @@ async fn subscribe() -> impl IntoResponse {
     Json(subscriptions)
 }
 
+/// Lists the subscriptions of queries to this source
+async fn list_subscriptions(State(state): State<Arc<AppState>>) -> impl IntoResponse {
+    match state.subscription_store.get_all().await {
+        Ok(subscriptions) => {
+            let subscriptions: Vec<Value> = subscriptions
+                .into_iter()
+                .map(|s| {
+                    json!({
+                        "queryNodeId": s["queryNodeId"],
+                        "queryId": s["queryId"],
+                        "nodeLabels": s["nodeLabels"],
+                        "relLabels": s["relLabels"],
+                        "subscribedAt": s["subscribedAt"],
+                    })
+                })
+                .collect();
+            (StatusCode::OK, Json(json!(subscriptions)))
+        }
+        Err(e) => {
+            log::error!("Error listing subscriptions: {:?}", e);
+            (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(json!({"error": e.to_string()})),
+            )
+        }
+    }
+}
+
 #[axum::debug_handler]
 async fn receive(
     State(state): State<Arc<AppState>>,
@@ -228,7 +317,7 @@ async fn receive(
     let json_data = body["data"].clone();
 
     let publisher = &state.publisher;
-    let state_manager = &state.state_manager;
+    let subscription_store = state.subscription_store.as_ref();
     match process_changes(
         publisher,
         json_data,
@@ -236,7 +325,8 @@ async fn receive(
         node_subscriber,
         rel_subscriber,
         trace_parent,
-        state_manager,
+        subscription_store,
+        &state.sequencer,
         receive_time,
     )
     .await
@@ -253,15 +343,64 @@ async fn receive(
     (StatusCode::OK, Json(json!({"message": "Success"})))
 }
 
+/// Routes the changes that sources publish directly to Redis.
+/// A stream entry is acknowledged once its changes have been dispatched; when routing fails,
+/// the unacknowledged entries are read again after a short delay.
+async fn ingest_from_redis(state: Arc<AppState>, mut ingest: RedisIngest) {
+    loop {
+        let batch = match ingest.next_batch().await {
+            Ok(batch) => batch,
+            Err(e) => {
+                log::error!("Error reading changes from Redis: {:?}", e);
+                tokio::time::sleep(INGEST_RETRY_DELAY).await;
+                continue;
+            }
+        };
+
+        for entry in batch {
+            let receive_time = chrono::Utc::now().timestamp_nanos_opt().unwrap_or_default();
+            let error = process_changes(
+                &state.publisher,
+                entry.changes,
+                state.config.clone(),
+                &state.node_subscriber,
+                &state.rel_subscriber,
+                entry.traceparent,
+                state.subscription_store.as_ref(),
+                &state.sequencer,
+                receive_time,
+            )
+            .await
+            .err()
+            .map(|e| e.to_string());
+
+            match error {
+                None => {
+                    if let Err(e) = ingest.ack(&entry.id).await {
+                        log::error!("Error acknowledging stream entry {}: {:?}", entry.id, e);
+                    }
+                }
+                Some(e) => {
+                    log::error!("Error routing changes of stream entry {}: {}", entry.id, e);
+                    ingest.redeliver_pending();
+                    tokio::time::sleep(INGEST_RETRY_DELAY).await;
+                    break;
+                }
+            }
+        }
+    }
+}
+
 #[allow(clippy::too_many_arguments)]
-async fn process_changes(
-    publisher: &DaprHttpPublisher,
+async fn process_changes<P: Publisher>(
+    publisher: &P,
     changes: Value,
     config: ChangeRouterConfig,
     node_subscriber: &Subscriber,
     rel_subscriber: &Subscriber,
     traceparent: String,
-    state_manager: &DaprStateManager,
+    subscription_store: &dyn SubscriptionStore,
+    sequencer: &Sequencer,
     receive_time: i64,
 ) -> Result<(), Box<dyn std::error::Error>> {
     // Use the receive_time to capture the time when the events are received via pubsub
@@ -289,7 +428,9 @@ async fn process_changes(
             && change["payload"]["source"]["table"] == "SourceSubscription"
         {
             match change["op"].as_str() {
-                Some("i") => {
+                // A query subscribes again with "i" when its labels change, so both ops replace
+                // the labels the query is subscribed to
+                Some("i") | Some("u") => {
                     let node_labels: Vec<&str> =
                         match change["payload"]["after"]["nodeLabels"].as_array() {
                             Some(labels) => labels
@@ -305,8 +446,22 @@ async fn process_changes(
                                 continue;
                             }
                         };
-                    node_subscriber.add_labels(
+                    let node_predicates =
+                        match parse_label_predicates(&change["payload"]["after"]["nodePredicates"])
+                        {
+                            Ok(predicates) => predicates,
+                            Err(e) => {
+                                log::error!(
+                                    "Error loading nodePredicates from the ChangeEvent: {:?}",
+                                    e
+                                );
+                                log::error!("Error path: 'payload' -> 'after' -> 'nodePredicates'");
+                                continue;
+                            }
+                        };
+                    node_subscriber.update_subscription(
                         node_labels,
+                        node_predicates,
                         match change["payload"]["after"]["queryNodeId"].as_str() {
                             Some(query_node_id) => query_node_id,
                             None => {
@@ -347,8 +502,22 @@ async fn process_changes(
                                 continue;
                             }
                         };
-                    rel_subscriber.add_labels(
+                    let rel_predicates = match parse_label_predicates(
+                        &change["payload"]["after"]["relPredicates"],
+                    ) {
+                        Ok(predicates) => predicates,
+                        Err(e) => {
+                            log::error!(
+                                "Error loading relPredicates from the ChangeEvent: {:?}",
+                                e
+                            );
+                            log::error!("Error path: 'payload' -> 'after' -> 'relPredicates'");
+                            continue;
+                        }
+                    };
+                    rel_subscriber.update_subscription(
                         rel_labels,
+                        rel_predicates,
                         match change["payload"]["after"]["queryNodeId"].as_str() {
                             Some(query_node_id) => query_node_id,
                             None => {
@@ -380,33 +549,34 @@ async fn process_changes(
                         "queryId": change["payload"]["after"]["queryId"],
                         "queryNodeId": change["payload"]["after"]["queryNodeId"],
                         "nodeLabels": change["payload"]["after"]["nodeLabels"],
-                        "relLabels": change["payload"]["after"]["relLabels"]
+                        "relLabels": change["payload"]["after"]["relLabels"],
+                        "nodePredicates": change["payload"]["after"]["nodePredicates"],
+                        "relPredicates": change["payload"]["after"]["relPredicates"],
+                        "subscribedAt": chrono::Utc::now().timestamp_millis()
                     });
 
-                    let state_key = format!(
-                        "SourceSubscription-{}-{}",
-                        match change["payload"]["after"]["queryNodeId"].as_str() {
-                            Some(query_node_id) => query_node_id,
-                            None =>
-                                return Err(Box::<dyn std::error::Error>::from(
-                                    "Error loading queryNodeId from the ChangeEvent"
-                                )),
-                        },
-                        match change["payload"]["after"]["queryId"].as_str() {
-                            Some(query_id) => query_id,
-                            None =>
-                                return Err(Box::<dyn std::error::Error>::from(
-                                    "Error loading queryId from the ChangeEvent"
-                                )),
+                    let query_node_id = match change["payload"]["after"]["queryNodeId"].as_str() {
+                        Some(query_node_id) => query_node_id,
+                        None => {
+                            return Err(Box::<dyn std::error::Error>::from(
+                                "Error loading queryNodeId from the ChangeEvent",
+                            ))
                         }
-                    );
-
-                    // combine statekey and source_subscription_value into a json
-                    // where the key is the state key and the value is the source subscription value
-                    let states = vec![StateEntry::new(&state_key, source_subscription_value)];
+                    };
+                    let query_id = match change["payload"]["after"]["queryId"].as_str() {
+                        Some(query_id) => query_id,
+                        None => {
+                            return Err(Box::<dyn std::error::Error>::from(
+                                "Error loading queryId from the ChangeEvent",
+                            ))
+                        }
+                    };
 
-                    match state_manager.save_state(states).await {
-                        Ok(_) => info!("Saved SourceSubscription to state store"),
+                    match subscription_store
+                        .save(query_node_id, query_id, source_subscription_value)
+                        .await
+                    {
+                        Ok(_) => info!("Saved SourceSubscription to the subscription store"),
                         Err(e) => {
                             return Err(e);
                         }
@@ -414,38 +584,52 @@ async fn process_changes(
                 }
                 Some("d") => {
                     // Handle unsubscription
-                    let state_key = format!(
-                        "SourceSubscription-{}-{}",
-                        match change["payload"]["before"]["queryNodeId"].as_str() {
-                            Some(query_node_id) => query_node_id,
-                            None =>
-                                return Err(Box::<dyn std::error::Error>::from(
-                                    "Error loading queryNodeId from the ChangeEvent"
-                                )),
-                        },
-                        match change["payload"]["before"]["queryId"].as_str() {
-                            Some(query_id) => query_id,
-                            None =>
-                                return Err(Box::<dyn std::error::Error>::from(
-                                    "Error loading queryId from the ChangeEvent"
-                                )),
+                    let query_node_id = match change["payload"]["before"]["queryNodeId"].as_str() {
+                        Some(query_node_id) => query_node_id,
+                        None => {
+                            return Err(Box::<dyn std::error::Error>::from(
+                                "Error loading queryNodeId from the ChangeEvent",
+                            ))
                         }
-                    );
-                    match state_manager.delete_state(&state_key, None).await {
-                        Ok(_) => info!("Deleted Subscription {} from state store", state_key),
+                    };
+                    let query_id = match change["payload"]["before"]["queryId"].as_str() {
+                        Some(query_id) => query_id,
+                        None => {
+                            return Err(Box::<dyn std::error::Error>::from(
+                                "Error loading queryId from the ChangeEvent",
+                            ))
+                        }
+                    };
+
+                    node_subscriber.remove_subscriber(query_node_id, query_id);
+                    rel_subscriber.remove_subscriber(query_node_id, query_id);
+
+                    match subscription_store.delete(query_node_id, query_id).await {
+                        Ok(_) => info!(
+                            "Deleted Subscription {}-{} from the subscription store",
+                            query_node_id, query_id
+                        ),
                         Err(e) => {
                             return Err(e);
                         }
                     }
                 }
-                _ => {
-                    // TODO - supprt other ops on SourceSubscriptions
+                op => {
+                    log::warn!("Unsupported SourceSubscription op: {:?}", op);
                 }
             }
             return Ok(());
         }
 
-        let mut subscriptions: Option<Vec<String>> = None;
+        // Subscription predicates are evaluated against the properties of the element before and after the change.
+        // An update without the previous properties is evaluated like an insert, against the properties after it.
+        let versions: Vec<&Map<String, Value>> = ["before", "after"]
+            .iter()
+            .filter_map(|v| change["payload"][*v]["properties"].as_object())
+            .collect();
+        let versions = Some(versions.as_slice());
+
+        let mut subscriptions = Vec::new();
         if change["payload"]["source"]["table"] == "node" {
             if change["op"] == "i" || change["op"] == "u" {
                 let labels: Vec<&str> = match change["payload"]["after"]["labels"].as_array() {
@@ -462,7 +646,7 @@ async fn process_changes(
                         continue;
                     }
                 };
-                subscriptions = node_subscriber.get_subscribers_for_labels(labels);
+                subscriptions = node_subscriber.get_subscribers_for_labels(labels, versions);
             } else if change["op"] == "d" {
                 let labels: Vec<&str> = match change["payload"]["before"]["labels"].as_array() {
                     Some(labels) => labels
@@ -478,7 +662,7 @@ async fn process_changes(
                         continue;
                     }
                 };
-                subscriptions = node_subscriber.get_subscribers_for_labels(labels);
+                subscriptions = node_subscriber.get_subscribers_for_labels(labels, versions);
             }
         } else if change["payload"]["source"]["table"] == "rel" {
             if change["op"] == "i" || change["op"] == "u" {
@@ -493,7 +677,7 @@ async fn process_changes(
                         continue;
                     }
                 };
-                subscriptions = rel_subscriber.get_subscribers_for_labels(labels);
+                subscriptions = rel_subscriber.get_subscribers_for_labels(labels, versions);
             } else if change["op"] == "d" {
                 let labels: Vec<&str> = match change["payload"]["before"]["labels"].as_array() {
                     Some(labels) => labels
@@ -506,73 +690,337 @@ async fn process_changes(
                         continue;
                     }
                 };
-                subscriptions = rel_subscriber.get_subscribers_for_labels(labels);
+                subscriptions = rel_subscriber.get_subscribers_for_labels(labels, versions);
             }
         }
 
-        match subscriptions {
-            Some(subscriptions) => {
-                let subscriptions: Vec<Value> = subscriptions
-                    .iter()
-                    .map(|subscription| {
-                        let parsed_subscription: Value = match serde_json::from_str(subscription) {
-                            Ok(parsed_subscription) => parsed_subscription,
-                            Err(e) => {
-                                log::error!("Error parsing subscription: {:?}", e);
-                                return json!({});
-                            }
-                        };
-                        parsed_subscription
-                    })
-                    .collect();
-
-                let publish_topic = format!("{}-dispatch", config.source_id);
-                let mut headers = std::collections::HashMap::new();
-                headers.insert("traceparent".to_string(), traceparent.clone());
-                let headers = Headers::new(headers);
-
-                let change_dispatch_event = json!([{
-                    "id": change_id,
-                    "sourceId": config.source_id,
-                    "type": change["op"],
-                    "elementType": change["payload"]["source"]["table"],
-                    "subscriptions": subscriptions,
-                    "time": {
-                        "seq": change["payload"]["source"]["lsn"],
-                        "ms": change["payload"]["source"]["ts_ns"]
-                            .as_u64()
-                            .ok_or_else(|| Box::<dyn std::error::Error>::from("Error converting ts_ns to u64"))? / 1_000_000,  // convert to milliseconds for drasi-core
-                    },
-                    "before": change["payload"]["before"],
-                    "after": change["payload"]["after"],
-                    "metadata": {
-                        "tracking": {
-                            "source": {
-                                "seq": change["payload"]["source"]["lsn"],
-                                "changeRouterStart_ns": start_time,
-                                "changeRouterEnd_ns": chrono::Utc::now().timestamp_nanos_opt()
-                                    .unwrap_or_default(),
-                                "source_ns": change["payload"]["source"]["ts_ns"],
-                                "reactivatorStart_ns": change["reactivatorStart_ns"],
-                                "reactivatorEnd_ns": change["reactivatorEnd_ns"],
-                            }
-                        }
-                    }
-                }]);
+        if subscriptions.is_empty() {
+            log::info!("No subscribers for change: {:?}", change);
+            continue;
+        }
 
-                match publisher.publish(change_dispatch_event, headers).await {
-                    Ok(_) => {
-                        info!("published event to topic: {}", publish_topic);
-                    }
-                    Err(e) => {
-                        return Err(e);
+        let publish_topic = format!("{}-dispatch", config.source_id);
+        let mut headers = std::collections::HashMap::new();
+        headers.insert("traceparent".to_string(), traceparent.clone());
+        let headers = Headers::new(headers);
+
+        let sequence = sequencer.next(change).await?;
+        let change_dispatch_event = json!([{
+            "id": change_id,
+            "sourceId": config.source_id,
+            "sequence": sequence,
+            "type": change["op"],
+            "elementType": change["payload"]["source"]["table"],
+            "subscriptions": subscriptions.iter().map(|s| s.as_ref()).collect::<Vec<_>>(),
+            "time": {
+                "seq": change["payload"]["source"]["lsn"],
+                "ms": change["payload"]["source"]["ts_ns"]
+                    .as_u64()
+                    .ok_or_else(|| Box::<dyn std::error::Error>::from("Error converting ts_ns to u64"))? / 1_000_000,  // convert to milliseconds for drasi-core
+            },
+            "before": change["payload"]["before"],
+            "after": change["payload"]["after"],
+            "metadata": {
+                "tracking": {
+                    "source": {
+                        "seq": change["payload"]["source"]["lsn"],
+                        "changeRouterStart_ns": start_time,
+                        "changeRouterEnd_ns": chrono::Utc::now().timestamp_nanos_opt()
+                            .unwrap_or_default(),
+                        "source_ns": change["payload"]["source"]["ts_ns"],
+                        "reactivatorStart_ns": change["reactivatorStart_ns"],
+                        "reactivatorEnd_ns": change["reactivatorEnd_ns"],
                     }
                 }
             }
-            None => {
-                log::info!("No subscribers for change: {:?}", change);
+        }]);
+
+        match publisher.publish(change_dispatch_event, headers).await {
+            Ok(_) => {
+                info!("published event to topic: {}", publish_topic);
+            }
+            Err(e) => {
+                return Err(e);
             }
         }
     }
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use async_trait::async_trait;
+    use std::sync::Mutex;
+    use subscription_store::MemorySubscriptionStore;
+
+    #[derive(Default)]
+    struct RecordingPublisher {
+        published: Mutex<Vec<Value>>,
+    }
+
+    #[async_trait]
+    impl Publisher for RecordingPublisher {
+        fn new(_dapr_host: String, _dapr_port: u16, _pubsub: String, _topic: String) -> Self {
+            Self::default()
+        }
+
+        async fn publish(
+            &self,
+            data: Value,
+            _headers: Headers,
+        ) -> Result<(), Box<dyn std::error::Error>> {
+            self.published.lock().unwrap().push(data);
+            Ok(())
+        }
+    }
+
+    fn config() -> ChangeRouterConfig {
+        ChangeRouterConfig {
+            source_id: "source1".to_string(),
+            ..Default::default()
+        }
+    }
+
+    fn subscription_change(op: &str, query_id: &str, node_labels: Value) -> Value {
+        let subscription = json!({
+            "queryNodeId": "default",
+            "queryId": query_id,
+            "nodeLabels": node_labels,
+            "relLabels": []
+        });
+        let (before, after) = match op {
+            "d" => (subscription, Value::Null),
+            _ => (Value::Null, subscription),
+        };
+        json!({
+            "op": op,
+            "payload": {
+                "source": { "db": "Drasi", "table": "SourceSubscription" },
+                "before": before,
+                "after": after
+            }
+        })
+    }
+
+    fn node_change(label: &str) -> Value {
+        json!({
+            "op": "i",
+            "payload": {
+                "source": { "db": "db1", "table": "node", "lsn": 1, "ts_ns": 2_000_000 },
+                "before": null,
+                "after": { "id": "n1", "labels": [label], "properties": {} }
+            }
+        })
+    }
+
+    struct Router {
+        publisher: RecordingPublisher,
+        store: Arc<MemorySubscriptionStore>,
+        sequencer: Sequencer,
+        node_subscriber: Subscriber,
+        rel_subscriber: Subscriber,
+    }
+
+    impl Router {
+        fn new() -> Self {
+            Self::with_store(MemorySubscriptionStore::new())
+        }
+
+        fn with_store(store: MemorySubscriptionStore) -> Self {
+            let store = Arc::new(store);
+            Router {
+                publisher: RecordingPublisher::default(),
+                sequencer: Sequencer::new(store.clone(), false),
+                store,
+                node_subscriber: Subscriber::new(),
+                rel_subscriber: Subscriber::new(),
+            }
+        }
+
+        async fn process(&self, change: Value) {
+            process_changes(
+                &self.publisher,
+                json!([change]),
+                config(),
+                &self.node_subscriber,
+                &self.rel_subscriber,
+                String::new(),
+                self.store.as_ref(),
+                &self.sequencer,
+                0,
+            )
+            .await
+            .unwrap();
+        }
+
+        fn published(&self) -> Vec<Value> {
+            self.publisher.published.lock().unwrap().clone()
+        }
+    }
+
+    #[tokio::test]
+    async fn test_routes_changes_to_saved_subscriptions() {
+        let router = Router::new();
+        router
+            .process(subscription_change("i", "q1", json!(["Person"])))
+            .await;
+
+        let saved = router.store.get_all().await.unwrap();
+        assert_eq!(saved.len(), 1);
+        assert_eq!(saved[0]["type"], "SourceSubscription");
+        assert_eq!(saved[0]["queryId"], "q1");
+
+        router.process(node_change("Person")).await;
+        router.process(node_change("Company")).await;
+
+        let published = router.published();
+        assert_eq!(published.len(), 1);
+        assert_eq!(published[0][0]["sourceId"], "source1");
+        assert_eq!(
+            published[0][0]["subscriptions"],
+            json!([{ "queryNodeId": "default", "queryId": "q1" }])
+        );
+    }
+
+    #[tokio::test]
+    async fn test_routes_subscription_published_through_dapr_to_redis() {
+        // the query API publishes subscriptions through Dapr, which wraps them in a cloud event
+        let cloud_event = json!({
+            "specversion": "1.0",
+            "type": "com.dapr.event.sent",
+            "datacontenttype": "application/json",
+            "data": [subscription_change("i", "q1", json!(["Person"]))]
+        });
+        let entry = redis::streams::StreamId {
+            id: "1-0".to_string(),
+            map: [(
+                "data".to_string(),
+                redis::Value::Data(cloud_event.to_string().into_bytes()),
+            )]
+            .into_iter()
+            .collect(),
+        };
+        let entry = redis_ingest::parse_entry(&entry).unwrap();
+
+        let router = Router::new();
+        process_changes(
+            &router.publisher,
+            entry.changes,
+            config(),
+            &router.node_subscriber,
+            &router.rel_subscriber,
+            entry.traceparent,
+            router.store.as_ref(),
+            &router.sequencer,
+            0,
+        )
+        .await
+        .unwrap();
+
+        assert_eq!(router.store.get_all().await.unwrap().len(), 1);
+        router.process(node_change("Person")).await;
+        assert_eq!(router.published().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_evaluates_update_without_before_against_after() {
+        let router = Router::new();
+        let mut subscription = subscription_change("i", "q1", json!(["Order"]));
+        subscription["payload"]["after"]["nodePredicates"] =
+            json!({ "Order": [{ "property": "region", "eq": "EU" }] });
+        router.process(subscription).await;
+
+        let update = |region: &str| {
+            let mut change = node_change("Order");
+            change["op"] = json!("u");
+            change["payload"]["after"]["properties"] = json!({ "region": region });
+            change
+        };
+        router.process(update("US")).await;
+        assert!(router.published().is_empty());
+
+        router.process(update("EU")).await;
+        assert_eq!(router.published().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_stamps_sequence_numbers_in_receive_order() {
+        let router = Router::new();
+        router
+            .process(subscription_change("i", "q1", json!(["Person"])))
+            .await;
+
+        // the changes share an lsn, which is not trusted to order them
+        let change = node_change("Person");
+        router.process(change.clone()).await;
+        router.process(change.clone()).await;
+        // a change redelivered after it was routed is numbered again
+        router.process(change).await;
+
+        let sequences: Vec<Value> = router
+            .published()
+            .iter()
+            .map(|event| event[0]["sequence"].clone())
+            .collect();
+        assert_eq!(sequences, vec![json!(1), json!(2), json!(3)]);
+    }
+
+    #[tokio::test]
+    async fn test_unsubscribe_removes_saved_subscription() {
+        let router = Router::new();
+        router
+            .process(subscription_change("i", "q1", json!(["Person"])))
+            .await;
+        router
+            .process(subscription_change("d", "q1", json!(["Person"])))
+            .await;
+
+        assert!(router.store.get_all().await.unwrap().is_empty());
+
+        router.process(node_change("Person")).await;
+        assert!(router.published().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_load_subscriptions_from_store() {
+        let store = MemorySubscriptionStore::new();
+        store
+            .save(
+                "default",
+                "q1",
+                json!({
+                    "type": "SourceSubscription",
+                    "queryNodeId": "default",
+                    "queryId": "q1",
+                    "nodeLabels": ["Person"],
+                    "relLabels": ["KNOWS"]
+                }),
+            )
+            .await
+            .unwrap();
+
+        let router = Router::with_store(store);
+        load_subscriptions(
+            router.store.as_ref(),
+            &router.node_subscriber,
+            &router.rel_subscriber,
+        )
+        .await;
+
+        assert_eq!(
+            router
+                .node_subscriber
+                .get_subscribers_for_labels(vec!["Person"], None)
+                .len(),
+            1
+        );
+        assert_eq!(
+            router
+                .rel_subscriber
+                .get_subscribers_for_labels(vec!["KNOWS"], None)
+                .len(),
+            1
+        );
+    }
+}
@@ -0,0 +1,267 @@
+// Copyright 2024 The Drasi Authors.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use redis::{
+    streams::{StreamId, StreamReadOptions, StreamReadReply},
+    AsyncCommands,
+};
+use serde_json::Value;
+
+const CONSUMER_GROUP: &str = "change-router";
+const CONSUMER_NAME: &str = "change-router";
+
+/// Reads the changes published directly to the `{source}-change` Redis stream by sources that run without a Dapr sidecar.
+/// Each stream entry holds a JSON array of changes in its `data` field. Entries published through Dapr, such as the
+/// subscription control events of the query API, hold a cloud event instead, whose `data` is the array of changes.
+/// Entries stay pending in the consumer group until they are acknowledged, and pending entries
+/// are read again after a restart or after `redeliver_pending` is called.
+pub struct RedisIngest {
+    connection: redis::aio::Connection,
+    topic: String,
+    fetch_batch_size: usize,
+    read_pending: bool,
+}
+
+#[derive(Debug)]
+pub struct IngestEntry {
+    pub id: String,
+    pub changes: Value,
+    pub traceparent: String,
+}
+
+impl RedisIngest {
+    pub async fn connect(
+        url: &str,
+        source_id: &str,
+        fetch_batch_size: usize,
+    ) -> Result<Self, redis::RedisError> {
+        let client = redis::Client::open(url)?;
+        let mut connection = client.get_async_connection().await?;
+        let topic = format!("{}-change", source_id);
+
+        match connection
+            .xgroup_create_mkstream::<&str, &str, &str, String>(&topic, CONSUMER_GROUP, "0")
+            .await
+        {
+            Ok(_) => log::info!("Created consumer group {} on {}", CONSUMER_GROUP, topic),
+            Err(err) => match err.kind() {
+                redis::ErrorKind::ExtensionError => log::info!("Consumer group already exists"),
+                _ => return Err(err),
+            },
+        };
+
+        Ok(RedisIngest {
+            connection,
+            topic,
+            fetch_batch_size,
+            read_pending: true,
+        })
+    }
+
+    /// Waits for the next entries of the stream, starting with the entries that are still pending
+    pub async fn next_batch(&mut self) -> Result<Vec<IngestEntry>, redis::RedisError> {
+        loop {
+            let mut opts = StreamReadOptions::default()
+                .count(self.fetch_batch_size)
+                .group(CONSUMER_GROUP, CONSUMER_NAME);
+            let start_id = if self.read_pending {
+                "0"
+            } else {
+                opts = opts.block(5000);
+                ">"
+            };
+
+            let reply: StreamReadReply = self
+                .connection
+                .xread_options(&[&self.topic], &[start_id], &opts)
+                .await?;
+
+            let ids: Vec<StreamId> = reply.keys.into_iter().flat_map(|k| k.ids).collect();
+            if ids.is_empty() {
+                if self.read_pending {
+                    log::info!("All pending changes processed");
+                    self.read_pending = false;
+                }
+                continue;
+            }
+
+            let mut entries = Vec::with_capacity(ids.len());
+            for id in ids {
+                match parse_entry(&id) {
+                    Ok(entry) => entries.push(entry),
+                    Err(e) => {
+                        // an entry that cannot be parsed would otherwise stay pending forever
+                        log::error!("Dropping invalid stream entry {}: {}", id.id, e);
+                        self.ack(&id.id).await?;
+                    }
+                }
+            }
+            if !entries.is_empty() {
+                return Ok(entries);
+            }
+        }
+    }
+
+    pub async fn ack(&mut self, id: &str) -> Result<(), redis::RedisError> {
+        let _: i64 = self
+            .connection
+            .xack(&self.topic, CONSUMER_GROUP, &[id])
+            .await?;
+        Ok(())
+    }
+
+    /// Reads the unacknowledged entries again on the next call to `next_batch`
+    pub fn redeliver_pending(&mut self) {
+        self.read_pending = true;
+    }
+}
+
+pub(crate) fn parse_entry(entry: &StreamId) -> Result<IngestEntry, String> {
+    let data: String = entry
+        .get("data")
+        .ok_or_else(|| "Missing data".to_string())?;
+    let data: Value = serde_json::from_str(&data).map_err(|e| format!("Invalid data: {}", e))?;
+    let (changes, traceparent) = match data {
+        Value::Object(mut event) if event.contains_key("specversion") => {
+            let traceparent = event
+                .get("traceparent")
+                .and_then(|t| t.as_str())
+                .map(|t| t.to_string());
+            (event.remove("data").unwrap_or(Value::Null), traceparent)
+        }
+        changes => (changes, None),
+    };
+    if !changes.is_array() {
+        return Err("Data is not an array of changes".to_string());
+    }
+
+    Ok(IngestEntry {
+        id: entry.id.clone(),
+        changes,
+        traceparent: entry.get("traceparent").or(traceparent).unwrap_or_default(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+    use std::{collections::HashMap, env};
+
+    fn stream_entry(fields: &[(&str, &str)]) -> StreamId {
+        StreamId {
+            id: "1-0".to_string(),
+            map: fields
+                .iter()
+                .map(|(k, v)| (k.to_string(), redis::Value::Data(v.as_bytes().to_vec())))
+                .collect::<HashMap<String, redis::Value>>(),
+        }
+    }
+
+    #[test]
+    fn test_parse_entry() {
+        let entry = parse_entry(&stream_entry(&[
+            ("data", r#"[{"op":"i"},{"op":"d"}]"#),
+            ("traceparent", "00-abc-def-01"),
+        ]))
+        .unwrap();
+        assert_eq!(entry.id, "1-0");
+        assert_eq!(entry.changes.as_array().unwrap().len(), 2);
+        assert_eq!(entry.traceparent, "00-abc-def-01");
+
+        assert!(parse_entry(&stream_entry(&[("data", r#"{"op":"i"}"#)])).is_err());
+        assert!(parse_entry(&stream_entry(&[("data", r#"{"specversion":"1.0"}"#)])).is_err());
+        assert!(parse_entry(&stream_entry(&[("data", "not json")])).is_err());
+        assert!(parse_entry(&stream_entry(&[])).is_err());
+    }
+
+    #[test]
+    fn test_parse_entry_unwraps_dapr_cloud_event() {
+        let cloud_event = json!({
+            "specversion": "1.0",
+            "id": "8b7b6d0c",
+            "source": "my-source-query-api",
+            "type": "com.dapr.event.sent",
+            "topic": "my-source-change",
+            "pubsubname": "drasi-pubsub",
+            "datacontenttype": "application/json",
+            "traceparent": "00-abc-def-01",
+            "data": [{
+                "op": "i",
+                "payload": {
+                    "source": {"db": "Drasi", "table": "SourceSubscription"},
+                    "after": {"queryId": "q1", "queryNodeId": "default"}
+                }
+            }]
+        });
+        let entry = parse_entry(&stream_entry(&[("data", &cloud_event.to_string())])).unwrap();
+
+        assert_eq!(entry.changes.as_array().unwrap().len(), 1);
+        assert_eq!(
+            entry.changes[0]["payload"]["source"]["table"],
+            "SourceSubscription"
+        );
+        assert_eq!(entry.changes[0]["payload"]["after"]["queryId"], "q1");
+        assert_eq!(entry.traceparent, "00-abc-def-01");
+    }
+
+    fn get_url() -> String {
+        env::var("REDIS_URL").unwrap_or_else(|_| "redis://127.0.0.1:6379".to_string())
+    }
+
+    // needs a local Redis, run with `cargo test -- --ignored`
+    #[tokio::test]
+    #[ignore]
+    async fn test_reads_and_redelivers_unacknowledged_entries() {
+        let source_id = format!("test-{}", uuid::Uuid::new_v4());
+        let topic = format!("{}-change", source_id);
+        let mut connection = redis::Client::open(get_url())
+            .unwrap()
+            .get_multiplexed_async_connection()
+            .await
+            .unwrap();
+
+        let mut ingest = RedisIngest::connect(&get_url(), &source_id, 10)
+            .await
+            .unwrap();
+
+        let _: String = connection
+            .xadd(&topic, "*", &[("data", r#"[{"op":"i"}]"#)])
+            .await
+            .unwrap();
+        let _: String = connection
+            .xadd(&topic, "*", &[("data", "not json")])
+            .await
+            .unwrap();
+        let _: String = connection
+            .xadd(&topic, "*", &[("data", r#"[{"op":"u"}]"#)])
+            .await
+            .unwrap();
+
+        let batch = ingest.next_batch().await.unwrap();
+        assert_eq!(batch.len(), 2);
+        assert_eq!(batch[0].changes[0]["op"], "i");
+        assert_eq!(batch[1].changes[0]["op"], "u");
+
+        ingest.ack(&batch[0].id).await.unwrap();
+        ingest.redeliver_pending();
+
+        let batch = ingest.next_batch().await.unwrap();
+        assert_eq!(batch.len(), 1);
+        assert_eq!(batch[0].changes[0]["op"], "u");
+        ingest.ack(&batch[0].id).await.unwrap();
+
+        let _: () = connection.del(&topic).await.unwrap();
+    }
+}
@@ -0,0 +1,111 @@
+// Copyright 2024 The Drasi Authors.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::{error::Error, sync::Arc};
+
+use serde_json::Value;
+use tokio::sync::Mutex;
+
+use crate::subscription_store::SubscriptionStore;
+
+/// Number of sequence numbers reserved in the subscription store at a time
+const RESERVATION_SIZE: u64 = 1000;
+
+/// Stamps the changes of a source with sequence numbers that increase in the order the router receives them.
+///
+/// The numbers are reserved in blocks from a counter persisted next to the subscriptions of the source,
+/// so they keep increasing when the router restarts; the rest of a block reserved before a restart is skipped.
+/// A change delivered again gets a new sequence number, so it is applied again rather than discarded as stale.
+///
+/// Sources whose `lsn` increases with every change can set `SEQUENCE_FROM_LSN`, to sequence the changes
+/// in the order of the source instead.
+pub struct Sequencer {
+    store: Arc<dyn SubscriptionStore>,
+    from_lsn: bool,
+    reservation: Mutex<Reservation>,
+}
+
+/// The sequence numbers from `next` up to and including `last` are reserved and not yet used
+struct Reservation {
+    next: u64,
+    last: u64,
+}
+
+impl Sequencer {
+    pub fn new(store: Arc<dyn SubscriptionStore>, from_lsn: bool) -> Self {
+        Self {
+            store,
+            from_lsn,
+            reservation: Mutex::new(Reservation { next: 1, last: 0 }),
+        }
+    }
+
+    /// Returns the sequence number of the next change of the source
+    pub async fn next(&self, change: &Value) -> Result<u64, Box<dyn Error>> {
+        if self.from_lsn {
+            return change["payload"]["source"]["lsn"]
+                .as_u64()
+                .ok_or_else(|| Box::from("The change has no lsn to sequence it by"));
+        }
+
+        let mut reservation = self.reservation.lock().await;
+        if reservation.next > reservation.last {
+            let last = self.store.reserve_sequences(RESERVATION_SIZE).await?;
+            *reservation = Reservation {
+                next: last - RESERVATION_SIZE + 1,
+                last,
+            };
+        }
+
+        let sequence = reservation.next;
+        reservation.next += 1;
+        Ok(sequence)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::subscription_store::MemorySubscriptionStore;
+    use serde_json::json;
+
+    fn change(lsn: Value) -> Value {
+        json!({ "payload": { "source": { "lsn": lsn } } })
+    }
+
+    #[tokio::test]
+    async fn test_sequence_increases_across_restarts() {
+        let store: Arc<dyn SubscriptionStore> = Arc::new(MemorySubscriptionStore::new());
+
+        let sequencer = Sequencer::new(store.clone(), false);
+        let mut sequences = Vec::new();
+        for _ in 0..RESERVATION_SIZE + 1 {
+            // the lsn of the source is not trusted to be ordered
+            sequences.push(sequencer.next(&change(json!(0))).await.unwrap());
+        }
+        assert_eq!(sequences[0], 1);
+        assert!(sequences.windows(2).all(|w| w[0] + 1 == w[1]));
+
+        let restarted = Sequencer::new(store, false);
+        let sequence = restarted.next(&change(json!(0))).await.unwrap();
+        assert!(sequence > *sequences.last().unwrap());
+    }
+
+    #[tokio::test]
+    async fn test_sequence_follows_ordered_source_lsn() {
+        let sequencer = Sequencer::new(Arc::new(MemorySubscriptionStore::new()), true);
+        assert_eq!(sequencer.next(&change(json!(42))).await.unwrap(), 42);
+        assert!(sequencer.next(&change(Value::Null)).await.is_err());
+    }
+}
@@ -41,20 +41,12 @@ impl DaprStateManager {
         metadata: Option<HashMap<String, String>>,
     ) -> Result<Vec<Value>, Box<dyn std::error::Error>> {
         let addr = "https://127.0.0.1".to_string();
-        let mut dapr_client = dapr::Client::<dapr::client::TonicClient>::connect(addr)
-            .await
-            .expect("Unable to connect to Dapr");
+        let mut dapr_client = dapr::Client::<dapr::client::TonicClient>::connect(addr).await?;
 
-        let response = match dapr_client
+        let response = dapr_client
             .query_state_alpha1(&self.store_name, query_condition, metadata)
-            .await
-        {
-            Ok(response) => response.results,
-            Err(e) => {
-                log::error!("Error querying the Dapr state store: {:?}", e);
-                vec![]
-            }
-        };
+            .await?
+            .results;
 
         // for each item in response, serialize the data field in json
         let mut result = vec![];
@@ -112,6 +104,35 @@ impl DaprStateManager {
         }
     }
 
+    pub async fn get_state(&self, key: &str) -> Result<Option<Value>, Box<dyn std::error::Error>> {
+        let url = format!(
+            "http://{}:{}/v1.0/state/{}/{}?metadata.contentType=application/json",
+            self.dapr_host, self.dapr_port, self.store_name, key
+        );
+
+        let resp = self.client.get(url).send().await?;
+        if !resp.status().is_success() {
+            let error_message = format!(
+                "State get request failed with status: {} and body: {}",
+                resp.status(),
+                resp.text().await.unwrap_or_default()
+            );
+            return Err(Box::from(error_message));
+        }
+
+        // Dapr responds with 204 when the key does not exist
+        if resp.status() == reqwest::StatusCode::NO_CONTENT {
+            return Ok(None);
+        }
+
+        let body = resp.bytes().await?;
+        if body.is_empty() {
+            return Ok(None);
+        }
+
+        Ok(Some(serde_json::from_slice(&body)?))
+    }
+
     pub async fn delete_state(
         &self,
         key: &str,
@@ -12,52 +12,368 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
-use serde_json::json;
+use serde::Serialize;
+use serde_json::{Map, Value};
 use std::collections::{HashMap, HashSet};
+use std::sync::Arc;
+
+use drasi_source_predicates::{matches_all, LabelPredicates};
+
+/// A query that is subscribed to changes, as it appears in the subscriptions of a dispatched change
+#[derive(Debug, Clone, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct QuerySubscription {
+    pub query_node_id: String,
+    pub query_id: String,
+}
+
+impl QuerySubscription {
+    pub fn new(query_node_id: &str, query_id: &str) -> Self {
+        Self {
+            query_node_id: query_node_id.to_string(),
+            query_id: query_id.to_string(),
+        }
+    }
+}
 
 #[derive(Debug, Clone, Default)]
 pub struct SubscriberMap {
-    pub label_map: HashMap<String, HashSet<String>>,
+    pub label_map: HashMap<String, HashSet<Arc<QuerySubscription>>>,
+    pub predicates: HashMap<Arc<QuerySubscription>, LabelPredicates>,
 }
 
 impl SubscriberMap {
     pub fn new() -> Self {
         Self {
             label_map: HashMap::new(),
+            predicates: HashMap::new(),
         }
     }
 
-    fn add(&mut self, label: &str, query_node_id: &str, query_id: &str) {
-        let set = self.label_map.entry(label.to_string()).or_default();
-
-        let json_data = json!({
-            "queryNodeId": query_node_id,
-            "queryId": query_id
-        });
+    pub fn add_labels(&mut self, labels: Vec<&str>, query_node_id: &str, query_id: &str) {
+        let subscription = Arc::new(QuerySubscription::new(query_node_id, query_id));
+        for label in labels {
+            self.label_map
+                .entry(label.to_string())
+                .or_default()
+                .insert(subscription.clone());
+        }
+    }
 
-        set.insert(json_data.to_string());
+    /// Replaces the labels a query is subscribed to
+    pub fn update_labels(&mut self, labels: Vec<&str>, query_node_id: &str, query_id: &str) {
+        self.remove_subscriber(query_node_id, query_id);
+        self.add_labels(labels, query_node_id, query_id);
     }
 
-    pub fn add_labels(&mut self, labels: Vec<&str>, query_node_id: &str, query_id: &str) {
-        for label in labels {
-            self.add(label, query_node_id, query_id);
+    /// Replaces the labels a query is subscribed to, and the property predicates for those labels
+    pub fn update_subscription(
+        &mut self,
+        labels: Vec<&str>,
+        predicates: LabelPredicates,
+        query_node_id: &str,
+        query_id: &str,
+    ) {
+        self.update_labels(labels, query_node_id, query_id);
+        if !predicates.is_empty() {
+            self.predicates.insert(
+                Arc::new(QuerySubscription::new(query_node_id, query_id)),
+                predicates,
+            );
         }
     }
 
-    pub fn get_subscribers_for_labels(&self, labels: Vec<&str>) -> Option<Vec<String>> {
+    /// Removes a query from every label it is subscribed to
+    pub fn remove_subscriber(&mut self, query_node_id: &str, query_id: &str) {
+        let subscription = QuerySubscription::new(query_node_id, query_id);
+
+        self.label_map.retain(|_, set| {
+            set.remove(&subscription);
+            !set.is_empty()
+        });
+        self.predicates.remove(&subscription);
+    }
+
+    /// Returns every query subscribed to at least one of the labels, each query appears once.
+    /// `versions` holds the properties of the element before and/or after the change, a query with
+    /// predicates for a label only matches that label if one of the versions satisfies them.
+    /// If `versions` is `None` the predicates are not evaluated.
+    pub fn get_subscribers_for_labels(
+        &self,
+        labels: Vec<&str>,
+        versions: Option<&[&Map<String, Value>]>,
+    ) -> Vec<Arc<QuerySubscription>> {
         let mut result = Vec::new();
+        let mut included: HashSet<&QuerySubscription> = HashSet::new();
 
         for label in labels {
-            match self.label_map.get(label) {
-                Some(set) => {
-                    for subscriber in set {
-                        result.push(subscriber.clone());
+            let set = match self.label_map.get(label) {
+                Some(set) => set,
+                None => continue,
+            };
+
+            for subscription in set {
+                if included.contains(subscription.as_ref()) {
+                    continue;
+                }
+
+                let matched = match (versions, self.predicates.get(subscription.as_ref())) {
+                    (Some(versions), Some(predicates)) => match predicates.get(label) {
+                        Some(predicates) => versions.iter().any(|v| matches_all(predicates, v)),
+                        None => true,
+                    },
+                    _ => true,
+                };
+
+                if matched {
+                    included.insert(subscription.as_ref());
+                    result.push(subscription.clone());
+                }
+            }
+        }
+
+        result
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use proptest::prelude::*;
+
+    fn subscribers(map: &SubscriberMap, label: &str) -> Vec<QuerySubscription> {
+        sorted(map.get_subscribers_for_labels(vec![label], None))
+    }
+
+    fn sorted(subscriptions: Vec<Arc<QuerySubscription>>) -> Vec<QuerySubscription> {
+        let mut result: Vec<QuerySubscription> =
+            subscriptions.iter().map(|s| s.as_ref().clone()).collect();
+        result.sort();
+        result
+    }
+
+    fn subscription(query_id: &str) -> QuerySubscription {
+        QuerySubscription::new("node1", query_id)
+    }
+
+    #[test]
+    fn test_remove_subscriber() {
+        let mut map = SubscriberMap::new();
+        map.add_labels(vec!["Vehicle", "Driver"], "node1", "q1");
+        map.add_labels(vec!["Vehicle"], "node1", "q2");
+
+        map.remove_subscriber("node1", "q1");
+
+        assert_eq!(subscribers(&map, "Vehicle"), vec![subscription("q2")]);
+        assert!(!map.label_map.contains_key("Driver"));
+        assert!(map
+            .get_subscribers_for_labels(vec!["Driver"], None)
+            .is_empty());
+    }
+
+    #[test]
+    fn test_remove_unknown_subscriber() {
+        let mut map = SubscriberMap::new();
+        map.add_labels(vec!["Vehicle"], "node1", "q1");
+
+        map.remove_subscriber("node2", "q1");
+
+        assert_eq!(subscribers(&map, "Vehicle"), vec![subscription("q1")]);
+    }
+
+    #[test]
+    fn test_update_labels() {
+        let mut map = SubscriberMap::new();
+        map.add_labels(vec!["Vehicle", "Driver"], "node1", "q1");
+        map.add_labels(vec!["Driver"], "node1", "q2");
+
+        map.update_labels(vec!["Driver", "Zone"], "node1", "q1");
+
+        assert!(!map.label_map.contains_key("Vehicle"));
+        assert_eq!(
+            subscribers(&map, "Driver"),
+            vec![subscription("q1"), subscription("q2")]
+        );
+        assert_eq!(subscribers(&map, "Zone"), vec![subscription("q1")]);
+    }
+
+    #[test]
+    fn test_churned_subscriptions() {
+        let mut map = SubscriberMap::new();
+        let mut expected: HashMap<String, HashSet<&str>> = HashMap::new();
+        let labels = ["Vehicle", "Driver", "Zone"];
+
+        for i in 0..200 {
+            let query_id = format!("q{}", i % 7);
+            let label = labels[i % labels.len()];
+            let subscribed = expected.entry(query_id.clone()).or_default();
+            match i % 4 {
+                0 | 1 => {
+                    map.add_labels(vec![label], "node1", &query_id);
+                    subscribed.insert(label);
+                }
+                2 => {
+                    map.update_labels(vec![label, "Zone"], "node1", &query_id);
+                    subscribed.clear();
+                    subscribed.extend([label, "Zone"]);
+                }
+                _ => {
+                    map.remove_subscriber("node1", &query_id);
+                    subscribed.clear();
+                }
+            }
+
+            for label in labels {
+                let mut expected_subscribers: Vec<QuerySubscription> = expected
+                    .iter()
+                    .filter(|(_, subscribed)| subscribed.contains(label))
+                    .map(|(query_id, _)| subscription(query_id))
+                    .collect();
+                expected_subscribers.sort();
+                assert_eq!(subscribers(&map, label), expected_subscribers);
+            }
+        }
+
+        assert!(map.label_map.values().all(|set| !set.is_empty()));
+    }
+
+    #[test]
+    fn test_union_across_labels() {
+        let mut map = SubscriberMap::new();
+        map.add_labels(vec!["Vehicle"], "node1", "q1");
+        map.add_labels(vec!["Vehicle", "Electric"], "node1", "q2");
+
+        let result = map.get_subscribers_for_labels(vec!["Vehicle", "Electric", "Unknown"], None);
+
+        assert_eq!(sorted(result), vec![subscription("q1"), subscription("q2")]);
+    }
+
+    #[test]
+    fn test_predicates_filter_subscribers() {
+        let mut map = SubscriberMap::new();
+        let predicates: LabelPredicates = serde_json::from_value(serde_json::json!({
+            "Order": [{ "property": "region", "eq": "EU" }]
+        }))
+        .unwrap();
+        map.update_subscription(vec!["Order", "Customer"], predicates, "node1", "q1");
+        map.add_labels(vec!["Order"], "node1", "q2");
+
+        let eu = serde_json::json!({ "region": "EU" });
+        let us = serde_json::json!({ "region": "US" });
+        let eu = eu.as_object().unwrap();
+        let us = us.as_object().unwrap();
+
+        assert_eq!(
+            sorted(map.get_subscribers_for_labels(vec!["Order"], Some(&[eu]))),
+            vec![subscription("q1"), subscription("q2")]
+        );
+        assert_eq!(
+            sorted(map.get_subscribers_for_labels(vec!["Order"], Some(&[us]))),
+            vec![subscription("q2")]
+        );
+        // a change that moves an element out of the predicate still reaches the query
+        assert_eq!(
+            sorted(map.get_subscribers_for_labels(vec!["Order"], Some(&[eu, us]))),
+            vec![subscription("q1"), subscription("q2")]
+        );
+        // labels without predicates are not filtered
+        assert_eq!(
+            sorted(map.get_subscribers_for_labels(vec!["Order", "Customer"], Some(&[us]))),
+            vec![subscription("q1"), subscription("q2")]
+        );
+
+        // without versions of the element the predicates cannot be evaluated
+        assert_eq!(
+            sorted(map.get_subscribers_for_labels(vec!["Order"], None)),
+            vec![subscription("q1"), subscription("q2")]
+        );
+
+        map.update_labels(vec!["Order"], "node1", "q1");
+        assert_eq!(
+            sorted(map.get_subscribers_for_labels(vec!["Order"], Some(&[us]))),
+            vec![subscription("q1"), subscription("q2")]
+        );
+    }
+
+    const LABELS: [&str; 4] = ["A", "B", "C", "D"];
+
+    fn label_set() -> impl Strategy<Value = Vec<&'static str>> {
+        proptest::sample::subsequence(LABELS.to_vec(), 0..=LABELS.len())
+    }
+
+    #[derive(Debug, Clone)]
+    enum Op {
+        Add(String, Vec<&'static str>),
+        Update(String, Vec<&'static str>),
+        Remove(String),
+    }
+
+    fn op() -> impl Strategy<Value = Op> {
+        let query_id = (0..5u8).prop_map(|i| format!("q{}", i));
+        prop_oneof![
+            (query_id.clone(), label_set()).prop_map(|(q, l)| Op::Add(q, l)),
+            (query_id.clone(), label_set()).prop_map(|(q, l)| Op::Update(q, l)),
+            query_id.prop_map(Op::Remove),
+        ]
+    }
+
+    proptest! {
+        #[test]
+        fn routes_to_union_of_label_subscribers(
+            ops in proptest::collection::vec(op(), 0..40),
+            labels in proptest::collection::vec(proptest::sample::select(LABELS.to_vec()), 0..6),
+        ) {
+            let mut map = SubscriberMap::new();
+            let mut model: HashMap<String, HashSet<&str>> = HashMap::new();
+
+            for op in ops {
+                match op {
+                    Op::Add(query_id, l) => {
+                        map.add_labels(l.clone(), "node1", &query_id);
+                        model.entry(query_id).or_default().extend(l);
+                    }
+                    Op::Update(query_id, l) => {
+                        map.update_labels(l.clone(), "node1", &query_id);
+                        model.insert(query_id, l.into_iter().collect());
+                    }
+                    Op::Remove(query_id) => {
+                        map.remove_subscriber("node1", &query_id);
+                        model.remove(&query_id);
                     }
                 }
-                None => return None,
             }
+
+            let result = map.get_subscribers_for_labels(labels.clone(), None);
+
+            let unique: HashSet<&QuerySubscription> = result.iter().map(|s| s.as_ref()).collect();
+            prop_assert_eq!(unique.len(), result.len(), "subscribers are not duplicated");
+
+            let mut expected: Vec<QuerySubscription> = model
+                .iter()
+                .filter(|(_, subscribed)| labels.iter().any(|l| subscribed.contains(l)))
+                .map(|(query_id, _)| subscription(query_id))
+                .collect();
+            expected.sort();
+            prop_assert_eq!(sorted(result), expected);
         }
 
-        Some(result)
+        #[test]
+        fn label_order_does_not_change_subscribers(
+            subscriptions in proptest::collection::vec(label_set(), 0..6),
+            labels in label_set(),
+        ) {
+            let mut map = SubscriberMap::new();
+            for (i, l) in subscriptions.into_iter().enumerate() {
+                map.add_labels(l, "node1", &format!("q{}", i));
+            }
+
+            let mut reversed = labels.clone();
+            reversed.reverse();
+
+            prop_assert_eq!(
+                sorted(map.get_subscribers_for_labels(labels, None)),
+                sorted(map.get_subscribers_for_labels(reversed, None))
+            );
+        }
     }
 }
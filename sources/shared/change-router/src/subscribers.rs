@@ -12,9 +12,11 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
-use crate::subscriber_map::SubscriberMap;
+use crate::subscriber_map::{QuerySubscription, SubscriberMap};
+use drasi_source_predicates::LabelPredicates;
+use serde_json::{Map, Value};
 use std::collections::{HashMap, HashSet};
-use std::sync::Mutex;
+use std::sync::{Arc, Mutex};
 
 #[derive(Debug, Default)]
 pub struct Subscriber {
@@ -28,19 +30,34 @@ impl Subscriber {
         }
     }
 
-    pub fn add_labels(&self, labels: Vec<&str>, query_node_id: &str, query_id: &str) {
+    pub fn update_subscription(
+        &self,
+        labels: Vec<&str>,
+        predicates: LabelPredicates,
+        query_node_id: &str,
+        query_id: &str,
+    ) {
         let mut subscriber_map = self.subscriber_map.lock().unwrap();
-        subscriber_map.add_labels(labels, query_node_id, query_id);
+        subscriber_map.update_subscription(labels, predicates, query_node_id, query_id);
     }
 
-    pub fn get_label_map(&self) -> HashMap<String, HashSet<String>> {
+    pub fn remove_subscriber(&self, query_node_id: &str, query_id: &str) {
+        let mut subscriber_map = self.subscriber_map.lock().unwrap();
+        subscriber_map.remove_subscriber(query_node_id, query_id);
+    }
+
+    pub fn get_label_map(&self) -> HashMap<String, HashSet<Arc<QuerySubscription>>> {
         let subscriber_map = self.subscriber_map.lock().unwrap();
         subscriber_map.label_map.clone()
     }
 
-    pub fn get_subscribers_for_labels(&self, labels: Vec<&str>) -> Option<Vec<String>> {
+    pub fn get_subscribers_for_labels(
+        &self,
+        labels: Vec<&str>,
+        versions: Option<&[&Map<String, Value>]>,
+    ) -> Vec<Arc<QuerySubscription>> {
         let subscriber_map = self.subscriber_map.lock().unwrap();
 
-        subscriber_map.get_subscribers_for_labels(labels).clone()
+        subscriber_map.get_subscribers_for_labels(labels, versions)
     }
 }
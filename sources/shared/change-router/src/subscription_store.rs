@@ -0,0 +1,341 @@
+// Copyright 2024 The Drasi Authors.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::{collections::HashMap, error::Error, sync::Arc};
+
+use async_trait::async_trait;
+use redis::AsyncCommands;
+use serde_json::{json, Value};
+use tokio::sync::Mutex;
+
+use crate::{
+    change_router_config::ChangeRouterConfig,
+    state_manager::{DaprStateManager, StateEntry},
+};
+
+/// Persists the subscriptions of queries to a source, so they can be restored when the router starts
+#[async_trait]
+pub trait SubscriptionStore: Send + Sync {
+    async fn get_all(&self) -> Result<Vec<Value>, Box<dyn Error>>;
+    async fn save(
+        &self,
+        query_node_id: &str,
+        query_id: &str,
+        subscription: Value,
+    ) -> Result<(), Box<dyn Error>>;
+    async fn delete(&self, query_node_id: &str, query_id: &str) -> Result<(), Box<dyn Error>>;
+
+    /// Raises the highest sequence number reserved for the changes of the source by `count`,
+    /// and returns the new highest reserved sequence number
+    async fn reserve_sequences(&self, count: u64) -> Result<u64, Box<dyn Error>>;
+}
+
+pub async fn from_config(
+    config: &ChangeRouterConfig,
+    dapr_port: u16,
+) -> Result<Arc<dyn SubscriptionStore>, Box<dyn Error>> {
+    match config.subscription_store_type.as_str() {
+        "dapr" => Ok(Arc::new(DaprSubscriptionStore::new(DaprStateManager::new(
+            "127.0.0.1",
+            dapr_port,
+            &config.subscriber_store,
+        )))),
+        "redis" => Ok(Arc::new(
+            RedisSubscriptionStore::new(&config.redis_url, &config.source_id).await?,
+        )),
+        "memory" => Ok(Arc::new(MemorySubscriptionStore::new())),
+        other => Err(Box::from(format!(
+            "Unknown subscription store type: {}",
+            other
+        ))),
+    }
+}
+
+/// Stores each subscription as an entry in a Dapr state store, along with an index entry
+/// listing the keys of all subscriptions, so that only the basic state API is required.
+pub struct DaprSubscriptionStore {
+    state_manager: DaprStateManager,
+    index_lock: Mutex<()>,
+    sequence_lock: Mutex<()>,
+}
+
+const DAPR_INDEX_KEY: &str = "SourceSubscriptions";
+const DAPR_SEQUENCE_KEY: &str = "SourceSequence";
+
+impl DaprSubscriptionStore {
+    pub fn new(state_manager: DaprStateManager) -> Self {
+        Self {
+            state_manager,
+            index_lock: Mutex::new(()),
+            sequence_lock: Mutex::new(()),
+        }
+    }
+
+    fn state_key(query_node_id: &str, query_id: &str) -> String {
+        format!("SourceSubscription-{}-{}", query_node_id, query_id)
+    }
+
+    async fn read_index(&self) -> Result<Option<Vec<String>>, Box<dyn Error>> {
+        match self.state_manager.get_state(DAPR_INDEX_KEY).await? {
+            Some(index) => Ok(Some(serde_json::from_value(index)?)),
+            None => Ok(None),
+        }
+    }
+
+    /// Finds subscriptions saved before the index existed, using the state query API.
+    /// Fails if the state store does not support the query API.
+    async fn query_subscriptions(&self) -> Result<Vec<Value>, Box<dyn Error>> {
+        let query_condition = json!({
+            "filter": {
+                "EQ": { "type": "SourceSubscription" }
+            }
+        });
+        let mut metadata = HashMap::new();
+        metadata.insert("contentType".to_string(), "application/json".to_string());
+
+        self.state_manager
+            .query_state(query_condition, Some(metadata))
+            .await
+    }
+}
+
+#[async_trait]
+impl SubscriptionStore for DaprSubscriptionStore {
+    async fn get_all(&self) -> Result<Vec<Value>, Box<dyn Error>> {
+        let _lock = self.index_lock.lock().await;
+
+        let index = self.read_index().await?;
+        let keys = match index {
+            Some(keys) => keys,
+            None => {
+                log::info!("No subscription index found, querying the state store");
+                // most state stores do not support the query API, and then there are no
+                // subscriptions saved before the index existed to carry over
+                let subscriptions = match self.query_subscriptions().await {
+                    Ok(subscriptions) => subscriptions,
+                    Err(e) => {
+                        log::warn!(
+                            "Could not query the state store, starting without subscriptions: {}",
+                            e
+                        );
+                        Vec::new()
+                    }
+                };
+                let keys: Vec<String> = subscriptions
+                    .iter()
+                    .filter_map(|s| {
+                        Some(Self::state_key(
+                            s["queryNodeId"].as_str()?,
+                            s["queryId"].as_str()?,
+                        ))
+                    })
+                    .collect();
+                self.state_manager
+                    .save_state(vec![StateEntry::new(DAPR_INDEX_KEY, json!(keys))])
+                    .await?;
+                return Ok(subscriptions);
+            }
+        };
+
+        let mut result = Vec::new();
+        for key in keys {
+            match self.state_manager.get_state(&key).await? {
+                Some(subscription) => result.push(subscription),
+                None => log::warn!(
+                    "Subscription {} is in the index but not the state store",
+                    key
+                ),
+            }
+        }
+
+        Ok(result)
+    }
+
+    async fn save(
+        &self,
+        query_node_id: &str,
+        query_id: &str,
+        subscription: Value,
+    ) -> Result<(), Box<dyn Error>> {
+        let _lock = self.index_lock.lock().await;
+        let key = Self::state_key(query_node_id, query_id);
+
+        let mut index = self.read_index().await?.unwrap_or_default();
+        if !index.contains(&key) {
+            index.push(key.clone());
+        }
+
+        self.state_manager
+            .save_state(vec![
+                StateEntry::new(&key, subscription),
+                StateEntry::new(DAPR_INDEX_KEY, json!(index)),
+            ])
+            .await
+    }
+
+    async fn delete(&self, query_node_id: &str, query_id: &str) -> Result<(), Box<dyn Error>> {
+        let _lock = self.index_lock.lock().await;
+        let key = Self::state_key(query_node_id, query_id);
+
+        self.state_manager.delete_state(&key, None).await?;
+
+        let index = self.read_index().await?;
+        if let Some(mut index) = index {
+            index.retain(|k| k != &key);
+            self.state_manager
+                .save_state(vec![StateEntry::new(DAPR_INDEX_KEY, json!(index))])
+                .await?;
+        }
+
+        Ok(())
+    }
+
+    async fn reserve_sequences(&self, count: u64) -> Result<u64, Box<dyn Error>> {
+        let _lock = self.sequence_lock.lock().await;
+
+        let reserved = match self.state_manager.get_state(DAPR_SEQUENCE_KEY).await? {
+            Some(reserved) => reserved
+                .as_u64()
+                .ok_or_else(|| Box::<dyn Error>::from("Invalid reserved sequence number"))?,
+            None => 0,
+        };
+        let reserved = reserved + count;
+
+        self.state_manager
+            .save_state(vec![StateEntry::new(DAPR_SEQUENCE_KEY, json!(reserved))])
+            .await?;
+        Ok(reserved)
+    }
+}
+
+/// Stores the subscriptions of a source as fields of a single Redis hash,
+/// and the highest reserved sequence number of the source in a key next to it
+pub struct RedisSubscriptionStore {
+    connection: redis::aio::MultiplexedConnection,
+    hash_key: String,
+    sequence_key: String,
+}
+
+impl RedisSubscriptionStore {
+    pub async fn new(url: &str, source_id: &str) -> Result<Self, Box<dyn Error>> {
+        let client = redis::Client::open(url)?;
+        let connection = client.get_multiplexed_tokio_connection().await?;
+
+        Ok(Self {
+            connection,
+            hash_key: format!("{}-subscriptions", source_id),
+            sequence_key: format!("{}-sequence", source_id),
+        })
+    }
+
+    fn field(query_node_id: &str, query_id: &str) -> String {
+        format!("{}/{}", query_node_id, query_id)
+    }
+}
+
+#[async_trait]
+impl SubscriptionStore for RedisSubscriptionStore {
+    async fn get_all(&self) -> Result<Vec<Value>, Box<dyn Error>> {
+        let mut connection = self.connection.clone();
+        let entries: HashMap<String, String> = connection.hgetall(&self.hash_key).await?;
+
+        let mut result = Vec::new();
+        for (field, data) in entries {
+            match serde_json::from_str(&data) {
+                Ok(subscription) => result.push(subscription),
+                Err(e) => log::error!("Error parsing subscription {}: {:?}", field, e),
+            }
+        }
+
+        Ok(result)
+    }
+
+    async fn save(
+        &self,
+        query_node_id: &str,
+        query_id: &str,
+        subscription: Value,
+    ) -> Result<(), Box<dyn Error>> {
+        let mut connection = self.connection.clone();
+        let _: () = connection
+            .hset(
+                &self.hash_key,
+                Self::field(query_node_id, query_id),
+                subscription.to_string(),
+            )
+            .await?;
+        Ok(())
+    }
+
+    async fn delete(&self, query_node_id: &str, query_id: &str) -> Result<(), Box<dyn Error>> {
+        let mut connection = self.connection.clone();
+        let _: () = connection
+            .hdel(&self.hash_key, Self::field(query_node_id, query_id))
+            .await?;
+        Ok(())
+    }
+
+    async fn reserve_sequences(&self, count: u64) -> Result<u64, Box<dyn Error>> {
+        let mut connection = self.connection.clone();
+        let reserved: u64 = connection.incr(&self.sequence_key, count).await?;
+        Ok(reserved)
+    }
+}
+
+/// Keeps subscriptions in memory only, they are lost when the router restarts
+#[derive(Default)]
+pub struct MemorySubscriptionStore {
+    subscriptions: std::sync::Mutex<HashMap<(String, String), Value>>,
+    reserved_sequence: std::sync::Mutex<u64>,
+}
+
+impl MemorySubscriptionStore {
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+#[async_trait]
+impl SubscriptionStore for MemorySubscriptionStore {
+    async fn get_all(&self) -> Result<Vec<Value>, Box<dyn Error>> {
+        let subscriptions = self.subscriptions.lock().unwrap();
+        Ok(subscriptions.values().cloned().collect())
+    }
+
+    async fn save(
+        &self,
+        query_node_id: &str,
+        query_id: &str,
+        subscription: Value,
+    ) -> Result<(), Box<dyn Error>> {
+        let mut subscriptions = self.subscriptions.lock().unwrap();
+        subscriptions.insert(
+            (query_node_id.to_string(), query_id.to_string()),
+            subscription,
+        );
+        Ok(())
+    }
+
+    async fn delete(&self, query_node_id: &str, query_id: &str) -> Result<(), Box<dyn Error>> {
+        let mut subscriptions = self.subscriptions.lock().unwrap();
+        subscriptions.remove(&(query_node_id.to_string(), query_id.to_string()));
+        Ok(())
+    }
+
+    async fn reserve_sequences(&self, count: u64) -> Result<u64, Box<dyn Error>> {
+        let mut reserved = self.reserved_sequence.lock().unwrap();
+        *reserved += count;
+        Ok(*reserved)
+    }
+}
@@ -13,6 +13,7 @@
 // limitations under the License.
 
 use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
 
 #[derive(Serialize, Deserialize, Clone)]
 pub struct SubscriptionRequest {
@@ -24,6 +25,54 @@ pub struct SubscriptionRequest {
     pub node_labels: Vec<String>,
     #[serde(rename = "relLabels")]
     pub rel_labels: Vec<String>,
+    /// Property predicates by node label, evaluated by the change router and the source proxy
+    #[serde(
+        rename = "nodePredicates",
+        default,
+        skip_serializing_if = "Map::is_empty"
+    )]
+    pub node_predicates: Map<String, Value>,
+    /// Property predicates by relation label, evaluated by the change router and the source proxy
+    #[serde(
+        rename = "relPredicates",
+        default,
+        skip_serializing_if = "Map::is_empty"
+    )]
+    pub rel_predicates: Map<String, Value>,
+    /// Bootstrap data is returned in pages of this size, with the token of the next page in the `x-continuation-token` header
+    #[serde(rename = "pageSize", default, skip_serializing_if = "Option::is_none")]
+    pub page_size: Option<usize>,
+    /// Requests the next page of bootstrap data, the subscription itself was registered with the first page
+    #[serde(
+        rename = "continuationToken",
+        default,
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub continuation_token: Option<String>,
+}
+
+/// A subscription of a query to the source, as recorded by the change router
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct SubscriptionInfo {
+    pub query_node_id: String,
+    pub query_id: String,
+    pub node_labels: Vec<String>,
+    pub rel_labels: Vec<String>,
+    /// Milliseconds since the epoch, unknown for subscriptions recorded before it was tracked
+    #[serde(default)]
+    pub subscribed_at: Option<i64>,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct SubscriptionParams {
+    /// When false, the subscription is only registered with the change router and no data is bootstrapped
+    #[serde(default = "default_bootstrap")]
+    pub bootstrap: bool,
+}
+
+fn default_bootstrap() -> bool {
+    true
 }
 
 #[derive(Serialize)]
@@ -88,6 +137,26 @@ pub mod v2 {
         pub node_labels: Vec<String>,
         #[serde(rename = "relLabels")]
         pub rel_labels: Vec<String>,
+        #[serde(
+            rename = "nodePredicates",
+            default,
+            skip_serializing_if = "Map::is_empty"
+        )]
+        pub node_predicates: Map<String, Value>,
+        #[serde(
+            rename = "relPredicates",
+            default,
+            skip_serializing_if = "Map::is_empty"
+        )]
+        pub rel_predicates: Map<String, Value>,
+        #[serde(rename = "pageSize", default, skip_serializing_if = "Option::is_none")]
+        pub page_size: Option<usize>,
+        #[serde(
+            rename = "continuationToken",
+            default,
+            skip_serializing_if = "Option::is_none"
+        )]
+        pub continuation_token: Option<String>,
     }
 
     impl From<SubscriptionRequest> for AcquireRequest {
@@ -95,6 +164,10 @@ pub mod v2 {
             Self {
                 node_labels: subscription_request.node_labels,
                 rel_labels: subscription_request.rel_labels,
+                node_predicates: subscription_request.node_predicates,
+                rel_predicates: subscription_request.rel_predicates,
+                page_size: subscription_request.page_size,
+                continuation_token: subscription_request.continuation_token,
             }
         }
     }
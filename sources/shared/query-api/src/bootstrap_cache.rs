@@ -0,0 +1,454 @@
+// Copyright 2024 The Drasi Authors.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::{
+    collections::HashMap,
+    future::Future,
+    path::PathBuf,
+    sync::Arc,
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
+
+use async_trait::async_trait;
+use redis::AsyncCommands;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use tokio::sync::Mutex;
+
+use crate::{api::SubscriptionRequest, query_api_config::QueryApiConfig};
+
+/// Where cached bootstrap data is kept, entries expire after the TTL they were stored with
+#[async_trait]
+pub trait CacheStore: Send + Sync {
+    async fn get(&self, key: &str) -> Result<Option<Vec<Value>>, String>;
+    async fn put(&self, key: &str, elements: &[Value], ttl: Duration) -> Result<(), String>;
+}
+
+/// Caches the bootstrap data of a source by label set, so that queries subscribing to the same
+/// labels share one read of the source.
+/// Concurrent requests for the same labels wait for a single fetch instead of each reading the source.
+///
+/// Cached data is a snapshot taken when it was fetched, and is served for up to the TTL afterwards.
+/// A query that subscribes later only receives the changes made after its own subscription, so the
+/// changes made between the snapshot and the subscription are missing from its results. The TTL bounds
+/// that staleness window, caching should only be enabled for sources whose data changes slowly, or
+/// whose queries tolerate it.
+///
+/// Bootstrap data with more than `max_elements` elements is not cached, it is streamed from the source
+/// instead, and its key is remembered for the TTL so that it is not read into memory again.
+pub struct BootstrapCache {
+    store: Box<dyn CacheStore>,
+    ttl: Duration,
+    max_elements: usize,
+    in_flight: Mutex<HashMap<String, Arc<Mutex<()>>>>,
+    oversized: Mutex<HashMap<String, Instant>>,
+}
+
+impl BootstrapCache {
+    pub fn new(store: Box<dyn CacheStore>, ttl: Duration, max_elements: usize) -> Self {
+        BootstrapCache {
+            store,
+            ttl,
+            max_elements,
+            in_flight: Mutex::new(HashMap::new()),
+            oversized: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Creates the cache configured for the query API, or returns `None` if caching is disabled
+    pub async fn from_config(config: &QueryApiConfig) -> Result<Option<Self>, String> {
+        let ttl = Duration::from_secs(config.bootstrap_cache_ttl_secs);
+        let store: Box<dyn CacheStore> = match config.bootstrap_cache.as_str() {
+            "none" => return Ok(None),
+            "file" => Box::new(FileCacheStore::new(PathBuf::from(
+                &config.bootstrap_cache_dir,
+            ))),
+            "redis" => Box::new(RedisCacheStore::connect(&config.redis_url).await?),
+            other => return Err(format!("Unknown bootstrap cache type: {}", other)),
+        };
+
+        Ok(Some(Self::new(
+            store,
+            ttl,
+            config.bootstrap_cache_max_elements,
+        )))
+    }
+
+    /// The labels and predicates of a subscription determine its bootstrap data, the query does not
+    pub fn key(source_id: &str, request: &SubscriptionRequest) -> String {
+        let mut node_labels = request.node_labels.clone();
+        node_labels.sort();
+        node_labels.dedup();
+        let mut rel_labels = request.rel_labels.clone();
+        rel_labels.sort();
+        rel_labels.dedup();
+
+        json!({
+            "sourceId": source_id,
+            "nodeLabels": node_labels,
+            "relLabels": rel_labels,
+            "nodePredicates": request.node_predicates,
+            "relPredicates": request.rel_predicates,
+        })
+        .to_string()
+    }
+
+    /// Returns the cached elements for the key, or fetches and caches them if they are not cached.
+    /// `fetch` is given the maximum number of elements to read, and returns `None` if there are more.
+    /// Returns `None` if the elements are too many to cache, they should be streamed from the source instead.
+    pub async fn get_or_fetch<F, Fut>(
+        &self,
+        key: &str,
+        fetch: F,
+    ) -> Result<Option<Arc<Vec<Value>>>, String>
+    where
+        F: FnOnce(usize) -> Fut,
+        Fut: Future<Output = Result<Option<Vec<Value>>, String>>,
+    {
+        if self.is_oversized(key).await {
+            return Ok(None);
+        }
+
+        let key_lock = {
+            let mut in_flight = self.in_flight.lock().await;
+            in_flight.entry(key.to_string()).or_default().clone()
+        };
+
+        let result = {
+            let _guard = key_lock.lock().await;
+            self.read_through(key, fetch).await
+        };
+
+        let mut in_flight = self.in_flight.lock().await;
+        if Arc::strong_count(&key_lock) == 2 {
+            in_flight.remove(key);
+        }
+
+        result
+    }
+
+    async fn is_oversized(&self, key: &str) -> bool {
+        let mut oversized = self.oversized.lock().await;
+        oversized.retain(|_, expires_at| *expires_at > Instant::now());
+        oversized.contains_key(key)
+    }
+
+    async fn read_through<F, Fut>(
+        &self,
+        key: &str,
+        fetch: F,
+    ) -> Result<Option<Arc<Vec<Value>>>, String>
+    where
+        F: FnOnce(usize) -> Fut,
+        Fut: Future<Output = Result<Option<Vec<Value>>, String>>,
+    {
+        // requests that waited for a fetch of too many elements do not fetch again
+        if self.is_oversized(key).await {
+            return Ok(None);
+        }
+
+        match self.store.get(key).await {
+            Ok(Some(elements)) => {
+                log::info!(
+                    "Serving {} bootstrap elements from the cache",
+                    elements.len()
+                );
+                return Ok(Some(Arc::new(elements)));
+            }
+            Ok(None) => {}
+            Err(e) => log::warn!("Error reading the bootstrap cache: {}", e),
+        }
+
+        let elements = match fetch(self.max_elements).await? {
+            Some(elements) => elements,
+            None => {
+                log::info!(
+                    "Bootstrap data has more than {} elements, streaming it instead of caching it",
+                    self.max_elements
+                );
+                self.oversized
+                    .lock()
+                    .await
+                    .insert(key.to_string(), Instant::now() + self.ttl);
+                return Ok(None);
+            }
+        };
+        log::info!("Caching {} bootstrap elements", elements.len());
+        if let Err(e) = self.store.put(key, &elements, self.ttl).await {
+            log::warn!("Error writing the bootstrap cache: {}", e);
+        }
+
+        Ok(Some(Arc::new(elements)))
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct CacheEntry {
+    key: String,
+    expires_at_ms: u64,
+    elements: Vec<Value>,
+}
+
+/// Keeps each cached entry in a file in a local directory
+pub struct FileCacheStore {
+    dir: PathBuf,
+}
+
+impl FileCacheStore {
+    pub fn new(dir: PathBuf) -> Self {
+        FileCacheStore { dir }
+    }
+
+    fn path(&self, key: &str) -> PathBuf {
+        self.dir.join(format!("{:016x}.json", fnv1a(key)))
+    }
+}
+
+#[async_trait]
+impl CacheStore for FileCacheStore {
+    async fn get(&self, key: &str) -> Result<Option<Vec<Value>>, String> {
+        let data = match tokio::fs::read(self.path(key)).await {
+            Ok(data) => data,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+            Err(e) => return Err(format!("Error reading cache file: {}", e)),
+        };
+
+        let entry: CacheEntry = serde_json::from_slice(&data)
+            .map_err(|e| format!("Error parsing cache file: {}", e))?;
+        if entry.key != key || entry.expires_at_ms <= now_ms() {
+            return Ok(None);
+        }
+
+        Ok(Some(entry.elements))
+    }
+
+    async fn put(&self, key: &str, elements: &[Value], ttl: Duration) -> Result<(), String> {
+        let entry = CacheEntry {
+            key: key.to_string(),
+            expires_at_ms: now_ms() + ttl.as_millis() as u64,
+            elements: elements.to_vec(),
+        };
+        let data =
+            serde_json::to_vec(&entry).map_err(|e| format!("Error serializing entry: {}", e))?;
+
+        tokio::fs::create_dir_all(&self.dir)
+            .await
+            .map_err(|e| format!("Error creating cache directory: {}", e))?;
+
+        // write to a temporary file first, so readers never see a partially written entry
+        let path = self.path(key);
+        let tmp_path = path.with_extension("json.tmp");
+        tokio::fs::write(&tmp_path, data)
+            .await
+            .map_err(|e| format!("Error writing cache file: {}", e))?;
+        tokio::fs::rename(&tmp_path, &path)
+            .await
+            .map_err(|e| format!("Error writing cache file: {}", e))
+    }
+}
+
+/// Keeps each cached entry in a Redis key that expires with the entry
+pub struct RedisCacheStore {
+    connection: redis::aio::MultiplexedConnection,
+}
+
+impl RedisCacheStore {
+    pub async fn connect(url: &str) -> Result<Self, String> {
+        let client =
+            redis::Client::open(url).map_err(|e| format!("Error connecting to redis: {}", e))?;
+        let connection = client
+            .get_multiplexed_tokio_connection()
+            .await
+            .map_err(|e| format!("Error connecting to redis: {}", e))?;
+
+        Ok(RedisCacheStore { connection })
+    }
+
+    fn redis_key(key: &str) -> String {
+        format!("bootstrap-cache-{:016x}", fnv1a(key))
+    }
+}
+
+#[async_trait]
+impl CacheStore for RedisCacheStore {
+    async fn get(&self, key: &str) -> Result<Option<Vec<Value>>, String> {
+        let mut connection = self.connection.clone();
+        let data: Option<String> = connection
+            .get(Self::redis_key(key))
+            .await
+            .map_err(|e| format!("Error reading from redis: {}", e))?;
+
+        let entry: CacheEntry = match data {
+            Some(data) => serde_json::from_str(&data)
+                .map_err(|e| format!("Error parsing cache entry: {}", e))?,
+            None => return Ok(None),
+        };
+        if entry.key != key {
+            return Ok(None);
+        }
+
+        Ok(Some(entry.elements))
+    }
+
+    async fn put(&self, key: &str, elements: &[Value], ttl: Duration) -> Result<(), String> {
+        let entry = CacheEntry {
+            key: key.to_string(),
+            expires_at_ms: now_ms() + ttl.as_millis() as u64,
+            elements: elements.to_vec(),
+        };
+        let data =
+            serde_json::to_string(&entry).map_err(|e| format!("Error serializing entry: {}", e))?;
+
+        let mut connection = self.connection.clone();
+        let _: () = connection
+            .set_ex(Self::redis_key(key), data, ttl.as_secs().max(1) as usize)
+            .await
+            .map_err(|e| format!("Error writing to redis: {}", e))?;
+        Ok(())
+    }
+}
+
+fn now_ms() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_millis() as u64)
+        .unwrap_or_default()
+}
+
+/// A hash that is stable across builds, for naming cache entries
+fn fnv1a(value: &str) -> u64 {
+    value.bytes().fold(0xcbf29ce484222325, |hash, byte| {
+        (hash ^ byte as u64).wrapping_mul(0x100000001b3)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    fn request(query_id: &str, node_labels: &[&str]) -> SubscriptionRequest {
+        SubscriptionRequest {
+            query_id: query_id.to_string(),
+            query_node_id: "default".to_string(),
+            node_labels: node_labels.iter().map(|l| l.to_string()).collect(),
+            rel_labels: vec![],
+            node_predicates: Default::default(),
+            rel_predicates: Default::default(),
+            page_size: None,
+            continuation_token: None,
+        }
+    }
+
+    fn file_cache(name: &str, ttl: Duration) -> BootstrapCache {
+        let dir =
+            std::env::temp_dir().join(format!("query-api-cache-{}-{}", name, std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        BootstrapCache::new(Box::new(FileCacheStore::new(dir)), ttl, 100)
+    }
+
+    #[test]
+    fn test_key_ignores_query_and_label_order() {
+        assert_eq!(
+            BootstrapCache::key("s1", &request("q1", &["A", "B"])),
+            BootstrapCache::key("s1", &request("q2", &["B", "A"]))
+        );
+        assert_ne!(
+            BootstrapCache::key("s1", &request("q1", &["A"])),
+            BootstrapCache::key("s1", &request("q1", &["A", "B"]))
+        );
+        assert_ne!(
+            BootstrapCache::key("s1", &request("q1", &["A"])),
+            BootstrapCache::key("s2", &request("q1", &["A"]))
+        );
+    }
+
+    #[tokio::test]
+    async fn test_concurrent_requests_share_one_fetch() {
+        let cache = Arc::new(file_cache("shared", Duration::from_secs(60)));
+        let fetches = Arc::new(AtomicUsize::new(0));
+
+        let requests = (0..10).map(|_| {
+            let cache = cache.clone();
+            let fetches = fetches.clone();
+            tokio::spawn(async move {
+                cache
+                    .get_or_fetch("k1", |_| async move {
+                        fetches.fetch_add(1, Ordering::SeqCst);
+                        tokio::time::sleep(Duration::from_millis(50)).await;
+                        Ok(Some(vec![json!({"id": "n1"})]))
+                    })
+                    .await
+            })
+        });
+
+        for result in futures::future::join_all(requests).await {
+            assert_eq!(
+                *result.unwrap().unwrap().unwrap(),
+                vec![json!({"id": "n1"})]
+            );
+        }
+        assert_eq!(fetches.load(Ordering::SeqCst), 1);
+        assert!(cache.in_flight.lock().await.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_expired_entries_are_fetched_again() {
+        let cache = file_cache("expired", Duration::ZERO);
+        let fetches = AtomicUsize::new(0);
+        let fetch = |_| async {
+            fetches.fetch_add(1, Ordering::SeqCst);
+            Ok(Some(vec![json!({"id": "n1"})]))
+        };
+
+        cache.get_or_fetch("k1", fetch).await.unwrap();
+        cache.get_or_fetch("k1", fetch).await.unwrap();
+        assert_eq!(fetches.load(Ordering::SeqCst), 2);
+    }
+
+    #[tokio::test]
+    async fn test_failed_fetch_is_not_cached() {
+        let cache = file_cache("failed", Duration::from_secs(60));
+
+        let result = cache
+            .get_or_fetch("k1", |_| async { Err("source unavailable".to_string()) })
+            .await;
+        assert!(result.is_err());
+
+        let elements = cache
+            .get_or_fetch("k1", |_| async { Ok(Some(vec![json!({"id": "n1"})])) })
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(elements.len(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_oversized_data_is_not_cached_or_fetched_again() {
+        let cache = file_cache("oversized", Duration::from_secs(60));
+        let fetches = &AtomicUsize::new(0);
+        let fetch = |max_elements: usize| async move {
+            fetches.fetch_add(1, Ordering::SeqCst);
+            let elements: Vec<Value> = (0..150).map(|i| json!({ "id": i })).collect();
+            // the fetch stops reading once it has more than the maximum
+            Ok((elements.len() <= max_elements).then_some(elements))
+        };
+
+        assert!(cache.get_or_fetch("k1", fetch).await.unwrap().is_none());
+        assert!(cache.get_or_fetch("k1", fetch).await.unwrap().is_none());
+        assert_eq!(fetches.load(Ordering::SeqCst), 1);
+        assert!(cache.store.get("k1").await.unwrap().is_none());
+    }
+}
@@ -12,17 +12,21 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
-use api::{v2::AcquireRequest, ControlEvent, Source, SubscriptionPayload, SubscriptionRequest};
+use api::{
+    v2::AcquireRequest, ControlEvent, Source, SubscriptionInfo, SubscriptionParams,
+    SubscriptionPayload, SubscriptionRequest,
+};
 use async_stream::stream;
 use axum::{
-    extract::{Path, State},
+    extract::{Path, Query, State},
     http::{HeaderMap, StatusCode},
     response::IntoResponse,
     response::Json,
-    routing::{delete, post},
+    routing::{delete, get, post},
     Router,
 };
 use axum_streams::StreamBodyAs;
+use bootstrap_cache::BootstrapCache;
 use chrono::Utc;
 use drasi_comms_http::{HttpStreamingInvoker, StreamType, Verb};
 use futures::StreamExt;
@@ -35,6 +39,10 @@ use drasi_comms_abstractions::comms::{Headers, Invoker, Payload, Publisher};
 use drasi_comms_dapr::comms::{DaprHttpInvoker, DaprHttpPublisher};
 
 mod api;
+mod bootstrap_cache;
+
+/// Set by the source proxy when there is another page of bootstrap data
+const CONTINUATION_TOKEN_HEADER: &str = "x-continuation-token";
 mod query_api_config;
 
 #[tokio::main]
@@ -69,15 +77,26 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
     let invoker = DaprHttpInvoker::new("127.0.0.1".to_string(), dapr_port);
     let streaming_invoker = HttpStreamingInvoker::new();
 
+    let bootstrap_cache = match BootstrapCache::from_config(&config).await {
+        Ok(cache) => cache,
+        Err(e) => {
+            panic!("Error creating the bootstrap cache: {}", e);
+        }
+    };
+
     let shared_state = Arc::new(AppState {
         config: config.clone(),
         publisher,
         invoker,
         streaming_invoker,
+        http_client: reqwest::Client::new(),
+        bootstrap_cache,
     });
 
     let app = Router::new()
         .route("/subscription", post(handle_subscription))
+        .route("/subscriptions", get(handle_list_subscriptions))
+        .route("/schema", get(handle_schema))
         .route(
             "/subscription/:queryNodeId/:queryId",
             delete(handle_unsubscription),
@@ -109,6 +128,7 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
 async fn handle_subscription(
     State(state): State<Arc<AppState>>,
     headers: HeaderMap,
+    Query(params): Query<SubscriptionParams>,
     Json(subscription_request): Json<SubscriptionRequest>,
 ) -> impl IntoResponse {
     log::info!(
@@ -130,8 +150,19 @@ async fn handle_subscription(
     };
     let headers = Headers::new(headers_map);
 
-    if let Err(err) = dispatch_control_event(&subscription_request, &state, headers.clone()).await {
-        return err;
+    // the subscription was registered with the first page of the bootstrap data
+    let first_page = subscription_request.continuation_token.is_none();
+
+    if first_page {
+        if let Err(err) =
+            dispatch_control_event(&subscription_request, &state, headers.clone()).await
+        {
+            return err;
+        }
+    }
+
+    if !params.bootstrap {
+        return "Subscription event dispatched".into_response();
     }
 
     log::info!("Checking if the source supports streaming");
@@ -142,12 +173,28 @@ async fn handle_subscription(
         .await
         .is_ok();
 
+    // pages are read from the source, so that each page is consistent with the token the source returned
+    let paged = subscription_request.page_size.is_some();
+
+    if let Some(cache) = state.bootstrap_cache.as_ref().filter(|_| !paged) {
+        return acquire_cached(
+            &state,
+            cache,
+            subscription_request,
+            headers,
+            supports_streaming,
+        )
+        .await
+        .into_response();
+    }
+
     if supports_streaming {
         log::info!("Source supports streaming");
         acquire_v2(&state, subscription_request, headers)
             .await
             .into_response()
     } else {
+        // sources that do not support streaming always return all of their data, without a continuation token
         log::info!("Source does not support streaming");
         acquire_v1(&state, subscription_request)
             .await
@@ -155,6 +202,111 @@ async fn handle_subscription(
     }
 }
 
+async fn handle_list_subscriptions(State(state): State<Arc<AppState>>) -> impl IntoResponse {
+    let url = format!(
+        "http://127.0.0.1:{}/v1.0/invoke/{}-change-router/method/subscriptions",
+        state.config.dapr_port, state.config.source_id
+    );
+
+    let response = match state.http_client.get(url).send().await {
+        Ok(response) => response,
+        Err(e) => {
+            log::error!(
+                "Error listing subscriptions from the change router: {:?}",
+                e
+            );
+            return (
+                StatusCode::BAD_GATEWAY,
+                format!("Error listing subscriptions: {:?}", e),
+            )
+                .into_response();
+        }
+    };
+
+    if !response.status().is_success() {
+        let status = response.status();
+        let body = response.text().await.unwrap_or_default();
+        log::error!(
+            "Error listing subscriptions from the change router: {} {}",
+            status,
+            body
+        );
+        return (
+            StatusCode::BAD_GATEWAY,
+            format!("Error listing subscriptions: {} {}", status, body),
+        )
+            .into_response();
+    }
+
+    match response.json::<Vec<SubscriptionInfo>>().await {
+        Ok(subscriptions) => Json(subscriptions).into_response(),
+        Err(e) => {
+            log::error!("Error parsing the subscriptions: {:?}", e);
+            (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                format!("Error parsing the subscriptions: {:?}", e),
+            )
+                .into_response()
+        }
+    }
+}
+
+/// Returns the schema the proxy declares, sources that do not declare one respond with 404
+async fn handle_schema(State(state): State<Arc<AppState>>) -> impl IntoResponse {
+    let url = format!(
+        "http://127.0.0.1:{}/v1.0/invoke/{}-proxy/method/schema",
+        state.config.dapr_port, state.config.source_id
+    );
+
+    let response = match state.http_client.get(url).send().await {
+        Ok(response) => response,
+        Err(e) => {
+            log::error!("Error getting the schema from the proxy: {:?}", e);
+            return (
+                StatusCode::BAD_GATEWAY,
+                format!("Error getting the schema: {:?}", e),
+            )
+                .into_response();
+        }
+    };
+
+    match response.status() {
+        StatusCode::NOT_FOUND => {
+            return (
+                StatusCode::NOT_FOUND,
+                "The source does not declare a schema",
+            )
+                .into_response()
+        }
+        status if !status.is_success() => {
+            let body = response.text().await.unwrap_or_default();
+            log::error!(
+                "Error getting the schema from the proxy: {} {}",
+                status,
+                body
+            );
+            return (
+                StatusCode::BAD_GATEWAY,
+                format!("Error getting the schema: {} {}", status, body),
+            )
+                .into_response();
+        }
+        _ => {}
+    }
+
+    match response.json::<serde_json::Value>().await {
+        Ok(schema) => Json(schema).into_response(),
+        Err(e) => {
+            log::error!("Error parsing the schema: {:?}", e);
+            (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                format!("Error parsing the schema: {:?}", e),
+            )
+                .into_response()
+        }
+    }
+}
+
 async fn handle_unsubscription(
     State(state): State<Arc<AppState>>,
     headers: HeaderMap,
@@ -215,6 +367,10 @@ async fn dispatch_unsubscription_event(
         query_node_id: query_node_id.to_string(),
         node_labels: vec![],
         rel_labels: vec![],
+        node_predicates: Default::default(),
+        rel_predicates: Default::default(),
+        page_size: None,
+        continuation_token: None,
     };
     let control_event = ControlEvent {
         op: "d".to_string(),
@@ -256,15 +412,169 @@ async fn acquire_v1(
     state: &AppState,
     subscription_request: SubscriptionRequest,
 ) -> impl IntoResponse {
-    let acquire_request = match serde_json::to_value(&subscription_request) {
+    let response_json = match invoke_acquire(state, &subscription_request).await {
+        Ok(response) => response,
+        Err(e) => return e.into_response(),
+    };
+
+    let stream = stream! {
+        for node in response_json.nodes {
+            log::info!("loading node: {:?}", node.id);
+            yield api::v2::BootstrapElement::from(node);
+        }
+        for rel in response_json.rels {
+            yield api::v2::BootstrapElement::from(rel);
+        }
+    };
+
+    log::info!("Returning the stream");
+
+    StreamBodyAs::json_nl(stream).into_response()
+}
+
+async fn acquire_v2(
+    state: &AppState,
+    subscription_request: SubscriptionRequest,
+    headers: Headers,
+) -> impl IntoResponse {
+    let (response_headers, mut resp) =
+        match invoke_acquire_stream(state, subscription_request, headers).await {
+            Ok(resp) => resp,
+            Err(e) => return e.into_response(),
+        };
+
+    let mut continuation = HeaderMap::new();
+    if let Some(token) = response_headers.headers.get(CONTINUATION_TOKEN_HEADER) {
+        if let Ok(token) = token.parse() {
+            continuation.insert(CONTINUATION_TOKEN_HEADER, token);
+        }
+    }
+
+    let stream = stream! {
+        while let Some(element) = resp.next().await {
+            match element {
+                Ok(element) => yield element,
+                Err(e) => {
+                    log::error!("Error reading the stream: {:?}", e);
+                    break;
+                }
+            }
+        }
+    };
+
+    (continuation, StreamBodyAs::json_nl(stream)).into_response()
+}
+
+/// Serves the bootstrap data from the cache, reading it from the source only if it is not cached
+async fn acquire_cached(
+    state: &AppState,
+    cache: &BootstrapCache,
+    subscription_request: SubscriptionRequest,
+    headers: Headers,
+    supports_streaming: bool,
+) -> impl IntoResponse {
+    let key = BootstrapCache::key(&state.config.source_id, &subscription_request);
+    let fetch_request = subscription_request.clone();
+    let fetch_headers = headers.clone();
+    let elements = match cache
+        .get_or_fetch(&key, |max_elements| {
+            fetch_bootstrap(
+                state,
+                fetch_request,
+                fetch_headers,
+                supports_streaming,
+                max_elements,
+            )
+        })
+        .await
+    {
+        Ok(Some(elements)) => elements,
+        // too many elements to keep in memory, they are streamed from the source like without the cache
+        Ok(None) if supports_streaming => {
+            return acquire_v2(state, subscription_request, headers)
+                .await
+                .into_response()
+        }
+        Ok(None) => {
+            return acquire_v1(state, subscription_request)
+                .await
+                .into_response()
+        }
+        Err(e) => {
+            log::error!("Error fetching the bootstrap data: {}", e);
+            return (StatusCode::BAD_GATEWAY, e).into_response();
+        }
+    };
+
+    let stream = stream! {
+        for element in elements.iter() {
+            yield element.clone();
+        }
+    };
+
+    StreamBodyAs::json_nl(stream).into_response()
+}
+
+/// Reads all the bootstrap data of a subscription from the source, or returns `None` as soon as it has
+/// more than `max_elements` elements.
+/// Unlike the uncached path, a broken stream is an error, so that partial data is never cached.
+async fn fetch_bootstrap(
+    state: &AppState,
+    subscription_request: SubscriptionRequest,
+    headers: Headers,
+    supports_streaming: bool,
+    max_elements: usize,
+) -> Result<Option<Vec<serde_json::Value>>, String> {
+    if !supports_streaming {
+        let response_json = invoke_acquire(state, &subscription_request)
+            .await
+            .map_err(|(_, e)| e)?;
+
+        let elements = response_json
+            .nodes
+            .into_iter()
+            .map(api::v2::BootstrapElement::from)
+            .chain(
+                response_json
+                    .rels
+                    .into_iter()
+                    .map(api::v2::BootstrapElement::from),
+            )
+            .map(|element| serde_json::to_value(element).map_err(|e| e.to_string()))
+            .collect::<Result<Vec<_>, _>>()?;
+        return Ok((elements.len() <= max_elements).then_some(elements));
+    }
+
+    let (_, resp) = invoke_acquire_stream(state, subscription_request, headers)
+        .await
+        .map_err(|(_, e)| e)?;
+    let mut resp = Box::pin(resp);
+
+    let mut elements = Vec::new();
+    while let Some(element) = resp.next().await {
+        match element {
+            Ok(element) => elements.push(element),
+            Err(e) => return Err(format!("Error reading the stream: {:?}", e)),
+        }
+        if elements.len() > max_elements {
+            return Ok(None);
+        }
+    }
+    Ok(Some(elements))
+}
+
+async fn invoke_acquire(
+    state: &AppState,
+    subscription_request: &SubscriptionRequest,
+) -> Result<api::v1::BootstrapEvents, (StatusCode, String)> {
+    let acquire_request = match serde_json::to_value(subscription_request) {
         Ok(json) => json,
         Err(e) => {
             log::error!("Error serializing the subscription request: {:?}", e);
-            return (
+            return Err((
                 StatusCode::INTERNAL_SERVER_ERROR,
                 format!("Error serializing the subscription request: {:?}", e),
-            )
-                .into_response();
+            ));
         }
     };
 
@@ -283,62 +593,51 @@ async fn acquire_v1(
         Ok(response) => response,
         Err(e) => {
             log::error!("Error invoking the acquire method on the proxy: {:?}", e);
-            return (
+            return Err((
                 StatusCode::INTERNAL_SERVER_ERROR,
                 format!("Error invoking acquire: {:?}", e),
-            )
-                .into_response();
+            ));
         }
     };
 
-    let response_json: api::v1::BootstrapEvents = match serde_json::from_slice(&response) {
-        Ok(json) => json,
+    match serde_json::from_slice(&response) {
+        Ok(json) => Ok(json),
         Err(e) => {
             log::error!("Error parsing the response from the proxy: {:?}", e);
-            return (
+            Err((
                 StatusCode::INTERNAL_SERVER_ERROR,
                 format!("Error parsing the response from the proxy: {:?}", e),
-            )
-                .into_response();
+            ))
         }
-    };
-
-    let stream = stream! {
-        for node in response_json.nodes {
-            log::info!("loading node: {:?}", node.id);
-            yield api::v2::BootstrapElement::from(node);
-        }
-        for rel in response_json.rels {
-            yield api::v2::BootstrapElement::from(rel);
-        }
-    };
-
-    log::info!("Returning the stream");
-
-    StreamBodyAs::json_nl(stream).into_response()
+    }
 }
 
-async fn acquire_v2(
+async fn invoke_acquire_stream(
     state: &AppState,
     subscription_request: SubscriptionRequest,
     headers: Headers,
-) -> impl IntoResponse {
+) -> Result<
+    (
+        Headers,
+        impl futures::Stream<Item = Result<serde_json::Value, impl std::fmt::Debug>> + Send,
+    ),
+    (StatusCode, String),
+> {
     let acquire_request: AcquireRequest = subscription_request.into();
     let acquire_request = match serde_json::to_value(&acquire_request) {
         Ok(json) => json,
         Err(e) => {
             log::error!("Error serializing the subscription request: {:?}", e);
-            return (
+            return Err((
                 StatusCode::INTERNAL_SERVER_ERROR,
                 format!("Error serializing the subscription request: {:?}", e),
-            )
-                .into_response();
+            ));
         }
     };
     let app_id = format!("{}-proxy", state.config.source_id);
-    let mut resp = match state
+    match state
         .streaming_invoker
-        .invoke(
+        .invoke_with_response_headers(
             Payload::Json(acquire_request),
             &app_id,
             Verb::Post,
@@ -348,33 +647,18 @@ async fn acquire_v2(
         )
         .await
     {
-        Ok(resp) => resp,
+        Ok(resp) => Ok(resp),
         Err(e) => {
             log::error!(
                 "Error invoking the acquire-stream method on the proxy: {:?}",
                 e
             );
-            return (
+            Err((
                 StatusCode::BAD_GATEWAY,
                 format!("Error invoking acquire-stream: {:?}", e),
-            )
-                .into_response();
+            ))
         }
-    };
-
-    let stream = stream! {
-        while let Some(element) = resp.next().await {
-            match element {
-                Ok(element) => yield element,
-                Err(e) => {
-                    log::error!("Error reading the stream: {:?}", e);
-                    break;
-                }
-            }
-        }
-    };
-
-    StreamBodyAs::json_nl(stream).into_response()
+    }
 }
 
 struct AppState {
@@ -382,4 +666,6 @@ struct AppState {
     publisher: DaprHttpPublisher,
     invoker: DaprHttpInvoker,
     streaming_invoker: HttpStreamingInvoker,
+    http_client: reqwest::Client,
+    bootstrap_cache: Option<BootstrapCache>,
 }
@@ -19,6 +19,11 @@ pub struct QueryApiConfig {
     pub app_port: String,
     pub source_id: String,
     pub dapr_port: String,
+    pub bootstrap_cache: String,
+    pub bootstrap_cache_ttl_secs: u64,
+    pub bootstrap_cache_dir: String,
+    pub bootstrap_cache_max_elements: usize,
+    pub redis_url: String,
 }
 
 impl QueryApiConfig {
@@ -29,11 +34,36 @@ impl QueryApiConfig {
             env::var("SOURCE_ID").map_err(|_| "Missing SOURCE_ID environment variable")?;
         let dapr_port = env::var("DAPR_HTTP_PORT").unwrap_or_else(|_| "3500".to_string());
 
+        // none, file or redis
+        let bootstrap_cache = env::var("BOOTSTRAP_CACHE").unwrap_or_else(|_| "none".to_string());
+        let bootstrap_cache_ttl_secs = match env::var("BOOTSTRAP_CACHE_TTL_SECS") {
+            Ok(ttl) => ttl
+                .parse()
+                .map_err(|_| "Invalid BOOTSTRAP_CACHE_TTL_SECS environment variable")?,
+            Err(_) => 300,
+        };
+        let bootstrap_cache_dir = env::var("BOOTSTRAP_CACHE_DIR")
+            .unwrap_or_else(|_| "/tmp/drasi-bootstrap-cache".to_string());
+        // bootstrap data with more elements is streamed from the source instead of being cached
+        let bootstrap_cache_max_elements = match env::var("BOOTSTRAP_CACHE_MAX_ELEMENTS") {
+            Ok(max) => max
+                .parse()
+                .map_err(|_| "Invalid BOOTSTRAP_CACHE_MAX_ELEMENTS environment variable")?,
+            Err(_) => 100_000,
+        };
+        let redis_url =
+            env::var("REDIS_BROKER").unwrap_or_else(|_| "redis://drasi-redis:6379".to_string());
+
         Ok(Self {
             pubsub_name,
             app_port,
             source_id,
             dapr_port,
+            bootstrap_cache,
+            bootstrap_cache_ttl_secs,
+            bootstrap_cache_dir,
+            bootstrap_cache_max_elements,
+            redis_url,
         })
     }
 }